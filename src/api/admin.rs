@@ -0,0 +1,678 @@
+use std::collections::BTreeMap;
+use std::env;
+use rocket::fairing::AdHoc;
+use rocket::http::Status;
+use rocket::request::{FromRequest, Outcome};
+use rocket::serde::json::{json, Json, Value};
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+use rocket::{delete, get, post, Request};
+use uuid::Uuid;
+
+use crate::api::error::ErrorBody;
+use crate::api::routes;
+use crate::utils::{api_tokens, audit, canary, config, git, hmac, http_client, jobs, mirror_schedule, paused, repo_health, webhook_secrets};
+use crate::utils::api_tokens::{ApiToken, Role};
+use crate::utils::audit::AuditEntry;
+use crate::utils::mirror_schedule::SyncStatus;
+use crate::utils::paused::PausedEvent;
+use crate::utils::webhook_secrets::{RotateReport, RotateRequest};
+use crate::utils::onboard::{self, OnboardReport, OnboardRequest};
+
+/// Caller of an authenticated endpoint
+#[derive(Debug, Clone)]
+pub struct ApiCaller {
+    /// Name of the token, `ADMIN_TOKEN` for the bootstrap token
+    pub name: String,
+    pub role: Role,
+}
+
+/// Authenticated caller of the current request, audited once it is answered
+#[derive(Debug, Clone)]
+struct AuditedCall {
+    caller: ApiCaller,
+    allowed: bool,
+}
+
+/// Authenticates `Authorization: Bearer <token>` against `ADMIN_TOKEN`, which
+/// acts as an admin token, and the stored API tokens, then checks that the
+/// token's role includes `required`; every attempt by a known token is
+/// audited by `audit_fairing` with the response status
+fn authorize(request: &Request<'_>, required: Role) -> Outcome<ApiCaller, &'static str> {
+    let bootstrap = env::var("ADMIN_TOKEN").ok().filter(|token| !token.is_empty());
+    let tokens = api_tokens::store().lock().unwrap();
+    if bootstrap.is_none() && tokens.list().is_empty() {
+        println!("❌ Admin request rejected: no ADMIN_TOKEN or API token configured");
+        return Outcome::Error((Status::Forbidden, "Admin API disabled"));
+    }
+
+    let provided = request
+        .headers()
+        .get_one("Authorization")
+        .and_then(|value| value.strip_prefix("Bearer "));
+    let caller = match provided {
+        Some(token) if bootstrap.as_deref().is_some_and(|bootstrap| hmac::constant_time_eq(bootstrap.as_bytes(), token.as_bytes())) => ApiCaller { name: "ADMIN_TOKEN".to_string(), role: Role::Admin },
+        Some(token) => match tokens.authenticate(token) {
+            Some(token) => ApiCaller { name: token.name, role: token.role },
+            None => {
+                println!("❌ Admin request rejected: invalid token");
+                return Outcome::Error((Status::Unauthorized, "Invalid admin token"));
+            }
+        },
+        None => {
+            println!("❌ Admin request rejected: missing token");
+            return Outcome::Error((Status::Unauthorized, "Invalid admin token"));
+        }
+    };
+    drop(tokens);
+
+    let allowed = caller.role >= required;
+    request.local_cache(|| Some(AuditedCall { caller: caller.clone(), allowed }));
+    if !allowed {
+        println!("❌ Admin request rejected: token {} is {}, {} required", caller.name, caller.role.as_str(), required.as_str());
+        return Outcome::Error((Status::Forbidden, "Insufficient role"));
+    }
+    Outcome::Success(caller)
+}
+
+/// Writes the audit entry of each authenticated request once its response,
+/// and so the outcome of the call, is known
+pub fn audit_fairing() -> AdHoc {
+    AdHoc::on_response("Audit log", |request, response| Box::pin(async move {
+        if let Some(call) = request.local_cache(|| None::<AuditedCall>) {
+            let caller = &call.caller;
+            audit::log().lock().unwrap().record(
+                &caller.name,
+                caller.role.as_str(),
+                request.method().as_str(),
+                request.uri().path().as_str(),
+                call.allowed,
+                response.status().code,
+            );
+        }
+    }))
+}
+
+/// Request guard for read-only endpoints (jobs, dead letters, backport graphs)
+#[derive(Debug)]
+pub struct ReadToken(pub ApiCaller);
+
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for ReadToken {
+    type Error = &'static str;
+
+    async fn from_request(request: &'r Request<'_>) -> Outcome<Self, Self::Error> {
+        authorize(request, Role::ReadOnly).map(ReadToken)
+    }
+}
+
+/// Request guard for operator endpoints (pausing, resuming, requeueing)
+#[derive(Debug)]
+pub struct OperatorToken(pub ApiCaller);
+
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for OperatorToken {
+    type Error = &'static str;
+
+    async fn from_request(request: &'r Request<'_>) -> Outcome<Self, Self::Error> {
+        authorize(request, Role::Operator).map(OperatorToken)
+    }
+}
+
+/// Request guard for admin endpoints (onboarding, secrets, tokens)
+#[derive(Debug)]
+pub struct AdminToken(pub ApiCaller);
+
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for AdminToken {
+    type Error = &'static str;
+
+    async fn from_request(request: &'r Request<'_>) -> Outcome<Self, Self::Error> {
+        authorize(request, Role::Admin).map(AdminToken)
+    }
+}
+
+/// Onboards a repository: verifies access, registers the webhook, writes config, dry-runs
+#[utoipa::path(
+    post,
+    path = "/admin/onboard",
+    tag = "admin",
+    request_body = OnboardRequest,
+    responses(
+        (status = 200, description = "Onboarded", body = OnboardReport),
+        (status = 422, description = "A step failed", body = OnboardReport),
+    ),
+    security(("adminToken" = [])),
+)]
+#[post("/admin/onboard", data = "<request>")]
+pub async fn onboard_handle(_admin: AdminToken, request: Json<OnboardRequest>) -> (Status, Json<OnboardReport>) {
+    let request = request.into_inner();
+    match tokio::task::spawn_blocking(move || onboard::onboard(&request)).await {
+        Ok(report) if report.is_success() => (Status::Ok, Json(report)),
+        Ok(report) => (Status::UnprocessableEntity, Json(report)),
+        Err(e) => {
+            println!("Task join error: {}", e);
+            let report = OnboardReport {
+                errors: vec!["Internal Server Error".to_string()],
+                ..Default::default()
+            };
+            (Status::InternalServerError, Json(report))
+        }
+    }
+}
+
+/// State of a repository after an admin action; only what the action
+/// changed is set
+#[derive(Debug, Default, Serialize, ToSchema)]
+pub struct RepoState {
+    pub repo: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub enabled: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub healthy: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub confirmed: Option<bool>,
+    /// Held events replayed in the background
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub replayed: Option<usize>,
+    /// Events still held because the replay was skipped
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub held: Option<usize>,
+}
+
+/// Disables a repository: its webhooks are ignored (or held, with
+/// `queue_when_disabled`) while its configuration is kept
+#[utoipa::path(
+    post,
+    path = "/admin/repos/{repo}/disable",
+    tag = "admin",
+    params(("repo" = String, Path, description = "Repository name in config.yml")),
+    responses(
+        (status = 200, description = "Disabled", body = RepoState),
+        (status = 404, description = "Repository not found in config", body = ErrorBody),
+    ),
+    security(("adminToken" = [])),
+)]
+#[post("/admin/repos/<repo>/disable")]
+pub fn disable_repo_handle(_operator: OperatorToken, repo: &str) -> (Status, Json<Value>) {
+    match config::set_repo_enabled(config::CONFIG_FILE, repo, false) {
+        Ok(true) => {
+            println!("Repository {} disabled", repo);
+            (Status::Ok, Json(json!(RepoState { repo: repo.to_string(), enabled: Some(false), ..Default::default() })))
+        }
+        Ok(false) => (Status::NotFound, Json(json!({ "error": "Repository not found in config" }))),
+        Err(e) => {
+            println!("Failed to disable {}: {}", repo, e);
+            (Status::InternalServerError, Json(json!({ "error": "Failed to update config" })))
+        }
+    }
+}
+
+/// Re-enables a repository and, unless `replay=false`, replays the events held while it was disabled
+#[utoipa::path(
+    post,
+    path = "/admin/repos/{repo}/enable",
+    tag = "admin",
+    params(
+        ("repo" = String, Path, description = "Repository name in config.yml"),
+        ("replay" = Option<bool>, Query, description = "Replay held events (default true)"),
+    ),
+    responses(
+        (status = 200, description = "Enabled", body = RepoState),
+        (status = 404, description = "Repository not found in config", body = ErrorBody),
+    ),
+    security(("adminToken" = [])),
+)]
+#[post("/admin/repos/<repo>/enable?<replay>")]
+pub fn enable_repo_handle(_operator: OperatorToken, repo: &str, replay: Option<bool>) -> (Status, Json<Value>) {
+    match config::set_repo_enabled(config::CONFIG_FILE, repo, true) {
+        Ok(true) => {}
+        Ok(false) => return (Status::NotFound, Json(json!({ "error": "Repository not found in config" }))),
+        Err(e) => {
+            println!("Failed to enable {}: {}", repo, e);
+            return (Status::InternalServerError, Json(json!({ "error": "Failed to update config" })));
+        }
+    }
+    println!("Repository {} enabled", repo);
+    let state = RepoState { repo: repo.to_string(), enabled: Some(true), ..Default::default() };
+    (Status::Ok, Json(json!(replay_unless_skipped(state, replay))))
+}
+
+/// Replays the events held for `state.repo` unless `replay=false`, and
+/// records how many were replayed or are still held
+fn replay_unless_skipped(state: RepoState, replay: Option<bool>) -> RepoState {
+    if !replay.unwrap_or(true) {
+        let held = paused::store().lock().unwrap().count(&state.repo);
+        return RepoState { replayed: Some(0), held: Some(held), ..state };
+    }
+    let replayed = replay_held(&state.repo);
+    RepoState { replayed: Some(replayed), ..state }
+}
+
+/// Replays the events held for `repo` in the background and returns their number
+fn replay_held(repo: &str) -> usize {
+    let events = paused::store().lock().unwrap().take_repo(repo);
+    replay_events(events)
+}
+
+/// Replays held events in the background, dropping those whose stored
+/// payload fails verification; returns the number replayed
+fn replay_events(events: Vec<PausedEvent>) -> usize {
+    let events: Vec<(PausedEvent, String)> = events
+        .into_iter()
+        .filter_map(|event| match event.verify() {
+            Ok(body) => Some((event, body)),
+            Err(e) => {
+                println!("❌ Not replaying held {} event for {}: {}", event.event, event.repo, e);
+                None
+            }
+        })
+        .collect();
+    let replayed = events.len();
+    tokio::spawn(async move {
+        for (event, body) in events {
+            if let Err(e) = routes::process_event(&event.platform, &event.event, body.into(), http_client::shared()).await {
+                println!("Replay of held {} event for {} failed: {}", event.event, event.repo, e);
+            }
+        }
+    });
+    replayed
+}
+
+/// Clears the unhealthy mark of a repository that exhausted its failure
+/// budget and, unless `replay=false`, replays the events held meanwhile
+#[utoipa::path(
+    post,
+    path = "/admin/repos/{repo}/resume",
+    tag = "admin",
+    params(
+        ("repo" = String, Path, description = "Repository name in config.yml"),
+        ("replay" = Option<bool>, Query, description = "Replay held events (default true)"),
+    ),
+    responses(
+        (status = 200, description = "Resumed", body = RepoState),
+        (status = 409, description = "Repository is not marked unhealthy", body = ErrorBody),
+    ),
+    security(("adminToken" = [])),
+)]
+#[post("/admin/repos/<repo>/resume?<replay>")]
+pub fn resume_repo_handle(_operator: OperatorToken, repo: &str, replay: Option<bool>) -> (Status, Json<Value>) {
+    if !repo_health::store().lock().unwrap().resume(repo) {
+        return (Status::Conflict, Json(json!({ "error": "Repository is not marked unhealthy" })));
+    }
+    println!("Repository {} resumed after exhausting its failure budget", repo);
+    let state = RepoState { repo: repo.to_string(), healthy: Some(true), ..Default::default() };
+    (Status::Ok, Json(json!(replay_unless_skipped(state, replay))))
+}
+
+/// Confirms the current configuration of a repository with `canary_jobs`:
+/// its jobs push again and, unless `replay=false`, the events held after its
+/// dry runs are replayed
+#[utoipa::path(
+    post,
+    path = "/admin/repos/{repo}/canary/confirm",
+    tag = "admin",
+    params(
+        ("repo" = String, Path, description = "Repository name in config.yml"),
+        ("replay" = Option<bool>, Query, description = "Replay held events (default true)"),
+    ),
+    responses(
+        (status = 200, description = "Confirmed", body = RepoState),
+        (status = 404, description = "Repository not found in config", body = ErrorBody),
+        (status = 409, description = "Configuration is already confirmed", body = ErrorBody),
+    ),
+    security(("adminToken" = [])),
+)]
+#[post("/admin/repos/<repo>/canary/confirm?<replay>")]
+pub fn confirm_canary_handle(_operator: OperatorToken, repo: &str, replay: Option<bool>) -> (Status, Json<Value>) {
+    let repo_config = match config::load_repo_config(repo) {
+        Ok(Some(repo_config)) => repo_config,
+        Ok(None) => return (Status::NotFound, Json(json!({ "error": "Repository not found in config" }))),
+        Err(e) => {
+            println!("Failed to read config for {}: {}", repo, e);
+            return (Status::InternalServerError, Json(json!({ "error": "Failed to read config" })));
+        }
+    };
+    if !canary::store().lock().unwrap().confirm(repo, &canary::fingerprint(&repo_config)) {
+        return (Status::Conflict, Json(json!({ "error": "Configuration is already confirmed" })));
+    }
+    println!("Configuration of {} confirmed, resuming real pushes", repo);
+    let state = RepoState { repo: repo.to_string(), confirmed: Some(true), ..Default::default() };
+    (Status::Ok, Json(json!(replay_unless_skipped(state, replay))))
+}
+
+/// Reply to a request that started a background job
+#[derive(Debug, Serialize, ToSchema)]
+pub struct JobStarted {
+    pub repo: String,
+    pub job: Uuid,
+}
+
+/// Body of `POST /admin/repos/<repo>/backport-range`
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct BackportRangeRequest {
+    /// Branch the commits are on
+    pub branch: String,
+    /// `<sha1>..<sha2>`: the commits after `sha1` up to `sha2`
+    pub range: String,
+    pub target_branch: String,
+}
+
+/// Cherry-picks a commit range of a repository onto a branch outside of any
+/// PR, for hotfixes that landed without one; runs as a job in the background
+#[utoipa::path(
+    post,
+    path = "/admin/repos/{repo}/backport-range",
+    tag = "admin",
+    params(("repo" = String, Path, description = "Repository name in config.yml")),
+    request_body = BackportRangeRequest,
+    responses(
+        (status = 202, description = "Backport started as a job", body = JobStarted),
+        (status = 400, description = "Malformed range", body = ErrorBody),
+        (status = 404, description = "Repository not found in config", body = ErrorBody),
+    ),
+    security(("adminToken" = [])),
+)]
+#[post("/admin/repos/<repo>/backport-range", data = "<request>")]
+pub fn backport_range_handle(_operator: OperatorToken, repo: &str, request: Json<BackportRangeRequest>) -> (Status, Json<Value>) {
+    let repo_config = match config::load_repo_config(repo) {
+        Ok(Some(repo_config)) => repo_config,
+        Ok(None) => return (Status::NotFound, Json(json!({ "error": "Repository not found in config" }))),
+        Err(e) => {
+            println!("Failed to read config for {}: {}", repo, e);
+            return (Status::InternalServerError, Json(json!({ "error": "Failed to read config" })));
+        }
+    };
+    if !request.range.contains("..") {
+        return (Status::BadRequest, Json(json!({ "error": "Expected a range of the form <sha1>..<sha2>" })));
+    }
+    let job_id = jobs::store().lock().unwrap().start(repo_config.source_platform(), repo, "backport_range");
+    let request = request.into_inner();
+    tokio::task::spawn_blocking(move || {
+        let result = git::backport_range(&repo_config, &request.branch, &request.range, &request.target_branch, job_id);
+        match &result {
+            Ok(message) => println!("Job {}: {}", job_id, message),
+            Err(e) => println!("Job {} failed: {}", job_id, e.details()),
+        }
+        let mut store = jobs::store().lock().unwrap();
+        if let Err(e) = &result {
+            store.update(job_id, |job| job.error_kind = Some(e.kind().to_string()));
+        }
+        store.finish(job_id, result.map(|_| ()).map_err(|e| e.to_string()));
+    });
+    (Status::Accepted, Json(json!(JobStarted { repo: repo.to_string(), job: job_id })))
+}
+
+/// Mirrors a configured repository to its target right away, e.g. to
+/// re-sync the target after a force-push; the mirror runs as a job in the
+/// background
+#[utoipa::path(
+    post,
+    path = "/mirror/{repo}",
+    tag = "admin",
+    params(("repo" = String, Path, description = "Repository name in config.yml")),
+    responses(
+        (status = 202, description = "Mirror started as a job", body = JobStarted),
+        (status = 404, description = "Repository not found in config", body = ErrorBody),
+        (status = 409, description = "Repository is disabled, or a mirror of it is already running", body = ErrorBody),
+    ),
+    security(("adminToken" = [])),
+)]
+#[post("/mirror/<repo>")]
+pub fn mirror_handle(_operator: OperatorToken, repo: &str) -> (Status, Json<Value>) {
+    let repo_config = match config::load_repo_config(repo) {
+        Ok(Some(repo_config)) => repo_config,
+        Ok(None) => return (Status::NotFound, Json(json!({ "error": "Repository not found in config" }))),
+        Err(e) => {
+            println!("Failed to read config for {}: {}", repo, e);
+            return (Status::InternalServerError, Json(json!({ "error": "Failed to read config" })));
+        }
+    };
+    if !repo_config.enabled {
+        return (Status::Conflict, Json(json!({ "error": "Repository is disabled" })));
+    }
+    if let Some(job_id) = mirror_schedule::store().lock().unwrap().running(repo) {
+        return (Status::Conflict, Json(json!({ "error": "A mirror of the repository is already running", "job": job_id })));
+    }
+    let job_id = mirror_schedule::start_job(repo, &repo_config);
+    let name = repo.to_string();
+    tokio::task::spawn_blocking(move || mirror_schedule::sync(&name, &repo_config, job_id));
+    (Status::Accepted, Json(json!(JobStarted { repo: repo.to_string(), job: job_id })))
+}
+
+/// Sync status of the scheduled mirrors, by repository
+#[derive(Debug, Serialize, ToSchema)]
+pub struct MirrorList {
+    pub mirrors: BTreeMap<String, SyncStatus>,
+}
+
+/// Last scheduled sync and next due sync of every repository with a
+/// `mirror` schedule
+#[utoipa::path(
+    get,
+    path = "/admin/mirrors",
+    tag = "admin",
+    responses((status = 200, description = "Repository -> sync status", body = MirrorList)),
+    security(("adminToken" = [])),
+)]
+#[get("/admin/mirrors")]
+pub fn mirrors_handle(_reader: ReadToken) -> Json<MirrorList> {
+    Json(MirrorList { mirrors: mirror_schedule::store().lock().unwrap().list().clone() })
+}
+
+/// Platforms paused after a pause or resume
+#[derive(Debug, Serialize, ToSchema)]
+pub struct PauseState {
+    /// Paused platforms, `*` for all
+    pub paused: Vec<String>,
+    /// Held events replayed on resume
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub replayed: Option<usize>,
+}
+
+/// Checks the `platform` query parameter of the pause endpoints; none means every platform
+fn pause_target(platform: Option<&str>) -> Result<&str, (Status, Json<Value>)> {
+    match platform {
+        None => Ok(paused::ALL_PLATFORMS),
+        Some(platform @ ("github" | "gitcode")) => Ok(platform),
+        Some(_) => Err((Status::BadRequest, Json(json!({ "error": "Unknown platform" })))),
+    }
+}
+
+/// Stops dispatching jobs for a platform (or all of them) during forge incidents;
+/// webhooks are still accepted and held until resumed
+#[utoipa::path(
+    post,
+    path = "/admin/pause",
+    tag = "admin",
+    params(("platform" = Option<String>, Query, description = "`github` or `gitcode`; every platform when omitted")),
+    responses(
+        (status = 200, description = "Paused", body = PauseState),
+        (status = 400, description = "Unknown platform", body = ErrorBody),
+    ),
+    security(("adminToken" = [])),
+)]
+#[post("/admin/pause?<platform>")]
+pub fn pause_handle(_operator: OperatorToken, platform: Option<&str>) -> (Status, Json<Value>) {
+    let target = match pause_target(platform) {
+        Ok(target) => target,
+        Err(response) => return response,
+    };
+    let mut pauses = paused::platforms().lock().unwrap();
+    pauses.pause(target);
+    println!("Paused job dispatch for {}", target);
+    (Status::Ok, Json(json!(PauseState { paused: pauses.list(), replayed: None })))
+}
+
+/// Resumes a platform (or all of them) and, unless `replay=false`, replays
+/// the events held while it was paused
+#[utoipa::path(
+    post,
+    path = "/admin/resume",
+    tag = "admin",
+    params(
+        ("platform" = Option<String>, Query, description = "`github` or `gitcode`; every platform when omitted"),
+        ("replay" = Option<bool>, Query, description = "Replay held events (default true)"),
+    ),
+    responses(
+        (status = 200, description = "Resumed", body = PauseState),
+        (status = 400, description = "Unknown platform", body = ErrorBody),
+    ),
+    security(("adminToken" = [])),
+)]
+#[post("/admin/resume?<platform>&<replay>")]
+pub fn resume_handle(_operator: OperatorToken, platform: Option<&str>, replay: Option<bool>) -> (Status, Json<Value>) {
+    let target = match pause_target(platform) {
+        Ok(target) => target,
+        Err(response) => return response,
+    };
+    let still_paused = {
+        let mut pauses = paused::platforms().lock().unwrap();
+        pauses.resume(target);
+        pauses.list()
+    };
+    println!("Resumed job dispatch for {}", target);
+    if !replay.unwrap_or(true) {
+        return (Status::Ok, Json(json!(PauseState { paused: still_paused, replayed: Some(0) })));
+    }
+
+    let events = paused::store().lock().unwrap().take_platforms(|platform| {
+        !paused::platforms().lock().unwrap().is_paused(platform)
+    });
+    let replayed = replay_events(events);
+    (Status::Ok, Json(json!(PauseState { paused: still_paused, replayed: Some(replayed) })))
+}
+
+/// Rotates a repository's webhook secret: generates a new one, updates the
+/// hook on the forge and stores it encrypted for signature verification
+#[utoipa::path(
+    post,
+    path = "/admin/webhook-secret",
+    tag = "admin",
+    request_body = RotateRequest,
+    responses(
+        (status = 200, description = "Rotated", body = RotateReport),
+        (status = 422, description = "Rotation failed", body = ErrorBody),
+    ),
+    security(("adminToken" = [])),
+)]
+#[post("/admin/webhook-secret", data = "<request>")]
+pub async fn rotate_webhook_secret_handle(_admin: AdminToken, request: Json<RotateRequest>) -> (Status, Json<Value>) {
+    let request = request.into_inner();
+    match tokio::task::spawn_blocking(move || webhook_secrets::rotate(&request)).await {
+        Ok(Ok(report)) => (Status::Ok, Json(json!(report))),
+        Ok(Err(e)) => {
+            println!("Webhook secret rotation failed: {}", e);
+            (Status::UnprocessableEntity, Json(json!({ "error": e.to_string() })))
+        }
+        Err(e) => {
+            println!("Task join error: {}", e);
+            (Status::InternalServerError, Json(json!({ "error": "Internal Server Error" })))
+        }
+    }
+}
+
+/// Body of `POST /admin/tokens`
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct CreateTokenRequest {
+    pub name: String,
+    pub role: Role,
+}
+
+/// A new API token with its only plain-text copy
+#[derive(Debug, Serialize, ToSchema)]
+pub struct CreatedToken {
+    pub name: String,
+    pub role: Role,
+    pub token: String,
+}
+
+/// Creates an API token; the response holds its only plain-text copy
+#[utoipa::path(
+    post,
+    path = "/admin/tokens",
+    tag = "admin",
+    request_body = CreateTokenRequest,
+    responses(
+        (status = 200, description = "Created", body = CreatedToken),
+        (status = 409, description = "Token name taken or empty", body = ErrorBody),
+    ),
+    security(("adminToken" = [])),
+)]
+#[post("/admin/tokens", data = "<request>")]
+pub fn create_token_handle(admin: AdminToken, request: Json<CreateTokenRequest>) -> (Status, Json<Value>) {
+    let request = request.into_inner();
+    match api_tokens::store().lock().unwrap().create(&request.name, request.role) {
+        Ok(token) => {
+            println!("API token {} ({}) created by {}", request.name.trim(), request.role.as_str(), admin.0.name);
+            (Status::Ok, Json(json!(CreatedToken { name: request.name.trim().to_string(), role: request.role, token })))
+        }
+        Err(e) => (Status::Conflict, Json(json!({ "error": e }))),
+    }
+}
+
+/// API tokens without their hashes
+#[derive(Debug, Serialize, ToSchema)]
+pub struct TokenList {
+    pub tokens: Vec<ApiToken>,
+}
+
+/// Lists the API tokens without their hashes
+#[utoipa::path(
+    get,
+    path = "/admin/tokens",
+    tag = "admin",
+    responses((status = 200, description = "API tokens", body = TokenList)),
+    security(("adminToken" = [])),
+)]
+#[get("/admin/tokens")]
+pub fn tokens_handle(_admin: AdminToken) -> Json<TokenList> {
+    Json(TokenList { tokens: api_tokens::store().lock().unwrap().list() })
+}
+
+/// Name of a revoked API token
+#[derive(Debug, Serialize, ToSchema)]
+pub struct Revoked {
+    pub revoked: String,
+}
+
+/// Revokes an API token
+#[utoipa::path(
+    delete,
+    path = "/admin/tokens/{name}",
+    tag = "admin",
+    params(("name" = String, Path, description = "Token name")),
+    responses(
+        (status = 200, description = "Revoked", body = Revoked),
+        (status = 404, description = "Token not found", body = ErrorBody),
+    ),
+    security(("adminToken" = [])),
+)]
+#[delete("/admin/tokens/<name>")]
+pub fn revoke_token_handle(admin: AdminToken, name: &str) -> (Status, Json<Value>) {
+    if !api_tokens::store().lock().unwrap().revoke(name) {
+        return (Status::NotFound, Json(json!({ "error": "Token not found" })));
+    }
+    println!("API token {} revoked by {}", name, admin.0.name);
+    (Status::Ok, Json(json!(Revoked { revoked: name.to_string() })))
+}
+
+/// Audited admin API requests, newest first
+#[derive(Debug, Serialize, ToSchema)]
+pub struct AuditLog {
+    pub entries: Vec<AuditEntry>,
+}
+
+/// Most recent admin API requests (default 100), newest first
+#[utoipa::path(
+    get,
+    path = "/admin/audit",
+    tag = "admin",
+    params(("limit" = Option<usize>, Query, description = "Maximum number of entries (default 100)")),
+    responses((status = 200, description = "Audit entries", body = AuditLog)),
+    security(("adminToken" = [])),
+)]
+#[get("/admin/audit?<limit>")]
+pub fn audit_handle(_admin: AdminToken, limit: Option<usize>) -> Json<AuditLog> {
+    Json(AuditLog { entries: audit::log().lock().unwrap().recent(limit.unwrap_or(100)) })
+}
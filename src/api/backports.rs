@@ -0,0 +1,39 @@
+use rocket::get;
+use rocket::http::{ContentType, Status};
+
+use crate::api::admin::ReadToken;
+use crate::utils::backports::{self, BackportGraph};
+
+/// Exports the backport graph of a repository: source PRs, the commits they
+/// produced on each branch and cascades between branches
+///
+/// `format` is `json` (default) or `dot` for Graphviz.
+#[utoipa::path(
+    get,
+    path = "/backports/{repo}/graph",
+    tag = "jobs",
+    params(
+        ("repo" = String, Path, description = "Repository name"),
+        ("format" = Option<String>, Query, description = "`json` (default) or `dot`"),
+    ),
+    responses(
+        (status = 200, description = "The graph (Graphviz source for `dot`)", content(
+            (BackportGraph = "application/json"),
+            (String = "text/vnd.graphviz"),
+        )),
+        (status = 400, description = "Unknown format"),
+    ),
+    security(("adminToken" = [])),
+)]
+#[get("/backports/<repo>/graph?<format>")]
+pub fn backport_graph_handle(_reader: ReadToken, repo: &str, format: Option<&str>) -> Result<(ContentType, String), Status> {
+    let graph = {
+        let store = backports::store().lock().unwrap();
+        BackportGraph::build(&store.for_repo(repo))
+    };
+    match format.unwrap_or("json") {
+        "json" => Ok((ContentType::JSON, graph.to_json().to_string())),
+        "dot" => Ok((ContentType::new("text", "vnd.graphviz"), graph.to_dot(repo))),
+        _ => Err(Status::BadRequest),
+    }
+}
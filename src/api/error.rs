@@ -0,0 +1,78 @@
+use std::fmt;
+use rocket::http::Status;
+use rocket::request::Request;
+use rocket::response::{self, Responder};
+use rocket::serde::json::Json;
+use serde::Serialize;
+use utoipa::ToSchema;
+
+/// Body of the error responses of the API
+#[derive(Debug, Serialize, ToSchema)]
+pub struct ErrorBody {
+    pub error: String,
+}
+
+/// Why a webhook was not accepted, returned as `{"error": "..."}` with the
+/// matching status
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ApiError {
+    /// The request or its event is not one the endpoint handles (400)
+    BadRequest(String),
+    /// Signature or token mismatch (401)
+    Unauthorized,
+    /// The body exceeds the size limit of its event (413)
+    PayloadTooLarge,
+    /// The body is not a valid payload of its event (422)
+    Unprocessable(String),
+    /// Processing failed after the webhook was accepted (500)
+    Internal(String),
+}
+
+impl ApiError {
+    pub fn status(&self) -> Status {
+        match self {
+            ApiError::BadRequest(_) => Status::BadRequest,
+            ApiError::Unauthorized => Status::Unauthorized,
+            ApiError::PayloadTooLarge => Status::PayloadTooLarge,
+            ApiError::Unprocessable(_) => Status::UnprocessableEntity,
+            ApiError::Internal(_) => Status::InternalServerError,
+        }
+    }
+}
+
+impl fmt::Display for ApiError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ApiError::BadRequest(message) | ApiError::Unprocessable(message) | ApiError::Internal(message) => write!(f, "{}", message),
+            ApiError::Unauthorized => write!(f, "Unauthorized"),
+            ApiError::PayloadTooLarge => write!(f, "Payload Too Large"),
+        }
+    }
+}
+
+impl std::error::Error for ApiError {}
+
+impl<'r> Responder<'r, 'static> for ApiError {
+    fn respond_to(self, request: &'r Request<'_>) -> response::Result<'static> {
+        (self.status(), Json(ErrorBody { error: self.to_string() })).respond_to(request)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rocket::local::blocking::Client;
+
+    #[test]
+    fn test_errors_respond_with_status_and_json() {
+        let client = Client::untracked(rocket::build()).unwrap();
+        let request = client.get("/");
+        let response = ApiError::Unprocessable("Invalid github payload".to_string()).respond_to(request.inner()).unwrap();
+        assert_eq!(response.status(), Status::UnprocessableEntity);
+        assert_eq!(response.content_type(), Some(rocket::http::ContentType::JSON));
+
+        assert_eq!(ApiError::Unauthorized.status(), Status::Unauthorized);
+        assert_eq!(ApiError::BadRequest("Unsupported event type".to_string()).status(), Status::BadRequest);
+        assert_eq!(ApiError::Internal("Job 1 failed".to_string()).to_string(), "Job 1 failed");
+    }
+}
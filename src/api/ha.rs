@@ -0,0 +1,128 @@
+use std::time::{Duration, Instant};
+use rocket::data::{Data, Limits};
+use rocket::http::Status;
+use rocket::request::{FromRequest, Outcome};
+use rocket::{post, Request};
+use log::{info, error};
+
+use crate::api::routes::{self, EventKind};
+use crate::utils::{hmac, http_client};
+use crate::utils::ha::{self, ForwardedEvent, HaRole, HaSettings, Heartbeat, HA_SIGNATURE_HEADER};
+
+/// Request guard carrying the `X-HA-Signature` header of a peer message
+pub struct HaSignature(String);
+
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for HaSignature {
+    type Error = &'static str;
+
+    async fn from_request(request: &'r Request<'_>) -> Outcome<Self, Self::Error> {
+        if ha::settings().role != HaRole::Standby {
+            return Outcome::Error((Status::NotFound, "HA standby mode disabled"));
+        }
+        match request.headers().get_one(HA_SIGNATURE_HEADER) {
+            Some(signature) => Outcome::Success(HaSignature(signature.to_string())),
+            None => {
+                println!("❌ HA request rejected: missing {} header", HA_SIGNATURE_HEADER);
+                Outcome::Error((Status::Unauthorized, "Missing HA signature"))
+            }
+        }
+    }
+}
+
+async fn read_signed(body: Data<'_>, limits: &Limits, signature: &HaSignature) -> Result<String, Status> {
+    let body_str = routes::read_body(body, limits, EventKind::PullRequest)
+        .await
+        .map_err(|e| e.status())?;
+    if let Err(e) = hmac::verify_hmac_sha256(body_str.as_bytes(), &ha::settings().secret, &signature.0) {
+        println!("❌ HA request rejected: {}", e);
+        return Err(Status::Unauthorized);
+    }
+    Ok(body_str)
+}
+
+/// Receives a verified webhook forwarded by the active instance (standby only)
+#[utoipa::path(
+    post,
+    path = "/ha/event",
+    tag = "ha",
+    request_body = ForwardedEvent,
+    responses(
+        (status = 202, description = "Buffered"),
+        (status = 400, description = "Invalid forwarded event"),
+        (status = 401, description = "Missing or invalid signature"),
+    ),
+    security(("haSignature" = [])),
+)]
+#[post("/ha/event", data = "<body>")]
+pub async fn ha_event_handle(signature: HaSignature, limits: &Limits, body: Data<'_>) -> Status {
+    let body_str = match read_signed(body, limits, &signature).await {
+        Ok(body_str) => body_str,
+        Err(status) => return status,
+    };
+    match serde_json::from_str::<ForwardedEvent>(&body_str) {
+        Ok(event) => {
+            ha::state().lock().unwrap().record_event(event, Instant::now());
+            Status::Accepted
+        }
+        Err(e) => {
+            println!("Invalid forwarded event: {}", e);
+            Status::BadRequest
+        }
+    }
+}
+
+/// Receives a heartbeat from the active instance (standby only)
+#[utoipa::path(
+    post,
+    path = "/ha/heartbeat",
+    tag = "ha",
+    request_body = Heartbeat,
+    responses(
+        (status = 200, description = "Recorded"),
+        (status = 400, description = "Invalid heartbeat"),
+        (status = 401, description = "Missing or invalid signature"),
+    ),
+    security(("haSignature" = [])),
+)]
+#[post("/ha/heartbeat", data = "<body>")]
+pub async fn ha_heartbeat_handle(signature: HaSignature, limits: &Limits, body: Data<'_>) -> Status {
+    let body_str = match read_signed(body, limits, &signature).await {
+        Ok(body_str) => body_str,
+        Err(status) => return status,
+    };
+    match serde_json::from_str::<Heartbeat>(&body_str) {
+        Ok(heartbeat) => {
+            ha::state().lock().unwrap().record_heartbeat(&heartbeat, Instant::now());
+            Status::Ok
+        }
+        Err(e) => {
+            println!("Invalid heartbeat: {}", e);
+            Status::BadRequest
+        }
+    }
+}
+
+/// Watches the active's heartbeats and replays its unfinished events on takeover (standby only)
+pub fn spawn_standby_monitor(settings: &'static HaSettings) {
+    if settings.role != HaRole::Standby {
+        return;
+    }
+    info!("HA: standby, taking over after {:?} without heartbeat", settings.failover_after);
+    tokio::spawn(async move {
+        // Give the active a chance to announce itself after a standby restart
+        tokio::time::sleep(settings.failover_after).await;
+        let mut interval = tokio::time::interval(Duration::from_secs(1));
+        loop {
+            interval.tick().await;
+            let replay = ha::state().lock().unwrap().take_over(settings.failover_after, Instant::now());
+            let Some(events) = replay else { continue };
+            error!("ALERT: HA active instance missed heartbeats, standby takes over ({} events to replay)", events.len());
+            for event in events {
+                if let Err(e) = routes::process_event(&event.platform, &event.event, event.body.into(), http_client::shared()).await {
+                    error!("HA: replay of event {} failed: {}", event.seq, e);
+                }
+            }
+        }
+    });
+}
@@ -0,0 +1,275 @@
+use rocket::{get, post};
+use rocket::http::Status;
+use rocket::serde::json::{json, Json, Value};
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+use uuid::Uuid;
+
+use crate::api::admin::{OperatorToken, ReadToken};
+use crate::api::error::ErrorBody;
+use crate::api::routes;
+use crate::utils::dlq::{self, DeadLetter};
+use crate::utils::jobs::{self, JobQuery, JobRecord, JobStatus};
+use crate::utils::http_client;
+
+/// The job with ID or unambiguous ID prefix `id`, or the error response
+fn find_job(id: &str) -> Result<JobRecord, (Status, Json<Value>)> {
+    if id.len() < 8 {
+        return Err((Status::BadRequest, Json(json!({ "error": "Job ID prefix must have at least 8 characters" }))));
+    }
+    let mut found = jobs::store().lock().unwrap().search(&JobQuery { id: Some(id), limit: 2, ..Default::default() });
+    match found.len() {
+        1 => Ok(found.remove(0)),
+        0 => Err((Status::NotFound, Json(json!({ "error": "Job not found" })))),
+        _ => Err((Status::Conflict, Json(json!({ "error": "Job ID prefix is ambiguous" })))),
+    }
+}
+
+/// Looks up a job by its ID or an unambiguous ID prefix (at least 8 characters)
+///
+/// The ID appears in the `Backport-Job:` trailer of backported commits and at
+/// the bottom of every comment the bot posts.
+#[utoipa::path(
+    get,
+    path = "/jobs/{id}",
+    tag = "jobs",
+    params(("id" = String, Path, description = "Job ID or prefix")),
+    responses(
+        (status = 200, description = "The job", body = JobRecord),
+        (status = 400, description = "Prefix too short", body = ErrorBody),
+        (status = 404, description = "Job not found", body = ErrorBody),
+        (status = 409, description = "Prefix is ambiguous", body = ErrorBody),
+    ),
+    security(("adminToken" = [])),
+)]
+#[get("/jobs/<id>")]
+pub fn job_handle(_reader: ReadToken, id: &str) -> (Status, Json<Value>) {
+    match find_job(id) {
+        Ok(job) => (Status::Ok, Json(json!(job))),
+        Err(response) => response,
+    }
+}
+
+/// `GET /jobs/<id>` under the admin API
+#[utoipa::path(
+    get,
+    path = "/admin/jobs/{id}",
+    tag = "jobs",
+    params(("id" = String, Path, description = "Job ID or prefix")),
+    responses(
+        (status = 200, description = "The job", body = JobRecord),
+        (status = 400, description = "Prefix too short", body = ErrorBody),
+        (status = 404, description = "Job not found", body = ErrorBody),
+        (status = 409, description = "Prefix is ambiguous", body = ErrorBody),
+    ),
+    security(("adminToken" = [])),
+)]
+#[get("/admin/jobs/<id>")]
+pub fn admin_job_handle(reader: ReadToken, id: &str) -> (Status, Json<Value>) {
+    job_handle(reader, id)
+}
+
+/// Jobs matching a search
+#[derive(Debug, Serialize, ToSchema)]
+pub struct JobList {
+    pub jobs: Vec<JobRecord>,
+}
+
+/// Lists recent jobs, optionally filtered by ID prefix, repository and status
+#[utoipa::path(
+    get,
+    path = "/jobs",
+    tag = "jobs",
+    params(
+        ("id" = Option<String>, Query, description = "Job ID prefix"),
+        ("repo" = Option<String>, Query, description = "Repository name"),
+        ("status" = Option<String>, Query, description = "`running`, `succeeded`, `failed` or `partial`"),
+        ("limit" = Option<usize>, Query, description = "Maximum number of jobs (default 50)"),
+    ),
+    responses((status = 200, description = "Matching jobs, most recent first", body = JobList)),
+    security(("adminToken" = [])),
+)]
+#[get("/jobs?<id>&<repo>&<status>&<limit>")]
+pub fn jobs_handle(
+    _reader: ReadToken,
+    id: Option<&str>,
+    repo: Option<&str>,
+    status: Option<&str>,
+    limit: Option<usize>,
+) -> Json<JobList> {
+    let query = JobQuery { id, repo, status, limit: limit.unwrap_or(50) };
+    let found = jobs::store().lock().unwrap().search(&query);
+    Json(JobList { jobs: found })
+}
+
+/// `GET /jobs` under the admin API
+#[utoipa::path(
+    get,
+    path = "/admin/jobs",
+    tag = "jobs",
+    params(
+        ("id" = Option<String>, Query, description = "Job ID prefix"),
+        ("repo" = Option<String>, Query, description = "Repository name"),
+        ("status" = Option<String>, Query, description = "`running`, `succeeded`, `failed` or `partial`"),
+        ("limit" = Option<usize>, Query, description = "Maximum number of jobs (default 50)"),
+    ),
+    responses((status = 200, description = "Matching jobs, most recent first", body = JobList)),
+    security(("adminToken" = [])),
+)]
+#[get("/admin/jobs?<id>&<repo>&<status>&<limit>")]
+pub fn admin_jobs_handle(
+    reader: ReadToken,
+    id: Option<&str>,
+    repo: Option<&str>,
+    status: Option<&str>,
+    limit: Option<usize>,
+) -> Json<JobList> {
+    jobs_handle(reader, id, repo, status, limit)
+}
+
+/// Job whose webhook is processed again
+#[derive(Debug, Serialize, ToSchema)]
+pub struct Retried {
+    pub retried: Uuid,
+}
+
+/// Processes the webhook of a failed or partial job again, from its dead
+/// letter, without asking the platform to redeliver it
+///
+/// The retry runs as a new job; branches the failed job already backported
+/// are skipped through the backport mapping DB.
+#[utoipa::path(
+    post,
+    path = "/admin/jobs/{id}/retry",
+    tag = "jobs",
+    params(("id" = String, Path, description = "Job ID or prefix")),
+    responses(
+        (status = 202, description = "Retry started as a new job", body = Retried),
+        (status = 400, description = "Prefix too short", body = ErrorBody),
+        (status = 404, description = "Job not found, or its payload is no longer in the dead-letter queue", body = ErrorBody),
+        (status = 409, description = "Prefix is ambiguous, or the job did not fail", body = ErrorBody),
+        (status = 422, description = "Stored payload failed verification", body = ErrorBody),
+    ),
+    security(("adminToken" = [])),
+)]
+#[post("/admin/jobs/<id>/retry")]
+pub fn job_retry_handle(_operator: OperatorToken, id: &str) -> (Status, Json<Value>) {
+    let job = match find_job(id) {
+        Ok(job) => job,
+        Err(response) => return response,
+    };
+    if !matches!(job.status, JobStatus::Failed | JobStatus::Partial) {
+        return (Status::Conflict, Json(json!({ "error": format!("Job is {}, only failed jobs can be retried", job.status.as_str()) })));
+    }
+    let mut queue = dlq::queue().lock().unwrap();
+    let Some(letter) = queue.take(job.id) else {
+        return (Status::NotFound, Json(json!({ "error": "The job's payload is no longer in the dead-letter queue" })));
+    };
+    let body = match letter.verify() {
+        Ok(body) => body,
+        Err(e) => {
+            println!("❌ Not retrying job {}: {}", job.id, e);
+            queue.push(letter);
+            return (Status::UnprocessableEntity, Json(json!({ "error": format!("Stored payload rejected: {}", e) })));
+        }
+    };
+    drop(queue);
+    replay(vec![(letter, body)]);
+    (Status::Accepted, Json(json!(Retried { retried: job.id })))
+}
+
+/// Processes verified dead letters again in the background
+fn replay(letters: Vec<(DeadLetter, String)>) {
+    tokio::spawn(async move {
+        for (letter, body) in letters {
+            println!("Requeueing dead letter of job {}", letter.job_id);
+            if let Err(e) = routes::process_event(&letter.platform, &letter.event, body.into(), http_client::shared()).await {
+                println!("Requeued job {} failed again: {}", letter.job_id, e);
+            }
+        }
+    });
+}
+
+/// Body of `POST /admin/dlq`: requeue one dead letter by job ID, or all of them
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct RequeueRequest {
+    #[serde(default)]
+    pub job_id: Option<Uuid>,
+    #[serde(default)]
+    pub all: bool,
+}
+
+/// Dead letters, most recent first
+#[derive(Debug, Serialize, ToSchema)]
+pub struct DeadLetterList {
+    pub dead_letters: Vec<DeadLetter>,
+}
+
+/// Lists the jobs that failed after exhausting their retries, most recent first
+#[utoipa::path(
+    get,
+    path = "/admin/dlq",
+    tag = "jobs",
+    responses((status = 200, description = "Dead letters, most recent first", body = DeadLetterList)),
+    security(("adminToken" = [])),
+)]
+#[get("/admin/dlq")]
+pub fn dlq_handle(_reader: ReadToken) -> Json<DeadLetterList> {
+    let letters = dlq::queue().lock().unwrap().list();
+    Json(DeadLetterList { dead_letters: letters })
+}
+
+/// Job IDs of the dead letters processed again
+#[derive(Debug, Serialize, ToSchema)]
+pub struct Requeued {
+    pub requeued: Vec<Uuid>,
+    /// Dead letters dropped because their stored payload failed signature verification
+    pub rejected: Vec<Uuid>,
+}
+
+/// Removes dead letters from the queue and processes their webhooks again
+///
+/// Each requeued delivery runs as a new job; if it fails again it returns to
+/// the queue under the new job ID.
+#[utoipa::path(
+    post,
+    path = "/admin/dlq",
+    tag = "jobs",
+    request_body = RequeueRequest,
+    responses(
+        (status = 202, description = "Requeued job IDs", body = Requeued),
+        (status = 400, description = "Neither or both of job_id and all given", body = ErrorBody),
+        (status = 404, description = "Dead letter not found", body = ErrorBody),
+    ),
+    security(("adminToken" = [])),
+)]
+#[post("/admin/dlq", data = "<request>")]
+pub fn dlq_requeue_handle(_operator: OperatorToken, request: Json<RequeueRequest>) -> (Status, Json<Value>) {
+    let letters = {
+        let mut queue = dlq::queue().lock().unwrap();
+        match (request.job_id, request.all) {
+            (Some(job_id), false) => queue.take(job_id).into_iter().collect(),
+            (None, true) => queue.take_all(),
+            _ => return (Status::BadRequest, Json(json!({ "error": "Specify either job_id or all" }))),
+        }
+    };
+    if letters.is_empty() {
+        return (Status::NotFound, Json(json!({ "error": "Dead letter not found" })));
+    }
+
+    let mut rejected = Vec::new();
+    let letters: Vec<(DeadLetter, String)> = letters
+        .into_iter()
+        .filter_map(|letter| match letter.verify() {
+            Ok(body) => Some((letter, body)),
+            Err(e) => {
+                println!("❌ Not requeueing dead letter of job {}: {}", letter.job_id, e);
+                rejected.push(letter.job_id);
+                None
+            }
+        })
+        .collect();
+    let requeued: Vec<Uuid> = letters.iter().map(|(letter, _)| letter.job_id).collect();
+    replay(letters);
+    (Status::Accepted, Json(json!(Requeued { requeued, rejected })))
+}
@@ -1 +1,10 @@
 pub mod routes;
+pub mod error;
+pub mod platform;
+pub mod admin;
+pub mod jobs;
+pub mod ha;
+pub mod patches;
+pub mod openapi;
+pub mod stats;
+pub mod backports;
@@ -0,0 +1,124 @@
+use rocket::get;
+use rocket::serde::json::Json;
+use utoipa::{Modify, OpenApi};
+use utoipa::openapi::security::{ApiKey, ApiKeyValue, HttpAuthScheme, HttpBuilder, SecurityScheme};
+
+use crate::api::{admin, backports, ha, jobs, patches, routes, stats};
+use crate::utils::ha::HA_SIGNATURE_HEADER;
+
+/// The document generated from the handlers' `utoipa::path` attributes and
+/// the `ToSchema` types of their requests and responses
+#[derive(OpenApi)]
+#[openapi(
+    info(
+        title = "Webhook service",
+        description = "Backport and mirroring bot. Webhook endpoints are mounted under `HOOKS_PREFIX` (default `/hooks`) and, for existing configurations, at `/github` and `/gitcode`.",
+    ),
+    paths(
+        routes::github_handle, routes::gitcode_handle, routes::gitlab_handle,
+        routes::healthz_handle, routes::readyz_handle, routes::metrics_handle, openapi_handle,
+        patches::patch_handle, stats::repo_stats_handle, backports::backport_graph_handle,
+        jobs::job_handle, jobs::jobs_handle, jobs::admin_job_handle, jobs::admin_jobs_handle, jobs::job_retry_handle,
+        jobs::dlq_handle, jobs::dlq_requeue_handle,
+        admin::onboard_handle, admin::disable_repo_handle, admin::enable_repo_handle, admin::resume_repo_handle,
+        admin::confirm_canary_handle, admin::backport_range_handle, admin::mirror_handle, admin::mirrors_handle,
+        admin::pause_handle, admin::resume_handle, admin::rotate_webhook_secret_handle,
+        admin::create_token_handle, admin::tokens_handle, admin::revoke_token_handle, admin::audit_handle,
+        ha::ha_event_handle, ha::ha_heartbeat_handle,
+    ),
+    modifiers(&SecuritySchemes),
+)]
+struct ApiDoc;
+
+#[cfg(feature = "dashboard")]
+#[derive(OpenApi)]
+#[openapi(paths(stats::stats_dashboard_handle))]
+struct DashboardDoc;
+
+/// Adds the schemes named in the handlers' `security` attributes
+struct SecuritySchemes;
+
+impl Modify for SecuritySchemes {
+    fn modify(&self, openapi: &mut utoipa::openapi::OpenApi) {
+        let components = openapi.components.get_or_insert_with(Default::default);
+        components.add_security_scheme(
+            "adminToken",
+            SecurityScheme::Http(
+                HttpBuilder::new()
+                    .scheme(HttpAuthScheme::Bearer)
+                    .description(Some("`ADMIN_TOKEN` (admin role) or an API token from `/admin/tokens`"))
+                    .build(),
+            ),
+        );
+        components.add_security_scheme("haSignature", SecurityScheme::ApiKey(ApiKey::Header(ApiKeyValue::new(HA_SIGNATURE_HEADER))));
+    }
+}
+
+/// The OpenAPI document describing every HTTP endpoint of the service
+pub fn spec() -> utoipa::openapi::OpenApi {
+    let mut spec = ApiDoc::openapi();
+    // Taken from Cargo.toml, which declares none
+    spec.info.license = None;
+    #[cfg(feature = "dashboard")]
+    spec.merge(DashboardDoc::openapi());
+    spec
+}
+
+/// This document
+#[utoipa::path(
+    get,
+    path = "/openapi.json",
+    tag = "operations",
+    responses((status = 200, description = "OpenAPI document", body = serde_json::Value)),
+)]
+#[get("/openapi.json")]
+pub fn openapi_handle() -> Json<utoipa::openapi::OpenApi> {
+    Json(spec())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rocket::routes;
+
+    /// Default mount point of the webhook endpoints (`HOOKS_PREFIX`)
+    const HOOKS_PREFIX: &str = "/hooks";
+
+    #[test]
+    fn test_spec_covers_every_route() {
+        let spec = serde_json::to_value(spec()).unwrap();
+        let mut mounted = routes![
+            routes::healthz_handle, routes::readyz_handle, routes::metrics_handle, openapi_handle, patches::patch_handle,
+            stats::repo_stats_handle,
+            admin::onboard_handle, admin::disable_repo_handle, admin::enable_repo_handle, admin::rotate_webhook_secret_handle,
+            admin::resume_repo_handle, admin::confirm_canary_handle, admin::backport_range_handle, admin::mirror_handle, admin::mirrors_handle, admin::pause_handle, admin::resume_handle,
+            admin::create_token_handle, admin::tokens_handle, admin::revoke_token_handle, admin::audit_handle,
+            jobs::job_handle, jobs::jobs_handle, jobs::admin_job_handle, jobs::admin_jobs_handle, jobs::job_retry_handle,
+            jobs::dlq_handle, jobs::dlq_requeue_handle,
+            backports::backport_graph_handle,
+            ha::ha_event_handle, ha::ha_heartbeat_handle,
+        ];
+        mounted.extend(routes::webhook_routes());
+        #[cfg(feature = "dashboard")]
+        mounted.extend(routes![stats::stats_dashboard_handle]);
+        for route in mounted {
+            let mut path = route.uri.path().to_string().replace('<', "{").replace('>', "}");
+            if path == "/github" || path == "/gitcode" || path == "/gitlab" {
+                path = format!("{}{}", HOOKS_PREFIX, path);
+            }
+            let method = route.method.as_str().to_lowercase();
+            assert!(spec["paths"][&path][&method].is_object(), "{} {} missing from the OpenAPI spec", method, path);
+        }
+        // Every referenced schema and security scheme exists
+        let text = spec.to_string();
+        for reference in text.split("#/components/schemas/").skip(1) {
+            let name = reference.split('"').next().unwrap();
+            assert!(spec["components"]["schemas"][name].is_object(), "schema {} missing", name);
+        }
+        for scheme in ["adminToken", "haSignature"] {
+            assert!(spec["components"]["securitySchemes"][scheme].is_object(), "security scheme {} missing", scheme);
+        }
+        assert_eq!(spec["paths"]["/admin/repos/{repo}/enable"]["post"]["parameters"][1]["name"], "replay");
+        assert!(spec["paths"]["/stats/repos"]["get"]["security"][0]["adminToken"].is_array());
+    }
+}
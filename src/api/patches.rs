@@ -0,0 +1,37 @@
+use rocket::get;
+use rocket::http::{ContentType, Status};
+
+use crate::api::admin::ReadToken;
+use crate::utils::patches::PatchStore;
+
+/// Serves a stored patch by digest (`/patches/<sha256>.patch`)
+///
+/// Requires a read token like the other read-only endpoints: digests are
+/// posted in PR comments, which may be public.
+#[utoipa::path(
+    get,
+    path = "/patches/{file}",
+    tag = "patches",
+    params(("file" = String, Path, description = "`<sha256>.patch`")),
+    responses(
+        (status = 200, description = "The patch", body = String, content_type = "text/plain"),
+        (status = 401, description = "Missing or unknown token"),
+        (status = 404, description = "Unknown patch"),
+    ),
+    security(("adminToken" = [])),
+)]
+#[get("/patches/<file>")]
+pub async fn patch_handle(_reader: ReadToken, file: &str) -> Result<(ContentType, Vec<u8>), Status> {
+    let digest = file.strip_suffix(".patch").ok_or(Status::NotFound)?.to_string();
+    let found = tokio::task::spawn_blocking(move || PatchStore::from_env().get(&digest))
+        .await
+        .map_err(|_| Status::InternalServerError)?;
+    match found {
+        Ok(Some(contents)) => Ok((ContentType::Plain, contents)),
+        Ok(None) => Err(Status::NotFound),
+        Err(e) => {
+            println!("Failed to read patch {}: {}", file, e);
+            Err(Status::InternalServerError)
+        }
+    }
+}
@@ -0,0 +1,196 @@
+use std::env;
+use rocket::{routes, Build, Rocket, Route};
+use log::info;
+
+use crate::api::routes::{self as webhook_routes, github_handle, gitcode_handle, gitlab_handle};
+use crate::error::{Error, Result};
+use crate::utils::{http_client, http_headers};
+
+/// Reads a boolean flag from the environment, accepting `true/false`, `1/0`, `yes/no`
+pub fn env_flag(name: &str, default: bool) -> bool {
+    match env::var(name) {
+        Ok(value) => match value.trim().to_lowercase().as_str() {
+            "1" | "true" | "yes" | "on" => true,
+            "0" | "false" | "no" | "off" => false,
+            _ => default,
+        },
+        Err(_) => default,
+    }
+}
+
+/// Which platforms are served and where their webhook endpoints are mounted
+#[derive(Debug, Clone)]
+pub struct PlatformSettings {
+    pub hooks_prefix: String,
+    pub github_enabled: bool,
+    pub gitcode_enabled: bool,
+    pub gitlab_enabled: bool,
+}
+
+impl PlatformSettings {
+    /// Loads settings from `HOOKS_PREFIX`, `GITHUB_ENABLED`, `GITCODE_ENABLED`
+    /// and `GITLAB_ENABLED` (off unless set)
+    pub fn from_env() -> Self {
+        let prefix = env::var("HOOKS_PREFIX").unwrap_or_else(|_| "/hooks".to_string());
+        let prefix = format!("/{}", prefix.trim_matches('/'));
+        PlatformSettings {
+            hooks_prefix: prefix,
+            github_enabled: env_flag("GITHUB_ENABLED", true),
+            gitcode_enabled: env_flag("GITCODE_ENABLED", true),
+            gitlab_enabled: env_flag("GITLAB_ENABLED", false),
+        }
+    }
+
+    /// Names of the enabled platforms
+    pub fn enabled_platforms(&self) -> Vec<&'static str> {
+        let mut platforms = Vec::new();
+        if self.github_enabled {
+            platforms.push("github");
+        }
+        if self.gitcode_enabled {
+            platforms.push("gitcode");
+        }
+        if self.gitlab_enabled {
+            platforms.push("gitlab");
+        }
+        platforms
+    }
+
+    /// The webhook endpoints of the enabled platforms, see `routes::webhook_routes`
+    pub fn webhook_routes(&self) -> Vec<Route> {
+        let enabled = self.enabled_platforms();
+        webhook_routes::webhook_routes()
+            .into_iter()
+            .filter(|route| enabled.iter().any(|platform| route.uri.path() == format!("/{}", platform).as_str()))
+            .collect()
+    }
+
+    /// Names of the encrypted environment variables required by enabled platforms
+    pub fn required_secrets(&self) -> Vec<&'static str> {
+        let mut vars = Vec::new();
+        if self.gitcode_enabled {
+            vars.push("GITCODE_TOKEN_ENCRYPTED");
+            vars.push("GITCODE_WEBHOOK_VERIFYING_KEY_ENCRYPTED");
+        }
+        if self.github_enabled {
+            vars.push("GITHUB_TOKEN_ENCRYPTED");
+            vars.push("GITHUB_WEBHOOK_VERIFYING_KEY_ENCRYPTED");
+        }
+        if self.gitlab_enabled {
+            vars.push("GITLAB_TOKEN_ENCRYPTED");
+            vars.push("GITLAB_WEBHOOK_VERIFYING_KEY_ENCRYPTED");
+        }
+        vars
+    }
+}
+
+/// Managed state of the GitHub endpoints
+pub struct GitHubPlatform {
+    pub webhook_key: String,
+    pub client: reqwest::Client,
+}
+
+/// Managed state of the GitCode endpoints
+pub struct GitCodePlatform {
+    pub webhook_key: String,
+    pub client: reqwest::Client,
+}
+
+/// Managed state of the GitLab endpoint
+pub struct GitLabPlatform {
+    pub webhook_key: String,
+    pub client: reqwest::Client,
+}
+
+/// Async client of the `platform` endpoints, used for the API calls their
+/// events make on the async runtime
+fn platform_client(platform: &str) -> Result<reqwest::Client> {
+    http_client::configure_async(reqwest::Client::builder())
+        .user_agent("HiTLS_GIT_BOT")
+        .default_headers(http_headers::static_headers(platform))
+        .build()
+        .map_err(|e| Error::config(format!("Failed to build the {} HTTP client: {}", platform, e)))
+}
+
+impl GitHubPlatform {
+    /// State verifying deliveries with `webhook_key`
+    pub fn new(webhook_key: &str) -> Result<Self> {
+        Ok(GitHubPlatform { webhook_key: webhook_key.to_string(), client: platform_client("github")? })
+    }
+}
+
+impl GitCodePlatform {
+    /// State verifying deliveries with `webhook_key`
+    pub fn new(webhook_key: &str) -> Result<Self> {
+        Ok(GitCodePlatform { webhook_key: webhook_key.to_string(), client: platform_client("gitcode")? })
+    }
+}
+
+impl GitLabPlatform {
+    /// State verifying deliveries with `webhook_key`
+    pub fn new(webhook_key: &str) -> Result<Self> {
+        Ok(GitLabPlatform { webhook_key: webhook_key.to_string(), client: platform_client("gitlab")? })
+    }
+}
+
+/// Environment variable holding the webhook secret of `platform`
+fn webhook_key(platform: &str) -> Result<String> {
+    let var = format!("{}_WEBHOOK_VERIFYING_KEY", platform.to_uppercase());
+    env::var(&var).map_err(|_| Error::config(format!("{} not set in environment", var)))
+}
+
+/// Manages the state of every enabled platform, with the webhook secrets
+/// from `<PLATFORM>_WEBHOOK_VERIFYING_KEY`
+///
+/// Together with `PlatformSettings::webhook_routes` this is what an existing
+/// Rocket application needs to serve the webhooks itself. Fails when a key
+/// is not set or a client cannot be built.
+pub fn manage_platforms(rocket: Rocket<Build>, settings: &PlatformSettings) -> Result<Rocket<Build>> {
+    let mut rocket = rocket;
+    if settings.github_enabled {
+        rocket = rocket.manage(GitHubPlatform::new(&webhook_key("github")?)?);
+    }
+    if settings.gitcode_enabled {
+        rocket = rocket.manage(GitCodePlatform::new(&webhook_key("gitcode")?)?);
+    }
+    if settings.gitlab_enabled {
+        rocket = rocket.manage(GitLabPlatform::new(&webhook_key("gitlab")?)?);
+    }
+    Ok(rocket)
+}
+
+/// Mounts the webhook endpoints of every enabled platform
+///
+/// Each platform is served under `<prefix>/<platform>` and, for existing
+/// webhook configurations, under the legacy `/<platform>` path; GitLab is
+/// newer than that path and only served under the prefix.
+pub fn mount_platforms(rocket: Rocket<Build>, settings: &PlatformSettings) -> Result<Rocket<Build>> {
+    let mut rocket = manage_platforms(rocket, settings)?;
+
+    if settings.github_enabled {
+        rocket = rocket
+            .mount(settings.hooks_prefix.as_str(), routes![github_handle])
+            .mount("/", routes![github_handle]);
+        info!("GitHub webhooks mounted at {}/github and /github", settings.hooks_prefix);
+    } else {
+        info!("GitHub platform disabled");
+    }
+
+    if settings.gitcode_enabled {
+        rocket = rocket
+            .mount(settings.hooks_prefix.as_str(), routes![gitcode_handle])
+            .mount("/", routes![gitcode_handle]);
+        info!("GitCode webhooks mounted at {}/gitcode and /gitcode", settings.hooks_prefix);
+    } else {
+        info!("GitCode platform disabled");
+    }
+
+    if settings.gitlab_enabled {
+        rocket = rocket.mount(settings.hooks_prefix.as_str(), routes![gitlab_handle]);
+        info!("GitLab webhooks mounted at {}/gitlab", settings.hooks_prefix);
+    } else {
+        info!("GitLab platform disabled");
+    }
+
+    Ok(rocket)
+}
@@ -1,20 +1,126 @@
-use rocket::post;
+use rocket::{get, post, routes, Route, State};
 use rocket::http::Status;   
 use rocket::request::{FromRequest, Outcome};
 use rocket::Request;
-use rocket::data::{Data, ByteUnit};
-use crate::utils::{hmac, parser, git};
-use std::env;
+use rocket::data::{Data, ByteUnit, Limits};
+use std::path::PathBuf;
+use crate::utils::{parser, git, gitlab, hmac, metrics, service_key, jobs, archive, ha, dlq, config, paused, webhook_secrets, stats, connectivity, repo_health, payload_drift, token_scopes, deliveries, comment_queue, redact, canary, retry};
+use crate::utils::paused::{HeldFor, PausedEvent};
+use crate::utils::dlq::{DeadLetter, Delivery};
+use crate::utils::body::WebhookBody;
+use crate::utils::hmac::SignatureError;
+use rocket::serde::json::Json;
+use serde::Serialize;
+use utoipa::ToSchema;
+use crate::api::error::{ApiError, ErrorBody};
+use crate::error::Error;
+use crate::api::platform::{GitHubPlatform, GitCodePlatform, GitLabPlatform, PlatformSettings};
+use crate::models::webhook::ParsedWebhookData;
+use crate::utils::gitcode::{self, GitCommit};
+use crate::utils::repo_cache::RepoCache;
+use crate::utils::connectivity::HostReport;
+use crate::utils::service_key::SecretProviderHealth;
+use crate::utils::token_scopes::ScopeCheck;
 
 const GITHUB_SIGNATURE_HEADER: &str = "X-Hub-Signature-256";
 const GITCODE_SIGNATURE_HEADER: &str = "X-GitCode-Signature-256";
 const GITHUB_EVENT_HEADER: &str = "X-GitHub-Event";
 const GITCODE_EVENT_HEADER: &str = "X-GitCode-Event";
+const GITHUB_DELIVERY_HEADER: &str = "X-GitHub-Delivery";
+const GITCODE_DELIVERY_HEADER: &str = "X-GitCode-Delivery";
+const GITLAB_TOKEN_HEADER: &str = "X-Gitlab-Token";
+const GITLAB_EVENT_HEADER: &str = "X-Gitlab-Event";
+
+/// Streams the request body into a temporary file, rejecting bodies larger
+/// than the event's limit
+///
+/// Used for push events, whose payloads can be too large to buffer in memory.
+async fn spool_body(body: Data<'_>, limits: &Limits, kind: EventKind) -> Result<WebhookBody, ApiError> {
+    let limit = limits.get(kind.limit_name()).unwrap_or(kind.default_limit());
+    let spool = tempfile::NamedTempFile::new().and_then(|file| Ok((file.reopen()?, file)));
+    let (writer, file) = match spool {
+        Ok(spool) => spool,
+        Err(e) => {
+            println!("Failed to create spool file for request body: {}", e);
+            return Err(ApiError::Internal("Failed to read request body".to_string()));
+        }
+    };
+    match body.open(limit).stream_to(tokio::fs::File::from_std(writer)).await {
+        Ok(n) if n.complete => Ok(WebhookBody::Spooled(file)),
+        Ok(_) => {
+            println!("Request body exceeds the {} limit of {}", kind.limit_name(), limit);
+            Err(ApiError::PayloadTooLarge)
+        },
+        Err(e) => {
+            println!("Failed to spool request body: {}", e);
+            Err(ApiError::Internal("Failed to read request body".to_string()))
+        }
+    }
+}
+
+/// Webhook event categories with distinct body size limits
+///
+/// Limits are read from Rocket's `limits` configuration (e.g. `webhook-push = "50 MiB"`
+/// under `[default.limits]` in Rocket.toml) and fall back to the defaults below.
+#[derive(Debug, Clone, Copy)]
+pub enum EventKind {
+    Push,
+    PullRequest,
+    Comment,
+}
+
+impl EventKind {
+    pub fn limit_name(&self) -> &'static str {
+        match self {
+            EventKind::Push => "webhook-push",
+            EventKind::PullRequest => "webhook-pr",
+            EventKind::Comment => "webhook-comment",
+        }
+    }
+
+    pub fn default_limit(&self) -> ByteUnit {
+        match self {
+            EventKind::Push => ByteUnit::Mebibyte(25),
+            EventKind::PullRequest => ByteUnit::Mebibyte(5),
+            EventKind::Comment => ByteUnit::Mebibyte(1),
+        }
+    }
+}
+
+/// Reply to an accepted webhook
+#[derive(Debug, Serialize, ToSchema)]
+pub struct WebhookAck {
+    pub message: &'static str,
+}
+
+impl WebhookAck {
+    fn received() -> Json<WebhookAck> {
+        Json(WebhookAck { message: "Webhook received" })
+    }
+}
+
+/// Reads the full request body, rejecting bodies larger than the event's limit
+pub(crate) async fn read_body(body: Data<'_>, limits: &Limits, kind: EventKind) -> Result<String, ApiError> {
+    let limit = limits.get(kind.limit_name()).unwrap_or(kind.default_limit());
+    match body.open(limit).into_string().await {
+        Ok(s) if s.is_complete() => Ok(s.into_inner()),
+        Ok(_) => {
+            println!("Request body exceeds the {} limit of {}", kind.limit_name(), limit);
+            Err(ApiError::PayloadTooLarge)
+        },
+        Err(e) => {
+            println!("Failed to read request body: {}", e);
+            Err(ApiError::Internal("Failed to read request body".to_string()))
+        }
+    }
+}
 
 #[derive(Debug)]
 pub struct HmacVerified {
     pub signature: String,
     pub event: String,
+    /// Unique ID of the delivery, kept by the forge when it redelivers
+    pub delivery: Option<String>,
 }
 
 #[rocket::async_trait]
@@ -30,12 +136,17 @@ impl<'r> FromRequest<'r> for HmacVerified {
         let event = request.headers().get_one(GITHUB_EVENT_HEADER)
             .or_else(|| request.headers().get_one(GITCODE_EVENT_HEADER));
 
+        let delivery = request.headers().get_one(GITHUB_DELIVERY_HEADER)
+            .or_else(|| request.headers().get_one(GITCODE_DELIVERY_HEADER))
+            .map(str::to_string);
+
         match (signature, event) {
             (Some(sig), Some(evt)) => {
                 if let Some(signature) = sig.strip_prefix("sha256=") {
                     Outcome::Success(HmacVerified {
                         signature: signature.to_string(),
                         event: evt.to_string(),
+                        delivery,
                     })
                 } else {
                     println!("❌ Invalid signature format (missing sha256= prefix)");
@@ -56,108 +167,487 @@ impl<'r> FromRequest<'r> for HmacVerified {
     }
 }
 
-/// Verify the HMAC signature of a webhook request
-fn verify_signature(body: &str, key: &str, expected_signature: &str) -> Result<(), &'static str> {
-    let computed_signature = hmac::compute_hmac_sha256(body.as_bytes(), key);
-    println!("Computed signature: {}", computed_signature);
-    println!("Expected signature: {}", expected_signature);
+/// Secret token and event of a GitLab webhook, which sends the secret
+/// itself instead of an HMAC signature
+#[derive(Debug)]
+pub struct GitLabToken {
+    pub token: String,
+    pub event: String,
+}
 
-    if computed_signature != expected_signature {
-        println!("❌ Signature mismatch");
-        return Err("Unauthorized");
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for GitLabToken {
+    type Error = ();
+
+    async fn from_request(request: &'r Request<'_>) -> Outcome<Self, Self::Error> {
+        let token = request.headers().get_one(GITLAB_TOKEN_HEADER);
+        let event = request.headers().get_one(GITLAB_EVENT_HEADER);
+        match (token, event) {
+            (Some(token), Some(event)) => Outcome::Success(GitLabToken {
+                token: token.to_string(),
+                event: event.to_string(),
+            }),
+            (None, _) => {
+                println!("❌ No {} header found", GITLAB_TOKEN_HEADER);
+                Outcome::Forward(Status::BadRequest)
+            },
+            (_, None) => {
+                println!("❌ No {} header found", GITLAB_EVENT_HEADER);
+                Outcome::Forward(Status::BadRequest)
+            }
+        }
     }
+}
+
+/// Verify the HMAC signature of a webhook request against any of `keys`
+fn verify_signature(body: &WebhookBody, keys: &[String], signature: &str) -> Result<(), ApiError> {
+    match hmac::verify_webhook_signature(body, keys, signature) {
+        Ok(()) => {
+            println!("✅ Signature verification successful");
+            Ok(())
+        },
+        Err(SignatureError::Unreadable(e)) => {
+            println!("Failed to read request body: {}", e);
+            Err(ApiError::Internal("Failed to read request body".to_string()))
+        },
+        Err(e) => {
+            println!("❌ Webhook rejected: {}", e);
+            Err(ApiError::Unauthorized)
+        },
+    }
+}
+
+/// Why a job failed
+struct JobFailure {
+    message: String,
+    /// Whether the job kept failing on transport errors until `run_job` used
+    /// up its attempts
+    transient: bool,
+}
+
+/// Runs a blocking job, running it again with `retry::job_policy` while it
+/// fails transiently
+async fn run_job<T, F>(job: F) -> Result<crate::error::Result<T>, tokio::task::JoinError>
+where
+    F: Fn() -> crate::error::Result<T> + Send + 'static,
+    T: Send + 'static,
+{
+    tokio::task::spawn_blocking(move || retry::job_policy().retry_blocking(|_| job(), Error::is_transient)).await
+}
+
+/// Records the outcome of a job
+///
+/// The workspace of a failed job is archived. A job that failed transiently
+/// on every attempt moves to the dead-letter queue, notifying the operators;
+/// conflicts, guards and other deterministic failures are only recorded.
+async fn finish_job(
+    job_id: uuid::Uuid,
+    repo_name: &str,
+    delivery: Delivery<'_>,
+    workspace: Option<PathBuf>,
+    result: Result<(), JobFailure>,
+) {
+    let dead_letter = match &result {
+        Err(failure) if failure.transient => DeadLetter::new(job_id, repo_name, delivery, &failure.message)
+            .map_err(|e| println!("Failed to read body of job {} for the dead-letter queue: {}", job_id, e))
+            .ok(),
+        _ => None,
+    };
+    let result = result.map_err(|failure| failure.message);
+    let outcome = tokio::task::spawn_blocking(move || {
+        let artifact = match (&result, workspace) {
+            (Err(_), Some(workspace)) => archive::archive_failed_workspace(&workspace, &job_id.to_string()),
+            _ => None,
+        };
+        {
+            let mut store = jobs::store().lock().unwrap();
+            if let Some(artifact) = artifact {
+                println!("Failed job {} archived to {}", job_id, artifact.display());
+                store.update(job_id, |job| job.artifact = Some(artifact));
+            }
+            let outcome = stats::JobOutcome::from_result(&result);
+            let error = result.as_ref().err().cloned();
+            store.finish(job_id, result);
+            if let Some(job) = store.get(job_id) {
+                let duration = job.finished_at.map(|finished| finished - job.created_at).unwrap_or_default();
+                stats::store().lock().unwrap().record(&job.repo, outcome, duration);
+                let budget = repo_health::failure_budget();
+                if repo_health::store().lock().unwrap().record(&job.repo, outcome, error.as_deref(), budget) {
+                    let text = format!(
+                        "Repository {} failed {} consecutive jobs and is marked unhealthy; its events are held until POST /admin/repos/{}/resume. Last error: {}",
+                        job.repo, budget, job.repo, error.as_deref().unwrap_or("unknown")
+                    );
+                    println!("{}", text);
+                    dlq::notify_text(&text, &format!("unhealthy repository {}", job.repo));
+                }
+            }
+        }
+        if let Some(letter) = dead_letter {
+            println!("Job {} moved to the dead-letter queue", job_id);
+            dlq::notify(&letter);
+            dlq::queue().lock().unwrap().push(letter);
+        }
+    }).await;
+    if let Err(e) = outcome {
+        println!("Failed to record outcome of job {}: {}", job_id, e);
+    }
+}
+
+/// Holds back webhooks of disabled repositories
+///
+/// Returns true when the event must not be processed now; it is queued for
+/// replay when the repository has `queue_when_disabled` set.
+fn hold_if_disabled(namespace: &str, repo_name: &str, delivery: Delivery) -> bool {
+    let repo_config = match config::load_repo_config_in(namespace, repo_name) {
+        Ok(Some(repo_config)) => repo_config,
+        _ => return false,
+    };
+    if repo_config.enabled {
+        return false;
+    }
+    if repo_config.queue_when_disabled {
+        match PausedEvent::new(repo_name, delivery, HeldFor::Repo) {
+            Ok(event) => {
+                paused::store().lock().unwrap().push(event);
+                println!("Repository {} is disabled, {} event queued for replay", repo_name, delivery.event);
+            },
+            Err(e) => println!("Repository {} is disabled, failed to queue {} event: {}", repo_name, delivery.event, e),
+        }
+    } else {
+        println!("Repository {} is disabled, {} event ignored", repo_name, delivery.event);
+    }
+    true
+}
 
-    println!("✅ Signature verification successful");
-    Ok(())
+/// Holds back webhooks of repositories whose changed configuration ran its
+/// `canary_jobs` dry runs, until an operator confirms it
+fn hold_if_canary_pending(namespace: &str, repo_name: &str, delivery: Delivery) -> bool {
+    let repo_config = match config::load_repo_config_in(namespace, repo_name) {
+        Ok(Some(repo_config)) => repo_config,
+        _ => return false,
+    };
+    let fingerprint = canary::fingerprint(&repo_config);
+    if !canary::store().lock().unwrap().awaiting_confirmation(repo_name, &fingerprint, repo_config.canary_jobs) {
+        return false;
+    }
+    match PausedEvent::new(repo_name, delivery, HeldFor::Repo) {
+        Ok(event) => {
+            paused::store().lock().unwrap().push(event);
+            println!("Repository {} awaits confirmation of its configuration, {} event held", repo_name, delivery.event);
+        },
+        Err(e) => println!("Repository {} awaits confirmation of its configuration, failed to hold {} event: {}", repo_name, delivery.event, e),
+    }
+    true
+}
+
+/// Holds back webhooks of repositories that exhausted their failure budget
+/// until an operator resumes them
+fn hold_if_unhealthy(repo_name: &str, delivery: Delivery) -> bool {
+    if !repo_health::store().lock().unwrap().is_unhealthy(repo_name) {
+        return false;
+    }
+    match PausedEvent::new(repo_name, delivery, HeldFor::Repo) {
+        Ok(event) => {
+            paused::store().lock().unwrap().push(event);
+            println!("Repository {} is unhealthy, {} event held until resumed", repo_name, delivery.event);
+        },
+        Err(e) => println!("Repository {} is unhealthy, failed to hold {} event: {}", repo_name, delivery.event, e),
+    }
+    true
+}
+
+/// Holds back webhooks of paused platforms until they are resumed
+///
+/// Returns true when the event was held (or could not be stored) and must
+/// not be processed now.
+fn hold_if_platform_paused(delivery: Delivery) -> bool {
+    if !paused::platforms().lock().unwrap().is_paused(delivery.platform) {
+        return false;
+    }
+    let repo_name = delivery.body.repo_identity(delivery.platform).map(|(_, repo)| repo).unwrap_or_default();
+    match PausedEvent::new(&repo_name, delivery, HeldFor::Platform) {
+        Ok(event) => {
+            paused::store().lock().unwrap().push(event);
+            println!("Platform {} is paused, {} event for {} held", delivery.platform, delivery.event, repo_name);
+        },
+        Err(e) => println!("Platform {} is paused, failed to hold {} event: {}", delivery.platform, delivery.event, e),
+    }
+    true
+}
+
+/// Whether a PR webhook action reports new commits on the PR
+/// (`synchronize` on GitHub, `update` on GitCode and GitLab)
+fn is_update_action(platform: &str, action: Option<&str>) -> bool {
+    match platform {
+        "github" => action == Some("synchronize"),
+        _ => action == Some("update"),
+    }
+}
+
+/// Fetches the head of an open backport-labeled PR of an enabled repository
+/// into the repository cache
+///
+/// Runs in the background so the webhook is acknowledged immediately; when
+/// the PR merges, the backport job clones from warm objects.
+fn prewarm_cache(platform: &str, parsed_data: &ParsedWebhookData) {
+    let labeled = parsed_data.labels.iter().any(|label| label.title.starts_with("br:"));
+    let number = match parsed_data.iid {
+        // GitHub reports open PRs as `open`, GitCode and GitLab as `opened`
+        Some(number) if labeled && matches!(parsed_data.state.as_deref(), Some("open" | "opened")) => number,
+        _ => return,
+    };
+    let enabled = config::load_repo_config_in(&parsed_data.namespace, &parsed_data.repo_name)
+        .ok()
+        .flatten()
+        .is_some_and(|repo_config| repo_config.enabled);
+    if !enabled {
+        return;
+    }
+    let platform = platform.to_string();
+    let repo_url = parsed_data.repo_url.clone();
+    let full_name = format!("{}/{}", parsed_data.namespace, parsed_data.repo_name);
+    tokio::task::spawn_blocking(move || {
+        match RepoCache::from_env().prewarm_pr(&platform, &repo_url, &full_name, number) {
+            Ok(path) => println!("Pre-warmed cache {} with PR #{}", path.display(), number),
+            Err(e) => println!("Failed to pre-warm cache for PR #{}: {}", number, e),
+        }
+    });
+}
+
+/// Fetches the commits of an approved, merged merge request on the async
+/// `client`, so the job does not wait on the API from a blocking thread; the
+/// job fetches them itself when this fails
+async fn prefetch_commits(client: &reqwest::Client, platform: &str, parsed_data: &ParsedWebhookData) -> Option<Vec<GitCommit>> {
+    let iid = parsed_data.iid.filter(|_| git::needs_commits(parsed_data, platform))?;
+    let base = git::api_base_url(platform);
+    match gitcode::get_commit_list_of_pr_async(client, base, &parsed_data.namespace, &parsed_data.repo_name, iid, platform).await {
+        Ok(commits) => Some(commits),
+        Err(e) => {
+            println!("Failed to prefetch commits of PR #{}: {}", iid, e);
+            None
+        }
+    }
+}
+
+/// Logs the structured details of the error a job failed with, counts it and
+/// records its kind on the job
+fn job_error(job_id: uuid::Uuid, repo_name: &str, e: &Error) -> JobFailure {
+    println!("Job {} failed: {}", job_id, e.details());
+    metrics::record_failure(repo_name, &e.to_string());
+    jobs::store().lock().unwrap().update(job_id, |job| job.error_kind = Some(e.kind().to_string()));
+    JobFailure { message: e.to_string(), transient: e.is_transient() }
+}
+
+/// A job whose worker panicked or was cancelled; running it again would not help
+fn join_error(e: tokio::task::JoinError) -> JobFailure {
+    println!("Task join error: {}", e);
+    JobFailure { message: e.to_string(), transient: false }
+}
+
+fn job_failed(job_id: uuid::Uuid) -> ApiError {
+    ApiError::Internal(format!("Job {} failed", job_id))
+}
+
+/// Runs `read` on the blocking pool: spooled bodies are read from disk
+async fn read_blocking<T, F>(body: WebhookBody, read: F) -> Result<(WebhookBody, T), ApiError>
+where
+    T: Send + 'static,
+    F: FnOnce(&WebhookBody) -> T + Send + 'static,
+{
+    tokio::task::spawn_blocking(move || {
+        let value = read(&body);
+        (body, value)
+    })
+    .await
+    .map_err(|e| {
+        println!("Task join error: {}", e);
+        ApiError::Internal("Failed to read request body".to_string())
+    })
 }
 
 /// Common webhook handling logic for pull/merge requests
 async fn handle_pr_webhook(
     body: Data<'_>, 
     hmac_verified: &HmacVerified, 
-    env_key: &str,
-    platform: &str
-) -> Result<String, &'static str> {
+    limits: &Limits,
+    key: &str,
+    platform: &str,
+    client: &reqwest::Client,
+) -> Result<WebhookBody, ApiError> {
     // Read the request body
-    let body_str = match body.open(ByteUnit::Mebibyte(1)).into_string().await {
-        Ok(s) => s.into_inner(),
-        Err(e) => {
-            println!("Failed to read request body: {}", e);
-            return Err("Internal Server Error");
+    let body = WebhookBody::from(read_body(body, limits, EventKind::PullRequest).await?);
+
+    // Verify HMAC signature with the repository's secret, if provisioned
+    let keys = webhook_secrets::verification_keys(platform, &body, key);
+    verify_signature(&body, &keys, &hmac_verified.signature)?;
+
+    dispatch_once(platform, hmac_verified, body, client).await
+}
+
+/// Dispatches a verified webhook unless its delivery was already handled;
+/// a delivery that fails is forgotten so that the forge can redeliver it
+async fn dispatch_once(platform: &str, hmac_verified: &HmacVerified, body: WebhookBody, client: &reqwest::Client) -> Result<WebhookBody, ApiError> {
+    let key = hmac_verified.delivery.as_deref().map(|id| deliveries::delivery_key(platform, id));
+    if let Some(key) = &key {
+        if !deliveries::store().lock().unwrap().begin(key) {
+            println!("Delivery {} was already handled, skipping", key);
+            return Ok(body);
         }
-    };
+    }
+    let result = dispatch_verified(platform, &hmac_verified.event, body, client).await;
+    if let (Err(_), Some(key)) = (&result, &key) {
+        deliveries::store().lock().unwrap().forget(key);
+    }
+    result
+}
 
-    // Get the key from environment variable
-    let key = match env::var(env_key) {
-        Ok(k) => k,
+/// Hands a verified webhook to the HA layer and processes it locally when this
+/// instance is responsible for it
+async fn dispatch_verified(platform: &str, event: &str, body: WebhookBody, client: &reqwest::Client) -> Result<WebhookBody, ApiError> {
+    let settings = ha::settings();
+    if !ha::should_process_locally(settings) {
+        println!("Standby instance: active is healthy, not processing {} event", platform);
+        return Ok(body);
+    }
+
+    let seq = ha::forward(settings, platform, event, &body);
+    let result = process_event(platform, event, body, client).await;
+    if let Some(seq) = seq {
+        ha::complete(seq);
+    }
+    result
+}
+
+/// Whether `event` is a push webhook of `platform`
+fn is_push_event(platform: &str, event: &str) -> bool {
+    matches!((platform, event), ("gitcode", "Push Hook") | ("github", "push"))
+}
+
+/// Processes a verified webhook body (also used to replay events on HA takeover)
+/// and hands it back; forge API calls made on the async runtime go through
+/// `client`
+pub async fn process_event(platform: &str, event: &str, body: WebhookBody, client: &reqwest::Client) -> Result<WebhookBody, ApiError> {
+    if hold_if_platform_paused(Delivery { platform, event, body: &body }) {
+        return Ok(body);
+    }
+    let (platform_name, event_name) = (platform.to_string(), event.to_string());
+    let (body, ()) = read_blocking(body, move |body| payload_drift::inspect(&platform_name, &event_name, body)).await?;
+    if is_push_event(platform, event) {
+        process_push_body(platform, event, body, client).await
+    } else {
+        process_pr_body(platform, event, body, client).await
+    }
+}
+
+async fn process_pr_body(platform: &str, event: &str, body: WebhookBody, client: &reqwest::Client) -> Result<WebhookBody, ApiError> {
+    let body_str = match body.text() {
+        Ok(body_str) => body_str,
         Err(e) => {
-            println!("Failed to get webhook key: {}", e);
-            return Err("Internal Server Error");
+            println!("Failed to read webhook body: {}", e);
+            return Err(ApiError::Internal("Failed to read request body".to_string()));
         }
     };
-
-    // Verify HMAC signature
-    verify_signature(&body_str, &key, &hmac_verified.signature)?;
-
     // Parse the webhook data using the parser function
     match if platform == "github" {
         parser::parse_github_pr_data(&body_str)
     } else if platform == "gitcode" {
         parser::parse_gitcode_pr_data(&body_str)
+    } else if platform == "gitlab" {
+        parser::parse_gitlab_pr_data(&body_str)
     } else {
-        return Err("Unsupported platform");
+        return Err(ApiError::BadRequest("Unsupported platform".to_string()));
     } {
         Ok(parsed_data) => {
-            println!("Parsed Webhook Data:\n{}", parsed_data.to_string());
+            println!("Parsed Webhook Data:\n{}", parsed_data);
 
             // Check if this is a merge request
             let event_type = match platform {
                 "github" => "pull_request",
-                "gitcode" => "merge_request",
-                _ => return Err("Unsupported platform"),
+                "gitcode" | "gitlab" => "merge_request",
+                _ => return Err(ApiError::BadRequest("Unsupported platform".to_string())),
             };
             
+            if is_update_action(platform, parsed_data.action.as_deref()) {
+                prewarm_cache(platform, &parsed_data);
+                return Ok(body);
+            }
+
             if parsed_data.event_type == event_type {
+                let repo_name = parsed_data.repo_name.clone();
+                let delivery = Delivery { platform, event, body: &body };
+                if hold_if_disabled(&parsed_data.namespace, &repo_name, delivery)
+                    || hold_if_unhealthy(&repo_name, delivery)
+                    || hold_if_canary_pending(&parsed_data.namespace, &repo_name, delivery)
+                {
+                    return Ok(body);
+                }
+                let job_id = jobs::store().lock().unwrap().start(platform, &repo_name, event_type);
+                let workspace = git::workspace_path(platform, &repo_name).ok();
+                let delivery = Delivery { platform, event, body: &body };
                 // Spawn blocking operation in a separate thread
                 match platform {
                     "github" => {
-                        match tokio::task::spawn_blocking(move || {
-                            git::process_github_pr(&parsed_data)
-                        }).await {
-                            Ok(Ok(_)) => println!("Successfully processed GitHub pull request"),
+                        match run_job(move || git::process_github_pr(&parsed_data, job_id)).await {
+                            Ok(Ok(_)) => {
+                                println!("Successfully processed GitHub pull request");
+                                finish_job(job_id, &repo_name, delivery, None, Ok(())).await;
+                            },
                             Ok(Err(e)) => {
                                 println!("Error processing GitHub pull request: {}", e);
-                                return Err("Internal Server Error");
+                                finish_job(job_id, &repo_name, delivery, workspace, Err(job_error(job_id, &repo_name, &e))).await;
+                                return Err(job_failed(job_id));
                             },
                             Err(e) => {
-                                println!("Task join error: {}", e);
-                                return Err("Internal Server Error");
+                                finish_job(job_id, &repo_name, delivery, workspace, Err(join_error(e))).await;
+                                return Err(job_failed(job_id));
                             },
                         }
                     },
                     "gitcode" => {
-                        match tokio::task::spawn_blocking(move || {
-                            git::process_pr(&parsed_data)
-                        }).await {
-                            Ok(Ok(_)) => println!("Successfully processed GitCode merge request"),
+                        let commits = prefetch_commits(client, platform, &parsed_data).await;
+                        match run_job(move || git::process_pr(&parsed_data, commits.as_deref(), job_id)).await {
+                            Ok(Ok(_)) => {
+                                println!("Successfully processed GitCode merge request");
+                                finish_job(job_id, &repo_name, delivery, None, Ok(())).await;
+                            },
                             Ok(Err(e)) => {
                                 println!("Error processing GitCode merge request: {}", e);
-                                return Err("Internal Server Error");
+                                finish_job(job_id, &repo_name, delivery, workspace, Err(job_error(job_id, &repo_name, &e))).await;
+                                return Err(job_failed(job_id));
+                            },
+                            Err(e) => {
+                                finish_job(job_id, &repo_name, delivery, workspace, Err(join_error(e))).await;
+                                return Err(job_failed(job_id));
+                            },
+                        }
+                    },
+                    "gitlab" => {
+                        let commits = prefetch_commits(client, platform, &parsed_data).await;
+                        match run_job(move || git::process_gitlab_pr(&parsed_data, commits.as_deref(), job_id)).await {
+                            Ok(Ok(_)) => {
+                                println!("Successfully processed GitLab merge request");
+                                finish_job(job_id, &repo_name, delivery, None, Ok(())).await;
+                            },
+                            Ok(Err(e)) => {
+                                println!("Error processing GitLab merge request: {}", e);
+                                finish_job(job_id, &repo_name, delivery, workspace, Err(job_error(job_id, &repo_name, &e))).await;
+                                return Err(job_failed(job_id));
                             },
                             Err(e) => {
-                                println!("Task join error: {}", e);
-                                return Err("Internal Server Error");
+                                finish_job(job_id, &repo_name, delivery, workspace, Err(join_error(e))).await;
+                                return Err(job_failed(job_id));
                             },
                         }
                     },
-                    _ => return Err("Unsupported platform"),
+                    _ => return Err(ApiError::BadRequest("Unsupported platform".to_string())),
                 }
             }
-            Ok(body_str)
+            Ok(body)
         },
         Err(e) => {
             println!("Error parsing webhook data: {}", e);
-            Err("Internal Server Error")
+            metrics::record_failure("unknown", &e.to_string());
+            Err(ApiError::Unprocessable(format!("Invalid {} payload: {}", platform, e)))
         },
     }
 }
@@ -166,104 +656,290 @@ async fn handle_pr_webhook(
 async fn handle_push_webhook(
     body: Data<'_>,
     hmac_verified: &HmacVerified,
-    env_key: &str,
-) -> Result<String, &'static str> {
-    // Read the request body
-    let body_str = match body.open(ByteUnit::Mebibyte(1)).into_string().await {
-        Ok(s) => s.into_inner(),
-        Err(e) => {
-            println!("Failed to read request body: {}", e);
-            return Err("Internal Server Error");
-        }
-    };
+    limits: &Limits,
+    key: &str,
+    platform: &str,
+    client: &reqwest::Client,
+) -> Result<WebhookBody, ApiError> {
+    // Spool the request body; push payloads of history imports can be tens of MB
+    let body = spool_body(body, limits, EventKind::Push).await?;
 
-    // Get the key from environment variable
-    let key = match env::var(env_key) {
-        Ok(k) => k,
-        Err(e) => {
-            println!("Failed to get webhook key: {}", e);
-            return Err("Internal Server Error");
-        }
-    };
+    // Verify HMAC signature with the repository's secret, if provisioned
+    let (platform_name, key, signature) = (platform.to_string(), key.to_string(), hmac_verified.signature.clone());
+    let (body, verified) = read_blocking(body, move |body| {
+        let keys = webhook_secrets::verification_keys(&platform_name, body, &key);
+        verify_signature(body, &keys, &signature)
+    })
+    .await?;
+    verified?;
 
-    // Verify HMAC signature
-    verify_signature(&body_str, &key, &hmac_verified.signature)?;
+    dispatch_once(platform, hmac_verified, body, client).await
+}
 
-    // Parse the push event data
-    match parser::parse_gitcode_push_data(&body_str) {
+async fn process_push_body(platform: &str, event: &str, body: WebhookBody, client: &reqwest::Client) -> Result<WebhookBody, ApiError> {
+    // Parse the push event data straight from the body, without loading it as a string
+    let github = platform == "github";
+    let (body, parsed) = read_blocking(body, move |body| match body.reader() {
+        Ok(reader) if github => parser::parse_github_push_reader(reader).map_err(|e| e.to_string()),
+        Ok(reader) => parser::parse_gitcode_push_reader(reader).map_err(|e| e.to_string()),
+        Err(e) => Err(e.to_string()),
+    })
+    .await?;
+    match parsed {
         Ok(push_data) => {
             println!("=== Handle Push Webhook Debug ===");
-            println!("Webhook Event Type: {}", hmac_verified.event);
+            println!("Webhook Event Type: {}", event);
             println!("Push Data Details:");
             println!("- Repository: {}/{}", push_data.namespace, push_data.repo_name);
-            println!("- User: {}", push_data.user_name);
+            println!("- User: {}", redact::user(&push_data.user_name));
             println!("- Commit Count: {}", push_data.commits.len());
             println!("================================");
 
+            let repo_name = push_data.repo_name.clone();
+            let delivery = Delivery { platform, event, body: &body };
+            if hold_if_disabled(&push_data.namespace, &repo_name, delivery) || hold_if_unhealthy(&repo_name, delivery) {
+                return Ok(body);
+            }
+            let job_id = jobs::store().lock().unwrap().start(platform, &repo_name, "push");
+            let delivery = Delivery { platform, event, body: &body };
             // Spawn blocking operation in a separate thread
-            match tokio::task::spawn_blocking(move || {
+            match run_job(move || {
                 println!("Starting push event processing in spawned thread");
-                let result = git::process_push_event(&push_data);
+                let result = git::process_push_event(&push_data, job_id);
                 println!("Push event processing result: {:?}", result);
                 result
             }).await {
-                Ok(Ok(_)) => {
+                Ok(Ok(comments)) => {
+                    // Posted on the async client, without holding a blocking thread
+                    let posted = comment_queue::deliver_leased(client, &comments).await;
+                    println!("Posted {}/{} comments, the rest stay queued for retry", posted, comments.len());
                     println!("Successfully processed push event");
-                    Ok(body_str)
+                    finish_job(job_id, &repo_name, delivery, None, Ok(())).await;
+                    Ok(body)
                 },
                 Ok(Err(e)) => {
                     println!("Error processing push event: {}", e);
-                    Err("Internal Server Error")
+                    finish_job(job_id, &repo_name, delivery, None, Err(job_error(job_id, &repo_name, &e))).await;
+                    Err(job_failed(job_id))
                 },
                 Err(e) => {
-                    println!("Task join error: {}", e);
-                    Err("Internal Server Error")
+                    finish_job(job_id, &repo_name, delivery, None, Err(join_error(e))).await;
+                    Err(job_failed(job_id))
                 },
             }
         },
         Err(e) => {
             println!("Error parsing push data: {}", e);
-            Err("Internal Server Error")
+            metrics::record_failure("unknown", &e.to_string());
+            Err(ApiError::Unprocessable(format!("Invalid push payload: {}", e)))
         },
     }
 }
 
+/// The webhook endpoints of every platform, for mounting in an existing
+/// Rocket application (e.g. under `/hooks`)
+///
+/// Each endpoint needs the managed state of its platform: `GitHubPlatform`,
+/// `GitCodePlatform` and `GitLabPlatform`, or `platform::manage_platforms`
+/// to manage the enabled ones from the environment. Rocket refuses to launch
+/// while a mounted endpoint misses its state, so mount only the endpoints
+/// of managed platforms, see `PlatformSettings::webhook_routes`.
+pub fn webhook_routes() -> Vec<Route> {
+    routes![github_handle, gitcode_handle, gitlab_handle]
+}
+
+/// Receives GitHub webhooks (signed with `X-Hub-Signature-256`)
+#[utoipa::path(
+    post,
+    path = "/hooks/github",
+    tag = "webhooks",
+    request_body(content = serde_json::Value, description = "GitHub webhook payload"),
+    responses(
+        (status = 200, description = "Webhook received, or a redelivery of one already handled", body = WebhookAck),
+        (status = 400, description = "Missing signature, token or event header, or unsupported event", body = ErrorBody),
+        (status = 401, description = "Signature or token mismatch", body = ErrorBody),
+        (status = 413, description = "Payload too large", body = ErrorBody),
+        (status = 422, description = "Body is not a valid payload of the event", body = ErrorBody),
+        (status = 500, description = "Processing failed; the error names the failed job", body = ErrorBody),
+    ),
+)]
 #[post("/github", data = "<body>")]
-pub async fn github_handle(body: Data<'_>, hmac_verified: HmacVerified) -> &'static str {
-    match handle_pr_webhook(body, &hmac_verified, "GITHUB_WEBHOOK_VERIFYING_KEY", "github").await {
-        Ok(_) => "Webhook received",
-        Err(e) => e,
-    }
+pub async fn github_handle(body: Data<'_>, hmac_verified: HmacVerified, limits: &Limits, platform: &State<GitHubPlatform>) -> Result<Json<WebhookAck>, ApiError> {
+    match hmac_verified.event.as_str() {
+        "push" => {
+            println!("Processing GitHub push event");
+            handle_push_webhook(body, &hmac_verified, limits, &platform.webhook_key, "github", &platform.client).await?
+        },
+        _ => handle_pr_webhook(body, &hmac_verified, limits, &platform.webhook_key, "github", &platform.client).await?,
+    };
+    Ok(WebhookAck::received())
 }
 
+/// Receives GitCode webhooks (signed with `X-GitCode-Signature-256`)
+#[utoipa::path(
+    post,
+    path = "/hooks/gitcode",
+    tag = "webhooks",
+    request_body(content = serde_json::Value, description = "GitCode webhook payload"),
+    responses(
+        (status = 200, description = "Webhook received, or a redelivery of one already handled", body = WebhookAck),
+        (status = 400, description = "Missing signature, token or event header, or unsupported event", body = ErrorBody),
+        (status = 401, description = "Signature or token mismatch", body = ErrorBody),
+        (status = 413, description = "Payload too large", body = ErrorBody),
+        (status = 422, description = "Body is not a valid payload of the event", body = ErrorBody),
+        (status = 500, description = "Processing failed; the error names the failed job", body = ErrorBody),
+    ),
+)]
 #[post("/gitcode", data = "<body>")]
-pub async fn gitcode_handle(body: Data<'_>, hmac_verified: HmacVerified) -> &'static str {
+pub async fn gitcode_handle(body: Data<'_>, hmac_verified: HmacVerified, limits: &Limits, platform: &State<GitCodePlatform>) -> Result<Json<WebhookAck>, ApiError> {
     println!("=== GitCode Webhook Handler ===");
     println!("Received event type: {}", hmac_verified.event);
 
     let result = match hmac_verified.event.as_str() {
         "Push Hook" => {
             println!("Processing push event");
-            handle_push_webhook(body, &hmac_verified, "GITCODE_WEBHOOK_VERIFYING_KEY").await
+            handle_push_webhook(body, &hmac_verified, limits, &platform.webhook_key, "gitcode", &platform.client).await
         },
         "Merge Request Hook" => {
             println!("Processing merge request event");
-            handle_pr_webhook(body, &hmac_verified, "GITCODE_WEBHOOK_VERIFYING_KEY", "gitcode").await
+            handle_pr_webhook(body, &hmac_verified, limits, &platform.webhook_key, "gitcode", &platform.client).await
         },
         _ => {
             println!("Unsupported GitCode event type: {}", hmac_verified.event);
-            Err("Unsupported event type")
+            Err(ApiError::BadRequest("Unsupported event type".to_string()))
         }
     };
 
     match result {
         Ok(_) => {
             println!("Successfully processed GitCode webhook");
-            "Webhook received"
+            Ok(WebhookAck::received())
         },
         Err(e) => {
             println!("Error processing GitCode webhook: {}", e);
-            e
+            Err(e)
         }
     }
 }
+
+/// Receives GitLab webhooks (secret token in `X-Gitlab-Token`)
+#[utoipa::path(
+    post,
+    path = "/hooks/gitlab",
+    tag = "webhooks",
+    request_body(content = serde_json::Value, description = "GitLab webhook payload"),
+    responses(
+        (status = 200, description = "Webhook received, or a redelivery of one already handled", body = WebhookAck),
+        (status = 400, description = "Missing signature, token or event header, or unsupported event", body = ErrorBody),
+        (status = 401, description = "Signature or token mismatch", body = ErrorBody),
+        (status = 413, description = "Payload too large", body = ErrorBody),
+        (status = 422, description = "Body is not a valid payload of the event", body = ErrorBody),
+        (status = 500, description = "Processing failed; the error names the failed job", body = ErrorBody),
+    ),
+)]
+#[post("/gitlab", data = "<body>")]
+pub async fn gitlab_handle(body: Data<'_>, gitlab_token: GitLabToken, limits: &Limits, platform: &State<GitLabPlatform>) -> Result<Json<WebhookAck>, ApiError> {
+    println!("=== GitLab Webhook Handler ===");
+    println!("Received event type: {}", gitlab_token.event);
+
+    if gitlab_token.event != "Merge Request Hook" {
+        println!("Unsupported GitLab event type: {}", gitlab_token.event);
+        return Err(ApiError::BadRequest("Unsupported event type".to_string()));
+    }
+
+    let body = WebhookBody::from(read_body(body, limits, EventKind::PullRequest).await?);
+    let keys = webhook_secrets::verification_keys("gitlab", &body, &platform.webhook_key);
+    let result = if gitlab::verify_token(&keys, &gitlab_token.token) {
+        println!("✅ Token verification successful");
+        dispatch_verified("gitlab", &gitlab_token.event, body, &platform.client).await
+    } else {
+        println!("❌ Token mismatch");
+        Err(ApiError::Unauthorized)
+    };
+
+    match result {
+        Ok(_) => Ok(WebhookAck::received()),
+        Err(e) => {
+            println!("Error processing GitLab webhook: {}", e);
+            Err(e)
+        }
+    }
+}
+
+/// Prometheus scrape endpoint
+#[utoipa::path(
+    get,
+    path = "/metrics",
+    tag = "operations",
+    responses((status = 200, description = "Metrics in the Prometheus text format", body = String, content_type = "text/plain")),
+)]
+#[get("/metrics")]
+pub fn metrics_handle() -> String {
+    metrics::render_prometheus()
+}
+
+/// Readiness of the service
+#[derive(Debug, Serialize, ToSchema)]
+pub struct Readiness {
+    /// `ok`, `degraded` or `unavailable`
+    pub status: &'static str,
+    pub forges: Vec<HostReport>,
+    /// Token permission checks; null while they are running
+    pub tokens: Option<Vec<ScopeCheck>>,
+}
+
+/// Readiness endpoint: probes every enabled forge over IPv4 and IPv6 and
+/// reports the token permission checks made at startup
+///
+/// Returns 503 when a forge is unreachable over both families, when a token
+/// lacks a permission the bot needs or while the token checks are running; a
+/// forge only reachable over its non-preferred family is reported as a warning.
+#[utoipa::path(
+    get,
+    path = "/readyz",
+    tag = "operations",
+    responses(
+        (status = 200, description = "Every forge reachable (status `degraded` when only over the non-preferred family)", body = Readiness),
+        (status = 503, description = "A forge is unreachable or a token lacks a permission", body = Readiness),
+    ),
+)]
+#[get("/readyz")]
+pub async fn readyz_handle() -> (Status, Json<Readiness>) {
+    let platforms = PlatformSettings::from_env().enabled_platforms();
+    let forges = tokio::task::spawn_blocking(move || connectivity::check_forges(&platforms))
+        .await
+        .unwrap_or_default();
+    let tokens = token_scopes::last_results();
+    let tokens_ok = tokens.as_ref().is_some_and(|checks| checks.iter().all(|check| check.ok));
+    let ready = forges.iter().all(|forge| forge.reachable) && tokens_ok;
+    let status = if !ready { "unavailable" } else if forges.iter().any(|forge| forge.warning.is_some()) { "degraded" } else { "ok" };
+    (if ready { Status::Ok } else { Status::ServiceUnavailable }, Json(Readiness { status, forges, tokens }))
+}
+
+/// Liveness of the service
+#[derive(Debug, Serialize, ToSchema)]
+pub struct Health {
+    /// `ok`, or `degraded` when the secret provider is unreachable
+    pub status: &'static str,
+    pub secret_provider: SecretProviderHealth,
+}
+
+/// Liveness endpoint including the health of the secret provider
+#[utoipa::path(
+    get,
+    path = "/healthz",
+    tag = "operations",
+    responses(
+        (status = 200, description = "Healthy", body = Health),
+        (status = 503, description = "Secret provider unreachable", body = Health),
+    ),
+)]
+#[get("/healthz")]
+pub fn healthz_handle() -> (Status, Json<Health>) {
+    let secret_provider = service_key::health();
+    let status = if secret_provider.reachable { Status::Ok } else { Status::ServiceUnavailable };
+    (status, Json(Health {
+        status: if secret_provider.reachable { "ok" } else { "degraded" },
+        secret_provider,
+    }))
+}
@@ -0,0 +1,92 @@
+use rocket::get;
+#[cfg(feature = "dashboard")]
+use rocket::response::content::RawHtml;
+use rocket::serde::json::Json;
+use serde::Serialize;
+use utoipa::ToSchema;
+
+use crate::api::admin::ReadToken;
+use crate::utils::stats::{self, RepoSummary};
+
+/// Period covered when `days` is not given
+const DEFAULT_DAYS: u32 = 30;
+
+#[cfg(feature = "dashboard")]
+fn escape_html(value: &str) -> String {
+    value.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;").replace('"', "&quot;")
+}
+
+#[cfg(feature = "dashboard")]
+fn render_dashboard(days: u32, summaries: &[RepoSummary]) -> String {
+    let mut rows = String::new();
+    for summary in summaries {
+        let last_activity = summary
+            .last_activity
+            .map(|at| at.format("%Y-%m-%d %H:%M UTC").to_string())
+            .unwrap_or_else(|| "-".to_string());
+        rows.push_str(&format!(
+            "<tr><td>{}</td><td>{}</td><td>{}</td><td>{}</td><td>{:.0}%</td><td>{:.1}s</td><td>{}</td></tr>\n",
+            escape_html(&summary.repo),
+            summary.succeeded,
+            summary.conflicted,
+            summary.failed,
+            summary.conflict_rate * 100.0,
+            summary.average_duration_secs,
+            last_activity,
+        ));
+    }
+    format!(
+        "<!DOCTYPE html>\n<html><head><meta charset=\"utf-8\"><title>Backport statistics</title>\n\
+         <style>body{{font-family:sans-serif}}table{{border-collapse:collapse}}td,th{{border:1px solid #ccc;padding:4px 8px;text-align:right}}td:first-child{{text-align:left}}</style>\n\
+         </head><body>\n<h1>Backport statistics, last {} days</h1>\n\
+         <table>\n<tr><th>Repository</th><th>Succeeded</th><th>Conflicted</th><th>Failed</th><th>Conflict rate</th><th>Average duration</th><th>Last activity</th></tr>\n\
+         {}</table>\n<p>JSON: <a href=\"/stats/repos?days={}\">/stats/repos</a></p>\n</body></html>\n",
+        days, rows, days
+    )
+}
+
+/// Backport statistics of every repository over a period
+#[derive(Debug, Serialize, ToSchema)]
+pub struct RepoStats {
+    pub days: u32,
+    pub repos: Vec<RepoSummary>,
+}
+
+/// Per-repository backport statistics over the last `days` days (default 30)
+#[utoipa::path(
+    get,
+    path = "/stats/repos",
+    tag = "operations",
+    params(("days" = Option<u32>, Query, description = "Period in days (default 30)")),
+    responses(
+        (status = 200, description = "Statistics", body = RepoStats),
+        (status = 401, description = "Missing or unknown token"),
+    ),
+    security(("adminToken" = [])),
+)]
+#[get("/stats/repos?<days>")]
+pub fn repo_stats_handle(_reader: ReadToken, days: Option<u32>) -> Json<RepoStats> {
+    let days = days.unwrap_or(DEFAULT_DAYS);
+    let summaries = stats::store().lock().unwrap().summaries(days);
+    Json(RepoStats { days, repos: summaries })
+}
+
+/// The same statistics as an HTML table
+#[cfg(feature = "dashboard")]
+#[utoipa::path(
+    get,
+    path = "/stats",
+    tag = "operations",
+    params(("days" = Option<u32>, Query, description = "Period in days (default 30)")),
+    responses(
+        (status = 200, description = "HTML page", body = String, content_type = "text/html"),
+        (status = 401, description = "Missing or unknown token"),
+    ),
+    security(("adminToken" = [])),
+)]
+#[get("/stats?<days>")]
+pub fn stats_dashboard_handle(_reader: ReadToken, days: Option<u32>) -> RawHtml<String> {
+    let days = days.unwrap_or(DEFAULT_DAYS);
+    let summaries = stats::store().lock().unwrap().summaries(days);
+    RawHtml(render_dashboard(days, &summaries))
+}
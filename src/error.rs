@@ -0,0 +1,159 @@
+use std::io;
+use serde_json::{json, Value};
+
+/// Error of the git, forge API, parsing and configuration layers, keeping
+/// what failed instead of flattening it into a message
+#[derive(Debug, thiserror::Error)]
+pub enum Error {
+    /// A git operation failed
+    #[error("{}", .0.message())]
+    Git(#[from] git2::Error),
+    /// A forge API request failed or was answered with an error status
+    #[error("{message}")]
+    HttpApi { status: Option<u16>, message: String },
+    /// A payload or API response could not be parsed
+    #[error("{0}")]
+    Parse(String),
+    /// The configuration or environment is missing or invalid
+    #[error("{0}")]
+    Config(String),
+    /// Encrypting, decrypting or deriving a key failed
+    #[error("{0}")]
+    Crypto(String),
+    #[error(transparent)]
+    Io(#[from] io::Error),
+    /// A backport or mirror step failed for a reason of its own (conflicts,
+    /// guards, deadlines)
+    #[error("{0}")]
+    Job(String),
+}
+
+pub type Result<T, E = Error> = std::result::Result<T, E>;
+
+impl Error {
+    pub fn job(message: impl Into<String>) -> Self {
+        Error::Job(message.into())
+    }
+
+    pub fn config(message: impl Into<String>) -> Self {
+        Error::Config(message.into())
+    }
+
+    pub fn crypto(message: impl Into<String>) -> Self {
+        Error::Crypto(message.into())
+    }
+
+    /// A forge API request answered with the error `status` and `body`
+    pub fn api_status(status: reqwest::StatusCode, body: &str) -> Self {
+        Error::HttpApi { status: Some(status.as_u16()), message: format!("Request failed with status {}: {}", status, body) }
+    }
+
+    /// Short name of the layer the error comes from, for logs and responses
+    pub fn kind(&self) -> &'static str {
+        match self {
+            Error::Git(_) => "git",
+            Error::HttpApi { .. } => "http_api",
+            Error::Parse(_) => "parse",
+            Error::Config(_) => "config",
+            Error::Crypto(_) => "crypto",
+            Error::Io(_) => "io",
+            Error::Job(_) => "job",
+        }
+    }
+
+    /// HTTP status of a failed forge API request
+    pub fn status(&self) -> Option<u16> {
+        match self {
+            Error::HttpApi { status, .. } => *status,
+            _ => None,
+        }
+    }
+
+    /// Whether the failure is worth another attempt: a transport error, a
+    /// 5xx or 429 answer, or an interrupted network transfer
+    pub fn is_transient(&self) -> bool {
+        match self {
+            Error::HttpApi { status, .. } => status.is_none_or(|status| status >= 500 || status == 429),
+            Error::Git(e) => crate::utils::retry::is_transient_git_error(e),
+            Error::Io(e) => matches!(
+                e.kind(),
+                io::ErrorKind::TimedOut | io::ErrorKind::ConnectionReset | io::ErrorKind::ConnectionAborted | io::ErrorKind::Interrupted
+            ),
+            _ => false,
+        }
+    }
+
+    /// `{"kind", "message"}` plus the `status` of API errors
+    pub fn details(&self) -> Value {
+        let mut details = json!({ "kind": self.kind(), "message": self.to_string() });
+        if let Some(status) = self.status() {
+            details["status"] = json!(status);
+        }
+        details
+    }
+}
+
+impl From<reqwest::Error> for Error {
+    fn from(e: reqwest::Error) -> Self {
+        if e.is_decode() {
+            return Error::Parse(e.to_string());
+        }
+        Error::HttpApi { status: e.status().map(|status| status.as_u16()), message: e.to_string() }
+    }
+}
+
+impl From<serde_json::Error> for Error {
+    fn from(e: serde_json::Error) -> Self {
+        Error::Parse(e.to_string())
+    }
+}
+
+impl From<serde_yaml::Error> for Error {
+    fn from(e: serde_yaml::Error) -> Self {
+        Error::Parse(e.to_string())
+    }
+}
+
+impl From<crate::utils::remote_url::RemoteUrlError> for Error {
+    fn from(e: crate::utils::remote_url::RemoteUrlError) -> Self {
+        Error::Config(e.to_string())
+    }
+}
+
+impl From<reqwest::header::InvalidHeaderValue> for Error {
+    fn from(e: reqwest::header::InvalidHeaderValue) -> Self {
+        Error::Config(format!("Invalid header value: {}", e))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_errors_keep_their_layer() {
+        let git: Error = git2::Error::from_str("failed to push some refs").into();
+        assert_eq!(git.to_string(), "failed to push some refs");
+        assert_eq!(git.kind(), "git");
+
+        let parse: Error = serde_json::from_str::<Value>("{").unwrap_err().into();
+        assert_eq!(parse.kind(), "parse");
+
+        let api = Error::HttpApi { status: Some(401), message: "Request failed with status 401 Unauthorized: Bad credentials".to_string() };
+        assert_eq!(api.details(), json!({ "kind": "http_api", "message": "Request failed with status 401 Unauthorized: Bad credentials", "status": 401 }));
+        assert_eq!(Error::config("GITHUB_TOKEN not set").details(), json!({ "kind": "config", "message": "GITHUB_TOKEN not set" }));
+    }
+
+    #[test]
+    fn test_only_transport_and_server_errors_are_transient() {
+        assert!(Error::HttpApi { status: None, message: "connection refused".to_string() }.is_transient());
+        assert!(Error::HttpApi { status: Some(502), message: "Bad Gateway".to_string() }.is_transient());
+        assert!(Error::HttpApi { status: Some(429), message: "Too Many Requests".to_string() }.is_transient());
+        assert!(!Error::HttpApi { status: Some(422), message: "Validation Failed".to_string() }.is_transient());
+        assert!(!Error::job("Cherry-pick conflicts in src/lib.rs").is_transient());
+        assert!(!Error::config("Invalid branch name").is_transient());
+        let network = git2::Error::new(git2::ErrorCode::GenericError, git2::ErrorClass::Net, "failed to connect");
+        assert!(Error::Git(network).is_transient());
+        assert!(!Error::Git(git2::Error::from_str("failed to push some refs")).is_transient());
+    }
+}
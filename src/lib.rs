@@ -1,8 +1,10 @@
 pub mod api;
+pub mod error;
 pub mod utils;
 pub mod models;
 
 // Re-export commonly used modules
 pub use crate::api::routes;
+pub use crate::error::Error;
 pub use crate::models::webhook;
 pub use crate::utils::{git, parser, hmac};
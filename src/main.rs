@@ -1,81 +1,525 @@
-#[macro_use] extern crate rocket;
 
 use rocket::routes;
 use std::sync::RwLock;
-use std::process;
-use crate::api::routes::{github_handle, gitcode_handle};
+use std::path::PathBuf;
+use std::time::Duration;
+use webhook_service::api::routes::{healthz_handle, metrics_handle, readyz_handle};
+use webhook_service::api::admin::{audit_fairing, audit_handle, backport_range_handle, confirm_canary_handle, create_token_handle, disable_repo_handle, enable_repo_handle, mirror_handle, mirrors_handle, onboard_handle, resume_repo_handle, pause_handle, resume_handle, revoke_token_handle, rotate_webhook_secret_handle, tokens_handle};
+use webhook_service::api::jobs::{admin_job_handle, admin_jobs_handle, dlq_handle, dlq_requeue_handle, job_handle, job_retry_handle, jobs_handle};
+use webhook_service::api::ha::{self as ha_api, ha_event_handle, ha_heartbeat_handle};
+use webhook_service::api::patches::patch_handle;
+use webhook_service::api::openapi::openapi_handle;
+use webhook_service::api::backports::backport_graph_handle;
+use webhook_service::api::stats::repo_stats_handle;
+#[cfg(feature = "dashboard")]
+use webhook_service::api::stats::stats_dashboard_handle;
+use webhook_service::api::platform::{self, PlatformSettings};
+use webhook_service::error::Result;
 use std::env;
 use hex::decode;
-use crate::utils::aes_cbc;
+use webhook_service::utils::branch::BranchMapping;
+use webhook_service::utils::{self, aes_cbc, comment_queue, config, connectivity, freeze, git, jobs, token_scopes, ha, http_client, logging, migrations, mirror_schedule, patches, privileges, s3_export, service_key, state, state_store};
+use webhook_service::routes;
+use clap::{Parser, Subcommand};
+use std::io::Read;
+use rocket::fairing::AdHoc;
 use log::{info, error};
-use keyring::Entry;
 
-mod models;
-mod utils;
-mod api;
+/// Backport and mirroring bot for GitHub and GitCode
+#[derive(Parser)]
+#[command(version, about)]
+struct Cli {
+    /// Same as `serve --check`, kept for existing deployments
+    #[arg(long, hide = true)]
+    check: bool,
+    #[command(subcommand)]
+    command: Option<Command>,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Serve the webhook endpoints (the default)
+    Serve {
+        /// Run the startup self-checks and exit with their outcome
+        #[arg(long)]
+        check: bool,
+    },
+    /// Copy every branch and tag of a repository to another one
+    Mirror {
+        source: String,
+        target: String,
+        /// Configured repository whose `internal_branches` and `branch_map`
+        /// apply (default: the one whose `target_repo` is the target)
+        #[arg(long)]
+        repo: Option<String>,
+        /// Additional internal-only branch pattern, may be repeated
+        #[arg(long = "internal")]
+        internal: Vec<String>,
+        /// Only record the reference changes the push would make on the job
+        #[arg(long)]
+        dry_run: bool,
+    },
+    /// Backport a PR of a configured repository onto a branch
+    Backport {
+        /// Repository name as configured in `config.yml`
+        repo: String,
+        pr: u32,
+        branch: String,
+    },
+    /// Cherry-pick a commit range of a configured repository onto a branch,
+    /// for hotfixes that landed without a PR
+    BackportRange {
+        /// Repository name as configured in `config.yml`
+        repo: String,
+        /// Branch the commits are on
+        branch: String,
+        /// `<sha1>..<sha2>`: the commits after `sha1` up to `sha2`
+        range: String,
+        target_branch: String,
+    },
+    /// Validate the repository configuration
+    VerifyConfig {
+        #[arg(long, default_value = config::CONFIG_FILE)]
+        config: PathBuf,
+    },
+    /// Encrypt a secret read from stdin and print its `.env` line
+    Encrypt {
+        /// Variable the secret is for, e.g. `GITHUB_TOKEN`; the line sets
+        /// `<var>_ENCRYPTED`
+        #[arg(long)]
+        var: String,
+    },
+    /// Same as `encrypt`, printing only the hex value; kept for existing scripts
+    #[command(hide = true)]
+    EncryptSecret,
+    /// Process a stored webhook payload as if it had just been delivered
+    Replay {
+        file: PathBuf,
+        /// `github` or `gitcode`
+        #[arg(long)]
+        platform: String,
+        /// Value of the event header, e.g. `pull_request` or `Merge Request Hook`
+        #[arg(long)]
+        event: String,
+    },
+    /// Write the state directory to an archive
+    ExportState {
+        archive: PathBuf,
+    },
+    /// Restore the state directory from an archive
+    ImportState {
+        archive: PathBuf,
+        /// Overwrite existing state files
+        #[arg(long)]
+        force: bool,
+    },
+}
 
-const SERVICE_NAME: &str = "webhook_service";
-const USERNAME: &str = "webhook";
-pub fn get_service_key() -> Result<String, keyring::Error> {
-    let entry = Entry::new(SERVICE_NAME, USERNAME)?;
-    match entry.get_password() {
-        Ok(password) => {
-            info!("Service key retrieved from keyring");
-            Ok(password)
+/// Runs `export-state` / `import-state`
+fn run_state_command(command: &str, result: std::io::Result<usize>) {
+    match result {
+        Ok(count) => println!("{}: {} state files processed", command, count),
+        Err(e) => {
+            eprintln!("{} failed: {}", command, e);
+            logging::exit(1);
         }
-        Err(err) => {
-            error!("Failed to retrieve service key from keyring: {}", err);
-            Err(err)
+    }
+}
+
+/// Internal-only branch patterns and branch renames of the configured
+/// repository `repo`, or of the one mirrored to `target`
+fn mirror_settings(repo: Option<&str>, target: &str) -> (Vec<String>, Vec<BranchMapping>) {
+    let config = match config::read_config(config::CONFIG_FILE) {
+        Ok(config) => config,
+        Err(e) if repo.is_some() => {
+            eprintln!("Failed to read {}: {}", config::CONFIG_FILE, e);
+            logging::exit(1);
+        }
+        Err(e) => {
+            eprintln!("warning: {} not read, only --internal patterns apply: {}", config::CONFIG_FILE, e);
+            return (Vec::new(), Vec::new());
+        }
+    };
+    let same_remote = |url: &str| url.trim_end_matches('/').trim_end_matches(".git").eq_ignore_ascii_case(target.trim_end_matches('/').trim_end_matches(".git"));
+    let repo_config = match repo {
+        Some(repo) => config.repos.get(repo).unwrap_or_else(|| {
+            eprintln!("Repository {} not found in {}", repo, config::CONFIG_FILE);
+            logging::exit(1);
+        }),
+        None => match config.repos.values().find(|repo_config| same_remote(&repo_config.target_repo)) {
+            Some(repo_config) => repo_config,
+            None => return (Vec::new(), Vec::new()),
+        },
+    };
+    (repo_config.internal_branches.clone(), repo_config.branch_map.clone())
+}
+
+/// Mirrors `source` to `target` through a temporary bare repository, recorded
+/// as a job listing the reference changes
+fn run_mirror(source: &str, target: &str, repo: Option<&str>, mut internal: Vec<String>, dry_run: bool) {
+    init_environment();
+    let (configured, branch_map) = mirror_settings(repo, target);
+    internal.extend(configured);
+    if !internal.is_empty() {
+        println!("Not mirroring internal-only branches: {}", internal.join(", "));
+    }
+    for mapping in &branch_map {
+        println!("Mirroring branches {} as {}", mapping.source, mapping.target);
+    }
+    let workdir = tempfile::tempdir().unwrap_or_else(|e| {
+        eprintln!("Failed to create a working directory: {}", e);
+        logging::exit(1);
+    });
+    let platform = utils::remote_url::RemoteUrl::parse(target).map(|remote| remote.platform()).unwrap_or("gitcode");
+    let job_id = jobs::store().lock().unwrap().start(platform, repo.unwrap_or(target), "mirror");
+    let result = git::mirror_repository(source, target, workdir.path(), &internal, &branch_map, job_id, dry_run);
+    jobs::store().lock().unwrap().finish(job_id, result.as_ref().map(|_| ()).map_err(|e| e.to_string()));
+    match result {
+        Ok(changes) => {
+            for change in &changes {
+                println!("{} {} {} -> {}", change.kind(), change.refname, change.old.as_deref().unwrap_or("-"), change.new.as_deref().unwrap_or("-"));
+            }
+            if dry_run {
+                println!("Dry run (job {}): {} references would change on {}", job_id, changes.len(), target);
+            } else {
+                println!("Mirrored {} references from {} to {} (job {})", changes.len(), source, target, job_id);
+            }
+        },
+        Err(e) => {
+            eprintln!("Mirror job {} failed: {}", job_id, e.details());
+            logging::exit(1);
         }
     }
 }
 
-#[launch]
-fn rocket() -> _ {
-    // Initialize logger
-    utils::logging::init_production_logger();
-    info!("Starting webhook service...");
+/// Configuration of `repo`, exiting when it is not configured
+fn configured_repo(repo: &str) -> config::RepoConfig {
+    match config::load_repo_config(repo) {
+        Ok(Some(repo_config)) => repo_config,
+        Ok(None) => {
+            eprintln!("Repository {} not found in {}", repo, config::CONFIG_FILE);
+            logging::exit(1);
+        }
+        Err(e) => {
+            eprintln!("Failed to read {}: {}", config::CONFIG_FILE, e);
+            logging::exit(1);
+        }
+    }
+}
 
-    // Load environment variables from .env file
+/// Runs the regular backport pipeline for one PR and branch, recorded as a job
+fn run_backport(repo: &str, pr: u32, branch: &str) {
+    init_environment();
+    let repo_config = configured_repo(repo);
+    let (platform, webhook_data) = git::operator_backport_request(&repo_config, pr, branch);
+    let job_id = jobs::store().lock().unwrap().start(platform, repo, &webhook_data.event_type);
+    let result = match platform {
+        "github" => git::process_github_pr(&webhook_data, job_id),
+        "gitlab" => git::process_gitlab_pr(&webhook_data, None, job_id),
+        _ => git::process_pr(&webhook_data, None, job_id),
+    };
+    jobs::store().lock().unwrap().finish(job_id, result.as_ref().map(|_| ()).map_err(|e| e.to_string()));
+    match result {
+        Ok(message) => println!("Job {}: {}", job_id, message),
+        Err(e) => {
+            eprintln!("Job {} failed: {}", job_id, e.details());
+            logging::exit(1);
+        }
+    }
+}
+
+/// Backports a commit range outside of any PR, recorded as a job
+fn run_backport_range(repo: &str, branch: &str, range: &str, target_branch: &str) {
+    init_environment();
+    let repo_config = configured_repo(repo);
+    let job_id = jobs::store().lock().unwrap().start(repo_config.source_platform(), repo, "backport_range");
+    let result = git::backport_range(&repo_config, branch, range, target_branch, job_id);
+    jobs::store().lock().unwrap().finish(job_id, result.as_ref().map(|_| ()).map_err(|e| e.to_string()));
+    match result {
+        Ok(message) => println!("Job {}: {}", job_id, message),
+        Err(e) => {
+            eprintln!("Job {} failed: {}", job_id, e.details());
+            logging::exit(1);
+        }
+    }
+}
+
+fn run_verify_config(path: &PathBuf) {
+    match config::read_config(path) {
+        Ok(config) => {
+            let enabled = config.repos.values().filter(|repo_config| repo_config.enabled).count();
+            println!("{:?} is valid: {} repositories ({} enabled)", path, config.repos.len(), enabled);
+        }
+        Err(e) => {
+            eprintln!("{:?} is invalid: {}", path, e);
+            logging::exit(1);
+        }
+    }
+}
+
+/// Prints stdin encrypted the way `*_ENCRYPTED` variables are decrypted: as
+/// the `.env` line of `var` when given, as the hex value alone otherwise
+fn run_encrypt_secret(var: Option<&str>) {
     dotenv::dotenv().ok();
-    
-    // Get service key
-    let password = match get_service_key() {
+    let mut secret = String::new();
+    if let Err(e) = std::io::stdin().read_to_string(&mut secret) {
+        eprintln!("Failed to read the secret from stdin: {}", e);
+        logging::exit(1);
+    }
+    let secret = secret.strip_suffix('\n').map(|s| s.strip_suffix('\r').unwrap_or(s)).unwrap_or(&secret);
+    match aes_cbc::encrypt_with_iv(&service_aes_key(), &[0u8; 16], secret.as_bytes()) {
+        Ok(encrypted) => match var {
+            Some(var) => println!("{}={}", encrypted_var_name(var), hex::encode(encrypted)),
+            None => println!("{}", hex::encode(encrypted)),
+        },
+        Err(e) => {
+            eprintln!("Failed to encrypt the secret: {}", e);
+            logging::exit(1);
+        }
+    }
+}
+
+/// `<var>_ENCRYPTED`, warning when the service never decrypts that variable
+fn encrypted_var_name(var: &str) -> String {
+    let var = var.trim().to_uppercase();
+    let name = if var.ends_with("_ENCRYPTED") { var } else { format!("{}_ENCRYPTED", var) };
+    let every_platform = PlatformSettings { hooks_prefix: String::new(), github_enabled: true, gitcode_enabled: true, gitlab_enabled: true };
+    let known = every_platform.required_secrets();
+    if !known.contains(&name.as_str()) {
+        eprintln!("warning: {} is not read by the service, expected one of: {}", name, known.join(", "));
+    }
+    name
+}
+
+/// Processes a stored payload like a verified delivery, without HA forwarding
+async fn run_replay(file: &PathBuf, platform: &str, event: &str) {
+    let body = std::fs::read_to_string(file).unwrap_or_else(|e| {
+        eprintln!("Failed to read {:?}: {}", file, e);
+        logging::exit(1);
+    });
+    match routes::process_event(platform, event, body.into(), http_client::shared()).await {
+        Ok(_) => println!("Replayed {} event from {:?}", event, file),
+        Err(e) => {
+            eprintln!("Replay of {:?} failed: {}", file, e);
+            logging::exit(1);
+        }
+    }
+}
+
+/// Runs the startup self-checks for `--check` and exits with their outcome
+fn run_self_check() -> ! {
+    let platforms = PlatformSettings::from_env().enabled_platforms();
+    let forges = connectivity::check_forges(&platforms);
+    let tokens = token_scopes::verify(&platforms);
+    println!("{}", serde_json::to_string_pretty(&serde_json::json!({ "forges": forges, "tokens": tokens })).unwrap_or_default());
+    for forge in &forges {
+        if let Some(warning) = &forge.warning {
+            eprintln!("warning: {}: {}", forge.host, warning);
+        }
+    }
+    let mut failed = false;
+    if !forges.iter().all(|forge| forge.reachable) {
+        eprintln!("Self-check failed: unreachable forges");
+        failed = true;
+    }
+    for check in tokens.iter().filter(|check| !check.ok) {
+        eprintln!("Self-check failed: {} token for {} needs {}: {}", check.platform, check.repo, check.permission, check.detail);
+        failed = true;
+    }
+    logging::exit(if failed { 1 } else { 0 });
+}
+
+fn main() {
+    let cli = Cli::parse();
+    match cli.command.unwrap_or(Command::Serve { check: false }) {
+        Command::Serve { check } => {
+            let (run_as, settings) = prepare_serve();
+            rocket::execute(serve(check || cli.check, run_as, settings))
+        }
+        Command::Mirror { source, target, repo, internal, dry_run } => run_mirror(&source, &target, repo.as_deref(), internal, dry_run),
+        Command::Backport { repo, pr, branch } => run_backport(&repo, pr, &branch),
+        Command::BackportRange { repo, branch, range, target_branch } => run_backport_range(&repo, &branch, &range, &target_branch),
+        Command::VerifyConfig { config } => run_verify_config(&config),
+        Command::Encrypt { var } => run_encrypt_secret(Some(&var)),
+        Command::EncryptSecret => run_encrypt_secret(None),
+        Command::Replay { file, platform, event } => {
+            // The decrypted tokens are set before the runtime starts threads
+            init_environment();
+            rocket::execute(run_replay(&file, &platform, &event))
+        }
+        Command::ExportState { archive } => {
+            // `.env` may select the state backend
+            dotenv::dotenv().ok();
+            run_state_command("export-state", state::export_state(state_store::backend(), &state::state_dir(), &archive))
+        }
+        Command::ImportState { archive, force } => {
+            dotenv::dotenv().ok();
+            run_state_command("import-state", state::import_state(state_store::backend(), &archive, &state::state_dir(), force))
+        }
+    }
+    logging::shutdown();
+}
+
+/// Startup of `serve` that must happen before the async runtime starts
+/// threads: loading `.env` (which may set `RUN_AS_USER`), assuming the
+/// target user and setting the environment
+fn prepare_serve() -> (Option<privileges::User>, PlatformSettings) {
+    dotenv::dotenv().ok();
+    if let Err(e) = privileges::check_startup_user() {
+        eprintln!("{}", e);
+        logging::exit(1);
+    }
+    // When started as root, everything up to binding runs with the target
+    // user's effective ids so the files it creates belong to that user
+    let run_as = match privileges::run_as_user() {
+        Some(name) if privileges::is_root() => {
+            let user = privileges::lookup_user(&name).unwrap_or_else(|e| {
+                eprintln!("Failed to look up RUN_AS_USER {}: {}", name, e);
+                logging::exit(1);
+            });
+            if let Err(e) = privileges::assume_user(&user) {
+                eprintln!("Failed to switch to {}: {}", name, e);
+                logging::exit(1);
+            }
+            privileges::set_user_env(&user);
+            Some(user)
+        }
+        _ => None,
+    };
+    (run_as, init_environment())
+}
+
+/// Starts the periodic tasks; called once the service runs as its final user
+fn spawn_background_tasks() {
+    let enabled_platforms = PlatformSettings::from_env().enabled_platforms();
+    tokio::task::spawn_blocking(move || token_scopes::verify(&enabled_platforms));
+
+    let refresh_secs = env::var("FREEZE_CALENDAR_REFRESH_SECS")
+        .ok()
+        .and_then(|value| value.parse::<u64>().ok())
+        .unwrap_or(900);
+    freeze::spawn_refresh_task(Duration::from_secs(refresh_secs));
+
+    let keyring_check_secs = env::var("KEYRING_CHECK_SECS")
+        .ok()
+        .and_then(|value| value.parse::<u64>().ok())
+        .unwrap_or(300);
+    service_key::spawn_health_task(Duration::from_secs(keyring_check_secs));
+
+    let comment_retry_secs = env::var("COMMENT_RETRY_SECS")
+        .ok()
+        .and_then(|value| value.parse::<u64>().ok())
+        .unwrap_or(30);
+    comment_queue::spawn_worker(Duration::from_secs(comment_retry_secs));
+
+    if let Some(export) = s3_export::ExportSettings::from_env() {
+        s3_export::spawn_exporter(export);
+    }
+
+    mirror_schedule::spawn_scheduler();
+
+    patches::spawn_prune_task(Duration::from_secs(60 * 60));
+
+    ha::spawn_heartbeat_task(ha::settings());
+    ha_api::spawn_standby_monitor(ha::settings());
+}
+
+async fn serve(check: bool, run_as: Option<privileges::User>, settings: PlatformSettings) {
+    info!("Starting webhook service...");
+    let mut rocket = rocket(&settings).unwrap_or_else(|e| {
+        error!("Failed to configure the webhook endpoints: {}", e);
+        logging::exit(1);
+    });
+    // Token checks need the decrypted tokens
+    if check {
+        // The checks use blocking HTTP clients
+        tokio::task::block_in_place(|| run_self_check());
+    }
+
+    let state_dir = state::state_dir();
+    match migrations::run_migrations(state_store::backend(), &state_dir, migrations::MIGRATIONS) {
+        Ok(version) => info!("State directory {:?} at schema version {}", state_dir, version),
+        Err(e) => {
+            error!("Failed to migrate state in {:?}: {}", state_dir, e);
+            logging::exit(1);
+        }
+    }
+
+    match run_as {
+        Some(user) => {
+            if let Err(e) = privileges::regain_root() {
+                error!("Failed to regain root to bind the port: {}", e);
+                logging::exit(1);
+            }
+            // Nothing else runs until the port is bound and root is given up
+            rocket = rocket.attach(AdHoc::on_liftoff("Drop privileges", move |_| Box::pin(async move {
+                if let Err(e) = privileges::drop_privileges(&user).and_then(|_| privileges::verify_service_dirs()) {
+                    error!("Refusing to serve: {}", e);
+                    logging::exit(1);
+                }
+                spawn_background_tasks();
+            })));
+        }
+        None => {
+            if let Err(e) = privileges::verify_service_dirs() {
+                error!("Refusing to start: {}", e);
+                logging::exit(1);
+            }
+            spawn_background_tasks();
+        }
+    }
+
+    if let Err(e) = rocket.launch().await {
+        error!("Rocket failed to launch: {}", e);
+        logging::exit(1);
+    }
+}
+
+/// Derives the AES key of the `*_ENCRYPTED` variables from the service key
+fn service_aes_key() -> Vec<u8> {
+    let password = match service_key::get_service_key() {
         Ok(password) => password,
         Err(err) => {
             error!("Failed to retrieve service key: {}", err);
-            process::exit(1);
+            eprintln!("Failed to retrieve service key: {}", err);
+            logging::exit(1);
         }
     };
-    let key = utils::hash::sha256_hex(&password);
+    hex::decode(utils::hash::sha256_hex(&password)).unwrap_or_else(|_| {
+        error!("Failed to decode hex key");
+        logging::exit(1);
+    })
+}
+
+/// Sets up logging and the environment, decrypting the tokens of the enabled platforms
+fn init_environment() -> PlatformSettings {
+    // Initialize logger
+    logging::init_production_logger();
+
+    // Load environment variables from .env file
+    dotenv::dotenv().ok();
+    
+    let key_bytes = service_aes_key();
     
-    // Decrypt environment variables
-    let env_vars = [
-        "GITCODE_TOKEN_ENCRYPTED",
-        "GITCODE_WEBHOOK_VERIFYING_KEY_ENCRYPTED",
-        "GITHUB_TOKEN_ENCRYPTED",
-        "GITHUB_WEBHOOK_VERIFYING_KEY_ENCRYPTED"
-    ];
+    // Decrypt environment variables of the enabled platforms
+    let settings = PlatformSettings::from_env();
+    let env_vars = settings.required_secrets();
     
     for var_name in env_vars.iter() {
         if let Ok(encrypted_value) = env::var(var_name) {
             let encrypted_bytes = decode(&encrypted_value).unwrap_or_else(|_| {
                 error!("Failed to decode hex value for {}", var_name);
-                process::exit(1);
+                logging::exit(1);
             });
             
-            let key_bytes = hex::decode(&key).unwrap_or_else(|_| {
-                error!("Failed to decode hex key");
-                process::exit(1);
-            });
             let decrypted_bytes = aes_cbc::decrypt(&key_bytes, &encrypted_bytes).unwrap_or_else(|err| {
                 error!("Failed to decrypt {}: {}", var_name, err);
-                process::exit(1);
+                logging::exit(1);
             });
             
             let decrypted_value = String::from_utf8(decrypted_bytes).unwrap_or_else(|_| {
                 error!("Failed to convert decrypted bytes to UTF-8 string for {}", var_name);
-                process::exit(1);
+                logging::exit(1);
             });
             
             let env_var_name = var_name.replace("_ENCRYPTED", "");
@@ -83,14 +527,29 @@ fn rocket() -> _ {
             info!("Successfully decrypted and set {}", env_var_name);
         } else {
             error!("Environment variable {} not found", var_name);
-            process::exit(1);
+            logging::exit(1);
         }
     }
     
+    service_key::record_refresh();
     info!("Environment variables decrypted successfully");
+    settings
+}
+
+fn rocket(settings: &PlatformSettings) -> Result<rocket::Rocket<rocket::Build>> {
     info!("Configuring Rocket server...");
 
-    rocket::build()
-        .mount("/", routes![github_handle, gitcode_handle])
+    let rocket = rocket::build()
+        .mount("/", routes![
+            healthz_handle, readyz_handle, metrics_handle, openapi_handle, patch_handle, repo_stats_handle,
+            onboard_handle, disable_repo_handle, enable_repo_handle, resume_repo_handle, confirm_canary_handle, backport_range_handle, mirror_handle, mirrors_handle, rotate_webhook_secret_handle, pause_handle, resume_handle,
+            create_token_handle, tokens_handle, revoke_token_handle, audit_handle,
+            job_handle, jobs_handle, admin_job_handle, admin_jobs_handle, job_retry_handle, dlq_handle, dlq_requeue_handle, backport_graph_handle,
+            ha_event_handle, ha_heartbeat_handle,
+        ])
         .manage(RwLock::new(true))
+        .attach(audit_fairing());
+    #[cfg(feature = "dashboard")]
+    let rocket = rocket.mount("/", routes![stats_dashboard_handle]);
+    platform::mount_platforms(rocket, settings)
 }
@@ -1,10 +1,18 @@
 use serde::{Deserialize, Serialize};
+use std::fmt;
+
+use crate::utils::jobs;
+use crate::utils::templates::{CommentTemplates, MessageKind};
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct Label {
     pub description: Option<String>,
     pub title: String,
     pub r#type: Option<String>,
+    /// Version label a derived `br:` label stands for; whoever applied that
+    /// label is accountable for it
+    #[serde(skip)]
+    pub source: Option<String>,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -24,6 +32,12 @@ pub struct Repository {
 #[derive(Debug, Serialize, Deserialize)]
 pub struct Project {
     pub namespace: String,
+    /// Full project path; group-level webhooks carry the group's display
+    /// name in `namespace`, this keeps the path with its subgroups
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub path_with_namespace: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub archived: Option<bool>,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -47,6 +61,11 @@ pub struct GitHubLabel {
     pub description: Option<String>,
 }
 
+#[derive(Debug, Serialize, Deserialize)]
+pub struct GitHubUser {
+    pub login: String,
+}
+
 #[derive(Debug, Serialize, Deserialize)]
 pub struct GitHubPullRequest {
     pub url: Option<String>,
@@ -55,6 +74,26 @@ pub struct GitHubPullRequest {
     #[serde(default)]
     pub labels: Vec<GitHubLabel>,
     pub html_url: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub merged: Option<bool>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub merge_commit_sha: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub merged_by: Option<GitHubUser>,
+    /// Set while auto-merge is enabled on the PR, including when it merged it
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub auto_merge: Option<serde_json::Value>,
+}
+
+/// How a pull request was merged, from a payload that reports it
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MergeInfo {
+    pub merged: bool,
+    /// Commit the merge created on the base branch
+    pub commit_sha: Option<String>,
+    /// Merged by a merge queue or by auto-merge, which may rewrite the PR's
+    /// commits before they land
+    pub queued: bool,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -62,6 +101,10 @@ pub struct GitHubRepository {
     pub name: String,
     pub clone_url: String,
     pub full_name: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub archived: Option<bool>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub disabled: Option<bool>,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -71,7 +114,26 @@ pub struct GitHubWebhookPayload {
     pub repository: GitHubRepository,
 }
 
-#[derive(Debug)]
+#[derive(Debug, Serialize, Deserialize)]
+pub struct GitLabProject {
+    /// Full path of the project, including its subgroups
+    pub path_with_namespace: String,
+    pub git_http_url: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub archived: Option<bool>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct GitLabWebhookPayload {
+    #[serde(default = "default_event_type")]
+    pub object_kind: String,
+    pub object_attributes: Option<ObjectAttributes>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub labels: Option<Vec<Label>>,
+    pub project: GitLabProject,
+}
+
+#[derive(Debug, Clone)]
 pub struct ParsedWebhookData {
     pub labels: Vec<Label>,
     pub event_type: String,
@@ -82,10 +144,18 @@ pub struct ParsedWebhookData {
     pub repo_url: String,
     pub namespace: String,
     pub iid: Option<u32>,
+    /// Labels were set by an operator (CLI) rather than read from the PR, so
+    /// `restrict_labels` does not apply
+    pub labels_trusted: bool,
+    /// Archived (or disabled) flag of the repository when the payload
+    /// carries one; `None` means it has to be asked from the API
+    pub archived: Option<bool>,
+    /// Merge state of a GitHub PR when the payload carries one
+    pub merge: Option<MergeInfo>,
 }
 
-impl ToString for ParsedWebhookData {
-    fn to_string(&self) -> String {
+impl fmt::Display for ParsedWebhookData {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         let mut output = String::new();
         
         output.push_str(&format!("Event Type: {}\n", self.event_type));
@@ -108,7 +178,7 @@ impl ToString for ParsedWebhookData {
             }
         }
         
-        output
+        write!(f, "{}", output)
     }
 }
 
@@ -128,7 +198,8 @@ pub struct GitCodeCommit {
 }
 
 impl GitCodeCommit {
-    pub fn get_cherry_pick_url(&self) -> Option<String> {
+    /// URL of the PR in the `Cherry-picked from:` trailer, when it is on `host`
+    pub fn get_cherry_pick_url(&self, host: &str) -> Option<String> {
         const CHERRY_PICK_MARKER: &str = "Cherry-picked from: ";
         
         // Find the marker in the message
@@ -137,9 +208,15 @@ impl GitCodeCommit {
             .and_then(|start_idx| {
                 // Get the substring starting after the marker
                 let url_start = start_idx + CHERRY_PICK_MARKER.len();
-                let url = self.message[url_start..].trim().to_string();
-                // Only return Some if the URL contains gitcode.com
-                if url.contains("gitcode.com") {
+                // Only the rest of the marker line, later trailers are not part of the URL
+                let url = self.message[url_start..]
+                    .lines()
+                    .next()
+                    .unwrap_or("")
+                    .trim()
+                    .to_string();
+                // Only return Some if the URL is on the forge of the push
+                if url.contains(host) {
                     Some(url)
                 } else {
                     None
@@ -147,10 +224,18 @@ impl GitCodeCommit {
             })
     }
 
-    pub fn get_original_pr_number(&self) -> Option<u32> {
-        self.get_cherry_pick_url().and_then(|url| {
+    /// Job ID from the `Backport-Job:` trailer the bot adds to backported commits
+    pub fn get_job_id(&self) -> Option<String> {
+        self.message
+            .lines()
+            .find_map(|line| line.trim().strip_prefix(jobs::JOB_TRAILER))
+            .map(|id| id.trim().to_string())
+    }
+
+    pub fn get_original_pr_number(&self, host: &str) -> Option<u32> {
+        self.get_cherry_pick_url(host).and_then(|url| {
             url.split('/')
-                .last()
+                .next_back()
                 .and_then(|num_str| num_str.parse::<u32>().ok())
         })
     }
@@ -166,6 +251,9 @@ pub struct CommentInfo {
 pub struct GitCodePushProject {
     pub name: String,
     pub namespace: String,
+    /// See `Project::path_with_namespace`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub path_with_namespace: Option<String>,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -183,8 +271,35 @@ pub struct GitCodePushPayload {
     pub git_branch: String,
 }
 
+/// Pusher of a GitHub push event; `name` is the user's login
+#[derive(Debug, Serialize, Deserialize)]
+pub struct GitHubPusher {
+    pub name: String,
+    #[serde(default)]
+    pub email: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct GitHubPushRepository {
+    pub name: String,
+    pub full_name: String,
+}
+
+/// Push event of GitHub; its commits carry the same fields as GitCode's
+#[derive(Debug, Serialize, Deserialize)]
+pub struct GitHubPushPayload {
+    /// Pushed ref, e.g. `refs/heads/release-1.0`
+    #[serde(rename = "ref")]
+    pub git_ref: String,
+    pub commits: Vec<GitCodeCommit>,
+    pub repository: GitHubPushRepository,
+    pub pusher: GitHubPusher,
+}
+
 #[derive(Debug)]
 pub struct ParsedPushData {
+    /// Forge the push happened on (`gitcode` or `github`)
+    pub platform: &'static str,
     pub user_name: String,
     pub user_email: String,
     pub commits: Vec<GitCodeCommit>,
@@ -194,8 +309,8 @@ pub struct ParsedPushData {
     pub branch: String,
 }
 
-impl ToString for ParsedPushData {
-    fn to_string(&self) -> String {
+impl fmt::Display for ParsedPushData {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         let mut output = String::new();
         
         output.push_str(&format!("User: {} <{}>\n", self.user_name, self.user_email));
@@ -213,23 +328,35 @@ impl ToString for ParsedPushData {
             ));
         }
         
-        output
+        write!(f, "{}", output)
     }
 }
 
 impl ParsedPushData {
-    pub fn get_comment_info(&self) -> Vec<CommentInfo> {
+    /// Builds the comments for the PRs referenced by the pushed commits
+    ///
+    /// Each comment references the job that produced the commit, or
+    /// `push_job_id` for commits without a `Backport-Job:` trailer.
+    pub fn get_comment_info(&self, templates: &CommentTemplates, push_job_id: &str) -> Vec<CommentInfo> {
+        let host = match self.platform {
+            "github" => "github.com",
+            _ => "gitcode.com",
+        };
         self.commits
             .iter()
             .filter_map(|commit| {
-                commit.get_cherry_pick_url().map(|_| {
+                commit.get_cherry_pick_url(host).map(|_| {
                     let commit_id = &commit.id[..8];
+                    let job_id = commit.get_job_id().unwrap_or_else(|| push_job_id.to_string());
+                    let message = templates.render(MessageKind::PushReference, &[
+                        ("user", &self.user_name),
+                        ("branch", &self.branch),
+                        ("commit", commit_id),
+                        ("url", &commit.url),
+                    ]);
                     CommentInfo {
-                        message: format!(
-                            "**{}** pushed a commit on branch {} that referenced this pull request: [{}]({})",
-                            self.user_name, self.branch, commit_id, format!("{}?ref={}", commit.url, self.branch)
-                        ),
-                        pr_id: commit.get_original_pr_number(),
+                        message: jobs::with_job_reference(&message, &job_id),
+                        pr_id: commit.get_original_pr_number(host),
                     }
                 })
             })
@@ -1,47 +1,128 @@
 use aes::cipher::KeyInit;
 use aes::Aes256;
-use cipher::BlockDecryptMut;
+use cipher::{BlockDecryptMut, BlockEncryptMut};
+use rand::RngCore;
+
+use crate::error::{Error, Result};
 
 const DEFAULT_IV: [u8; 16] = [0u8; 16];
 
 /// Removes PKCS5 padding from the data
-fn remove_pkcs5_padding(data: &[u8]) -> Result<Vec<u8>, &'static str> {
+fn remove_pkcs5_padding(data: &[u8]) -> Result<Vec<u8>> {
     if data.is_empty() {
-        return Err("Empty data");
+        return Err(Error::crypto("Empty data"));
     }
     
-    let last_byte = *data.last().ok_or("No padding byte found")?;
+    let last_byte = *data.last().ok_or_else(|| Error::crypto("No padding byte found"))?;
     let padding_length = last_byte as usize;
     
     if padding_length == 0 || padding_length > 16 {
-        return Err("Invalid padding length");
+        return Err(Error::crypto("Invalid padding length"));
     }
     
     if data.len() < padding_length {
-        return Err("Data length smaller than padding length");
+        return Err(Error::crypto("Data length smaller than padding length"));
     }
     
     // Verify padding bytes
     let padding_start = data.len() - padding_length;
     for &byte in &data[padding_start..] {
         if byte != padding_length as u8 {
-            return Err("Invalid padding bytes");
+            return Err(Error::crypto("Invalid padding bytes"));
         }
     }
     
     Ok(data[..data.len() - padding_length].to_vec())
 }
 
+/// Pads data to a multiple of 16 bytes (PKCS5)
+fn add_pkcs5_padding(data: &[u8]) -> Vec<u8> {
+    let padding_length = 16 - data.len() % 16;
+    let mut padded = data.to_vec();
+    padded.extend(std::iter::repeat_n(padding_length as u8, padding_length));
+    padded
+}
 
-/// Decrypts data using AES-256-CBC mode with PKCS5 padding
+/// Encrypts data using AES-256-CBC mode with PKCS5 padding and custom IV
+/// 
+/// # Arguments
+/// * `key` - 32-byte encryption key
+/// * `iv` - 16-byte initialization vector (must not be reused with the same key)
+/// * `data` - Data to encrypt
+/// 
+/// # Returns
+/// * `Result<Vec<u8>>` - Encrypted data or error message
+pub fn encrypt_with_iv(key: &[u8], iv: &[u8], data: &[u8]) -> Result<Vec<u8>> {
+    if key.len() != 32 {
+        return Err(Error::crypto("Key must be 32 bytes"));
+    }
+    if iv.len() != 16 {
+        return Err(Error::crypto("IV must be 16 bytes"));
+    }
+
+    let mut cipher = Aes256::new_from_slice(key).map_err(|_| Error::crypto("Invalid key"))?;
+
+    let mut ciphertext = add_pkcs5_padding(data);
+    let mut prev_block = iv.to_vec();
+
+    for block in ciphertext.chunks_mut(16) {
+        // XOR with previous ciphertext block (or IV for first block)
+        for i in 0..16 {
+            block[i] ^= prev_block[i];
+        }
+
+        // Encrypt the block
+        let mut block_array: [u8; 16] = (&*block).try_into().unwrap();
+        cipher.encrypt_block_mut((&mut block_array).into());
+        block.copy_from_slice(&block_array);
+
+        prev_block = block.to_vec();
+    }
+
+    Ok(ciphertext)
+}
+
+/// Encrypts data using AES-256-CBC mode with PKCS5 padding and a random IV
+///
+/// # Arguments
+/// * `key` - 32-byte encryption key
+/// * `data` - Data to encrypt
+///
+/// # Returns
+/// * `Result<Vec<u8>>` - The 16-byte IV followed by the encrypted data
+pub fn encrypt(key: &[u8], data: &[u8]) -> Result<Vec<u8>> {
+    let mut iv = [0u8; 16];
+    rand::thread_rng().fill_bytes(&mut iv);
+    let mut encrypted = iv.to_vec();
+    encrypted.extend(encrypt_with_iv(key, &iv, data)?);
+    Ok(encrypted)
+}
+
+/// Decrypts the output of `encrypt`
+///
+/// # Arguments
+/// * `key` - 32-byte decryption key
+/// * `data` - The 16-byte IV followed by the encrypted data
+///
+/// # Returns
+/// * `Result<Vec<u8>>` - Decrypted data or error message
+pub fn decrypt_prefixed(key: &[u8], data: &[u8]) -> Result<Vec<u8>> {
+    if data.len() < 32 {
+        return Err(Error::crypto("Data too short for an IV and a block"));
+    }
+    decrypt_with_iv(key, &data[..16], &data[16..])
+}
+
+/// Decrypts data using AES-256-CBC mode with PKCS5 padding and the all-zero
+/// IV of the `*_ENCRYPTED` variables
 /// 
 /// # Arguments
 /// * `key` - 32-byte decryption key
 /// * `data` - Data to decrypt (must be multiple of 16 bytes)
 /// 
 /// # Returns
-/// * `Result<Vec<u8>, &'static str>` - Decrypted data or error message
-pub fn decrypt(key: &[u8], data: &[u8]) -> Result<Vec<u8>, &'static str> {
+/// * `Result<Vec<u8>>` - Decrypted data or error message
+pub fn decrypt(key: &[u8], data: &[u8]) -> Result<Vec<u8>> {
     decrypt_with_iv(key, &DEFAULT_IV, data)
 }
 
@@ -53,19 +134,19 @@ pub fn decrypt(key: &[u8], data: &[u8]) -> Result<Vec<u8>, &'static str> {
 /// * `data` - Data to decrypt (must be multiple of 16 bytes)
 /// 
 /// # Returns
-/// * `Result<Vec<u8>, &'static str>` - Decrypted data or error message
-pub fn decrypt_with_iv(key: &[u8], iv: &[u8], data: &[u8]) -> Result<Vec<u8>, &'static str> {
+/// * `Result<Vec<u8>>` - Decrypted data or error message
+pub fn decrypt_with_iv(key: &[u8], iv: &[u8], data: &[u8]) -> Result<Vec<u8>> {
     if key.len() != 32 {
-        return Err("Key must be 32 bytes");
+        return Err(Error::crypto("Key must be 32 bytes"));
     }
     if iv.len() != 16 {
-        return Err("IV must be 16 bytes");
+        return Err(Error::crypto("IV must be 16 bytes"));
     }
-    if data.len() % 16 != 0 {
-        return Err("Data length must be multiple of 16 bytes");
+    if !data.len().is_multiple_of(16) {
+        return Err(Error::crypto("Data length must be multiple of 16 bytes"));
     }
 
-    let mut cipher = Aes256::new_from_slice(key).map_err(|_| "Invalid key")?;
+    let mut cipher = Aes256::new_from_slice(key).map_err(|_| Error::crypto("Invalid key"))?;
     
     let mut plaintext = data.to_vec();
     let blocks = plaintext.chunks_mut(16);
@@ -93,3 +174,25 @@ pub fn decrypt_with_iv(key: &[u8], iv: &[u8], data: &[u8]) -> Result<Vec<u8>, &'
     // Remove PKCS5 padding
     remove_pkcs5_padding(&plaintext)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_encrypt_decrypt_roundtrip() {
+        let key = [7u8; 32];
+        let iv = [9u8; 16];
+        for plaintext in [&b""[..], b"secret", b"exactly 16 bytes"] {
+            let ciphertext = encrypt_with_iv(&key, &iv, plaintext).unwrap();
+            assert_eq!(ciphertext.len() % 16, 0);
+            assert_eq!(decrypt_with_iv(&key, &iv, &ciphertext).unwrap(), plaintext);
+
+            let prefixed = encrypt(&key, plaintext).unwrap();
+            assert_eq!(decrypt_prefixed(&key, &prefixed).unwrap(), plaintext);
+        }
+        // Every encryption draws a new IV
+        assert_ne!(encrypt(&key, b"secret").unwrap()[..16], encrypt(&key, b"secret").unwrap()[..16]);
+        assert!(decrypt_prefixed(&key, &[0u8; 16]).is_err());
+    }
+}
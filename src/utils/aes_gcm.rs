@@ -0,0 +1,90 @@
+use aes_gcm::aead::{Aead, KeyInit};
+use aes_gcm::{Aes256Gcm, Nonce};
+use rand::RngCore;
+
+use crate::error::{Error, Result};
+
+/// Length of the nonce in front of the ciphertext
+pub const NONCE_LEN: usize = 12;
+/// Length of the authentication tag at the end of the ciphertext
+pub const TAG_LEN: usize = 16;
+
+/// Encrypts and authenticates data using AES-256-GCM with a random nonce
+///
+/// # Arguments
+/// * `key` - 32-byte encryption key
+/// * `data` - Data to encrypt
+///
+/// # Returns
+/// * `Result<Vec<u8>>` - The 12-byte nonce, the encrypted data
+///   and the 16-byte tag
+pub fn encrypt(key: &[u8], data: &[u8]) -> Result<Vec<u8>> {
+    let mut nonce = [0u8; NONCE_LEN];
+    rand::thread_rng().fill_bytes(&mut nonce);
+    let mut encrypted = nonce.to_vec();
+    encrypted.extend(encrypt_with_nonce(key, &nonce, data)?);
+    Ok(encrypted)
+}
+
+/// Encrypts and authenticates data using AES-256-GCM with a custom nonce
+///
+/// # Arguments
+/// * `key` - 32-byte encryption key
+/// * `nonce` - 12-byte nonce (must never be reused with the same key)
+/// * `data` - Data to encrypt
+///
+/// # Returns
+/// * `Result<Vec<u8>>` - The encrypted data followed by the 16-byte tag
+pub fn encrypt_with_nonce(key: &[u8], nonce: &[u8], data: &[u8]) -> Result<Vec<u8>> {
+    if nonce.len() != NONCE_LEN {
+        return Err(Error::crypto("Nonce must be 12 bytes"));
+    }
+    cipher(key)?.encrypt(Nonce::from_slice(nonce), data).map_err(|_| Error::crypto("Encryption failed"))
+}
+
+/// Decrypts the output of `encrypt`, rejecting data that was modified
+///
+/// # Arguments
+/// * `key` - 32-byte decryption key
+/// * `data` - The 12-byte nonce, the encrypted data and the 16-byte tag
+///
+/// # Returns
+/// * `Result<Vec<u8>>` - Decrypted data or error message
+pub fn decrypt(key: &[u8], data: &[u8]) -> Result<Vec<u8>> {
+    if data.len() < NONCE_LEN + TAG_LEN {
+        return Err(Error::crypto("Data too short for a nonce and a tag"));
+    }
+    let (nonce, ciphertext) = data.split_at(NONCE_LEN);
+    cipher(key)?
+        .decrypt(Nonce::from_slice(nonce), ciphertext)
+        .map_err(|_| Error::crypto("Authentication failed: wrong key or tampered data"))
+}
+
+fn cipher(key: &[u8]) -> Result<Aes256Gcm> {
+    if key.len() != 32 {
+        return Err(Error::crypto("Key must be 32 bytes"));
+    }
+    Aes256Gcm::new_from_slice(key).map_err(|_| Error::crypto("Invalid key"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_encrypt_decrypt_detects_tampering() {
+        let key = [7u8; 32];
+        for plaintext in [&b""[..], b"secret", b"longer than one sixteen byte block"] {
+            let encrypted = encrypt(&key, plaintext).unwrap();
+            assert_eq!(encrypted.len(), NONCE_LEN + plaintext.len() + TAG_LEN);
+            assert_eq!(decrypt(&key, &encrypted).unwrap(), plaintext);
+        }
+
+        let mut encrypted = encrypt(&key, b"secret").unwrap();
+        assert!(decrypt(&[8u8; 32], &encrypted).is_err());
+        encrypted[NONCE_LEN] ^= 1;
+        assert_eq!(decrypt(&key, &encrypted).unwrap_err().kind(), "crypto");
+        assert!(decrypt(&key, &[0u8; NONCE_LEN]).is_err());
+        assert!(encrypt_with_nonce(&key, &[0u8; 16], b"secret").is_err());
+    }
+}
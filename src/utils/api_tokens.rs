@@ -0,0 +1,153 @@
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex, OnceLock};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+use log::error;
+
+use crate::utils::clock::{self, Clock};
+use crate::utils::hash::sha256_hex;
+use crate::utils::webhook_secrets::generate_secret;
+use crate::utils::{state, state_store};
+
+/// What an API token may do; each role includes the ones before it
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "kebab-case")]
+pub enum Role {
+    /// Reading jobs, the dead-letter queue and backport graphs
+    ReadOnly,
+    /// Also pausing, disabling and resuming repositories and requeueing jobs
+    Operator,
+    /// Also onboarding repositories, rotating secrets and managing tokens
+    Admin,
+}
+
+impl Role {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Role::ReadOnly => "read-only",
+            Role::Operator => "operator",
+            Role::Admin => "admin",
+        }
+    }
+}
+
+/// A named API token; only the SHA-256 of the token is kept
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, ToSchema)]
+pub struct ApiToken {
+    pub name: String,
+    pub role: Role,
+    #[serde(skip_serializing_if = "String::is_empty")]
+    pub hash: String,
+    pub created_at: DateTime<Utc>,
+}
+
+impl ApiToken {
+    /// The token without its hash, for listings
+    pub fn redacted(&self) -> ApiToken {
+        ApiToken { hash: String::new(), ..self.clone() }
+    }
+}
+
+/// API tokens persisted as JSON in `api_tokens.json` under the state directory
+pub struct TokenStore {
+    path: PathBuf,
+    tokens: Vec<ApiToken>,
+    clock: Arc<dyn Clock>,
+}
+
+impl TokenStore {
+    pub fn open(path: &Path) -> TokenStore {
+        let tokens = match state_store::read_document(path) {
+            Ok(Some(contents)) => serde_json::from_str(&contents).unwrap_or_else(|e| {
+                error!("Failed to parse API tokens {:?}, starting empty: {}", path, e);
+                Vec::new()
+            }),
+            _ => Vec::new(),
+        };
+        TokenStore { path: path.to_path_buf(), tokens, clock: clock::system() }
+    }
+
+    /// Uses `clock` to date new tokens (a `MockClock` in tests)
+    pub fn with_clock(mut self, clock: Arc<dyn Clock>) -> Self {
+        self.clock = clock;
+        self
+    }
+
+    fn save(&self) {
+        let result = serde_json::to_string_pretty(&self.tokens)
+            .map_err(std::io::Error::from)
+            .and_then(|contents| state_store::write_document(&self.path, &contents));
+        if let Err(e) = result {
+            error!("Failed to persist API tokens {:?}: {}", self.path, e);
+        }
+    }
+
+    /// Creates a token named `name`, returning its only plain-text copy
+    pub fn create(&mut self, name: &str, role: Role) -> Result<String, String> {
+        let name = name.trim();
+        if name.is_empty() {
+            return Err("Token name must not be empty".to_string());
+        }
+        if self.tokens.iter().any(|token| token.name == name) {
+            return Err(format!("Token {} already exists", name));
+        }
+        let secret = generate_secret();
+        self.tokens.push(ApiToken { name: name.to_string(), role, hash: sha256_hex(&secret), created_at: self.clock.now() });
+        self.save();
+        Ok(secret)
+    }
+
+    /// Deletes the token named `name`; returns whether it existed
+    pub fn revoke(&mut self, name: &str) -> bool {
+        let before = self.tokens.len();
+        self.tokens.retain(|token| token.name != name);
+        let revoked = self.tokens.len() != before;
+        if revoked {
+            self.save();
+        }
+        revoked
+    }
+
+    /// The token whose secret is `secret`
+    pub fn authenticate(&self, secret: &str) -> Option<ApiToken> {
+        let hash = sha256_hex(secret);
+        self.tokens.iter().find(|token| token.hash == hash).cloned()
+    }
+
+    /// Every token, without hashes
+    pub fn list(&self) -> Vec<ApiToken> {
+        self.tokens.iter().map(ApiToken::redacted).collect()
+    }
+}
+
+/// The process-wide token store
+pub fn store() -> &'static Mutex<TokenStore> {
+    static STORE: OnceLock<Mutex<TokenStore>> = OnceLock::new();
+    STORE.get_or_init(|| Mutex::new(TokenStore::open(&state::state_dir().join("api_tokens.json"))))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_tokens_are_hashed_and_revocable() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("api_tokens.json");
+        let mut store = TokenStore::open(&path);
+        let secret = store.create("ci", Role::Operator).unwrap();
+        assert!(store.create("ci", Role::Admin).is_err());
+        assert!(!std::fs::read_to_string(&path).unwrap().contains(&secret));
+
+        let store = TokenStore::open(&path);
+        assert_eq!(store.authenticate(&secret).map(|token| token.role), Some(Role::Operator));
+        assert_eq!(store.authenticate("guess"), None);
+        assert!(store.list()[0].hash.is_empty());
+        assert!(Role::Admin > Role::Operator && Role::Operator > Role::ReadOnly);
+
+        let mut store = store;
+        assert!(store.revoke("ci"));
+        assert_eq!(store.authenticate(&secret), None);
+    }
+}
@@ -0,0 +1,157 @@
+use std::env;
+use std::fs::{self, File};
+use std::io;
+use std::path::{Path, PathBuf};
+use log::{info, error};
+
+/// Default upper bound of the uncompressed data added to one archive
+const DEFAULT_MAX_BYTES: u64 = 200 * 1024 * 1024;
+
+/// Returns the artifacts directory, taken from `ARTIFACTS_DIR` or defaulting to `artifacts`
+pub fn artifacts_dir() -> PathBuf {
+    env::var("ARTIFACTS_DIR")
+        .map(PathBuf::from)
+        .unwrap_or_else(|_| PathBuf::from("artifacts"))
+}
+
+/// Size budget from `ARCHIVE_MAX_BYTES`
+fn max_bytes() -> u64 {
+    env::var("ARCHIVE_MAX_BYTES")
+        .ok()
+        .and_then(|value| value.parse().ok())
+        .unwrap_or(DEFAULT_MAX_BYTES)
+}
+
+/// Result of archiving a workspace
+#[derive(Debug)]
+pub struct WorkspaceArchive {
+    pub path: PathBuf,
+    pub files: usize,
+    /// Set when files were left out to stay within the size budget
+    pub truncated: bool,
+}
+
+/// Lists the regular files below `root`, sorted by path
+fn list_files(root: &Path) -> io::Result<Vec<PathBuf>> {
+    let mut files = Vec::new();
+    let mut pending = vec![root.to_path_buf()];
+    while let Some(dir) = pending.pop() {
+        for entry in fs::read_dir(&dir)? {
+            let entry = entry?;
+            let file_type = entry.file_type()?;
+            if file_type.is_dir() {
+                pending.push(entry.path());
+            } else if file_type.is_file() {
+                files.push(entry.path());
+            }
+        }
+    }
+    files.sort();
+    Ok(files)
+}
+
+/// Packs `workspace` into `<artifacts_dir>/<name>.tar.zst`
+///
+/// The git metadata (`.git`) is added first so the archive always holds a
+/// reproducible repository state; files that would push the uncompressed
+/// total over `max_bytes` are skipped and the archive is marked truncated.
+pub fn archive_workspace(
+    workspace: &Path,
+    artifacts_dir: &Path,
+    name: &str,
+    max_bytes: u64,
+) -> io::Result<WorkspaceArchive> {
+    fs::create_dir_all(artifacts_dir)?;
+    let path = artifacts_dir.join(format!("{}.tar.zst", name));
+    info!("Archiving workspace {:?} to {:?}", workspace, path);
+
+    let mut files = list_files(workspace)?;
+    let git_dir = workspace.join(".git");
+    files.sort_by_key(|file| !file.starts_with(&git_dir));
+
+    let encoder = zstd::Encoder::new(File::create(&path)?, 0)?;
+    let mut builder = tar::Builder::new(encoder);
+    let root = workspace.file_name().map(PathBuf::from).unwrap_or_default();
+
+    let mut total = 0u64;
+    let mut count = 0;
+    let mut truncated = false;
+    for file in files {
+        let size = fs::metadata(&file)?.len();
+        if total + size > max_bytes {
+            truncated = true;
+            continue;
+        }
+        let relative = file.strip_prefix(workspace).unwrap_or(&file);
+        builder.append_path_with_name(&file, root.join(relative))?;
+        total += size;
+        count += 1;
+    }
+
+    builder.into_inner()?.finish()?;
+    if truncated {
+        info!("Workspace archive {:?} truncated at {} bytes", path, total);
+    }
+    Ok(WorkspaceArchive { path, files: count, truncated })
+}
+
+/// Archives the workspace of a failed job when `ARCHIVE_FAILED_WORKSPACES` is enabled
+///
+/// Returns the archive path, or `None` when archival is disabled, the
+/// workspace does not exist (e.g. removed under the `always` cleanup policy)
+/// or archiving failed.
+pub fn archive_failed_workspace(workspace: &Path, job_id: &str) -> Option<PathBuf> {
+    if !crate::api::platform::env_flag("ARCHIVE_FAILED_WORKSPACES", false) || !workspace.exists() {
+        return None;
+    }
+    match archive_workspace(workspace, &artifacts_dir(), job_id, max_bytes()) {
+        Ok(archive) => Some(archive.path),
+        Err(e) => {
+            error!("Failed to archive workspace {:?}: {}", workspace, e);
+            None
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entries(archive: &Path) -> Vec<String> {
+        let decoder = zstd::Decoder::new(File::open(archive).unwrap()).unwrap();
+        let mut archive = tar::Archive::new(decoder);
+        archive
+            .entries()
+            .unwrap()
+            .map(|entry| entry.unwrap().path().unwrap().to_string_lossy().to_string())
+            .collect()
+    }
+
+    #[test]
+    fn test_archive_workspace_roundtrip() {
+        let dir = tempfile::tempdir().unwrap();
+        let workspace = dir.path().join("repo");
+        fs::create_dir_all(workspace.join(".git")).unwrap();
+        fs::create_dir_all(workspace.join("src")).unwrap();
+        fs::write(workspace.join(".git/HEAD"), "ref: refs/heads/main\n").unwrap();
+        fs::write(workspace.join("src/lib.rs"), "fn main() {}\n").unwrap();
+
+        let archive = archive_workspace(&workspace, &dir.path().join("artifacts"), "job", 1024).unwrap();
+        assert_eq!(archive.files, 2);
+        assert!(!archive.truncated);
+        assert_eq!(entries(&archive.path), vec!["repo/.git/HEAD", "repo/src/lib.rs"]);
+    }
+
+    #[test]
+    fn test_archive_workspace_respects_size_budget() {
+        let dir = tempfile::tempdir().unwrap();
+        let workspace = dir.path().join("repo");
+        fs::create_dir_all(workspace.join(".git")).unwrap();
+        fs::write(workspace.join(".git/HEAD"), "ref: refs/heads/main\n").unwrap();
+        fs::write(workspace.join("big.bin"), vec![0u8; 4096]).unwrap();
+
+        let archive = archive_workspace(&workspace, &dir.path().join("artifacts"), "job", 1024).unwrap();
+        assert!(archive.truncated);
+        assert_eq!(entries(&archive.path), vec!["repo/.git/HEAD"]);
+    }
+}
@@ -0,0 +1,94 @@
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex, OnceLock};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+use log::{error, info};
+
+use crate::utils::clock::{self, Clock};
+use crate::utils::{state, state_store};
+
+/// Number of most recent entries kept in the audit log
+const MAX_ENTRIES: usize = 5000;
+
+/// One request to an authenticated endpoint
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, ToSchema)]
+pub struct AuditEntry {
+    pub at: DateTime<Utc>,
+    /// Name of the acting token
+    pub token: String,
+    pub role: String,
+    pub method: String,
+    pub path: String,
+    /// False when the token's role does not allow the endpoint
+    pub allowed: bool,
+    /// Status of the response; absent on entries written before the outcome was recorded
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub status: Option<u16>,
+}
+
+/// Admin API audit log persisted as JSON in `audit.json` under the state directory
+pub struct AuditLog {
+    path: PathBuf,
+    entries: Vec<AuditEntry>,
+    clock: Arc<dyn Clock>,
+}
+
+impl AuditLog {
+    pub fn open(path: &Path) -> AuditLog {
+        let entries = match state_store::read_document(path) {
+            Ok(Some(contents)) => serde_json::from_str(&contents).unwrap_or_else(|e| {
+                error!("Failed to parse audit log {:?}, starting empty: {}", path, e);
+                Vec::new()
+            }),
+            _ => Vec::new(),
+        };
+        AuditLog { path: path.to_path_buf(), entries, clock: clock::system() }
+    }
+
+    /// Uses `clock` to date entries (a `MockClock` in tests)
+    pub fn with_clock(mut self, clock: Arc<dyn Clock>) -> Self {
+        self.clock = clock;
+        self
+    }
+
+    fn save(&self) {
+        let result = serde_json::to_string_pretty(&self.entries)
+            .map_err(std::io::Error::from)
+            .and_then(|contents| state_store::write_document(&self.path, &contents));
+        if let Err(e) = result {
+            error!("Failed to persist audit log {:?}: {}", self.path, e);
+        }
+    }
+
+    /// Records a request by `token` and the status it was answered with, also
+    /// writing it to the `audit` log target
+    pub fn record(&mut self, token: &str, role: &str, method: &str, path: &str, allowed: bool, status: u16) {
+        info!(target: "audit", "{} ({}) {} {} -> {}{}", token, role, method, path, status, if allowed { "" } else { " denied" });
+        self.entries.push(AuditEntry {
+            at: self.clock.now(),
+            token: token.to_string(),
+            role: role.to_string(),
+            method: method.to_string(),
+            path: path.to_string(),
+            allowed,
+            status: Some(status),
+        });
+        if self.entries.len() > MAX_ENTRIES {
+            let excess = self.entries.len() - MAX_ENTRIES;
+            self.entries.drain(..excess);
+        }
+        self.save();
+    }
+
+    /// Returns up to `limit` entries, most recent first
+    pub fn recent(&self, limit: usize) -> Vec<AuditEntry> {
+        self.entries.iter().rev().take(limit).cloned().collect()
+    }
+}
+
+/// The process-wide audit log
+pub fn log() -> &'static Mutex<AuditLog> {
+    static LOG: OnceLock<Mutex<AuditLog>> = OnceLock::new();
+    LOG.get_or_init(|| Mutex::new(AuditLog::open(&state::state_dir().join("audit.json"))))
+}
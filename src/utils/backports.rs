@@ -0,0 +1,264 @@
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use std::sync::{Mutex, OnceLock};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+use serde_json::{json, Value};
+use uuid::Uuid;
+use log::error;
+
+use crate::utils::{state, state_store};
+
+/// One commit cherry-picked onto a maintenance branch
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct BackportRecord {
+    pub platform: String,
+    /// Namespace of `repo`; empty in records written before it was kept
+    #[serde(default)]
+    pub namespace: String,
+    pub repo: String,
+    /// Number of the PR that requested the backport
+    pub source_pr: u32,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub source_url: Option<String>,
+    pub branch: String,
+    pub original_commit: String,
+    pub backport_commit: String,
+    /// Tip of `branch` on the remote right after the push, when it was verified
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub verified_tip: Option<String>,
+    pub job_id: Uuid,
+    pub created_at: DateTime<Utc>,
+}
+
+/// The backport mapping DB: which commit landed where, persisted as JSON in `backports.json`
+pub struct BackportStore {
+    path: PathBuf,
+    records: Vec<BackportRecord>,
+}
+
+impl BackportStore {
+    pub fn open(path: &Path) -> BackportStore {
+        let records = match state_store::read_document(path) {
+            Ok(Some(contents)) => serde_json::from_str(&contents).unwrap_or_else(|e| {
+                error!("Failed to parse backport mappings {:?}, starting empty: {}", path, e);
+                Vec::new()
+            }),
+            _ => Vec::new(),
+        };
+        BackportStore { path: path.to_path_buf(), records }
+    }
+
+    fn save(&self) {
+        let result = serde_json::to_string_pretty(&self.records)
+            .map_err(std::io::Error::from)
+            .and_then(|contents| state_store::write_document(&self.path, &contents));
+        if let Err(e) = result {
+            error!("Failed to persist backport mappings {:?}: {}", self.path, e);
+        }
+    }
+
+    /// Records the commits of one job once they are pushed
+    pub fn record_all(&mut self, records: Vec<BackportRecord>) {
+        if records.is_empty() {
+            return;
+        }
+        self.records.extend(records);
+        self.save();
+    }
+
+    /// Records of PR `source_pr` of `namespace/repo`; records without a
+    /// namespace match any
+    fn of_pr<'a>(&'a self, platform: &'a str, namespace: &'a str, repo: &'a str, source_pr: u32) -> impl Iterator<Item = &'a BackportRecord> {
+        self.records.iter().filter(move |record| {
+            record.platform == platform
+                && (record.namespace.is_empty() || record.namespace.eq_ignore_ascii_case(namespace))
+                && record.repo == repo
+                && record.source_pr == source_pr
+        })
+    }
+
+    /// Whether `commit` of PR `source_pr` was already backported onto `branch`
+    pub fn contains(&self, platform: &str, namespace: &str, repo: &str, source_pr: u32, branch: &str, commit: &str) -> bool {
+        self.of_pr(platform, namespace, repo, source_pr)
+            .any(|record| record.branch == branch && record.original_commit == commit)
+    }
+
+    /// Branches PR `source_pr` was backported onto, in order
+    pub fn branches_of(&self, platform: &str, namespace: &str, repo: &str, source_pr: u32) -> Vec<String> {
+        let mut branches: Vec<String> = Vec::new();
+        for record in self.of_pr(platform, namespace, repo, source_pr) {
+            if !branches.contains(&record.branch) {
+                branches.push(record.branch.clone());
+            }
+        }
+        branches
+    }
+
+    /// Backports of `repo`, oldest first
+    pub fn for_repo(&self, repo: &str) -> Vec<&BackportRecord> {
+        self.records.iter().filter(|record| record.repo == repo).collect()
+    }
+}
+
+/// The process-wide backport mapping DB
+pub fn store() -> &'static Mutex<BackportStore> {
+    static STORE: OnceLock<Mutex<BackportStore>> = OnceLock::new();
+    STORE.get_or_init(|| Mutex::new(BackportStore::open(&state::state_dir().join("backports.json"))))
+}
+
+/// Graph of the backports of one repository
+///
+/// Nodes are source PRs and backport commits; a PR points to the commits it
+/// produced on each branch, and a backport commit points to the commits that
+/// were cherry-picked from it in turn (cascades).
+#[derive(Debug, Default, Serialize, ToSchema)]
+pub struct BackportGraph {
+    pub nodes: Vec<GraphNode>,
+    pub edges: Vec<GraphEdge>,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct GraphNode {
+    pub id: String,
+    pub kind: &'static str,
+    pub label: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub branch: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub url: Option<String>,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct GraphEdge {
+    pub from: String,
+    pub to: String,
+    pub branch: String,
+}
+
+fn short(sha: &str) -> &str {
+    &sha[..sha.len().min(8)]
+}
+
+impl BackportGraph {
+    pub fn build(records: &[&BackportRecord]) -> BackportGraph {
+        let mut graph = BackportGraph::default();
+        let mut prs: BTreeMap<u32, Option<String>> = BTreeMap::new();
+        for record in records {
+            let entry = prs.entry(record.source_pr).or_default();
+            if entry.is_none() {
+                *entry = record.source_url.clone();
+            }
+        }
+        for (number, url) in prs {
+            graph.nodes.push(GraphNode { id: format!("pr-{}", number), kind: "pr", label: format!("PR #{}", number), branch: None, url });
+        }
+
+        let backport_commits: BTreeMap<&str, &BackportRecord> =
+            records.iter().map(|record| (record.backport_commit.as_str(), *record)).collect();
+        for record in records {
+            let id = format!("commit-{}", record.backport_commit);
+            graph.nodes.push(GraphNode {
+                id: id.clone(),
+                kind: "commit",
+                label: format!("{} @ {}", short(&record.backport_commit), record.branch),
+                branch: Some(record.branch.clone()),
+                url: None,
+            });
+            // A commit picked from an earlier backport continues that chain
+            let from = match backport_commits.get(record.original_commit.as_str()) {
+                Some(parent) => format!("commit-{}", parent.backport_commit),
+                None => format!("pr-{}", record.source_pr),
+            };
+            graph.edges.push(GraphEdge { from, to: id, branch: record.branch.clone() });
+        }
+        graph
+    }
+
+    pub fn to_json(&self) -> Value {
+        json!(self)
+    }
+
+    /// Graphviz rendering, one cluster per branch
+    pub fn to_dot(&self, repo: &str) -> String {
+        let quote = |value: &str| format!("\"{}\"", value.replace('\\', "\\\\").replace('"', "\\\""));
+        let mut output = format!("digraph {} {{\n  rankdir=LR;\n", quote(&format!("backports of {}", repo)));
+        let mut branches: BTreeMap<&str, Vec<&GraphNode>> = BTreeMap::new();
+        for node in &self.nodes {
+            match &node.branch {
+                Some(branch) => branches.entry(branch).or_default().push(node),
+                None => output.push_str(&format!("  {} [label={}, shape=box];\n", quote(&node.id), quote(&node.label))),
+            }
+        }
+        for (index, (branch, nodes)) in branches.iter().enumerate() {
+            output.push_str(&format!("  subgraph cluster_{} {{\n    label={};\n", index, quote(branch)));
+            for node in nodes {
+                output.push_str(&format!("    {} [label={}];\n", quote(&node.id), quote(&node.label)));
+            }
+            output.push_str("  }\n");
+        }
+        for edge in &self.edges {
+            output.push_str(&format!("  {} -> {};\n", quote(&edge.from), quote(&edge.to)));
+        }
+        output.push_str("}\n");
+        output
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn record(pr: u32, branch: &str, original: &str, backport: &str) -> BackportRecord {
+        BackportRecord {
+            platform: "github".to_string(),
+            namespace: "org".to_string(),
+            repo: "repo".to_string(),
+            source_pr: pr,
+            source_url: Some(format!("https://github.com/org/repo/pull/{}", pr)),
+            branch: branch.to_string(),
+            original_commit: original.to_string(),
+            backport_commit: backport.to_string(),
+            verified_tip: None,
+            job_id: Uuid::nil(),
+            created_at: Utc::now(),
+        }
+    }
+
+    #[test]
+    fn test_graph_links_cascades() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("backports.json");
+        let mut store = BackportStore::open(&path);
+        store.record_all(vec![record(1, "release-2", "aaaa", "bbbb"), record(1, "release-1", "aaaa", "cccc")]);
+        // PR 2 backports the release-2 commit onwards to release-1.5
+        store.record_all(vec![record(2, "release-1.5", "bbbb", "dddd")]);
+
+        let reopened = BackportStore::open(&path);
+        let graph = BackportGraph::build(&reopened.for_repo("repo"));
+        assert_eq!(graph.nodes.len(), 5);
+        let into = |id: &str| graph.edges.iter().find(|edge| edge.to == id).unwrap().from.clone();
+        assert_eq!(into("commit-bbbb"), "pr-1");
+        assert_eq!(into("commit-dddd"), "commit-bbbb");
+
+        let dot = graph.to_dot("repo");
+        assert!(dot.contains("\"commit-bbbb\" -> \"commit-dddd\";"));
+        assert!(dot.contains("label=\"release-1.5\""));
+        assert!(BackportGraph::build(&reopened.for_repo("other")).nodes.is_empty());
+    }
+
+    #[test]
+    fn test_lookups_are_scoped_to_the_namespace() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut store = BackportStore::open(&dir.path().join("backports.json"));
+        store.record_all(vec![record(1, "release-1", "aaaa", "bbbb")]);
+        assert!(store.contains("github", "org", "repo", 1, "release-1", "aaaa"));
+        assert!(!store.contains("github", "other-org", "repo", 1, "release-1", "aaaa"));
+        assert!(store.branches_of("github", "other-org", "repo", 1).is_empty());
+
+        let legacy = BackportRecord { namespace: String::new(), ..record(2, "release-1", "cccc", "dddd") };
+        store.record_all(vec![legacy]);
+        assert_eq!(store.branches_of("github", "other-org", "repo", 2), vec!["release-1"]);
+    }
+}
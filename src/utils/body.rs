@@ -0,0 +1,89 @@
+use std::fs::File;
+use std::io::{self, BufReader, Read};
+use tempfile::NamedTempFile;
+use hmac::{Hmac, Mac};
+use sha2::Sha256;
+
+use crate::utils::parser;
+
+/// A verified webhook body
+///
+/// Small bodies are kept in memory. Push bodies can be tens of MB for history
+/// imports; they are spooled to a temporary file while they are received and
+/// parsed straight from it, so the full text is only loaded when it has to be
+/// stored (dead-letter queue, held events) or forwarded to the HA standby.
+#[derive(Debug)]
+pub enum WebhookBody {
+    Memory(String),
+    Spooled(NamedTempFile),
+}
+
+impl From<String> for WebhookBody {
+    fn from(body: String) -> Self {
+        WebhookBody::Memory(body)
+    }
+}
+
+impl WebhookBody {
+    /// A buffered reader over the body
+    pub fn reader(&self) -> io::Result<Box<dyn Read + '_>> {
+        match self {
+            WebhookBody::Memory(body) => Ok(Box::new(body.as_bytes())),
+            WebhookBody::Spooled(file) => Ok(Box::new(BufReader::new(File::open(file.path())?))),
+        }
+    }
+
+    /// The whole body as a string
+    pub fn text(&self) -> io::Result<String> {
+        match self {
+            WebhookBody::Memory(body) => Ok(body.clone()),
+            WebhookBody::Spooled(file) => std::fs::read_to_string(file.path()),
+        }
+    }
+
+    /// Feeds the body to `mac` without loading it
+    pub fn mac<M: Mac>(&self, mut mac: M) -> io::Result<M> {
+        let mut reader = self.reader()?;
+        let mut buf = [0u8; 64 * 1024];
+        loop {
+            match reader.read(&mut buf)? {
+                0 => break,
+                n => mac.update(&buf[..n]),
+            }
+        }
+        Ok(mac)
+    }
+
+    /// Hex HMAC-SHA256 of the body with `key`, computed without loading it
+    pub fn hmac_sha256(&self, key: &str) -> io::Result<String> {
+        let mac = Hmac::<Sha256>::new_from_slice(key.as_bytes())
+            .expect("HMAC can take key of any size");
+        Ok(hex::encode(self.mac(mac)?.finalize().into_bytes()))
+    }
+
+    /// Namespace and name of the repository the body belongs to
+    pub fn repo_identity(&self, platform: &str) -> Option<(String, String)> {
+        parser::repo_identity_reader(platform, self.reader().ok()?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Write;
+    use crate::utils::hmac::compute_hmac_sha256;
+
+    #[test]
+    fn test_spooled_body_matches_memory_body() {
+        let json = r#"{"project": {"namespace": "org"}, "repository": {"name": "repo"}}"#;
+        let mut file = NamedTempFile::new().unwrap();
+        file.write_all(json.as_bytes()).unwrap();
+        let spooled = WebhookBody::Spooled(file);
+        let memory = WebhookBody::from(json.to_string());
+
+        assert_eq!(spooled.text().unwrap(), json);
+        assert_eq!(spooled.hmac_sha256("secret").unwrap(), compute_hmac_sha256(json.as_bytes(), "secret"));
+        assert_eq!(memory.hmac_sha256("secret").unwrap(), compute_hmac_sha256(json.as_bytes(), "secret"));
+        assert_eq!(spooled.repo_identity("gitcode"), Some(("org".to_string(), "repo".to_string())));
+    }
+}
@@ -0,0 +1,284 @@
+use std::collections::BTreeMap;
+use git2::{BranchType, Repository};
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use log::info;
+
+use crate::models::webhook::Label;
+use crate::utils::templates::{CommentTemplates, MessageKind};
+
+/// Cleans up a branch name taken from a `br:` label description
+///
+/// Strips surrounding whitespace and a leading `refs/heads/`.
+pub fn normalize_branch_name(raw: &str) -> String {
+    let name = raw.trim();
+    name.strip_prefix("refs/heads/").unwrap_or(name).trim().to_string()
+}
+
+/// Checks `name` against git's ref naming rules (`git check-ref-format --branch`)
+///
+/// Returns a human-readable reason when the name is rejected.
+pub fn validate_branch_name(name: &str) -> Result<(), String> {
+    if name.is_empty() {
+        return Err("the branch name is empty".to_string());
+    }
+    if name == "@" || name == "HEAD" {
+        return Err(format!("`{}` is reserved", name));
+    }
+    if name.starts_with('-') {
+        return Err("branch names cannot start with `-`".to_string());
+    }
+    if let Some(c) = name.chars().find(|c| c.is_ascii_control() || c.is_whitespace()) {
+        return Err(format!("branch names cannot contain whitespace or control characters ({:?})", c));
+    }
+    if let Some(c) = name.chars().find(|c| matches!(c, '~' | '^' | ':' | '?' | '*' | '[' | '\\')) {
+        return Err(format!("branch names cannot contain `{}`", c));
+    }
+    for (pattern, reason) in [("..", "`..`"), ("@{", "`@{`"), ("//", "consecutive slashes")] {
+        if name.contains(pattern) {
+            return Err(format!("branch names cannot contain {}", reason));
+        }
+    }
+    if name.starts_with('/') || name.ends_with('/') || name.ends_with('.') {
+        return Err("branch names cannot start or end with `/` or end with `.`".to_string());
+    }
+    if name.split('/').any(|part| part.starts_with('.') || part.ends_with(".lock")) {
+        return Err("path components cannot start with `.` or end with `.lock`".to_string());
+    }
+    // Final word goes to libgit2 in case its rules are stricter than the above
+    if !git2::Reference::is_valid_name(&format!("refs/heads/{}", name)) {
+        return Err("git rejects it as a reference name".to_string());
+    }
+    Ok(())
+}
+
+/// Normalizes a label's branch name and validates the result
+pub fn parse_branch_label(raw: &str) -> Result<String, String> {
+    let name = normalize_branch_name(raw);
+    validate_branch_name(&name)?;
+    Ok(name)
+}
+
+/// Returns the existing branch whose name matches `name` ignoring case
+///
+/// The exact name wins; a case-insensitive match on `origin` is used only
+/// when it is unique (`Release-1.0` vs `release-1.0`).
+pub fn resolve_branch_case(repo: &Repository, name: &str) -> String {
+    if repo.find_branch(name, BranchType::Local).is_ok()
+        || repo.find_branch(&format!("origin/{}", name), BranchType::Remote).is_ok()
+    {
+        return name.to_string();
+    }
+    let candidates: Vec<String> = match repo.branches(Some(BranchType::Remote)) {
+        Ok(branches) => branches
+            .filter_map(|branch| branch.ok())
+            .filter_map(|(branch, _)| branch.name().ok().flatten().map(str::to_string))
+            .filter_map(|full| full.strip_prefix("origin/").map(str::to_string))
+            .filter(|candidate| candidate.eq_ignore_ascii_case(name))
+            .collect(),
+        Err(_) => Vec::new(),
+    };
+    match candidates.as_slice() {
+        [candidate] => {
+            info!("Resolved branch {} to existing branch {}", name, candidate);
+            candidate.clone()
+        }
+        _ => name.to_string(),
+    }
+}
+
+/// Maps version labels such as `affects: 1.2` to target branches, for teams
+/// that label PRs with affected versions instead of `br:` labels
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct VersionLabels {
+    /// Regex matched against label titles; its capture groups fill `branch`
+    pub pattern: String,
+    /// Target branch with `{name}` (named group) or `{1}` (numbered group) placeholders
+    pub branch: String,
+}
+
+impl Default for VersionLabels {
+    fn default() -> Self {
+        VersionLabels {
+            pattern: r"^affects:\s*(?P<major>\d+)\.(?P<minor>\d+)$".to_string(),
+            branch: "release-{major}.{minor}".to_string(),
+        }
+    }
+}
+
+/// Fills the `{name}` and `{1}` placeholders of `template` with the groups
+/// `pattern` captured from `title`; `None` when it does not match
+fn expand_captures(pattern: &Regex, title: &str, template: &str) -> Option<String> {
+    let captures = pattern.captures(title)?;
+    let mut branch = template.to_string();
+    for (index, name) in pattern.capture_names().enumerate() {
+        let value = captures.get(index).map(|value| value.as_str()).unwrap_or("");
+        branch = branch.replace(&format!("{{{}}}", index), value);
+        if let Some(name) = name {
+            branch = branch.replace(&format!("{{{}}}", name), value);
+        }
+    }
+    Some(branch)
+}
+
+/// Target branches of the labels matching `mapping` with the label each one
+/// comes from, in label order and without duplicate branches
+pub fn version_branches(labels: &[Label], mapping: &VersionLabels) -> Result<Vec<(String, String)>, regex::Error> {
+    let pattern = Regex::new(&mapping.pattern)?;
+    let mut branches: Vec<(String, String)> = Vec::new();
+    for label in labels {
+        let Some(branch) = expand_captures(&pattern, label.title.trim(), &mapping.branch) else { continue };
+        if !branches.iter().any(|(existing, _)| *existing == branch) {
+            branches.push((branch, label.title.clone()));
+        }
+    }
+    Ok(branches)
+}
+
+/// Compiles the regex keys (starting with `^`) of a `branch_labels` map
+pub fn branch_label_rules(branch_labels: &BTreeMap<String, String>) -> Result<Vec<(Regex, &str)>, regex::Error> {
+    branch_labels
+        .iter()
+        .filter(|(key, _)| key.starts_with('^'))
+        .map(|(key, branch)| Ok((Regex::new(key)?, branch.as_str())))
+        .collect()
+}
+
+/// Target branch configured in `branch_labels` for the label `title`: an
+/// exact title entry, else the first regex entry (key starting with `^`)
+/// that matches, its captures filling `{name}`/`{1}` in the branch
+pub fn configured_branch(title: &str, branch_labels: &BTreeMap<String, String>) -> Result<Option<String>, regex::Error> {
+    let title = title.trim();
+    if let Some(branch) = branch_labels.get(title).filter(|_| !title.starts_with('^')) {
+        return Ok(Some(branch.clone()));
+    }
+    for (pattern, branch) in branch_label_rules(branch_labels)? {
+        if let Some(branch) = expand_captures(&pattern, title, branch) {
+            return Ok(Some(branch));
+        }
+    }
+    Ok(None)
+}
+
+/// Renames a branch on the way to the target repository, e.g. `release/*` -> `rel-*`
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct BranchMapping {
+    /// Source branch name, where one `*` matches any run of characters
+    pub source: String,
+    /// Target branch name; its `*` is replaced with what the source's `*` matched
+    pub target: String,
+}
+
+impl BranchMapping {
+    /// Fails when the source has more than one `*`, or the target uses one
+    /// the source does not capture
+    pub fn validate(&self) -> Result<(), String> {
+        let wildcards = self.source.matches('*').count();
+        if wildcards > 1 {
+            return Err(format!("branch mapping {:?} has more than one `*`", self.source));
+        }
+        if wildcards == 0 && self.target.contains('*') {
+            return Err(format!("branch mapping {:?} -> {:?} uses `*` in the target only", self.source, self.target));
+        }
+        Ok(())
+    }
+}
+
+/// Name of `branch` on the target according to the first matching entry of
+/// `mappings`; unmapped branches keep their name
+pub fn map_branch(branch: &str, mappings: &[BranchMapping]) -> String {
+    for mapping in mappings {
+        let captured = match mapping.source.split_once('*') {
+            Some((prefix, suffix)) => branch
+                .strip_prefix(prefix)
+                .and_then(|rest| rest.strip_suffix(suffix))
+                .filter(|captured| !captured.is_empty()),
+            None => (mapping.source == branch).then_some(""),
+        };
+        if let Some(captured) = captured {
+            return mapping.target.replacen('*', captured, 1);
+        }
+    }
+    branch.to_string()
+}
+
+/// Renders the PR comment explaining why a `br:` label was rejected
+pub fn format_invalid_branch_comment(label: &str, reason: &str, templates: &CommentTemplates) -> String {
+    templates.render(MessageKind::InvalidBranch, &[("branch", label.trim()), ("reason", reason)])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_branch_label() {
+        assert_eq!(parse_branch_label("  refs/heads/release-1.0 \n").unwrap(), "release-1.0");
+        assert_eq!(parse_branch_label("feature/x").unwrap(), "feature/x");
+        for invalid in ["", "release 1.0", "a..b", "-x", "x.lock", "x/", "a//b", "x@{1}", "a:b", "a/.b", "HEAD"] {
+            assert!(parse_branch_label(invalid).is_err(), "{} should be rejected", invalid);
+        }
+    }
+
+    #[test]
+    fn test_version_labels_map_to_branches() {
+        let label = |title: &str| Label { title: title.to_string(), description: None, r#type: None, source: None };
+        let labels = vec![label("affects: 1.2"), label("bug"), label("affects:2.0"), label("affects: 1.2"), label("affects: 1.x")];
+        let branches = version_branches(&labels, &VersionLabels::default()).unwrap();
+        assert_eq!(branches, vec![
+            ("release-1.2".to_string(), "affects: 1.2".to_string()),
+            ("release-2.0".to_string(), "affects:2.0".to_string()),
+        ]);
+
+        let numbered = VersionLabels { pattern: r"^v(\d+)$".to_string(), branch: "stable/{1}.x".to_string() };
+        assert_eq!(version_branches(&[label("v3")], &numbered).unwrap()[0].0, "stable/3.x");
+        let invalid = VersionLabels { pattern: "(".to_string(), ..VersionLabels::default() };
+        assert!(version_branches(&labels, &invalid).is_err());
+    }
+
+    #[test]
+    fn test_configured_branch_labels() {
+        let branch_labels: BTreeMap<String, String> = [
+            ("backport-lts", "release-2.4"),
+            ("br: next", "develop"),
+            (r"^backport-(?P<major>\d+)\.x$", "release-{major}.x"),
+        ].into_iter().map(|(key, branch)| (key.to_string(), branch.to_string())).collect();
+        assert_eq!(configured_branch(" backport-lts ", &branch_labels).unwrap().as_deref(), Some("release-2.4"));
+        assert_eq!(configured_branch("br: next", &branch_labels).unwrap().as_deref(), Some("develop"));
+        assert_eq!(configured_branch("backport-3.x", &branch_labels).unwrap().as_deref(), Some("release-3.x"));
+        assert_eq!(configured_branch("backport-x.x", &branch_labels).unwrap(), None);
+
+        let invalid: BTreeMap<String, String> = [("^(".to_string(), "x".to_string())].into_iter().collect();
+        assert!(configured_branch("x", &invalid).is_err());
+    }
+
+    #[test]
+    fn test_branch_mapping_templates() {
+        let mapping = |source: &str, target: &str| BranchMapping { source: source.to_string(), target: target.to_string() };
+        let mappings = vec![mapping("release/*", "rel-*"), mapping("main", "trunk"), mapping("*-lts", "lts/*")];
+        assert_eq!(map_branch("release/1.2", &mappings), "rel-1.2");
+        assert_eq!(map_branch("main", &mappings), "trunk");
+        assert_eq!(map_branch("2.0-lts", &mappings), "lts/2.0");
+        assert_eq!(map_branch("release/", &mappings), "release/");
+        assert_eq!(map_branch("develop", &mappings), "develop");
+
+        assert!(mapping("release/*", "rel-*").validate().is_ok());
+        assert!(mapping("a/*/*", "b").validate().is_err());
+        assert!(mapping("main", "rel-*").validate().is_err());
+    }
+
+    #[test]
+    fn test_resolve_branch_case() {
+        let dir = tempfile::tempdir().unwrap();
+        let repo = Repository::init(dir.path()).unwrap();
+        let tree = repo.find_tree(repo.treebuilder(None).unwrap().write().unwrap()).unwrap();
+        let sig = git2::Signature::now("test", "test@example.com").unwrap();
+        let oid = repo.commit(None, &sig, &sig, "init", &tree, &[]).unwrap();
+        repo.reference("refs/remotes/origin/Release-1.0", oid, false, "test").unwrap();
+
+        assert_eq!(resolve_branch_case(&repo, "release-1.0"), "Release-1.0");
+        assert_eq!(resolve_branch_case(&repo, "Release-1.0"), "Release-1.0");
+        assert_eq!(resolve_branch_case(&repo, "release-2.0"), "release-2.0");
+    }
+}
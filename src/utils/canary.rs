@@ -0,0 +1,175 @@
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use std::sync::{Mutex, OnceLock};
+use serde::{Deserialize, Serialize};
+use log::error;
+
+use crate::utils::config::RepoConfig;
+use crate::utils::hash::sha256_hex;
+use crate::utils::templates::{CommentTemplates, MessageKind};
+use crate::utils::{state, state_store};
+
+/// Fingerprint of the settings of a repository that decide what is pushed
+/// where; toggling `enabled` is not a change worth a canary
+pub fn fingerprint(repo_config: &RepoConfig) -> String {
+    let mut settings = repo_config.clone();
+    settings.enabled = true;
+    sha256_hex(&serde_json::to_string(&settings).unwrap_or_default())
+}
+
+/// Canary state of one repository
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Canary {
+    /// Fingerprint of the configuration the canary runs for
+    pub fingerprint: String,
+    /// An operator confirmed the configuration, jobs push for real
+    pub confirmed: bool,
+    /// Jobs that ran as dry runs since the configuration changed
+    pub dry_runs: u32,
+}
+
+/// What a job of a repository does with its result
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CanaryDecision {
+    Push,
+    /// Report what would be pushed instead (`run` of `of`)
+    DryRun { run: u32, of: u32 },
+}
+
+/// Canary state of every repository with `canary_jobs` set, persisted as
+/// JSON in `canary.json`
+pub struct CanaryStore {
+    path: PathBuf,
+    repos: BTreeMap<String, Canary>,
+}
+
+impl CanaryStore {
+    pub fn open(path: &Path) -> CanaryStore {
+        let repos = match state_store::read_document(path) {
+            Ok(Some(contents)) => serde_json::from_str(&contents).unwrap_or_else(|e| {
+                error!("Failed to parse canary state {:?}, starting empty: {}", path, e);
+                BTreeMap::new()
+            }),
+            _ => BTreeMap::new(),
+        };
+        CanaryStore { path: path.to_path_buf(), repos }
+    }
+
+    fn save(&self) {
+        let result = serde_json::to_string_pretty(&self.repos)
+            .map_err(std::io::Error::from)
+            .and_then(|contents| state_store::write_document(&self.path, &contents));
+        if let Err(e) = result {
+            error!("Failed to persist canary state {:?}: {}", self.path, e);
+        }
+    }
+
+    /// Canary of `repo` for the configuration `fingerprint`, restarted when
+    /// the configuration changed since it was last seen
+    fn current(&mut self, repo: &str, fingerprint: &str) -> &mut Canary {
+        let canary = self.repos.entry(repo.to_string()).or_insert_with(|| Canary {
+            fingerprint: fingerprint.to_string(),
+            confirmed: false,
+            dry_runs: 0,
+        });
+        if canary.fingerprint != fingerprint {
+            *canary = Canary { fingerprint: fingerprint.to_string(), confirmed: false, dry_runs: 0 };
+        }
+        canary
+    }
+
+    /// Decides whether a job of `repo` pushes: until an operator confirms a
+    /// changed configuration, every job is a dry run
+    pub fn begin_job(&mut self, repo: &str, fingerprint: &str, canary_jobs: u32) -> CanaryDecision {
+        if canary_jobs == 0 {
+            return CanaryDecision::Push;
+        }
+        let canary = self.current(repo, fingerprint);
+        if canary.confirmed {
+            return CanaryDecision::Push;
+        }
+        canary.dry_runs += 1;
+        let run = canary.dry_runs;
+        self.save();
+        CanaryDecision::DryRun { run, of: canary_jobs }
+    }
+
+    /// Whether the `canary_jobs` dry runs of a changed configuration already
+    /// ran, so that further jobs wait for the confirmation
+    pub fn awaiting_confirmation(&self, repo: &str, fingerprint: &str, canary_jobs: u32) -> bool {
+        canary_jobs > 0
+            && self.repos.get(repo).is_some_and(|canary| {
+                canary.fingerprint == fingerprint && !canary.confirmed && canary.dry_runs >= canary_jobs
+            })
+    }
+
+    /// Confirms the configuration `fingerprint` of `repo`; false when it was
+    /// already confirmed
+    pub fn confirm(&mut self, repo: &str, fingerprint: &str) -> bool {
+        let canary = self.current(repo, fingerprint);
+        if canary.confirmed {
+            return false;
+        }
+        canary.confirmed = true;
+        self.save();
+        true
+    }
+
+    pub fn get(&self, repo: &str) -> Option<&Canary> {
+        self.repos.get(repo)
+    }
+}
+
+/// Comment reporting what a canary job would have pushed to `target`, as
+/// `(branch, commits)` pairs
+pub fn format_report(run: u32, of: u32, target: &str, branches: &[(String, usize)], templates: &CommentTemplates) -> String {
+    let branches: String = branches
+        .iter()
+        .map(|(branch, commits)| format!("- `{}`: {} commit(s)\n", branch, commits))
+        .collect();
+    templates.render(MessageKind::CanaryReport, &[
+        ("run", &run.to_string()),
+        ("of", &of.to_string()),
+        ("target", target),
+        ("branches", &branches),
+    ])
+}
+
+/// The process-wide canary state
+pub fn store() -> &'static Mutex<CanaryStore> {
+    static STORE: OnceLock<Mutex<CanaryStore>> = OnceLock::new();
+    STORE.get_or_init(|| Mutex::new(CanaryStore::open(&state::state_dir().join("canary.json"))))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_changed_configs_dry_run_until_confirmed() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("canary.json");
+        let mut repo_config = RepoConfig::new("https://gitcode.com/org/repo.git", "org", "repo");
+        repo_config.canary_jobs = 2;
+        let first = fingerprint(&repo_config);
+
+        let mut store = CanaryStore::open(&path);
+        assert_eq!(store.begin_job("repo", &first, 0), CanaryDecision::Push);
+        assert_eq!(store.begin_job("repo", &first, 2), CanaryDecision::DryRun { run: 1, of: 2 });
+        assert!(!store.awaiting_confirmation("repo", &first, 2));
+        assert_eq!(store.begin_job("repo", &first, 2), CanaryDecision::DryRun { run: 2, of: 2 });
+        assert!(store.awaiting_confirmation("repo", &first, 2));
+
+        let mut store = CanaryStore::open(&path);
+        assert!(store.confirm("repo", &first));
+        assert!(!store.confirm("repo", &first));
+        assert_eq!(store.begin_job("repo", &first, 2), CanaryDecision::Push);
+
+        repo_config.enabled = false;
+        assert_eq!(fingerprint(&repo_config), first);
+        repo_config.target_repo = "https://gitcode.com/org/rpeo.git".to_string();
+        let typo = fingerprint(&repo_config);
+        assert_eq!(store.begin_job("repo", &typo, 2), CanaryDecision::DryRun { run: 1, of: 2 });
+        assert_eq!(store.get("repo").map(|canary| canary.confirmed), Some(false));
+    }
+}
@@ -0,0 +1,122 @@
+use std::fmt;
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use chrono::{DateTime, Utc};
+
+/// Source of time for time-dependent logic (freeze windows, backoff, job timestamps)
+///
+/// Production code uses `SystemClock`; tests inject a `MockClock` to make
+/// timing deterministic.
+pub trait Clock: Send + Sync + fmt::Debug {
+    /// Wall-clock time
+    fn now(&self) -> DateTime<Utc>;
+    /// Monotonic time, for measuring elapsed durations
+    fn instant(&self) -> Instant;
+    /// Blocks the current thread for `duration`
+    fn sleep(&self, duration: Duration);
+    /// Waits for `duration` without blocking the async runtime
+    fn sleep_async(&self, duration: Duration) -> Pin<Box<dyn Future<Output = ()> + Send>>;
+}
+
+/// The real clock
+#[derive(Debug, Clone, Copy, Default)]
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> DateTime<Utc> {
+        Utc::now()
+    }
+
+    fn instant(&self) -> Instant {
+        Instant::now()
+    }
+
+    fn sleep(&self, duration: Duration) {
+        std::thread::sleep(duration);
+    }
+
+    fn sleep_async(&self, duration: Duration) -> Pin<Box<dyn Future<Output = ()> + Send>> {
+        Box::pin(tokio::time::sleep(duration))
+    }
+}
+
+/// Shared handle to the real clock
+pub fn system() -> Arc<dyn Clock> {
+    Arc::new(SystemClock)
+}
+
+#[derive(Debug)]
+struct MockState {
+    now: DateTime<Utc>,
+    instant: Instant,
+    sleeps: Vec<Duration>,
+}
+
+/// Manually driven clock for tests
+///
+/// Time only moves through `advance` or `sleep`, which returns immediately
+/// after advancing the clock and recording the requested duration. Clones
+/// share the same time.
+#[derive(Debug, Clone)]
+pub struct MockClock {
+    state: Arc<Mutex<MockState>>,
+}
+
+impl MockClock {
+    pub fn new(start: DateTime<Utc>) -> Self {
+        MockClock {
+            state: Arc::new(Mutex::new(MockState { now: start, instant: Instant::now(), sleeps: Vec::new() })),
+        }
+    }
+
+    pub fn advance(&self, duration: Duration) {
+        let mut state = self.state.lock().unwrap();
+        state.now += chrono::Duration::from_std(duration).expect("duration fits chrono");
+        state.instant += duration;
+    }
+
+    /// Durations passed to `sleep`/`sleep_async`, in order
+    pub fn sleeps(&self) -> Vec<Duration> {
+        self.state.lock().unwrap().sleeps.clone()
+    }
+}
+
+impl Clock for MockClock {
+    fn now(&self) -> DateTime<Utc> {
+        self.state.lock().unwrap().now
+    }
+
+    fn instant(&self) -> Instant {
+        self.state.lock().unwrap().instant
+    }
+
+    fn sleep(&self, duration: Duration) {
+        self.state.lock().unwrap().sleeps.push(duration);
+        self.advance(duration);
+    }
+
+    fn sleep_async(&self, duration: Duration) -> Pin<Box<dyn Future<Output = ()> + Send>> {
+        self.sleep(duration);
+        Box::pin(std::future::ready(()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_mock_clock_advances_on_sleep() {
+        let start = Utc::now();
+        let clock = MockClock::new(start);
+        let begun = clock.instant();
+        clock.sleep(Duration::from_secs(90));
+        clock.clone().advance(Duration::from_secs(30));
+
+        assert_eq!(clock.now() - start, chrono::Duration::seconds(120));
+        assert_eq!(clock.instant() - begun, Duration::from_secs(120));
+        assert_eq!(clock.sleeps(), vec![Duration::from_secs(90)]);
+    }
+}
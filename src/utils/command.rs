@@ -0,0 +1,209 @@
+use std::env;
+use std::io::{self, Read};
+use std::os::unix::process::CommandExt;
+use std::process::{Child, Command, ExitStatus, Stdio};
+use std::thread;
+use std::time::{Duration, Instant};
+use log::{info, error};
+
+/// Resource limits applied to commands executed on behalf of a job
+#[derive(Debug, Clone)]
+pub struct CommandLimits {
+    /// CPU time limit (`RLIMIT_CPU`) in seconds
+    pub cpu_seconds: Option<u64>,
+    /// Address space limit (`RLIMIT_AS`) in bytes
+    pub memory_bytes: Option<u64>,
+    /// Wall-clock timeout after which the whole process group is killed
+    pub timeout: Duration,
+    /// Bytes of stdout and of stderr kept; the rest is drained and discarded
+    pub max_output_bytes: usize,
+}
+
+impl Default for CommandLimits {
+    fn default() -> Self {
+        CommandLimits {
+            cpu_seconds: Some(600),
+            memory_bytes: Some(2 * 1024 * 1024 * 1024),
+            timeout: Duration::from_secs(900),
+            max_output_bytes: 1024 * 1024,
+        }
+    }
+}
+
+fn env_u64(name: &str) -> Option<u64> {
+    env::var(name).ok().and_then(|value| value.parse().ok())
+}
+
+impl CommandLimits {
+    /// Reads `CMD_CPU_SECONDS`, `CMD_MEMORY_BYTES`, `CMD_TIMEOUT_SECS` and
+    /// `CMD_MAX_OUTPUT_BYTES`, falling back to the defaults (`0` disables a limit)
+    pub fn from_env() -> Self {
+        let defaults = CommandLimits::default();
+        let limit = |name, default: Option<u64>| match env_u64(name) {
+            Some(0) => None,
+            Some(value) => Some(value),
+            None => default,
+        };
+        CommandLimits {
+            cpu_seconds: limit("CMD_CPU_SECONDS", defaults.cpu_seconds),
+            memory_bytes: limit("CMD_MEMORY_BYTES", defaults.memory_bytes),
+            timeout: env_u64("CMD_TIMEOUT_SECS").map(Duration::from_secs).unwrap_or(defaults.timeout),
+            max_output_bytes: env_u64("CMD_MAX_OUTPUT_BYTES")
+                .map(|value| value as usize)
+                .unwrap_or(defaults.max_output_bytes),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct CommandOutput {
+    /// Exit status, `None` when the command was killed for exceeding its timeout
+    pub status: Option<ExitStatus>,
+    pub stdout: String,
+    pub stderr: String,
+    /// Set when stdout or stderr exceeded `max_output_bytes`
+    pub truncated: bool,
+    pub timed_out: bool,
+}
+
+impl CommandOutput {
+    pub fn success(&self) -> bool {
+        self.status.map(|status| status.success()).unwrap_or(false)
+    }
+}
+
+/// Reads a stream to the end on a separate thread, keeping at most `max` bytes
+fn capture<R: Read + Send + 'static>(stream: Option<R>, max: usize) -> thread::JoinHandle<(Vec<u8>, bool)> {
+    thread::spawn(move || {
+        let mut kept = Vec::new();
+        let mut truncated = false;
+        if let Some(mut stream) = stream {
+            let mut buf = [0u8; 8192];
+            while let Ok(n) = stream.read(&mut buf) {
+                if n == 0 {
+                    break;
+                }
+                let room = max.saturating_sub(kept.len());
+                if n > room {
+                    truncated = true;
+                }
+                kept.extend_from_slice(&buf[..n.min(room)]);
+            }
+        }
+        (kept, truncated)
+    })
+}
+
+/// Type of the `setrlimit` resource argument: glibc declares its own, other
+/// C libraries take an `int`
+#[cfg(all(target_os = "linux", target_env = "gnu"))]
+type RlimitResource = libc::__rlimit_resource_t;
+#[cfg(not(all(target_os = "linux", target_env = "gnu")))]
+type RlimitResource = libc::c_int;
+
+fn set_rlimit(resource: RlimitResource, value: u64) -> io::Result<()> {
+    let limit = libc::rlimit { rlim_cur: value as libc::rlim_t, rlim_max: value as libc::rlim_t };
+    // SAFETY: setrlimit only reads the provided struct
+    if unsafe { libc::setrlimit(resource, &limit) } != 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(())
+}
+
+fn kill_group(child: &mut Child) {
+    // The child leads its own process group, so this also reaches its descendants
+    // SAFETY: kill has no memory safety requirements
+    unsafe {
+        libc::kill(-(child.id() as libc::pid_t), libc::SIGKILL);
+    }
+    let _ = child.kill();
+}
+
+/// Runs `command` under `limits`, capturing its (capped) output
+///
+/// CPU and memory limits are applied with `setrlimit` in the child before
+/// exec. The child runs in its own process group so that a timeout kills
+/// everything it spawned.
+pub fn run_limited(command: &mut Command, limits: &CommandLimits) -> io::Result<CommandOutput> {
+    info!("Running {:?} with limits {:?}", command, limits);
+    let cpu_seconds = limits.cpu_seconds;
+    let memory_bytes = limits.memory_bytes;
+    command
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .process_group(0);
+    // SAFETY: the closure only calls async-signal-safe setrlimit
+    unsafe {
+        command.pre_exec(move || {
+            if let Some(seconds) = cpu_seconds {
+                set_rlimit(libc::RLIMIT_CPU as RlimitResource, seconds)?;
+            }
+            if let Some(bytes) = memory_bytes {
+                set_rlimit(libc::RLIMIT_AS as RlimitResource, bytes)?;
+            }
+            Ok(())
+        });
+    }
+
+    let mut child = command.spawn()?;
+    let stdout = capture(child.stdout.take(), limits.max_output_bytes);
+    let stderr = capture(child.stderr.take(), limits.max_output_bytes);
+
+    let deadline = Instant::now() + limits.timeout;
+    let mut timed_out = false;
+    let status = loop {
+        if let Some(status) = child.try_wait()? {
+            break Some(status);
+        }
+        if Instant::now() >= deadline {
+            error!("Command {:?} exceeded its {:?} timeout, killing it", command, limits.timeout);
+            kill_group(&mut child);
+            let _ = child.wait();
+            timed_out = true;
+            break None;
+        }
+        thread::sleep(Duration::from_millis(20));
+    };
+
+    let (stdout, stdout_truncated) = stdout.join().unwrap_or_default();
+    let (stderr, stderr_truncated) = stderr.join().unwrap_or_default();
+    Ok(CommandOutput {
+        status,
+        stdout: String::from_utf8_lossy(&stdout).to_string(),
+        stderr: String::from_utf8_lossy(&stderr).to_string(),
+        truncated: stdout_truncated || stderr_truncated,
+        timed_out,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn shell(script: &str) -> Command {
+        let mut command = Command::new("sh");
+        command.arg("-c").arg(script);
+        command
+    }
+
+    #[test]
+    fn test_output_is_capped() {
+        let limits = CommandLimits { max_output_bytes: 10, ..CommandLimits::default() };
+        let output = run_limited(&mut shell("yes | head -c 100000; echo err >&2"), &limits).unwrap();
+        assert!(output.success());
+        assert!(output.truncated);
+        assert_eq!(output.stdout.len(), 10);
+        assert_eq!(output.stderr, "err\n");
+    }
+
+    #[test]
+    fn test_timeout_kills_command() {
+        let limits = CommandLimits { timeout: Duration::from_millis(200), ..CommandLimits::default() };
+        let started = Instant::now();
+        let output = run_limited(&mut shell("sleep 30 & sleep 30"), &limits).unwrap();
+        assert!(output.timed_out);
+        assert!(!output.success());
+        assert!(started.elapsed() < Duration::from_secs(10));
+    }
+}
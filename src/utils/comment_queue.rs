@@ -0,0 +1,274 @@
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex, OnceLock};
+use std::time::Duration;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+use log::{info, error};
+
+use crate::error::Error;
+use crate::utils::clock::{self, Clock};
+use crate::utils::retry::RetryPolicy;
+use crate::utils::{gitcode, http_headers, state, state_store};
+use crate::utils::git::api_base_url;
+
+/// Attempts after which a comment is given up
+const MAX_ATTEMPTS: u32 = 10;
+
+/// How long a leased comment is left to the caller posting it
+const LEASE: Duration = Duration::from_secs(300);
+
+/// A PR comment waiting to be posted
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct PendingComment {
+    pub id: Uuid,
+    /// Job that produced the comment
+    pub job_id: Uuid,
+    pub platform: String,
+    pub namespace: String,
+    pub repo: String,
+    pub pr_id: u32,
+    pub body: String,
+    pub attempts: u32,
+    pub next_attempt_at: DateTime<Utc>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub last_error: Option<String>,
+}
+
+/// Comments not posted yet, persisted as JSON in `comments.json` so that they
+/// survive restarts and are retried with backoff
+pub struct CommentQueue {
+    path: PathBuf,
+    comments: Vec<PendingComment>,
+    clock: Arc<dyn Clock>,
+    retry: RetryPolicy,
+}
+
+impl CommentQueue {
+    pub fn open(path: &Path) -> CommentQueue {
+        let comments = match state_store::read_document(path) {
+            Ok(Some(contents)) => serde_json::from_str(&contents).unwrap_or_else(|e| {
+                error!("Failed to parse comment queue {:?}, starting empty: {}", path, e);
+                Vec::new()
+            }),
+            _ => Vec::new(),
+        };
+        CommentQueue {
+            path: path.to_path_buf(),
+            comments,
+            clock: clock::system(),
+            retry: RetryPolicy::new().backoff(Duration::from_secs(30), Duration::from_secs(3600)),
+        }
+    }
+
+    /// Uses `clock` to schedule attempts (a `MockClock` in tests)
+    pub fn with_clock(mut self, clock: Arc<dyn Clock>) -> Self {
+        self.clock = clock;
+        self
+    }
+
+    fn save(&self) {
+        let result = serde_json::to_string_pretty(&self.comments)
+            .map_err(std::io::Error::from)
+            .and_then(|contents| state_store::write_document(&self.path, &contents));
+        if let Err(e) = result {
+            error!("Failed to persist comment queue {:?}: {}", self.path, e);
+        }
+    }
+
+    /// Queues a comment for immediate posting and returns its ID
+    pub fn enqueue(&mut self, job_id: Uuid, platform: &str, namespace: &str, repo: &str, pr_id: u32, body: &str) -> Uuid {
+        let now = self.clock.now();
+        self.insert(job_id, platform, namespace, repo, pr_id, body, now).id
+    }
+
+    /// Queues a comment the caller posts itself with `deliver_leased`; the
+    /// worker only picks it up when the caller has not reported back within `LEASE`
+    pub fn enqueue_leased(&mut self, job_id: Uuid, platform: &str, namespace: &str, repo: &str, pr_id: u32, body: &str) -> PendingComment {
+        let lease_end = self.clock.now() + chrono::Duration::from_std(LEASE).unwrap_or_default();
+        self.insert(job_id, platform, namespace, repo, pr_id, body, lease_end)
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    fn insert(&mut self, job_id: Uuid, platform: &str, namespace: &str, repo: &str, pr_id: u32, body: &str, next_attempt_at: DateTime<Utc>) -> PendingComment {
+        let comment = PendingComment {
+            id: Uuid::new_v4(),
+            job_id,
+            platform: platform.to_string(),
+            namespace: namespace.to_string(),
+            repo: repo.to_string(),
+            pr_id,
+            body: body.to_string(),
+            attempts: 0,
+            next_attempt_at,
+            last_error: None,
+        };
+        self.comments.push(comment.clone());
+        self.save();
+        comment
+    }
+
+    /// Comments whose next attempt is due, oldest first
+    pub fn due(&self) -> Vec<PendingComment> {
+        let now = self.clock.now();
+        self.comments.iter().filter(|comment| comment.next_attempt_at <= now).cloned().collect()
+    }
+
+    /// Removes a posted comment
+    pub fn complete(&mut self, id: Uuid) {
+        self.comments.retain(|comment| comment.id != id);
+        self.save();
+    }
+
+    /// Schedules the next attempt of a comment that failed to post, dropping
+    /// it once `MAX_ATTEMPTS` is reached
+    pub fn fail(&mut self, id: Uuid, error: &str) {
+        let now = self.clock.now();
+        let Some(index) = self.comments.iter().position(|comment| comment.id == id) else { return };
+        let comment = &mut self.comments[index];
+        comment.attempts += 1;
+        comment.last_error = Some(error.to_string());
+        if comment.attempts >= MAX_ATTEMPTS {
+            error!("Giving up on comment for PR #{} of {} after {} attempts: {}", comment.pr_id, comment.repo, comment.attempts, error);
+            self.comments.remove(index);
+        } else {
+            let delay = chrono::Duration::from_std(self.retry.delay_for(comment.attempts)).unwrap_or_default();
+            comment.next_attempt_at = now + delay;
+        }
+        self.save();
+    }
+
+    pub fn len(&self) -> usize {
+        self.comments.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.comments.is_empty()
+    }
+}
+
+/// The process-wide comment queue
+pub fn queue() -> &'static Mutex<CommentQueue> {
+    static QUEUE: OnceLock<Mutex<CommentQueue>> = OnceLock::new();
+    QUEUE.get_or_init(|| Mutex::new(CommentQueue::open(&state::state_dir().join("comments.json"))))
+}
+
+/// Posts the due comments of `ids` (every due comment when `None`) and
+/// returns how many were posted; the queue is not locked while posting
+pub fn deliver_due(ids: Option<&[Uuid]>) -> usize {
+    // Keeps the worker and a job from posting the same comment twice
+    static DELIVERING: Mutex<()> = Mutex::new(());
+    let _delivering = DELIVERING.lock().unwrap();
+    let due = queue().lock().unwrap().due();
+    let mut posted = 0;
+    for comment in due.iter().filter(|comment| ids.is_none_or(|ids| ids.contains(&comment.id))) {
+        let _correlation = http_headers::correlate(&comment.job_id.to_string());
+        let result = gitcode::post_comment_on_pr(
+            api_base_url(&comment.platform),
+            &comment.namespace,
+            &comment.repo,
+            comment.pr_id,
+            &comment.body,
+            &comment.platform,
+        );
+        match result {
+            Ok(()) => {
+                info!("Posted queued comment to PR #{} of {}", comment.pr_id, comment.repo);
+                queue().lock().unwrap().complete(comment.id);
+                posted += 1;
+            }
+            Err(e) => {
+                error!("Failed to post comment to PR #{} of {}, will retry: {}", comment.pr_id, comment.repo, e);
+                queue().lock().unwrap().fail(comment.id, &e.to_string());
+            }
+        }
+    }
+    posted
+}
+
+/// Posts comments queued with `enqueue_leased` on the async `client`,
+/// retrying transient failures, and returns how many were posted; failed
+/// ones are left to the worker
+pub async fn deliver_leased(client: &reqwest::Client, comments: &[PendingComment]) -> usize {
+    let mut posted = 0;
+    for comment in comments {
+        let result = RetryPolicy::new().retry_async(|_| gitcode::post_comment_on_pr_async(
+            client,
+            api_base_url(&comment.platform),
+            &comment.namespace,
+            &comment.repo,
+            comment.pr_id,
+            &comment.body,
+            &comment.platform,
+        ), Error::is_transient).await;
+        match result {
+            Ok(()) => {
+                info!("Posted comment to PR #{} of {}", comment.pr_id, comment.repo);
+                queue().lock().unwrap().complete(comment.id);
+                posted += 1;
+            }
+            Err(e) => {
+                error!("Failed to post comment to PR #{} of {}, will retry: {}", comment.pr_id, comment.repo, e);
+                queue().lock().unwrap().fail(comment.id, &e.to_string());
+            }
+        }
+    }
+    posted
+}
+
+/// Posts queued comments every `interval`, starting with those left over from
+/// before a restart
+pub fn spawn_worker(interval: Duration) {
+    tokio::spawn(async move {
+        let mut ticker = tokio::time::interval(interval);
+        loop {
+            ticker.tick().await;
+            if queue().lock().unwrap().is_empty() {
+                continue;
+            }
+            if let Err(e) = tokio::task::spawn_blocking(|| deliver_due(None)).await {
+                error!("Comment queue worker failed: {}", e);
+            }
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::clock::MockClock;
+
+    #[test]
+    fn test_failed_comments_are_retried_after_restart() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("comments.json");
+        let clock = MockClock::new("2024-06-01T08:00:00Z".parse().unwrap());
+        let mut queue = CommentQueue::open(&path).with_clock(Arc::new(clock.clone()));
+        let first = queue.enqueue(Uuid::nil(), "gitcode", "org", "repo", 1, "one");
+        let second = queue.enqueue(Uuid::nil(), "gitcode", "org", "repo", 2, "two");
+        assert_eq!(queue.due().len(), 2);
+
+        queue.complete(first);
+        queue.fail(second, "502 Bad Gateway");
+        assert!(queue.due().is_empty());
+
+        // A restart keeps the pending comment and its schedule
+        let mut reopened = CommentQueue::open(&path).with_clock(Arc::new(clock.clone()));
+        assert_eq!(reopened.len(), 1);
+        clock.advance(Duration::from_secs(31));
+        let due = reopened.due();
+        assert_eq!((due[0].pr_id, due[0].attempts), (2, 1));
+        assert_eq!(due[0].last_error.as_deref(), Some("502 Bad Gateway"));
+
+        for _ in 1..MAX_ATTEMPTS {
+            reopened.fail(second, "502 Bad Gateway");
+        }
+        assert!(reopened.is_empty());
+
+        // The worker leaves a leased comment to its caller until the lease runs out
+        let leased = reopened.enqueue_leased(Uuid::nil(), "gitcode", "org", "repo", 3, "three");
+        assert!(reopened.due().is_empty());
+        clock.advance(LEASE);
+        assert_eq!(reopened.due()[0].id, leased.id);
+    }
+}
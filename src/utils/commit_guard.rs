@@ -0,0 +1,244 @@
+use git2::build::TreeUpdateBuilder;
+use git2::{DiffOptions, FileMode, Oid, Repository};
+use serde::{Deserialize, Serialize};
+use log::info;
+
+use crate::utils::templates::{CommentTemplates, MessageKind};
+
+/// Marker preceding the trailers the bot appends to backported messages
+const CHERRY_PICK_MARKER: &str = "\n\nCherry-picked from: ";
+
+/// What happens to a commit with an oversized message or large files
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum GuardPolicy {
+    /// The commit is backported unchanged; only logged and counted
+    #[default]
+    Warn,
+    /// The commit is left out of the backport
+    Skip,
+    /// The message is truncated and large files keep the branch's version
+    Strip,
+    /// The backport fails unless the PR carries `override_label`
+    RequireOverride,
+}
+
+/// Limits on backported commits
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct CommitGuards {
+    pub max_message_bytes: usize,
+    /// Size limit of each added or modified file, binary or not
+    pub max_blob_bytes: u64,
+    pub policy: GuardPolicy,
+    pub override_label: String,
+}
+
+impl Default for CommitGuards {
+    fn default() -> Self {
+        CommitGuards {
+            max_message_bytes: 64 * 1024,
+            max_blob_bytes: 10 * 1024 * 1024,
+            policy: GuardPolicy::default(),
+            override_label: "backport: allow-large".to_string(),
+        }
+    }
+}
+
+/// A file of a commit above `max_blob_bytes`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LargeBlob {
+    pub path: String,
+    pub size: u64,
+}
+
+/// Limits a commit exceeds
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct GuardFindings {
+    /// Size of the message when above `max_message_bytes`
+    pub message_bytes: Option<usize>,
+    pub large_blobs: Vec<LargeBlob>,
+}
+
+impl GuardFindings {
+    pub fn is_empty(&self) -> bool {
+        self.message_bytes.is_none() && self.large_blobs.is_empty()
+    }
+}
+
+/// Outcome of the guards for one commit
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum GuardDecision {
+    Pick,
+    Skip,
+    Strip(GuardFindings),
+    Block(GuardFindings),
+}
+
+impl GuardDecision {
+    /// Metric label of the decision
+    pub fn action(&self) -> &'static str {
+        match self {
+            GuardDecision::Pick => "picked",
+            GuardDecision::Skip => "skipped",
+            GuardDecision::Strip(_) => "stripped",
+            GuardDecision::Block(_) => "blocked",
+        }
+    }
+}
+
+/// Checks the message and the files added or modified by `commit_id` against `guards`
+pub fn inspect(repo: &Repository, commit_id: Oid, guards: &CommitGuards) -> Result<GuardFindings, git2::Error> {
+    let commit = repo.find_commit(commit_id)?;
+    let message_len = commit.message_bytes().len();
+    let message_bytes = (message_len > guards.max_message_bytes).then_some(message_len);
+
+    let parent_tree = match commit.parent(0) {
+        Ok(parent) => Some(parent.tree()?),
+        Err(_) => None,
+    };
+    let mut opts = DiffOptions::new();
+    opts.skip_binary_check(true);
+    let diff = repo.diff_tree_to_tree(parent_tree.as_ref(), Some(&commit.tree()?), Some(&mut opts))?;
+    let odb = repo.odb()?;
+    let mut large_blobs = Vec::new();
+    for delta in diff.deltas() {
+        let file = delta.new_file();
+        if file.id().is_zero() || file.mode() == FileMode::Commit {
+            continue;
+        }
+        let (size, _) = odb.read_header(file.id())?;
+        if size as u64 > guards.max_blob_bytes {
+            let path = file.path().map(|path| path.to_string_lossy().to_string()).unwrap_or_default();
+            large_blobs.push(LargeBlob { path, size: size as u64 });
+        }
+    }
+    Ok(GuardFindings { message_bytes, large_blobs })
+}
+
+/// Applies the policy to the findings of a commit; the override label lets it through
+pub fn decide(findings: GuardFindings, guards: &CommitGuards, labels: &[&str]) -> GuardDecision {
+    if findings.is_empty() || labels.iter().any(|label| label.eq_ignore_ascii_case(&guards.override_label)) {
+        return GuardDecision::Pick;
+    }
+    match guards.policy {
+        GuardPolicy::Warn => GuardDecision::Pick,
+        GuardPolicy::Skip => GuardDecision::Skip,
+        GuardPolicy::Strip => GuardDecision::Strip(findings),
+        GuardPolicy::RequireOverride => GuardDecision::Block(findings),
+    }
+}
+
+/// Truncates the original part of a backported message to `max_bytes`,
+/// keeping the bot's trailers
+pub fn truncate_message(message: &str, max_bytes: usize) -> String {
+    let (body, trailers) = match message.rfind(CHERRY_PICK_MARKER) {
+        Some(index) => message.split_at(index),
+        None => (message, ""),
+    };
+    if body.len() <= max_bytes {
+        return message.to_string();
+    }
+    let mut end = max_bytes;
+    while !body.is_char_boundary(end) {
+        end -= 1;
+    }
+    format!("{}\n\n[Message truncated from {} bytes]{}", body[..end].trim_end(), body.len(), trailers)
+}
+
+fn file_mode(mode: i32) -> FileMode {
+    match mode {
+        0o100755 => FileMode::BlobExecutable,
+        0o120000 => FileMode::Link,
+        _ => FileMode::Blob,
+    }
+}
+
+/// Rewrites the backported commit `oid` (the current HEAD) according to a
+/// `Strip` decision: truncated message, large files restored to their
+/// version on the branch or removed when they are new
+pub fn strip(repo: &Repository, oid: Oid, findings: &GuardFindings, guards: &CommitGuards) -> Result<Oid, git2::Error> {
+    let commit = repo.find_commit(oid)?;
+    let parent_tree = commit.parent(0)?.tree()?;
+    let mut updates = TreeUpdateBuilder::new();
+    for blob in &findings.large_blobs {
+        match parent_tree.get_path(std::path::Path::new(&blob.path)) {
+            Ok(entry) => updates.upsert(&blob.path, entry.id(), file_mode(entry.filemode())),
+            Err(_) => updates.remove(&blob.path),
+        };
+    }
+    let tree = repo.find_tree(updates.create_updated(repo, &commit.tree()?)?)?;
+    let message = commit.message().unwrap_or("");
+    let message = match findings.message_bytes {
+        Some(_) => truncate_message(message, guards.max_message_bytes),
+        None => message.to_string(),
+    };
+    let stripped = commit.amend(Some("HEAD"), None, None, None, Some(&message), Some(&tree))?;
+    info!("Stripped backport commit {} into {}", oid, stripped);
+    Ok(stripped)
+}
+
+/// Renders the PR comment explaining why a commit was not backported
+pub fn format_blocked_comment(branch: &str, commit: &str, findings: &GuardFindings, guards: &CommitGuards, templates: &CommentTemplates) -> String {
+    let mut list = String::new();
+    if let Some(bytes) = findings.message_bytes {
+        list.push_str(&format!("- commit message: {} bytes (limit {})\n", bytes, guards.max_message_bytes));
+    }
+    for blob in &findings.large_blobs {
+        list.push_str(&format!("- `{}`: {} bytes (limit {})\n", blob.path, blob.size, guards.max_blob_bytes));
+    }
+    templates.render(MessageKind::OversizedCommit, &[
+        ("branch", branch),
+        ("commit", commit),
+        ("findings", &list),
+        ("label", &guards.override_label),
+    ])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn commit_files(repo: &Repository, parent: Option<Oid>, files: &[(&str, &[u8])], message: &str) -> Oid {
+        let mut builder = TreeUpdateBuilder::new();
+        for (path, content) in files {
+            builder.upsert(*path, repo.blob(content).unwrap(), FileMode::Blob);
+        }
+        let base = match parent {
+            Some(parent) => repo.find_commit(parent).unwrap().tree().unwrap(),
+            None => repo.find_tree(repo.treebuilder(None).unwrap().write().unwrap()).unwrap(),
+        };
+        let tree = repo.find_tree(builder.create_updated(repo, &base).unwrap()).unwrap();
+        let sig = git2::Signature::now("test", "test@example.com").unwrap();
+        let parents: Vec<git2::Commit> = parent.map(|oid| repo.find_commit(oid).unwrap()).into_iter().collect();
+        let parents: Vec<&git2::Commit> = parents.iter().collect();
+        repo.commit(Some("HEAD"), &sig, &sig, message, &tree, &parents).unwrap()
+    }
+
+    #[test]
+    fn test_inspect_and_strip_large_commits() {
+        let dir = tempfile::tempdir().unwrap();
+        let repo = Repository::init(dir.path()).unwrap();
+        let guards = CommitGuards { max_message_bytes: 16, max_blob_bytes: 8, policy: GuardPolicy::Strip, ..CommitGuards::default() };
+        let base = commit_files(&repo, None, &[("model.bin", b"old")], "base");
+        let message = format!("{}{}https://example.com/pull/1\n", "x".repeat(40), CHERRY_PICK_MARKER);
+        let large = commit_files(&repo, Some(base), &[("model.bin", &[0u8; 32]), ("new.bin", &[1u8; 32]), ("small.txt", b"ok")], &message);
+
+        let findings = inspect(&repo, large, &guards).unwrap();
+        assert_eq!(findings.message_bytes, Some(message.len()));
+        let paths: Vec<&str> = findings.large_blobs.iter().map(|blob| blob.path.as_str()).collect();
+        assert_eq!(paths, vec!["model.bin", "new.bin"]);
+
+        assert_eq!(decide(findings.clone(), &guards, &["backport: allow-large"]), GuardDecision::Pick);
+        let GuardDecision::Strip(findings) = decide(findings, &guards, &[]) else { panic!("expected strip") };
+        let stripped = repo.find_commit(strip(&repo, large, &findings, &guards).unwrap()).unwrap();
+        let tree = stripped.tree().unwrap();
+        assert_eq!(repo.find_blob(tree.get_path(std::path::Path::new("model.bin")).unwrap().id()).unwrap().content(), b"old");
+        assert!(tree.get_path(std::path::Path::new("new.bin")).is_err());
+        assert!(tree.get_path(std::path::Path::new("small.txt")).is_ok());
+        let stripped_message = stripped.message().unwrap();
+        assert!(stripped_message.starts_with(&"x".repeat(16)));
+        assert!(stripped_message.contains("[Message truncated from 40 bytes]\n\nCherry-picked from: https://example.com/pull/1"));
+        assert!(inspect(&repo, base, &guards).unwrap().is_empty());
+    }
+}
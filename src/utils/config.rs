@@ -1,23 +1,686 @@
 use serde::{Deserialize, Serialize};
-use std::fs;
-use std::path::Path;
-use std::collections::HashMap;
+use utoipa::ToSchema;
+use std::env;
+use std::fs::{self, File, OpenOptions};
+use std::io::Write;
+use std::os::unix::io::AsRawFd;
+use std::path::{Path, PathBuf};
+use std::collections::{BTreeMap, HashMap};
+use std::sync::Mutex;
+use log::{info, warn};
 
-#[derive(Debug, Serialize, Deserialize)]
+use crate::error::{Error, Result};
+use crate::utils::branch::{self, BranchMapping, VersionLabels};
+use crate::utils::commit_guard::CommitGuards;
+use crate::utils::dco::DcoPolicy;
+use crate::utils::reopened::ReopenedPolicy;
+use crate::utils::mirror_schedule::{self, MirrorSchedule};
+use crate::utils::file::CleanupPolicy;
+use crate::utils::conflict::RenameDetection;
+use crate::utils::remote_url::RemoteUrl;
+use crate::utils::templates::{self, CommentTemplates};
+use crate::utils::mentions::MentionMode;
+
+/// Default location of the repository configuration file
+pub const CONFIG_FILE: &str = "config.yml";
+
+/// CI run started on the target repository after a backport is pushed
+///
+/// Every run receives the inputs `branch`, `from` and `to` (the pushed commit
+/// range) plus the static `inputs` below.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct CiTrigger {
+    /// GitHub workflow file name or ID to dispatch (`release.yml`); GitCode
+    /// targets start a pipeline instead
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub workflow: Option<String>,
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub inputs: BTreeMap<String, String>,
+}
+
+/// Author and committer identity of the bot's commits on a target repository
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+pub struct GitIdentity {
+    pub name: String,
+    pub email: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct RepoConfig {
     pub target_repo: String,
     pub namespace: String,
     pub repo_name: String,
+    /// URL of an ICS calendar describing freeze windows for this repository
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub freeze_calendar: Option<String>,
+    /// IANA time zone applied to floating times in the freeze calendar
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub freeze_timezone: Option<String>,
+    /// Colors (hex, without `#`) for labels the bot creates on the target repository
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    pub label_colors: HashMap<String, String>,
+    /// Scan cherry-picked changes for secrets before pushing to the target
+    #[serde(default = "default_true")]
+    pub secret_scan: bool,
+    /// Sign-off (DCO) policy for backported commits
+    #[serde(default)]
+    pub dco: DcoPolicy,
+    /// Git configuration applied to the working copy right after cloning
+    /// (e.g. `core.autocrlf`, `merge.renamelimit`, `fsck.*`)
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub git_config: BTreeMap<String, String>,
+    /// Only honor `br:`/approval labels applied by `label_allowlist` users or
+    /// by users with maintainer permission (GitHub and GitLab; GitCode jobs
+    /// fail, as its API does not tell who applied a label)
+    #[serde(default)]
+    pub restrict_labels: bool,
+    /// Users whose labels are honored when `restrict_labels` is set
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub label_allowlist: Vec<String>,
+    /// When the working copy is deleted: `always`, `on-success` or `never`
+    #[serde(default)]
+    pub cleanup: CleanupPolicy,
+    /// Rename detection settings used when cherry-picking
+    #[serde(default)]
+    pub renames: RenameDetection,
+    /// Locale and template overrides for comments posted on PRs
+    #[serde(default)]
+    pub comments: CommentTemplates,
+    /// Identity the bot commits as on the target repository
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub identity: Option<GitIdentity>,
+    /// Disabled repositories keep their configuration but their webhooks are
+    /// not processed
+    #[serde(default = "default_true", skip_serializing_if = "is_true")]
+    pub enabled: bool,
+    /// Hold webhooks received while disabled and replay them when re-enabled
+    #[serde(default)]
+    pub queue_when_disabled: bool,
+    /// How @-mentions in posted comments are handled: `keep`, `translate` or `strip`
+    #[serde(default)]
+    pub mentions: MentionMode,
+    /// GitHub login -> GitCode login, used when `mentions` is `translate`
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    pub author_map: HashMap<String, String>,
+    /// Push the branches updated by one PR with a single atomic push, so that
+    /// either all of them are updated or none (when the server supports it)
+    #[serde(default)]
+    pub atomic_push: bool,
+    /// Workflow or pipeline triggered on the target after a successful push
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub ci_trigger: Option<CiTrigger>,
+    /// Mark the source PR with `backported: <branch>` / `backport-failed: <branch>` labels
+    #[serde(default)]
+    pub status_labels: bool,
+    /// Assign a conflicted backport to its owner on the source PR and request
+    /// their review
+    #[serde(default)]
+    pub assign_conflicts: bool,
+    /// Target branch -> user owning its backports; conflicts on unlisted
+    /// branches go to the PR author
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    pub branch_owners: HashMap<String, String>,
+    /// Limits on commit message and file sizes, and what to do with commits over them
+    #[serde(default)]
+    pub commit_guards: CommitGuards,
+    /// Branch patterns (`*` wildcard) that are internal-only and never
+    /// mirrored to the target, e.g. `security/*`
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub internal_branches: Vec<String>,
+    /// Derive target branches from version labels (`affects: 1.2` ->
+    /// `release-1.2`) in addition to `br:` labels
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub version_labels: Option<VersionLabels>,
+    /// Record where each original commit was backported in
+    /// `refs/notes/backports` and push the notes to the source repository
+    #[serde(default)]
+    pub backport_notes: bool,
+    /// Label title -> target branch, taking precedence over the branch in the
+    /// description of `br:` labels; keys starting with `^` are regexes whose
+    /// captures fill `{name}`/`{1}` in the branch (`"^backport-(?P<v>.+)$":
+    /// "release-{v}"`)
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub branch_labels: BTreeMap<String, String>,
+    /// Source branch -> target branch renames, applied to backports and
+    /// mirrors; the first matching entry wins
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub branch_map: Vec<BranchMapping>,
+    /// What happens when a backported PR is reopened and merged again:
+    /// `require-label` (the `backport: again` label) or `ignore`
+    #[serde(default)]
+    pub reopened_prs: ReopenedPolicy,
+    /// After the configuration changes, run this many jobs as dry runs that
+    /// report what they would push, and hold further jobs until an operator
+    /// confirms the configuration (0 disables the canary)
+    #[serde(default)]
+    pub canary_jobs: u32,
+    /// Mirror the repository to `target_repo` on a fixed interval, to catch
+    /// up on pushes whose webhooks were missed
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub mirror: Option<MirrorSchedule>,
+}
+
+pub(crate) fn default_true() -> bool {
+    true
+}
+
+fn is_true(value: &bool) -> bool {
+    *value
+}
+
+impl RepoConfig {
+    /// Creates an entry with default settings for every optional field
+    pub fn new(target_repo: &str, namespace: &str, repo_name: &str) -> Self {
+        RepoConfig {
+            target_repo: target_repo.to_string(),
+            namespace: namespace.to_string(),
+            repo_name: repo_name.to_string(),
+            freeze_calendar: None,
+            freeze_timezone: None,
+            label_colors: HashMap::new(),
+            secret_scan: true,
+            dco: DcoPolicy::default(),
+            git_config: BTreeMap::new(),
+            restrict_labels: false,
+            label_allowlist: Vec::new(),
+            cleanup: CleanupPolicy::default(),
+            renames: RenameDetection::default(),
+            comments: CommentTemplates::default(),
+            identity: None,
+            enabled: true,
+            queue_when_disabled: false,
+            mentions: MentionMode::default(),
+            author_map: HashMap::new(),
+            atomic_push: false,
+            ci_trigger: None,
+            status_labels: false,
+            assign_conflicts: false,
+            branch_owners: HashMap::new(),
+            commit_guards: CommitGuards::default(),
+            internal_branches: Vec::new(),
+            version_labels: None,
+            backport_notes: false,
+            branch_labels: BTreeMap::new(),
+            branch_map: Vec::new(),
+            reopened_prs: ReopenedPolicy::default(),
+            canary_jobs: 0,
+            mirror: None,
+        }
+    }
+
+    /// Platform the source PRs come from: the target's platform when the
+    /// target is the configured repository itself (GitCode PRs backported
+    /// within the repository), GitHub otherwise
+    pub fn source_platform(&self) -> &'static str {
+        let Ok(target) = RemoteUrl::parse(&self.target_repo) else { return "github" };
+        match target.namespace_and_name() {
+            Some((namespace, name)) if namespace.eq_ignore_ascii_case(&self.namespace) && name.eq_ignore_ascii_case(&self.repo_name) => target.platform(),
+            _ => "github",
+        }
+    }
 }
 
 #[derive(Debug, Serialize, Deserialize)]
 pub struct Config {
+    /// Namespace (group) -> default settings of its repositories, for
+    /// group-level webhooks; any `RepoConfig` field may be set, and
+    /// `target_repo` may use `{namespace}` and `{repo}`. Subgroups inherit
+    /// from their parents and repository entries override single fields.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub groups: BTreeMap<String, serde_yaml::Mapping>,
     #[serde(flatten)]
     pub repos: HashMap<String, RepoConfig>,
 }
 
-pub fn read_config<P: AsRef<Path>>(path: P) -> Result<Config, Box<dyn std::error::Error>> {
+/// Top-level key of the group sections in the config file
+const GROUPS_KEY: &str = "groups";
+
+/// Whether the group `group` covers repositories of `namespace`, directly
+/// or as a parent group
+fn group_covers(group: &str, namespace: &str) -> bool {
+    let group = group.to_lowercase();
+    let namespace = namespace.to_lowercase();
+    namespace == group || namespace.starts_with(&format!("{}/", group))
+}
+
+/// A config file or entry that does not deserialize
+fn invalid(e: serde_yaml::Error) -> Error {
+    Error::Config(e.to_string())
+}
+
+/// Resolves the settings of `repo_name` from the raw config file: its entry
+/// layered over the sections of the groups covering its namespace, or, for
+/// a repository without an entry, those sections alone when `namespace` is
+/// known; `None` when nothing applies
+///
+/// An entry whose `namespace` differs from a known `namespace` belongs to a
+/// same-named repository elsewhere and is ignored.
+pub fn resolve_repo_config(raw: &serde_yaml::Mapping, namespace: Option<&str>, repo_name: &str) -> Result<Option<RepoConfig>> {
+    let entry = raw.get(repo_name).and_then(serde_yaml::Value::as_mapping).filter(|entry| {
+        let entry_namespace = entry.get("namespace").and_then(serde_yaml::Value::as_str);
+        match (entry_namespace, namespace) {
+            (Some(entry_namespace), Some(namespace)) => entry_namespace.eq_ignore_ascii_case(namespace),
+            _ => true,
+        }
+    });
+    let namespace = entry
+        .and_then(|entry| entry.get("namespace"))
+        .and_then(serde_yaml::Value::as_str)
+        .or(namespace);
+    let Some(namespace) = namespace else {
+        return entry.map(|entry| serde_yaml::from_value(entry.clone().into())).transpose().map_err(invalid);
+    };
+
+    let mut groups: Vec<(&str, &serde_yaml::Mapping)> = raw
+        .get(GROUPS_KEY)
+        .and_then(serde_yaml::Value::as_mapping)
+        .into_iter()
+        .flatten()
+        .filter_map(|(group, settings)| Some((group.as_str()?, settings.as_mapping()?)))
+        .filter(|(group, _)| group_covers(group, namespace))
+        .collect();
+    if entry.is_none() && groups.is_empty() {
+        return Ok(None);
+    }
+    // Parents first, so the most specific group wins
+    groups.sort_by_key(|(group, _)| group.len());
+
+    let mut merged = serde_yaml::Mapping::new();
+    for (_, settings) in groups {
+        merged.extend(settings.iter().map(|(key, value)| (key.clone(), value.clone())));
+    }
+    merged.insert("namespace".into(), namespace.into());
+    merged.insert("repo_name".into(), repo_name.into());
+    if let Some(target) = merged.get("target_repo").and_then(serde_yaml::Value::as_str) {
+        let target = templates::render_template(target, &[("namespace", namespace), ("repo", repo_name)]);
+        merged.insert("target_repo".into(), target.into());
+    }
+    if let Some(entry) = entry {
+        merged.extend(entry.iter().map(|(key, value)| (key.clone(), value.clone())));
+    }
+    Ok(Some(serde_yaml::from_value(merged.into()).map_err(invalid)?))
+}
+
+pub fn read_config<P: AsRef<Path>>(path: P) -> Result<Config> {
     let contents = fs::read_to_string(path)?;
-    let config: Config = serde_yaml::from_str(&contents)?;
+    let config: Config = serde_yaml::from_str(&contents).map_err(invalid)?;
+    validate_config(&config)?;
     Ok(config)
 }
+
+/// Serializes config writers within the process; `ConfigLock` also takes an
+/// `flock` so that other processes editing the file wait as well
+static WRITERS: Mutex<()> = Mutex::new(());
+
+/// Exclusive lock on `<config>.lock`, released when dropped
+struct ConfigLock {
+    _guard: std::sync::MutexGuard<'static, ()>,
+    _file: File,
+}
+
+impl ConfigLock {
+    fn acquire(path: &Path) -> std::io::Result<ConfigLock> {
+        let guard = WRITERS.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+        let file = OpenOptions::new().create(true).truncate(false).write(true).open(sibling(path, "lock"))?;
+        // SAFETY: flock on a descriptor owned by `file`, which outlives the lock
+        if unsafe { libc::flock(file.as_raw_fd(), libc::LOCK_EX) } != 0 {
+            return Err(std::io::Error::last_os_error());
+        }
+        Ok(ConfigLock { _guard: guard, _file: file })
+    }
+}
+
+/// `<path>.<suffix>`, next to `path`
+fn sibling(path: &Path, suffix: &str) -> PathBuf {
+    let mut name = path.file_name().unwrap_or_default().to_os_string();
+    name.push(".");
+    name.push(suffix);
+    path.with_file_name(name)
+}
+
+/// Number of timestamped backups kept next to the config file (`CONFIG_BACKUPS`, default 5)
+fn backup_count() -> usize {
+    env::var("CONFIG_BACKUPS").ok().and_then(|value| value.parse().ok()).unwrap_or(5)
+}
+
+/// Copies the current file to `<path>.<timestamp>.bak` and prunes the oldest backups
+fn backup(path: &Path, keep: usize) -> std::io::Result<()> {
+    if keep == 0 || !path.exists() {
+        return Ok(());
+    }
+    let stamp = chrono::Utc::now().format("%Y%m%dT%H%M%S%.6fZ").to_string();
+    fs::copy(path, sibling(path, &format!("{}.bak", stamp)))?;
+
+    let prefix = format!("{}.", path.file_name().unwrap_or_default().to_string_lossy());
+    let dir = path.parent().filter(|dir| !dir.as_os_str().is_empty()).unwrap_or(Path::new("."));
+    let mut backups: Vec<PathBuf> = fs::read_dir(dir)?
+        .filter_map(|entry| entry.ok())
+        .filter(|entry| {
+            let name = entry.file_name().to_string_lossy().into_owned();
+            name.starts_with(&prefix) && name.ends_with(".bak")
+        })
+        .map(|entry| entry.path())
+        .collect();
+    // Timestamps sort chronologically
+    backups.sort();
+    let excess = backups.len().saturating_sub(keep);
+    for old in &backups[..excess] {
+        fs::remove_file(old)?;
+    }
+    Ok(())
+}
+
+/// Replaces `path` with `contents` through a temporary file and a rename, so
+/// readers (and the hot-reload watcher) never see a partial file
+fn write_atomically(path: &Path, contents: &str) -> std::io::Result<()> {
+    let dir = path.parent().filter(|dir| !dir.as_os_str().is_empty()).unwrap_or(Path::new("."));
+    let mut temp = tempfile::NamedTempFile::new_in(dir)?;
+    temp.write_all(contents.as_bytes())?;
+    if let Ok(metadata) = fs::metadata(path) {
+        temp.as_file().set_permissions(metadata.permissions())?;
+    }
+    temp.as_file().sync_all()?;
+    temp.persist(path).map_err(|e| e.error)?;
+    Ok(())
+}
+
+/// `contents` parsed as a configuration, with defaults filled in, for
+/// comparing files regardless of layout, key order and comments
+fn normalized(contents: &str) -> Option<serde_yaml::Value> {
+    let config: Config = serde_yaml::from_str(contents).ok()?;
+    serde_yaml::to_value(config).ok()
+}
+
+/// Backs up `path` and replaces it with `contents`
+fn replace_locked(path: &Path, contents: &str) -> Result<()> {
+    if let Err(e) = backup(path, backup_count()) {
+        warn!("Failed to back up {:?} before writing: {}", path, e);
+    }
+    write_atomically(path, contents)?;
+    info!("Wrote {:?}", path);
+    Ok(())
+}
+
+/// Writes `config` unless the file already holds the same configuration, so
+/// that a no-op edit keeps the file's comments and layout
+fn write_locked(path: &Path, config: &Config) -> Result<()> {
+    validate_config(config)?;
+    let value = serde_yaml::to_value(config).map_err(invalid)?;
+    let current = fs::read_to_string(path).ok().and_then(|current| normalized(&current));
+    if current.as_ref() == Some(&value) {
+        return Ok(());
+    }
+    replace_locked(path, &serde_yaml::to_string(&value).map_err(invalid)?)
+}
+
+/// Writes the configuration back to `path`
+pub fn write_config<P: AsRef<Path>>(path: P, config: &Config) -> Result<()> {
+    let _lock = ConfigLock::acquire(path.as_ref())?;
+    write_locked(path.as_ref(), config)
+}
+
+/// Reads, edits and writes back the configuration at `path` while holding the
+/// writer lock, so concurrent admin edits do not overwrite each other
+///
+/// The file is only rewritten when the edit changed it.
+pub fn update_config<P, T, F>(path: P, edit: F) -> Result<T>
+where
+    P: AsRef<Path>,
+    F: FnOnce(&mut Config) -> T,
+{
+    let path = path.as_ref();
+    let _lock = ConfigLock::acquire(path)?;
+    let mut config = read_config(path)?;
+    let value = edit(&mut config);
+    write_locked(path, &config)?;
+    Ok(value)
+}
+
+/// Rejects entries without a git `identity` (their own or their group's),
+/// whose `target_repo` is not a recognized git remote URL,
+/// whose `version_labels` or `branch_labels` patterns are not valid regexes,
+/// whose `branch_map` has malformed wildcards or whose `mirror` runs more
+/// often than every minute
+pub fn validate_config(config: &Config) -> Result<()> {
+    let mut resolved: Vec<(String, RepoConfig)> = config.repos.iter().map(|(name, repo)| (name.clone(), repo.clone())).collect();
+    if !config.groups.is_empty() {
+        let raw = serde_yaml::to_value(config).map_err(invalid)?;
+        let raw = raw.as_mapping().ok_or_else(|| Error::config("config is not a mapping"))?;
+        // Entries inherit the settings of their groups
+        for (name, repo) in resolved.iter_mut() {
+            if let Some(merged) = resolve_repo_config(raw, None, name)? {
+                *repo = merged;
+            }
+        }
+        for group in config.groups.keys() {
+            // A placeholder repository shows whether the group's settings form a complete entry
+            let repo = resolve_repo_config(raw, Some(group), "repo")
+                .map_err(|e| Error::Config(format!("Group {}: {}", group, e)))?
+                .ok_or_else(|| Error::Config(format!("Group {}: no settings", group)))?;
+            resolved.push((format!("group {}", group), repo));
+        }
+    }
+    for (name, repo) in &resolved {
+        RemoteUrl::parse(&repo.target_repo)
+            .map_err(|e| Error::Config(format!("Repository {}: {}", name, e)))?;
+        if repo.identity.is_none() {
+            return Err(Error::Config(format!("Repository {}: no git identity, set `identity` (name, email)", name)));
+        }
+        if let Some(version_labels) = &repo.version_labels {
+            regex::Regex::new(&version_labels.pattern)
+                .map_err(|e| Error::Config(format!("Repository {}: invalid version_labels pattern: {}", name, e)))?;
+        }
+        branch::branch_label_rules(&repo.branch_labels)
+            .map_err(|e| Error::Config(format!("Repository {}: invalid branch_labels pattern: {}", name, e)))?;
+        for mapping in &repo.branch_map {
+            mapping.validate().map_err(|e| Error::Config(format!("Repository {}: {}", name, e)))?;
+        }
+        if let Some(mirror) = &repo.mirror {
+            if mirror.interval_secs < mirror_schedule::MIN_INTERVAL_SECS {
+                return Err(Error::Config(format!(
+                    "Repository {}: mirror interval_secs must be at least {}",
+                    name,
+                    mirror_schedule::MIN_INTERVAL_SECS
+                )));
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Enables or disables `repo_name` in the config file at `path`
+///
+/// Only the entry's `enabled` line is written, keeping the rest of the file
+/// as is; the file is re-serialized only when the entry is not laid out as a
+/// plain block mapping. Returns false when the repository has no entry.
+pub fn set_repo_enabled<P: AsRef<Path>>(path: P, repo_name: &str, enabled: bool) -> Result<bool> {
+    let path = path.as_ref();
+    let _lock = ConfigLock::acquire(path)?;
+    let contents = fs::read_to_string(path)?;
+    let mut config: Config = serde_yaml::from_str(&contents).map_err(invalid)?;
+    let Some(repo) = config.repos.get_mut(repo_name) else {
+        return Ok(false);
+    };
+    if repo.enabled == enabled {
+        return Ok(true);
+    }
+    repo.enabled = enabled;
+    validate_config(&config)?;
+
+    let expected = serde_yaml::to_value(&config).map_err(invalid)?;
+    match with_enabled_line(&contents, repo_name, enabled) {
+        Some(edited) if normalized(&edited).as_ref() == Some(&expected) => replace_locked(path, &edited)?,
+        _ => write_locked(path, &config)?,
+    }
+    Ok(true)
+}
+
+/// `contents` with the `enabled` key of the top-level entry `repo_name` set
+/// to `enabled`, added as the entry's first key when missing; `None` when
+/// the entry is not a block mapping
+fn with_enabled_line(contents: &str, repo_name: &str, enabled: bool) -> Option<String> {
+    let mut lines: Vec<String> = contents.lines().map(str::to_string).collect();
+    let header = format!("{}:", repo_name);
+    let start = lines.iter().position(|line| {
+        line.strip_prefix(&header)
+            .is_some_and(|rest| rest.trim().is_empty() || rest.trim_start().starts_with('#'))
+    })?;
+    let is_content = |line: &String| !line.trim().is_empty() && !line.trim_start().starts_with('#');
+    let end = lines[start + 1..]
+        .iter()
+        .position(|line| is_content(line) && !line.starts_with(char::is_whitespace))
+        .map_or(lines.len(), |offset| start + 1 + offset);
+    let first = lines[start + 1..end].iter().find(|line| is_content(line))?;
+    let indent = first[..first.len() - first.trim_start().len()].to_string();
+
+    let key = format!("{}enabled:", indent);
+    match (start + 1..end).find(|&i| lines[i].starts_with(&key)) {
+        Some(i) => {
+            let comment = lines[i].find(" #").map(|at| lines[i][at..].to_string()).unwrap_or_default();
+            lines[i] = format!("{}enabled: {}{}", indent, enabled, comment);
+        }
+        None => lines.insert(start + 1, format!("{}enabled: {}", indent, enabled)),
+    }
+    let mut edited = lines.join("\n");
+    if contents.ends_with('\n') {
+        edited.push('\n');
+    }
+    Some(edited)
+}
+
+/// Reads the default config file once validated, untyped so that group
+/// sections can be layered under repository entries
+fn read_raw_config() -> Result<serde_yaml::Mapping> {
+    let contents = fs::read_to_string(CONFIG_FILE)?;
+    let config: Config = serde_yaml::from_str(&contents).map_err(invalid)?;
+    validate_config(&config)?;
+    serde_yaml::from_str(&contents).map_err(invalid)
+}
+
+/// Looks up the configuration entry of `repo_name` in the default config
+/// file, with the defaults of its group
+pub fn load_repo_config(repo_name: &str) -> Result<Option<RepoConfig>> {
+    resolve_repo_config(&read_raw_config()?, None, repo_name)
+}
+
+/// Like `load_repo_config`, falling back to the group defaults of
+/// `namespace` for repositories without an entry of their own
+pub fn load_repo_config_in(namespace: &str, repo_name: &str) -> Result<Option<RepoConfig>> {
+    resolve_repo_config(&read_raw_config()?, Some(namespace), repo_name)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(target_repo: &str, namespace: &str, repo_name: &str) -> RepoConfig {
+        let mut entry = RepoConfig::new(target_repo, namespace, repo_name);
+        entry.identity = Some(GitIdentity { name: "Bot".to_string(), email: "bot@example.com".to_string() });
+        entry
+    }
+
+    #[test]
+    fn test_concurrent_updates_keep_backups() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("config.yml");
+        let mut config = Config { groups: BTreeMap::new(), repos: HashMap::new() };
+        config.repos.insert("repo".to_string(), entry("https://gitcode.com/org/repo.git", "org", "repo"));
+        write_config(&path, &config).unwrap();
+
+        let writers: Vec<_> = (0..8)
+            .map(|i| {
+                let path = path.clone();
+                std::thread::spawn(move || {
+                    update_config(&path, |config| {
+                        config.repos.insert(format!("repo{}", i), entry("https://gitcode.com/org/repo.git", "org", &format!("repo{}", i)));
+                    })
+                    .unwrap();
+                })
+            })
+            .collect();
+        writers.into_iter().for_each(|writer| writer.join().unwrap());
+
+        // No edit was lost and the file still parses
+        assert_eq!(read_config(&path).unwrap().repos.len(), 9);
+        let backups = fs::read_dir(dir.path())
+            .unwrap()
+            .filter(|entry| entry.as_ref().unwrap().file_name().to_string_lossy().ends_with(".bak"))
+            .count();
+        assert_eq!(backups, backup_count());
+        assert!(!set_repo_enabled(&path, "missing", false).unwrap());
+        assert!(set_repo_enabled(&path, "repo", false).unwrap());
+    }
+
+    #[test]
+    fn test_edits_keep_comments_and_layout() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("config.yml");
+        let contents = "# Backported repositories\nrepo:\n  # Mirror target\n  target_repo: https://gitcode.com/org/repo.git  # primary\n  namespace: org\n  repo_name: repo\n  identity: { name: Bot, email: bot@example.com }\n\nother:\n  target_repo: https://gitcode.com/org/other.git\n  namespace: org\n  repo_name: other\n  identity: { name: Bot, email: bot@example.com }\n  enabled: false # until migrated\n";
+        fs::write(&path, contents).unwrap();
+        let backups = || fs::read_dir(dir.path()).unwrap().filter(|entry| entry.as_ref().unwrap().file_name().to_string_lossy().ends_with(".bak")).count();
+
+        // An edit that changes nothing leaves the file alone
+        update_config(&path, |config| config.repos.get_mut("repo").unwrap().enabled = true).unwrap();
+        assert!(set_repo_enabled(&path, "other", false).unwrap());
+        assert_eq!(fs::read_to_string(&path).unwrap(), contents);
+        assert_eq!(backups(), 0);
+
+        assert!(set_repo_enabled(&path, "repo", false).unwrap());
+        assert!(set_repo_enabled(&path, "other", true).unwrap());
+        let edited = fs::read_to_string(&path).unwrap();
+        assert_eq!(edited, contents.replace("repo:\n  # Mirror", "repo:\n  enabled: false\n  # Mirror").replace("enabled: false # until", "enabled: true # until"));
+        let config = read_config(&path).unwrap();
+        assert!(!config.repos["repo"].enabled);
+        assert!(config.repos["other"].enabled);
+    }
+
+    #[test]
+    fn test_shipped_config_is_valid() {
+        let config = read_config(Path::new(env!("CARGO_MANIFEST_DIR")).join(CONFIG_FILE)).unwrap();
+        assert!(config.repos.values().all(|repo| repo.identity.is_some()));
+    }
+
+    #[test]
+    fn test_group_defaults_and_repo_overrides() {
+        let contents = r#"
+groups:
+  openHiTLS:
+    target_repo: https://gitcode.com/{namespace}/{repo}.git
+    identity: { name: openHiTLS Bot, email: bot@example.com }
+    status_labels: true
+    atomic_push: true
+  openHiTLS/crypto:
+    atomic_push: false
+listed:
+  target_repo: https://github.com/openHiTLS/listed.git
+  namespace: openHiTLS
+  repo_name: listed
+  status_labels: false
+"#;
+        let config: Config = serde_yaml::from_str(contents).unwrap();
+        validate_config(&config).unwrap();
+        assert_eq!(config.repos.len(), 1);
+        let raw: serde_yaml::Mapping = serde_yaml::from_str(contents).unwrap();
+
+        let unlisted = resolve_repo_config(&raw, Some("openHiTLS/crypto"), "tls").unwrap().unwrap();
+        assert_eq!(unlisted.target_repo, "https://gitcode.com/openHiTLS/crypto/tls.git");
+        assert!(unlisted.status_labels && !unlisted.atomic_push);
+
+        let listed = resolve_repo_config(&raw, None, "listed").unwrap().unwrap();
+        assert_eq!(listed.target_repo, "https://github.com/openHiTLS/listed.git");
+        assert!(!listed.status_labels && listed.atomic_push);
+        assert_eq!(listed.identity.unwrap().name, "openHiTLS Bot");
+
+        assert!(resolve_repo_config(&raw, Some("other"), "tls").unwrap().is_none());
+        // A same-named repository in another namespace gets its own group's settings
+        let elsewhere = resolve_repo_config(&raw, Some("openHiTLS/crypto"), "listed").unwrap().unwrap();
+        assert_eq!(elsewhere.target_repo, "https://gitcode.com/openHiTLS/crypto/listed.git");
+        assert!(resolve_repo_config(&raw, Some("orgB"), "listed").unwrap().is_none());
+        assert!(resolve_repo_config(&raw, None, "tls").unwrap().is_none());
+
+        let incomplete: Config = serde_yaml::from_str("groups:\n  org:\n    status_labels: true\n").unwrap();
+        assert!(validate_config(&incomplete).is_err());
+        let anonymous: Config = serde_yaml::from_str("repo:\n  target_repo: https://gitcode.com/org/repo.git\n  namespace: org\n  repo_name: repo\n").unwrap();
+        assert!(validate_config(&anonymous).unwrap_err().to_string().contains("identity"));
+    }
+}
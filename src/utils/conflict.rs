@@ -0,0 +1,270 @@
+use git2::{Commit, MergeOptions, Repository};
+use serde::{Deserialize, Serialize};
+use log::{info, error};
+
+use crate::utils::templates::{CommentTemplates, MessageKind};
+
+/// Upper bound for the conflict snippet embedded in a PR comment
+pub const MAX_SNIPPET_BYTES: usize = 4000;
+
+#[derive(Debug, Clone)]
+pub struct ConflictReport {
+    pub commit_sha: String,
+    pub branch: String,
+    pub files: Vec<String>,
+    pub snippet: String,
+    pub truncated: bool,
+}
+
+/// Rename detection used when cherry-picking onto older branches
+///
+/// Mirrors git's `merge.renames` settings: `threshold` is the similarity
+/// index (0-100) above which a delete/add pair counts as a rename and
+/// `limit` caps the number of candidate files examined.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct RenameDetection {
+    pub enabled: bool,
+    pub threshold: u32,
+    pub limit: u32,
+}
+
+impl Default for RenameDetection {
+    fn default() -> Self {
+        RenameDetection { enabled: true, threshold: 50, limit: 1000 }
+    }
+}
+
+impl RenameDetection {
+    pub fn merge_options(&self) -> MergeOptions {
+        let mut options = MergeOptions::new();
+        options
+            .find_renames(self.enabled)
+            .rename_threshold(self.threshold.min(100))
+            .target_limit(self.limit);
+        options
+    }
+}
+
+fn entry_path(entry: &Option<git2::IndexEntry>) -> Option<String> {
+    entry
+        .as_ref()
+        .map(|e| String::from_utf8_lossy(&e.path).to_string())
+}
+
+/// Truncates `text` to at most `max` bytes on a char boundary
+fn truncate_to(text: &str, max: usize) -> &str {
+    if text.len() <= max {
+        return text;
+    }
+    let mut end = max;
+    while !text.is_char_boundary(end) {
+        end -= 1;
+    }
+    &text[..end]
+}
+
+/// Cherry-picks `commit` onto `onto` in memory and reports conflicting files
+///
+/// # Returns
+/// * `Ok(None)` when the commit applies cleanly
+/// * `Ok(Some(report))` with the conflicting files and a diff3-style snippet
+pub fn detect_conflicts(
+    repo: &Repository,
+    commit: &Commit,
+    onto: &Commit,
+    branch: &str,
+    renames: &RenameDetection,
+) -> Result<Option<ConflictReport>, git2::Error> {
+    let mainline = if commit.parent_count() > 1 { 1 } else { 0 };
+    let index = repo.cherrypick_commit(commit, onto, mainline, Some(&renames.merge_options()))?;
+    if !index.has_conflicts() {
+        return Ok(None);
+    }
+
+    let mut files = Vec::new();
+    let mut snippet = String::new();
+    let mut truncated = false;
+
+    for conflict in index.conflicts()? {
+        let conflict = conflict?;
+        let path = entry_path(&conflict.our)
+            .or_else(|| entry_path(&conflict.their))
+            .or_else(|| entry_path(&conflict.ancestor))
+            .unwrap_or_else(|| "<unknown>".to_string());
+        files.push(path.clone());
+
+        if truncated {
+            continue;
+        }
+
+        let (ancestor, ours, theirs) = match (&conflict.ancestor, &conflict.our, &conflict.their) {
+            (Some(ancestor), Some(ours), Some(theirs)) => (ancestor, ours, theirs),
+            _ => {
+                snippet.push_str(&format!("--- {} (added/deleted on one side)\n", path));
+                continue;
+            }
+        };
+
+        let blob_text = |entry: &git2::IndexEntry| -> Result<String, git2::Error> {
+            let blob = repo.find_blob(entry.id)?;
+            Ok(String::from_utf8_lossy(blob.content()).to_string())
+        };
+        let (base, ours, theirs) = match (blob_text(ancestor), blob_text(ours), blob_text(theirs)) {
+            (Ok(base), Ok(ours), Ok(theirs)) => (base, ours, theirs),
+            _ => {
+                error!("Failed to read conflicting blobs for {}", path);
+                continue;
+            }
+        };
+
+        let section = render_diff3(&base, &ours, &theirs, branch, &commit.id().to_string()[..8]);
+        let section = format!("--- {}\n{}", path, section);
+        let remaining = MAX_SNIPPET_BYTES.saturating_sub(snippet.len());
+        if section.len() > remaining {
+            snippet.push_str(truncate_to(&section, remaining));
+            truncated = true;
+        } else {
+            snippet.push_str(&section);
+        }
+    }
+
+    info!("Cherry-pick of {} onto {} conflicts in {} files", commit.id(), branch, files.len());
+    Ok(Some(ConflictReport {
+        commit_sha: commit.id().to_string(),
+        branch: branch.to_string(),
+        files,
+        snippet,
+        truncated,
+    }))
+}
+
+/// Renders a single diff3-style hunk spanning the region where the three
+/// versions differ (everything between their common prefix and suffix)
+fn render_diff3(base: &str, ours: &str, theirs: &str, our_label: &str, their_label: &str) -> String {
+    let base: Vec<&str> = base.lines().collect();
+    let ours: Vec<&str> = ours.lines().collect();
+    let theirs: Vec<&str> = theirs.lines().collect();
+
+    let shortest = base.len().min(ours.len()).min(theirs.len());
+    let prefix = (0..shortest)
+        .take_while(|&i| base[i] == ours[i] && base[i] == theirs[i])
+        .count();
+    let suffix = (0..shortest - prefix)
+        .take_while(|&i| {
+            let line = base[base.len() - 1 - i];
+            line == ours[ours.len() - 1 - i] && line == theirs[theirs.len() - 1 - i]
+        })
+        .count();
+
+    let mut output = format!("@@ line {} @@\n<<<<<<< {}\n", prefix + 1, our_label);
+    for (lines, marker) in [(&ours, "||||||| base"), (&base, "======="), (&theirs, "")] {
+        for line in &lines[prefix..lines.len() - suffix] {
+            output.push_str(line);
+            output.push('\n');
+        }
+        if !marker.is_empty() {
+            output.push_str(marker);
+            output.push('\n');
+        }
+    }
+    output.push_str(&format!(">>>>>>> {}\n", their_label));
+    output
+}
+
+/// Renders the PR comment describing a conflicting backport
+///
+/// `patches` is the rendered list of downloadable patches (may be empty).
+pub fn format_conflict_comment(report: &ConflictReport, templates: &CommentTemplates, patches: &str) -> String {
+    let files: String = report.files.iter().map(|file| format!("- `{}`\n", file)).collect();
+    let mut snippet = String::new();
+    if !report.snippet.is_empty() {
+        snippet.push_str("\n```diff\n");
+        snippet.push_str(&report.snippet);
+        if !report.snippet.ends_with('\n') {
+            snippet.push('\n');
+        }
+        if report.truncated {
+            snippet.push_str("... (truncated)\n");
+        }
+        snippet.push_str("```\n");
+    }
+    templates.render(MessageKind::Conflict, &[
+        ("branch", &report.branch),
+        ("commit", &report.commit_sha),
+        ("files", &files),
+        ("snippet", &snippet),
+        ("patches", patches),
+    ])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_render_diff3() {
+        let base = "a\nb\nc\n";
+        let ours = "a\nB1\nc\n";
+        let theirs = "a\nB2\nc\n";
+        assert_eq!(
+            render_diff3(base, ours, theirs, "release-1.0", "abcdef12"),
+            "@@ line 2 @@\n<<<<<<< release-1.0\nB1\n||||||| base\nb\n=======\nB2\n>>>>>>> abcdef12\n"
+        );
+    }
+
+    #[test]
+    fn test_format_conflict_comment() {
+        let report = ConflictReport {
+            commit_sha: "abcdef1234567890".to_string(),
+            branch: "release-1.0".to_string(),
+            files: vec!["src/lib.rs".to_string()],
+            snippet: "--- src/lib.rs\n<<<<<<< release-1.0\n".to_string(),
+            truncated: true,
+        };
+        let patches = "\nPatches attempted by the bot:\n- [abcdef12.patch](https://bot/patches/0.patch)\n";
+        let comment = format_conflict_comment(&report, &CommentTemplates::default(), patches);
+        assert!(comment.contains("- `src/lib.rs`"));
+        assert!(comment.contains("git cherry-pick -x abcdef1234567890"));
+        assert!(comment.contains("(truncated)"));
+        assert!(comment.contains("[abcdef12.patch]"));
+    }
+
+    #[test]
+    fn test_truncate_to_char_boundary() {
+        assert_eq!(truncate_to("你好", 4), "你");
+        assert_eq!(truncate_to("abc", 10), "abc");
+    }
+
+    fn commit_files(repo: &Repository, parent: Option<&Commit>, files: &[(&str, &str)]) -> git2::Oid {
+        let mut builder = repo.treebuilder(None).unwrap();
+        for (name, contents) in files {
+            let blob = repo.blob(contents.as_bytes()).unwrap();
+            builder.insert(name, blob, 0o100644).unwrap();
+        }
+        let tree = repo.find_tree(builder.write().unwrap()).unwrap();
+        let sig = git2::Signature::now("test", "test@example.com").unwrap();
+        let parents: Vec<&Commit> = parent.into_iter().collect();
+        repo.commit(None, &sig, &sig, "commit", &tree, &parents).unwrap()
+    }
+
+    #[test]
+    fn test_rename_detection_resolves_renamed_files() {
+        let dir = tempfile::tempdir().unwrap();
+        let repo = Repository::init(dir.path()).unwrap();
+        let body: String = (0..40).map(|i| format!("line {}\n", i)).collect();
+        let changed = body.replace("line 20\n", "line twenty\n");
+
+        let base = repo.find_commit(commit_files(&repo, None, &[("old.c", &body)])).unwrap();
+        // Upstream fix touching the file under its original name
+        let fix = repo.find_commit(commit_files(&repo, Some(&base), &[("old.c", &changed)])).unwrap();
+        // Release branch where the file was renamed
+        let release = repo.find_commit(commit_files(&repo, Some(&base), &[("new.c", &body)])).unwrap();
+
+        let renames = RenameDetection::default();
+        assert!(detect_conflicts(&repo, &fix, &release, "release", &renames).unwrap().is_none());
+
+        let disabled = RenameDetection { enabled: false, ..renames };
+        assert!(detect_conflicts(&repo, &fix, &release, "release", &disabled).unwrap().is_some());
+    }
+}
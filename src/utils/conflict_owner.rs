@@ -0,0 +1,85 @@
+use std::collections::HashMap;
+use log::{info, error};
+
+use crate::models::webhook::ParsedWebhookData;
+use crate::utils::{config, gitcode};
+use crate::utils::git::api_base_url;
+
+/// Who takes over a conflicted backport
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Assignment {
+    pub assignees: Vec<String>,
+    /// Users whose review is requested; never the PR author, whom GitHub
+    /// refuses as a reviewer of their own PR
+    pub reviewers: Vec<String>,
+}
+
+/// Owner of a conflict on `branch`: the configured branch owner, otherwise
+/// the PR author; `None` when neither is known
+pub fn conflict_assignment(branch: &str, author: Option<&str>, branch_owners: &HashMap<String, String>) -> Option<Assignment> {
+    let owner = branch_owners
+        .get(branch)
+        .map(|owner| owner.trim().trim_start_matches('@'))
+        .filter(|owner| !owner.is_empty())
+        .or(author)?;
+    let reviewers = match author {
+        Some(author) if author.eq_ignore_ascii_case(owner) => Vec::new(),
+        _ => vec![owner.to_string()],
+    };
+    Some(Assignment { assignees: vec![owner.to_string()], reviewers })
+}
+
+/// Assigns the conflicted backport of the source PR onto `branch` to its
+/// owner when the repository enables `assign_conflicts`; failures are logged
+pub fn assign_conflict(webhook_data: &ParsedWebhookData, platform: &str, branch: &str) {
+    let Some(iid) = webhook_data.iid else { return };
+    let repo_config = match config::load_repo_config_in(&webhook_data.namespace, &webhook_data.repo_name) {
+        Ok(Some(repo_config)) if repo_config.assign_conflicts => repo_config,
+        _ => return,
+    };
+    let base_url = api_base_url(platform);
+    let author = match gitcode::get_pr_author(base_url, &webhook_data.namespace, &webhook_data.repo_name, iid, platform) {
+        Ok(author) => Some(author),
+        Err(e) => {
+            error!("Failed to look up the author of PR #{}: {}", iid, e);
+            None
+        }
+    };
+    let Some(assignment) = conflict_assignment(branch, author.as_deref(), &repo_config.branch_owners) else {
+        error!("No owner for the conflicted backport of PR #{} onto {}", iid, branch);
+        return;
+    };
+
+    if let Err(e) = gitcode::add_assignees_to_pr(base_url, &webhook_data.namespace, &webhook_data.repo_name, iid, &assignment.assignees, platform) {
+        error!("Failed to assign {:?} to PR #{}: {}", assignment.assignees, iid, e);
+    }
+    // GitCode assignees already are the PR's reviewers
+    if platform == "github" && !assignment.reviewers.is_empty() {
+        if let Err(e) = gitcode::request_pr_reviewers(base_url, &webhook_data.namespace, &webhook_data.repo_name, iid, &assignment.reviewers, platform) {
+            error!("Failed to request review of PR #{} from {:?}: {}", iid, assignment.reviewers, e);
+        }
+    }
+    info!("Conflicted backport of PR #{} onto {} assigned to {:?}", iid, branch, assignment.assignees);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_branch_owner_takes_precedence_over_author() {
+        let mut owners = HashMap::new();
+        owners.insert("release-1.0".to_string(), "@lts-team".to_string());
+
+        let assignment = conflict_assignment("release-1.0", Some("alice"), &owners).unwrap();
+        assert_eq!(assignment.assignees, vec!["lts-team"]);
+        assert_eq!(assignment.reviewers, vec!["lts-team"]);
+
+        let assignment = conflict_assignment("release-2.0", Some("alice"), &owners).unwrap();
+        assert_eq!(assignment.assignees, vec!["alice"]);
+        assert!(assignment.reviewers.is_empty());
+
+        assert_eq!(conflict_assignment("release-2.0", None, &owners), None);
+        assert_eq!(conflict_assignment("release-1.0", None, &owners).unwrap().assignees, vec!["lts-team"]);
+    }
+}
@@ -0,0 +1,158 @@
+use std::env;
+use std::sync::OnceLock;
+use std::net::{SocketAddr, TcpStream, ToSocketAddrs};
+use std::time::{Duration, Instant};
+use serde::Serialize;
+use utoipa::ToSchema;
+use log::{info, warn};
+
+use crate::utils::gitlab;
+
+/// Port probed on every forge host (git and the APIs both use HTTPS)
+const HTTPS_PORT: u16 = 443;
+
+/// Hosts the service talks to for `platform`: git remotes and the REST API
+pub fn forge_hosts(platform: &str) -> &'static [&'static str] {
+    match platform {
+        "github" => &["github.com", "api.github.com"],
+        "gitcode" => &["gitcode.com", "api.gitcode.com"],
+        "gitlab" => {
+            static HOSTS: OnceLock<[&'static str; 1]> = OnceLock::new();
+            HOSTS.get_or_init(|| [gitlab::instance_host()])
+        },
+        _ => &[],
+    }
+}
+
+/// Connect timeout of each probe, from `CONNECTIVITY_TIMEOUT_SECS` (default 3)
+pub fn probe_timeout() -> Duration {
+    let secs = env::var("CONNECTIVITY_TIMEOUT_SECS")
+        .ok()
+        .and_then(|value| value.parse::<u64>().ok())
+        .unwrap_or(3);
+    Duration::from_secs(secs.max(1))
+}
+
+/// Result of probing one address family of a host
+#[derive(Debug, Clone, Default, Serialize, ToSchema)]
+pub struct PathReport {
+    /// Addresses of this family the host resolved to
+    pub addresses: Vec<String>,
+    pub reachable: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub latency_ms: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+/// Result of probing one host over IPv4 and IPv6
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct HostReport {
+    pub host: String,
+    pub ipv4: PathReport,
+    pub ipv6: PathReport,
+    /// Family of the first resolved address, which git and the HTTP client
+    /// try first; `None` when the host does not resolve
+    pub preferred: Option<&'static str>,
+    /// Reachable over at least one family
+    pub reachable: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub warning: Option<String>,
+}
+
+fn family(address: &SocketAddr) -> &'static str {
+    if address.is_ipv4() { "ipv4" } else { "ipv6" }
+}
+
+fn probe_family(addresses: &[SocketAddr], timeout: Duration) -> PathReport {
+    let mut report = PathReport {
+        addresses: addresses.iter().map(|address| address.ip().to_string()).collect(),
+        ..PathReport::default()
+    };
+    let Some(address) = addresses.first() else {
+        report.error = Some("no addresses".to_string());
+        return report;
+    };
+    let started = Instant::now();
+    match TcpStream::connect_timeout(address, timeout) {
+        Ok(_) => {
+            report.reachable = true;
+            report.latency_ms = Some(started.elapsed().as_millis() as u64);
+        }
+        Err(e) => report.error = Some(format!("{}: {}", address, e)),
+    }
+    report
+}
+
+/// Resolves `host` and connects to `port` over each address family
+pub fn probe_host(host: &str, port: u16, timeout: Duration) -> HostReport {
+    let resolved: Vec<SocketAddr> = match (host, port).to_socket_addrs() {
+        Ok(addresses) => addresses.collect(),
+        Err(e) => {
+            let failed = PathReport { error: Some(format!("resolution failed: {}", e)), ..PathReport::default() };
+            return HostReport {
+                host: host.to_string(),
+                ipv4: failed.clone(),
+                ipv6: failed,
+                preferred: None,
+                reachable: false,
+                warning: None,
+            };
+        }
+    };
+    let (v4, v6): (Vec<SocketAddr>, Vec<SocketAddr>) = resolved.iter().partition(|address| address.is_ipv4());
+    let ipv4 = probe_family(&v4, timeout);
+    let ipv6 = probe_family(&v6, timeout);
+    let preferred = resolved.first().map(family);
+    let preferred_reachable = match preferred {
+        Some("ipv4") => ipv4.reachable,
+        Some(_) => ipv6.reachable,
+        None => false,
+    };
+    let reachable = ipv4.reachable || ipv6.reachable;
+    // The broken-IPv6 case: connections are slow or fail before falling back, if at all
+    let warning = (reachable && !preferred_reachable).then(|| {
+        format!("{} is preferred but unreachable; connections depend on falling back to the other family", preferred.unwrap_or("?"))
+    });
+    HostReport { host: host.to_string(), ipv4, ipv6, preferred, reachable, warning }
+}
+
+/// Probes the forge hosts of `platforms`, logging problems
+pub fn check_forges(platforms: &[&str]) -> Vec<HostReport> {
+    let timeout = probe_timeout();
+    let reports: Vec<HostReport> = platforms
+        .iter()
+        .flat_map(|platform| forge_hosts(platform).iter())
+        .map(|host| probe_host(host, HTTPS_PORT, timeout))
+        .collect();
+    for report in &reports {
+        match (&report.warning, report.reachable) {
+            (_, false) => warn!("Connectivity: {} unreachable (ipv4: {:?}, ipv6: {:?})", report.host, report.ipv4.error, report.ipv6.error),
+            (Some(warning), true) => warn!("Connectivity: {}: {}", report.host, warning),
+            (None, true) => info!("Connectivity: {} reachable over {}", report.host, report.preferred.unwrap_or("?")),
+        }
+    }
+    reports
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::net::TcpListener;
+
+    #[test]
+    fn test_probe_reports_each_family() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let report = probe_host("127.0.0.1", port, Duration::from_secs(1));
+        assert!(report.reachable && report.ipv4.reachable);
+        assert_eq!(report.preferred, Some("ipv4"));
+        assert!(report.ipv6.addresses.is_empty() && !report.ipv6.reachable);
+        assert!(report.warning.is_none());
+
+        drop(listener);
+        let closed = probe_host("127.0.0.1", port, Duration::from_secs(1));
+        assert!(!closed.reachable);
+        assert!(closed.ipv4.error.is_some());
+    }
+}
@@ -0,0 +1,89 @@
+use serde::{Deserialize, Serialize};
+
+use crate::utils::templates::{CommentTemplates, MessageKind};
+
+const SIGN_OFF_TRAILER: &str = "Signed-off-by:";
+
+/// Developer Certificate of Origin policy applied to backported commits
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum DcoPolicy {
+    /// Commits are backported as they are
+    #[default]
+    Off,
+    /// The bot adds its own `Signed-off-by:` trailer to every backported commit
+    Add,
+    /// Commits without a `Signed-off-by:` trailer fail the backport
+    Require,
+}
+
+/// Returns true when the message carries at least one `Signed-off-by:` trailer
+pub fn has_sign_off(message: &str) -> bool {
+    message
+        .lines()
+        .any(|line| line.trim_start().starts_with(SIGN_OFF_TRAILER))
+}
+
+/// Appends a `Signed-off-by:` trailer for `name <email>` unless it is already present
+pub fn add_sign_off(message: &str, name: &str, email: &str) -> String {
+    let trailer = format!("{} {} <{}>", SIGN_OFF_TRAILER, name, email);
+    if message.lines().any(|line| line.trim() == trailer) {
+        return message.to_string();
+    }
+
+    let mut output = message.trim_end().to_string();
+    // Join an existing trailer block instead of starting a new paragraph
+    let last_line = output.lines().last().unwrap_or("");
+    if is_trailer(last_line) {
+        output.push('\n');
+    } else {
+        output.push_str("\n\n");
+    }
+    output.push_str(&trailer);
+    output.push('\n');
+    output
+}
+
+fn is_trailer(line: &str) -> bool {
+    // The bot's own cherry-pick marker is treated as part of the trailer block
+    if line.starts_with("Cherry-picked from: ") {
+        return true;
+    }
+    match line.split_once(": ") {
+        Some((key, _)) => !key.is_empty() && key.chars().all(|c| c.is_ascii_alphanumeric() || c == '-'),
+        None => false,
+    }
+}
+
+/// Renders the PR comment listing commits that lack a sign-off
+pub fn format_missing_sign_off_comment(commits: &[String], templates: &CommentTemplates) -> String {
+    let list: String = commits.iter().map(|sha| format!("- {}\n", sha)).collect();
+    templates.render(MessageKind::MissingSignOff, &[("commits", &list)])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_has_sign_off() {
+        assert!(has_sign_off("Fix bug\n\nSigned-off-by: Dev <dev@example.com>\n"));
+        assert!(!has_sign_off("Fix bug\n\nReviewed-by: Dev <dev@example.com>\n"));
+    }
+
+    #[test]
+    fn test_add_sign_off() {
+        let message = "Fix bug\n\nCherry-picked from: https://gitcode.com/org/repo/pull/1";
+        assert_eq!(
+            add_sign_off(message, "Bot", "bot@example.com"),
+            "Fix bug\n\nCherry-picked from: https://gitcode.com/org/repo/pull/1\nSigned-off-by: Bot <bot@example.com>\n"
+        );
+        assert_eq!(
+            add_sign_off("Fix bug", "Bot", "bot@example.com"),
+            "Fix bug\n\nSigned-off-by: Bot <bot@example.com>\n"
+        );
+
+        let signed = add_sign_off("Fix bug", "Bot", "bot@example.com");
+        assert_eq!(add_sign_off(&signed, "Bot", "bot@example.com"), signed);
+    }
+}
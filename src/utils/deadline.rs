@@ -0,0 +1,155 @@
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::env;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex, OnceLock};
+use std::time::{Duration, Instant};
+use git2::RemoteCallbacks;
+use uuid::Uuid;
+use log::warn;
+
+use crate::error::{Error, Result};
+
+/// Overall deadline of a job from `JOB_TIMEOUT_SECS` (default 1800, `0` disables)
+pub fn job_timeout() -> Option<Duration> {
+    let secs = env::var("JOB_TIMEOUT_SECS")
+        .ok()
+        .and_then(|value| value.parse::<u64>().ok())
+        .unwrap_or(1800);
+    (secs > 0).then(|| Duration::from_secs(secs))
+}
+
+/// Cancellation state of one job, shared between the worker running it and
+/// whoever cancels it
+#[derive(Debug, Clone)]
+pub struct CancellationToken {
+    timeout: Option<Duration>,
+    deadline: Option<Instant>,
+    cancelled: Arc<AtomicBool>,
+}
+
+impl CancellationToken {
+    /// A token that expires `timeout` from now, never when `None`
+    pub fn new(timeout: Option<Duration>) -> CancellationToken {
+        CancellationToken {
+            timeout,
+            deadline: timeout.map(|timeout| Instant::now() + timeout),
+            cancelled: Arc::new(AtomicBool::new(false)),
+        }
+    }
+
+    pub fn cancel(&self) {
+        self.cancelled.store(true, Ordering::SeqCst);
+    }
+
+    /// Whether the job was cancelled or ran past its deadline
+    pub fn is_cancelled(&self) -> bool {
+        self.cancelled.load(Ordering::SeqCst) || self.deadline.is_some_and(|deadline| Instant::now() >= deadline)
+    }
+
+    /// Fails with an explanation once the token is cancelled; `stage` names
+    /// the pipeline stage about to start
+    pub fn check(&self, stage: &str) -> Result<()> {
+        if !self.is_cancelled() {
+            return Ok(());
+        }
+        let reason = match self.timeout {
+            Some(timeout) if !self.cancelled.load(Ordering::SeqCst) => format!("exceeded its deadline of {}s", timeout.as_secs()),
+            _ => "was cancelled".to_string(),
+        };
+        warn!("Job {} before {}", reason, stage);
+        Err(Error::Job(format!("Job {} before {}", reason, stage)))
+    }
+}
+
+thread_local! {
+    static CURRENT: RefCell<Option<CancellationToken>> = const { RefCell::new(None) };
+}
+
+fn running() -> &'static Mutex<HashMap<Uuid, CancellationToken>> {
+    static RUNNING: OnceLock<Mutex<HashMap<Uuid, CancellationToken>>> = OnceLock::new();
+    RUNNING.get_or_init(|| Mutex::new(HashMap::new()))
+}
+
+/// Makes a job's token current on this thread until dropped
+pub struct JobScope {
+    job_id: Uuid,
+    previous: Option<CancellationToken>,
+}
+
+impl Drop for JobScope {
+    fn drop(&mut self) {
+        running().lock().unwrap().remove(&self.job_id);
+        let previous = self.previous.take();
+        CURRENT.with(|current| *current.borrow_mut() = previous);
+    }
+}
+
+/// Starts the deadline of `job_id` on the current thread
+///
+/// Git transfers started while the returned scope lives are aborted once the
+/// deadline passes, and `check` fails between pipeline stages.
+pub fn start(job_id: Uuid, timeout: Option<Duration>) -> JobScope {
+    let token = CancellationToken::new(timeout);
+    running().lock().unwrap().insert(job_id, token.clone());
+    let previous = CURRENT.with(|current| current.borrow_mut().replace(token));
+    JobScope { job_id, previous }
+}
+
+/// Cancels a running job; returns false when no such job is running
+pub fn cancel(job_id: Uuid) -> bool {
+    match running().lock().unwrap().get(&job_id) {
+        Some(token) => {
+            token.cancel();
+            true
+        }
+        None => false,
+    }
+}
+
+/// Checks the current job's token before `stage`; always passes outside a job
+pub fn check(stage: &str) -> Result<()> {
+    CURRENT.with(|current| match current.borrow().as_ref() {
+        Some(token) => token.check(stage),
+        None => Ok(()),
+    })
+}
+
+/// Whether the current job was cancelled or ran past its deadline
+pub fn expired() -> bool {
+    CURRENT.with(|current| current.borrow().as_ref().is_some_and(CancellationToken::is_cancelled))
+}
+
+/// Aborts fetches and pushes using `callbacks` once the current job expires
+///
+/// libgit2 calls the progress callbacks on the thread running the transfer,
+/// which is the job's thread.
+pub fn watch_transfers(callbacks: &mut RemoteCallbacks<'_>) {
+    callbacks.transfer_progress(|_| !expired());
+    callbacks.sideband_progress(|_| !expired());
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_scopes_expire_and_cancel_jobs() {
+        assert!(check("clone").is_ok());
+        let job = Uuid::new_v4();
+        {
+            let _scope = start(job, Some(Duration::ZERO));
+            assert!(expired());
+            let error = check("clone").unwrap_err();
+            assert_eq!(error.to_string(), "Job exceeded its deadline of 0s before clone");
+        }
+        assert!(!expired());
+        assert!(!cancel(job));
+
+        let job = Uuid::new_v4();
+        let _scope = start(job, None);
+        assert!(check("push").is_ok());
+        assert!(cancel(job));
+        assert_eq!(check("push").unwrap_err().to_string(), "Job was cancelled before push");
+    }
+}
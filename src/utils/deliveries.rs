@@ -0,0 +1,121 @@
+use std::collections::BTreeMap;
+use std::env;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex, OnceLock};
+use std::time::Duration;
+use chrono::{DateTime, Utc};
+use log::error;
+
+use crate::utils::clock::{self, Clock};
+use crate::utils::{state, state_store};
+
+/// How long a delivery ID is remembered, from `DELIVERY_TTL_SECS` (default 86400)
+pub fn delivery_ttl() -> Duration {
+    let secs = env::var("DELIVERY_TTL_SECS")
+        .ok()
+        .and_then(|value| value.parse::<u64>().ok())
+        .unwrap_or(86400);
+    Duration::from_secs(secs)
+}
+
+/// IDs of recently handled webhook deliveries, persisted as JSON in
+/// `deliveries.json` so that redeliveries are recognized across restarts
+pub struct DeliveryStore {
+    path: PathBuf,
+    /// Delivery key -> when it was first seen
+    seen: BTreeMap<String, DateTime<Utc>>,
+    ttl: Duration,
+    clock: Arc<dyn Clock>,
+}
+
+impl DeliveryStore {
+    pub fn open(path: &Path) -> DeliveryStore {
+        let seen = match state_store::read_document(path) {
+            Ok(Some(contents)) => serde_json::from_str(&contents).unwrap_or_else(|e| {
+                error!("Failed to parse delivery cache {:?}, starting empty: {}", path, e);
+                BTreeMap::new()
+            }),
+            _ => BTreeMap::new(),
+        };
+        DeliveryStore { path: path.to_path_buf(), seen, ttl: delivery_ttl(), clock: clock::system() }
+    }
+
+    /// Uses `clock` to expire deliveries (a `MockClock` in tests)
+    pub fn with_clock(mut self, clock: Arc<dyn Clock>) -> Self {
+        self.clock = clock;
+        self
+    }
+
+    pub fn with_ttl(mut self, ttl: Duration) -> Self {
+        self.ttl = ttl;
+        self
+    }
+
+    fn save(&self) {
+        let result = serde_json::to_string_pretty(&self.seen)
+            .map_err(std::io::Error::from)
+            .and_then(|contents| state_store::write_document(&self.path, &contents));
+        if let Err(e) = result {
+            error!("Failed to persist delivery cache {:?}: {}", self.path, e);
+        }
+    }
+
+    /// Records the delivery `key`; false when it was already seen within the
+    /// TTL, in which case it must not be processed again
+    pub fn begin(&mut self, key: &str) -> bool {
+        let now = self.clock.now();
+        let ttl = chrono::Duration::from_std(self.ttl).unwrap_or(chrono::Duration::MAX);
+        self.seen.retain(|_, seen_at| seen_at.checked_add_signed(ttl).is_none_or(|expires| expires > now));
+        if self.seen.contains_key(key) {
+            return false;
+        }
+        self.seen.insert(key.to_string(), now);
+        self.save();
+        true
+    }
+
+    /// Forgets a delivery whose processing failed, so that a redelivery is
+    /// processed again
+    pub fn forget(&mut self, key: &str) {
+        if self.seen.remove(key).is_some() {
+            self.save();
+        }
+    }
+}
+
+/// Key of a delivery: IDs are only unique per forge
+pub fn delivery_key(platform: &str, id: &str) -> String {
+    format!("{}:{}", platform, id)
+}
+
+/// The process-wide delivery cache
+pub fn store() -> &'static Mutex<DeliveryStore> {
+    static STORE: OnceLock<Mutex<DeliveryStore>> = OnceLock::new();
+    STORE.get_or_init(|| Mutex::new(DeliveryStore::open(&state::state_dir().join("deliveries.json"))))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::clock::MockClock;
+
+    #[test]
+    fn test_redeliveries_are_skipped_until_they_expire() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("deliveries.json");
+        let clock = MockClock::new("2024-06-01T08:00:00Z".parse().unwrap());
+        let mut store = DeliveryStore::open(&path).with_clock(Arc::new(clock.clone())).with_ttl(Duration::from_secs(3600));
+        let key = delivery_key("github", "72d3162e-cc78-11e3-81ab-4c9367dc0958");
+        assert!(store.begin(&key));
+        assert!(!store.begin(&key));
+        assert!(store.begin(&delivery_key("gitcode", "72d3162e-cc78-11e3-81ab-4c9367dc0958")));
+
+        let mut store = DeliveryStore::open(&path).with_clock(Arc::new(clock.clone())).with_ttl(Duration::from_secs(3600));
+        assert!(!store.begin(&key));
+        store.forget(&key);
+        assert!(store.begin(&key));
+
+        clock.advance(Duration::from_secs(3600));
+        assert!(store.begin(&key));
+    }
+}
@@ -0,0 +1,196 @@
+use std::env;
+use std::path::{Path, PathBuf};
+use std::sync::{Mutex, OnceLock};
+use std::time::Duration;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+use serde_json::json;
+use uuid::Uuid;
+use log::{info, error};
+
+use crate::error::Result;
+use crate::utils::{http_client, payload_signing, redact, state, state_store};
+use crate::utils::body::WebhookBody;
+
+/// Number of dead letters kept; the oldest are dropped beyond this
+const MAX_DEAD_LETTERS: usize = 500;
+
+/// A verified webhook as received, enough to process it again
+#[derive(Debug, Clone, Copy)]
+pub struct Delivery<'a> {
+    pub platform: &'a str,
+    /// Event header of the webhook (e.g. `pull_request`, `Push Hook`)
+    pub event: &'a str,
+    pub body: &'a WebhookBody,
+}
+
+/// A job that kept failing transiently until its retries were used up
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct DeadLetter {
+    pub job_id: Uuid,
+    pub platform: String,
+    pub repo: String,
+    pub event: String,
+    pub error: String,
+    pub failed_at: DateTime<Utc>,
+    /// Webhook body as received, or anonymized under `REDACT_PAYLOADS`
+    pub body: String,
+    /// Original body encrypted with the service key when `body` is redacted
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub sealed_body: Option<String>,
+    /// Signature of the original payload, checked before it is replayed
+    #[serde(default)]
+    pub signature: Option<String>,
+}
+
+impl DeadLetter {
+    /// Fails when a spooled body cannot be read back
+    pub fn new(job_id: Uuid, repo: &str, delivery: Delivery, error: &str) -> std::io::Result<Self> {
+        let body = delivery.body.text()?;
+        let (stored, sealed_body) = redact::for_storage(body.clone());
+        Ok(DeadLetter {
+            job_id,
+            platform: delivery.platform.to_string(),
+            repo: repo.to_string(),
+            event: delivery.event.to_string(),
+            error: error.to_string(),
+            failed_at: Utc::now(),
+            signature: payload_signing::sign(delivery.platform, delivery.event, &body),
+            body: stored,
+            sealed_body,
+        })
+    }
+
+    /// Checks that the stored payload was not modified since it was queued and
+    /// returns the original body to replay
+    pub fn verify(&self) -> Result<String> {
+        let body = redact::original(&self.body, self.sealed_body.as_deref())?;
+        payload_signing::verify(&self.platform, &self.event, &body, self.signature.as_deref())?;
+        Ok(body)
+    }
+}
+
+/// Dead letters persisted as JSON in `dlq.json` under the state directory
+pub struct DeadLetterQueue {
+    path: PathBuf,
+    letters: Vec<DeadLetter>,
+}
+
+impl DeadLetterQueue {
+    pub fn open(path: &Path) -> DeadLetterQueue {
+        let letters = match state_store::read_document(path) {
+            Ok(Some(contents)) => serde_json::from_str(&contents).unwrap_or_else(|e| {
+                error!("Failed to parse dead-letter queue {:?}, starting empty: {}", path, e);
+                Vec::new()
+            }),
+            _ => Vec::new(),
+        };
+        DeadLetterQueue { path: path.to_path_buf(), letters }
+    }
+
+    fn save(&self) {
+        let result = serde_json::to_string_pretty(&self.letters)
+            .map_err(std::io::Error::from)
+            .and_then(|contents| state_store::write_document(&self.path, &contents));
+        if let Err(e) = result {
+            error!("Failed to persist dead-letter queue {:?}: {}", self.path, e);
+        }
+    }
+
+    pub fn push(&mut self, letter: DeadLetter) {
+        self.letters.push(letter);
+        if self.letters.len() > MAX_DEAD_LETTERS {
+            let excess = self.letters.len() - MAX_DEAD_LETTERS;
+            self.letters.drain(..excess);
+        }
+        self.save();
+    }
+
+    /// Returns the dead letters, most recent first
+    pub fn list(&self) -> Vec<DeadLetter> {
+        self.letters.iter().rev().cloned().collect()
+    }
+
+    /// Removes and returns the dead letter of `job_id` so it can be requeued
+    pub fn take(&mut self, job_id: Uuid) -> Option<DeadLetter> {
+        let index = self.letters.iter().position(|letter| letter.job_id == job_id)?;
+        let letter = self.letters.remove(index);
+        self.save();
+        Some(letter)
+    }
+
+    /// Removes and returns every dead letter, oldest first
+    pub fn take_all(&mut self) -> Vec<DeadLetter> {
+        let letters = std::mem::take(&mut self.letters);
+        self.save();
+        letters
+    }
+}
+
+/// The process-wide dead-letter queue
+pub fn queue() -> &'static Mutex<DeadLetterQueue> {
+    static QUEUE: OnceLock<Mutex<DeadLetterQueue>> = OnceLock::new();
+    QUEUE.get_or_init(|| Mutex::new(DeadLetterQueue::open(&state::state_dir().join("dlq.json"))))
+}
+
+/// Posts a short notice about a new dead letter to `DLQ_NOTIFY_URL`
+///
+/// The payload is `{"text": ...}`, accepted by Slack-compatible incoming
+/// webhooks. Does nothing when the variable is unset.
+pub fn notify(letter: &DeadLetter) {
+    let text = format!(
+        "Backport job {} for {}/{} ({}) failed on every attempt and was moved to the dead-letter queue: {}",
+        letter.job_id, letter.platform, letter.repo, letter.event, letter.error
+    );
+    notify_text(&text, &format!("dead letter {}", letter.job_id));
+}
+
+/// Posts `text` to `DLQ_NOTIFY_URL`; `subject` names it in the log
+pub fn notify_text(text: &str, subject: &str) {
+    let url = match env::var("DLQ_NOTIFY_URL") {
+        Ok(url) if !url.is_empty() => url,
+        _ => return,
+    };
+    if !cfg!(feature = "notifications") {
+        info!("Not sending notification about {}: built without the notifications feature", subject);
+        return;
+    }
+    let result = http_client::blocking()
+        .post(&url)
+        .timeout(Duration::from_secs(10))
+        .json(&json!({ "text": text }))
+        .send()
+        .and_then(|response| response.error_for_status());
+    match result {
+        Ok(_) => info!("Notified {} about {}", url, subject),
+        Err(e) => error!("Failed to send notification about {}: {}", subject, e),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_dead_letters_are_persisted_and_taken() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("dlq.json");
+        let body = WebhookBody::from("{}".to_string());
+        let delivery = Delivery { platform: "gitcode", event: "Merge Request Hook", body: &body };
+        let first = Uuid::new_v4();
+        let second = Uuid::new_v4();
+
+        let mut queue = DeadLetterQueue::open(&path);
+        queue.push(DeadLetter::new(first, "repo", delivery, "push rejected").unwrap());
+        queue.push(DeadLetter::new(second, "repo", delivery, "timeout").unwrap());
+
+        let mut reopened = DeadLetterQueue::open(&path);
+        assert_eq!(reopened.list()[0].job_id, second);
+        let taken = reopened.take(first).unwrap();
+        assert_eq!(taken.error, "push rejected");
+        assert_eq!(taken.body, "{}");
+        assert!(reopened.take(first).is_none());
+        assert_eq!(DeadLetterQueue::open(&path).list().len(), 1);
+    }
+}
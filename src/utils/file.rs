@@ -1,6 +1,8 @@
 use std::fs;
 use std::path::{Path, PathBuf};
 use std::io;
+use serde::{Deserialize, Serialize};
+use log::{info, error};
 
 pub fn create_empty_folder(path: &Path) -> io::Result<()> {
     if path.exists() {
@@ -20,6 +22,64 @@ pub fn delete_folder(folder_path: &PathBuf) -> Result<(), std::io::Error> {
     Ok(())
 }
 
+/// When a processor's working copy is deleted
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum CleanupPolicy {
+    /// Delete the workspace whatever the outcome
+    Always,
+    /// Keep the workspace of failed jobs for inspection
+    #[default]
+    OnSuccess,
+    /// Never delete the workspace
+    Never,
+}
+
+/// Deletes a workspace on drop according to a `CleanupPolicy`
+///
+/// The job counts as failed unless `succeed` is called, so early returns via
+/// `?` keep the workspace under `on-success`.
+pub struct WorkspaceGuard {
+    path: PathBuf,
+    policy: CleanupPolicy,
+    succeeded: bool,
+}
+
+impl WorkspaceGuard {
+    pub fn new(path: &Path, policy: CleanupPolicy) -> Self {
+        WorkspaceGuard { path: path.to_path_buf(), policy, succeeded: false }
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Marks the job as successful
+    pub fn succeed(&mut self) {
+        self.succeeded = true;
+    }
+}
+
+impl Drop for WorkspaceGuard {
+    fn drop(&mut self) {
+        let remove = match self.policy {
+            CleanupPolicy::Always => true,
+            CleanupPolicy::OnSuccess => self.succeeded,
+            CleanupPolicy::Never => false,
+        };
+        if !remove {
+            info!("Keeping workspace {} (policy {:?}, succeeded: {})", self.path.display(), self.policy, self.succeeded);
+            return;
+        }
+        if self.path.exists() {
+            match fs::remove_dir_all(&self.path) {
+                Ok(()) => info!("Removed workspace {}", self.path.display()),
+                Err(e) => error!("Failed to remove workspace {}: {}", self.path.display(), e),
+            }
+        }
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -38,4 +98,34 @@ mod tests {
         assert!(result.is_ok());
         assert!(!temp_dir.exists());
     }
+
+    #[test]
+    fn test_workspace_guard_policies() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let workspace = |name: &str| {
+            let path = temp_dir.path().join(name);
+            std::fs::create_dir_all(&path).unwrap();
+            path
+        };
+
+        let failed = workspace("failed");
+        drop(WorkspaceGuard::new(&failed, CleanupPolicy::OnSuccess));
+        assert!(failed.exists());
+
+        let succeeded = workspace("succeeded");
+        let mut guard = WorkspaceGuard::new(&succeeded, CleanupPolicy::OnSuccess);
+        guard.succeed();
+        drop(guard);
+        assert!(!succeeded.exists());
+
+        let always = workspace("always");
+        drop(WorkspaceGuard::new(&always, CleanupPolicy::Always));
+        assert!(!always.exists());
+
+        let never = workspace("never");
+        let mut guard = WorkspaceGuard::new(&never, CleanupPolicy::Never);
+        guard.succeed();
+        drop(guard);
+        assert!(never.exists());
+    }
 }
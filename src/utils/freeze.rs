@@ -0,0 +1,289 @@
+use std::collections::HashMap;
+use std::sync::{Mutex, OnceLock};
+use std::time::Duration;
+use chrono::{DateTime, NaiveDate, NaiveDateTime, TimeZone, Utc};
+use chrono_tz::Tz;
+use log::{info, error};
+
+use crate::error::{Error, Result};
+use crate::utils::clock::{Clock, SystemClock};
+use crate::utils::http_client;
+use crate::utils::config::{self, RepoConfig};
+
+/// How long a fetched calendar is trusted before it is fetched again
+const CALENDAR_TTL: Duration = Duration::from_secs(15 * 60);
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct FreezeWindow {
+    pub start: DateTime<Utc>,
+    pub end: DateTime<Utc>,
+    pub summary: Option<String>,
+}
+
+struct CachedCalendar {
+    fetched_at: DateTime<Utc>,
+    windows: Vec<FreezeWindow>,
+}
+
+fn cache() -> &'static Mutex<HashMap<String, CachedCalendar>> {
+    static CACHE: OnceLock<Mutex<HashMap<String, CachedCalendar>>> = OnceLock::new();
+    CACHE.get_or_init(|| Mutex::new(HashMap::new()))
+}
+
+/// Joins folded ICS content lines (RFC 5545 section 3.1)
+fn unfold_lines(contents: &str) -> Vec<String> {
+    let mut lines: Vec<String> = Vec::new();
+    for raw in contents.lines() {
+        let raw = raw.trim_end_matches('\r');
+        if let Some(rest) = raw.strip_prefix(' ').or_else(|| raw.strip_prefix('\t')) {
+            if let Some(last) = lines.last_mut() {
+                last.push_str(rest);
+                continue;
+            }
+        }
+        lines.push(raw.to_string());
+    }
+    lines
+}
+
+/// Parses a DTSTART/DTEND property into a UTC timestamp
+///
+/// Supports UTC (`...Z`), `TZID=` qualified, floating and all-day (`VALUE=DATE`) values.
+fn parse_date_property(params: &str, value: &str, default_tz: Tz) -> Option<DateTime<Utc>> {
+    let tz = params
+        .split(';')
+        .find_map(|param| param.strip_prefix("TZID="))
+        .and_then(|name| name.trim_matches('"').parse::<Tz>().ok())
+        .unwrap_or(default_tz);
+
+    if let Some(utc_value) = value.strip_suffix('Z') {
+        let naive = NaiveDateTime::parse_from_str(utc_value, "%Y%m%dT%H%M%S").ok()?;
+        return Some(Utc.from_utc_datetime(&naive));
+    }
+
+    let naive = match NaiveDateTime::parse_from_str(value, "%Y%m%dT%H%M%S") {
+        Ok(naive) => naive,
+        Err(_) => NaiveDate::parse_from_str(value, "%Y%m%d").ok()?.and_hms_opt(0, 0, 0)?,
+    };
+    tz.from_local_datetime(&naive)
+        .earliest()
+        .map(|local| local.with_timezone(&Utc))
+}
+
+/// Extracts freeze windows from the VEVENT entries of an ICS calendar
+pub fn parse_ics(contents: &str, default_tz: Tz) -> Vec<FreezeWindow> {
+    let mut windows = Vec::new();
+    let mut in_event = false;
+    let mut start = None;
+    let mut end = None;
+    let mut summary = None;
+
+    for line in unfold_lines(contents) {
+        let (name_and_params, value) = match line.split_once(':') {
+            Some(parts) => parts,
+            None => continue,
+        };
+        let (name, params) = name_and_params
+            .split_once(';')
+            .unwrap_or((name_and_params, ""));
+
+        match (name.to_ascii_uppercase().as_str(), value) {
+            ("BEGIN", "VEVENT") => {
+                in_event = true;
+                start = None;
+                end = None;
+                summary = None;
+            }
+            ("END", "VEVENT") => {
+                in_event = false;
+                match (start, end) {
+                    (Some(start), Some(end)) if end > start => windows.push(FreezeWindow {
+                        start,
+                        end,
+                        summary: summary.take(),
+                    }),
+                    _ => error!("Ignoring freeze event without a valid DTSTART/DTEND"),
+                }
+            }
+            ("DTSTART", _) if in_event => start = parse_date_property(params, value, default_tz),
+            ("DTEND", _) if in_event => end = parse_date_property(params, value, default_tz),
+            ("SUMMARY", _) if in_event => summary = Some(value.to_string()),
+            _ => {}
+        }
+    }
+
+    windows
+}
+
+/// Returns the freeze window covering `now`, if any
+pub fn active_window(windows: &[FreezeWindow], now: DateTime<Utc>) -> Option<&FreezeWindow> {
+    windows.iter().find(|window| window.start <= now && now < window.end)
+}
+
+fn default_timezone(repo_config: &RepoConfig) -> Tz {
+    repo_config
+        .freeze_timezone
+        .as_deref()
+        .and_then(|name| name.parse::<Tz>().ok())
+        .unwrap_or(Tz::UTC)
+}
+
+/// Fetches the calendar of a repository and stores it in the cache
+pub fn refresh_calendar(repo_config: &RepoConfig, clock: &dyn Clock) -> Result<Vec<FreezeWindow>> {
+    let url = repo_config.freeze_calendar.as_deref().ok_or_else(|| Error::config("No freeze calendar configured"))?;
+    info!("Fetching freeze calendar from {}", url);
+
+    let response = http_client::blocking().get(url).timeout(http_client::timeout_for("freeze_calendar")).send()?;
+    let status = response.status();
+    if !status.is_success() {
+        return Err(Error::HttpApi { status: Some(status.as_u16()), message: format!("Freeze calendar request failed with status {}", status) });
+    }
+    let windows = parse_ics(&response.text()?, default_timezone(repo_config));
+    info!("Loaded {} freeze windows from {}", windows.len(), url);
+
+    cache().lock().unwrap().insert(url.to_string(), CachedCalendar {
+        fetched_at: clock.now(),
+        windows: windows.clone(),
+    });
+    Ok(windows)
+}
+
+/// Whether a calendar fetched at `fetched_at` is still within its TTL; one
+/// fetched "in the future", after the clock was set back, is stale
+fn is_fresh(fetched_at: DateTime<Utc>, now: DateTime<Utc>) -> bool {
+    (now - fetched_at).to_std().is_ok_and(|age| age < CALENDAR_TTL)
+}
+
+/// Returns the active freeze window for a repository
+///
+/// Uses the cached calendar while it is fresh. When the calendar cannot be
+/// fetched, a stale cached copy is used; without one, processing is allowed.
+pub fn check_freeze(repo_config: &RepoConfig, clock: &dyn Clock) -> Option<FreezeWindow> {
+    let url = repo_config.freeze_calendar.as_deref()?;
+    let now = clock.now();
+    let cached = cache().lock().unwrap().get(url).map(|entry| (is_fresh(entry.fetched_at, now), entry.windows.clone()));
+
+    let windows = match cached {
+        Some((true, windows)) => windows,
+        Some((false, stale)) => refresh_calendar(repo_config, clock).unwrap_or_else(|e| {
+            error!("Failed to refresh freeze calendar {}, using cached copy: {}", url, e);
+            stale
+        }),
+        None => refresh_calendar(repo_config, clock).unwrap_or_else(|e| {
+            error!("Failed to fetch freeze calendar {}: {}", url, e);
+            Vec::new()
+        }),
+    };
+
+    active_window(&windows, now).cloned()
+}
+
+/// Processing gate: returns a message when the repository is currently frozen
+pub fn freeze_gate(namespace: &str, repo_name: &str, clock: &dyn Clock) -> Option<String> {
+    let repo_config = match config::load_repo_config_in(namespace, repo_name) {
+        Ok(repo_config) => repo_config?,
+        Err(e) => {
+            error!("Failed to read config for freeze check: {}", e);
+            return None;
+        }
+    };
+    check_freeze(&repo_config, clock).map(|window| {
+        format!(
+            "Repository {} is frozen until {} ({})",
+            repo_name,
+            window.end.to_rfc3339(),
+            window.summary.as_deref().unwrap_or("freeze window")
+        )
+    })
+}
+
+/// Periodically refreshes the freeze calendars of all configured repositories
+pub fn spawn_refresh_task(interval: Duration) {
+    tokio::spawn(async move {
+        let mut ticker = tokio::time::interval(interval);
+        loop {
+            ticker.tick().await;
+            let result = tokio::task::spawn_blocking(|| {
+                let config = match config::read_config(config::CONFIG_FILE) {
+                    Ok(config) => config,
+                    Err(e) => {
+                        error!("Freeze calendar refresh failed to read config: {}", e);
+                        return;
+                    }
+                };
+                for repo_config in config.repos.values() {
+                    if repo_config.freeze_calendar.is_some() {
+                        if let Err(e) = refresh_calendar(repo_config, &SystemClock) {
+                            error!("Failed to refresh freeze calendar for {}: {}", repo_config.repo_name, e);
+                        }
+                    }
+                }
+            }).await;
+            if let Err(e) = result {
+                error!("Freeze calendar refresh task failed: {}", e);
+            }
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::clock::MockClock;
+
+    const CALENDAR: &str = "BEGIN:VCALENDAR\r\n\
+BEGIN:VEVENT\r\n\
+SUMMARY:Release 1.2\r\n  freeze\r\n\
+DTSTART;TZID=Asia/Shanghai:20240601T080000\r\n\
+DTEND;TZID=Asia/Shanghai:20240603T080000\r\n\
+END:VEVENT\r\n\
+BEGIN:VEVENT\r\n\
+DTSTART;VALUE=DATE:20241001\r\n\
+DTEND;VALUE=DATE:20241008\r\n\
+END:VEVENT\r\n\
+END:VCALENDAR\r\n";
+
+    #[test]
+    fn test_parse_ics() {
+        let windows = parse_ics(CALENDAR, Tz::UTC);
+        assert_eq!(windows.len(), 2);
+        assert_eq!(windows[0].summary.as_deref(), Some("Release 1.2 freeze"));
+        assert_eq!(windows[0].start, Utc.with_ymd_and_hms(2024, 6, 1, 0, 0, 0).unwrap());
+        assert_eq!(windows[1].end, Utc.with_ymd_and_hms(2024, 10, 8, 0, 0, 0).unwrap());
+    }
+
+    #[test]
+    fn test_active_window() {
+        let windows = parse_ics(CALENDAR, Tz::UTC);
+        let inside = Utc.with_ymd_and_hms(2024, 6, 2, 12, 0, 0).unwrap();
+        let outside = Utc.with_ymd_and_hms(2024, 6, 3, 0, 0, 0).unwrap();
+        assert!(active_window(&windows, inside).is_some());
+        assert!(active_window(&windows, outside).is_none());
+    }
+
+    #[test]
+    fn test_check_freeze_with_mock_clock() {
+        let url = "http://calendar.invalid/test_check_freeze_with_mock_clock.ics";
+        let mut repo_config = RepoConfig::new("https://gitcode.com/org/target.git", "org", "repo");
+        repo_config.freeze_calendar = Some(url.to_string());
+
+        let clock = MockClock::new(Utc.with_ymd_and_hms(2024, 6, 2, 12, 0, 0).unwrap());
+        cache().lock().unwrap().insert(url.to_string(), CachedCalendar {
+            fetched_at: clock.now(),
+            windows: parse_ics(CALENDAR, Tz::UTC),
+        });
+        assert!(check_freeze(&repo_config, &clock).is_some());
+
+        // Within the cache TTL the calendar is not fetched again
+        clock.advance(Duration::from_secs(14 * 60));
+        assert!(check_freeze(&repo_config, &clock).is_some());
+        // After the window ends (keeping the cache fresh)
+        clock.advance(Duration::from_secs(12 * 60 * 60));
+        cache().lock().unwrap().get_mut(url).unwrap().fetched_at = clock.now();
+        assert!(check_freeze(&repo_config, &clock).is_none());
+
+        let now = clock.now();
+        assert!(is_fresh(now - chrono::Duration::minutes(1), now));
+        assert!(!is_fresh(now + chrono::Duration::minutes(1), now));
+    }
+}
@@ -1,24 +1,90 @@
-use std::path::PathBuf;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::path::{Path, PathBuf};
 use git2::{Repository, RemoteCallbacks, PushOptions};
 use std::env;
-use log::{info, error};
+use std::process::Command;
+use log::{info, warn, error};
 
-use crate::models::webhook::{ParsedWebhookData, Label, ParsedPushData};
-use crate::utils::{file, gitcode, config};
+use crate::models::webhook::{ParsedWebhookData, Label, ParsedPushData, MergeInfo};
+use crate::error::{Error, Result};
+use uuid::Uuid;
+use crate::utils::{file, gitcode, config, freeze, conflict, secrets, dco, retry, jobs, github_graphql, patches, branch, mentions, backports, status_labels, comment_queue, conflict_owner, commit_guard, metrics, http_headers, mirror, deadline, notes, gitlab, outbox, redact, patch_id, reopened, canary};
+use crate::utils::backports::BackportRecord;
+use crate::utils::comment_queue::PendingComment;
+use crate::utils::command::{self, CommandLimits};
+use crate::utils::patches::{PatchStore, StoredPatch};
+use crate::utils::retry::RetryPolicy;
+use crate::utils::clock::SystemClock;
+use crate::utils::dco::DcoPolicy;
+use crate::utils::reopened::ReopenedPolicy;
+use crate::utils::config::{CiTrigger, GitIdentity, RepoConfig};
+use crate::utils::remote_url::{CredentialKind, RemoteUrl};
+use crate::utils::file::WorkspaceGuard;
+use crate::utils::repo_cache::RepoCache;
+use crate::utils::templates::CommentTemplates;
+use crate::utils::conflict::{ConflictReport, RenameDetection};
+use crate::utils::commit_guard::{CommitGuards, GuardDecision};
+use crate::utils::gitcode::RepoAccess;
+use crate::utils::branch::BranchMapping;
+use crate::utils::mirror::RefChange;
 
-pub fn clone_repository(repo_url: &str, local_path: &PathBuf, platform: &str) -> Result<Repository, git2::Error> {
+/// Depth of shallow clones from `CLONE_DEPTH`; `0` (the default) clones full history
+pub fn clone_depth() -> i32 {
+    env::var("CLONE_DEPTH")
+        .ok()
+        .and_then(|value| value.parse().ok())
+        .filter(|depth: &i32| *depth > 0)
+        .unwrap_or(0)
+}
+
+/// Whether a clone failed because the server does not support shallow fetches
+fn is_shallow_unsupported(error: &git2::Error) -> bool {
+    let message = error.message().to_lowercase();
+    message.contains("shallow") || message.contains("depth")
+}
+
+fn clone_with_depth(repo_url: &str, local_path: &Path, depth: i32) -> Result<Repository, git2::Error> {
+    let mut callbacks = RemoteCallbacks::new();
+    deadline::watch_transfers(&mut callbacks);
+    let mut opts = git2::FetchOptions::new();
+    opts.remote_callbacks(callbacks);
+    if depth > 0 {
+        opts.depth(depth);
+    }
+    let mut builder = git2::build::RepoBuilder::new();
+    builder.fetch_options(opts);
+    builder.clone(repo_url, local_path)
+}
+
+pub fn clone_repository(repo_url: &str, local_path: &PathBuf, platform: &str) -> Result<Repository> {
     info!("Starting repository clone:");
     info!("  URL: {}", repo_url);
     info!("  Local path: {:?}", local_path);
     info!("  Platform: {}", platform);
 
-    // Set up Git configuration before cloning
-    let opts = git2::FetchOptions::new();
-    let mut builder = git2::build::RepoBuilder::new();
-    builder.fetch_options(opts);
+    let depth = clone_depth();
+    if depth > 0 {
+        info!("  Depth: {}", depth);
+        match clone_with_depth(repo_url, local_path, depth) {
+            Ok(repo) => {
+                info!("Repository cloned successfully (shallow)");
+                return Ok(repo);
+            }
+            Err(e) if is_shallow_unsupported(&e) => {
+                // Some self-hosted servers reject depth fetches; a full clone still works
+                error!("Server does not support shallow clones ({}), falling back to a full clone", e);
+                file::create_empty_folder(local_path)
+                    .map_err(|e| git2::Error::from_str(&format!("Failed to prepare directory: {}", e)))?;
+            }
+            Err(e) => {
+                error!("Failed to clone repository: {}", e);
+                return Err(e.into());
+            }
+        }
+    }
 
     // Clone the repository with specific options
-    let repo = builder.clone(repo_url, local_path).map_err(|e| {
+    let repo = clone_with_depth(repo_url, local_path, 0).map_err(|e| {
         error!("Failed to clone repository: {}", e);
         e
     })?;
@@ -27,16 +93,204 @@ pub fn clone_repository(repo_url: &str, local_path: &PathBuf, platform: &str) ->
     Ok(repo)
 }
 
-pub fn process_pr(webhook_data: &ParsedWebhookData) -> Result<String, git2::Error> {
-    // Check if action is "merge" and state is "merged"
+/// Clones a job workspace, from the repository cache when it is warm
+pub fn clone_workspace(webhook_data: &ParsedWebhookData, local_path: &PathBuf, platform: &str) -> Result<Repository> {
+    let repo_url = &webhook_data.repo_url;
+    let full_name = format!("{}/{}", webhook_data.namespace, webhook_data.repo_name);
+    match RepoCache::from_env().clone_workspace(platform, repo_url, &full_name, local_path) {
+        Ok(Some(repo)) => {
+            info!("Workspace cloned from repository cache");
+            return Ok(repo);
+        }
+        Ok(None) => {}
+        Err(e) => {
+            error!("Cloning from repository cache failed, cloning from {}: {}", repo_url, e);
+            file::create_empty_folder(local_path)?;
+        }
+    }
+    clone_repository(repo_url, local_path, platform)
+}
+
+/// Resolves target branches from the repository configuration: labels listed
+/// in `branch_labels` get the configured branch (a `br:` label's description
+/// is only the fallback) and version labels add `br:` labels when
+/// `version_labels` is set; `None` when nothing changes
+fn with_configured_branches(webhook_data: &ParsedWebhookData) -> Option<ParsedWebhookData> {
+    let repo_config = config::load_repo_config_in(&webhook_data.namespace, &webhook_data.repo_name).ok()??;
+    let mut expanded = webhook_data.clone();
+    let mut changed = false;
+
+    let mut added: Vec<Label> = Vec::new();
+    for label in expanded.labels.iter_mut() {
+        let branch = match branch::configured_branch(&label.title, &repo_config.branch_labels) {
+            Ok(Some(branch)) => branch,
+            Ok(None) => continue,
+            Err(e) => {
+                error!("Invalid branch_labels pattern for {}: {}", webhook_data.repo_name, e);
+                break;
+            }
+        };
+        if label.title.starts_with("br:") {
+            if label.description.as_deref() != Some(branch.as_str()) {
+                info!("Label {} maps to branch {} in the configuration", label.title, branch);
+                label.description = Some(branch);
+                changed = true;
+            }
+        } else {
+            added.push(Label { title: format!("br: {}", branch), description: Some(branch), r#type: None, source: Some(label.title.clone()) });
+        }
+    }
+
+    if let Some(mapping) = &repo_config.version_labels {
+        match branch::version_branches(&webhook_data.labels, mapping) {
+            Ok(branches) => added.extend(branches.into_iter().map(|(branch, source)| {
+                Label { title: format!("br: {}", branch), description: Some(branch), r#type: None, source: Some(source) }
+            })),
+            Err(e) => error!("Invalid version_labels pattern for {}: {}", webhook_data.repo_name, e),
+        }
+    }
+
+    let mut requested = status_labels::requested_branches(&expanded);
+    for label in added {
+        let branch = label.description.clone().unwrap_or_default();
+        if !requested.contains(&branch) {
+            info!("Label {} maps to branch {}", label.source.as_deref().unwrap_or_default(), branch);
+            requested.push(branch);
+            expanded.labels.push(label);
+            changed = true;
+        }
+    }
+    changed.then_some(expanded)
+}
+
+/// Backports a merged GitCode merge request; `commits` are the PR's commits
+/// when the caller already fetched them
+pub fn process_pr(webhook_data: &ParsedWebhookData, commits: Option<&[gitcode::GitCommit]>, job_id: Uuid) -> Result<String> {
+    process_merge_request(webhook_data, "gitcode", commits, job_id)
+}
+
+/// Backports a merged GitLab merge request within its project, like GitCode ones
+pub fn process_gitlab_pr(webhook_data: &ParsedWebhookData, commits: Option<&[gitcode::GitCommit]>, job_id: Uuid) -> Result<String> {
+    process_merge_request(webhook_data, "gitlab", commits, job_id)
+}
+
+/// Whether a merge request webhook reports an approved merge, whose commits
+/// the backport job will need
+pub fn needs_commits(webhook_data: &ParsedWebhookData, platform: &str) -> bool {
+    is_merged(webhook_data, platform) && webhook_data.labels.iter().any(|label| label.title == "approval: done")
+}
+
+fn process_merge_request(
+    webhook_data: &ParsedWebhookData,
+    platform: &str,
+    commits: Option<&[gitcode::GitCommit]>,
+    job_id: Uuid,
+) -> Result<String> {
+    let expanded = with_configured_branches(webhook_data);
+    let webhook_data = expanded.as_ref().unwrap_or(webhook_data);
+    let _correlation = http_headers::correlate(&job_id.to_string());
+    let _deadline = deadline::start(job_id, deadline::job_timeout());
+    let _outbox = outbox::start(job_id);
+    let again = match reopened_gate(webhook_data, platform, job_id) {
+        ReopenedGate::Proceed => None,
+        ReopenedGate::Again(key) => Some(key),
+        ReopenedGate::Hold(message) => {
+            info!("{}", message);
+            return Ok(message);
+        }
+    };
+    let result = backport_merge_request(webhook_data, platform, commits, again.is_some(), job_id);
+    if let (Ok(_), Some(key)) = (&result, &again) {
+        reopened::store().lock().unwrap().clear(key);
+    }
+    if result.is_err() && !branch_results_recorded(job_id) {
+        // Nothing was pushed, so every requested branch failed
+        status_labels::sync_status_labels(webhook_data, platform, &status_labels::requested_branches(webhook_data), false);
+    }
+    result
+}
+
+/// Whether the action and state of a merge request webhook mean it was
+/// merged: GitCode reports merges as closes, GitLab as merges
+fn is_merge_event(platform: &str, action: &str, state: &str) -> bool {
+    match platform {
+        "gitlab" => action == "merge" && state == "merged",
+        _ => action == "close" && state == "closed",
+    }
+}
+
+/// Outcome of the `reopened_prs` policy for a PR webhook
+enum ReopenedGate {
+    /// Not a reopened PR, handled as usual
+    Proceed,
+    /// A reopened PR merged again with the `backport: again` label (its key):
+    /// commits already backported are cherry-picked again
+    Again(String),
+    /// Nothing is backported, for the given reason
+    Hold(String),
+}
+
+/// Whether a PR webhook reports the PR as merged
+fn is_merged(webhook_data: &ParsedWebhookData, platform: &str) -> bool {
+    let (Some(action), Some(state)) = (&webhook_data.action, &webhook_data.state) else { return false };
+    match platform {
+        "github" => action == "closed" && state == "closed" && webhook_data.merge.as_ref().is_none_or(|merge| merge.merged),
+        _ => is_merge_event(platform, action, state),
+    }
+}
+
+/// Applies the repository's `reopened_prs` policy: reopening a PR that was
+/// backported flags it and warns on the PR, and merging a flagged PR again is
+/// only backported with the `backport: again` label
+fn reopened_gate(webhook_data: &ParsedWebhookData, platform: &str, job_id: Uuid) -> ReopenedGate {
+    let (Some(action), Some(source_pr)) = (webhook_data.action.as_deref(), webhook_data.iid) else {
+        return ReopenedGate::Proceed;
+    };
+    let repo_config = config::load_repo_config_in(&webhook_data.namespace, &webhook_data.repo_name).unwrap_or_else(|e| {
+        error!("Failed to read config, using default settings: {}", e);
+        None
+    });
+    if repo_config.as_ref().map(|c| c.reopened_prs).unwrap_or_default() == ReopenedPolicy::Ignore {
+        return ReopenedGate::Proceed;
+    }
+    let key = reopened::pr_key(platform, &webhook_data.namespace, &webhook_data.repo_name, source_pr);
+
+    if reopened::is_reopen_action(action) {
+        let branches = backports::store().lock().unwrap().branches_of(platform, &webhook_data.namespace, &webhook_data.repo_name, source_pr);
+        if branches.is_empty() {
+            return ReopenedGate::Proceed;
+        }
+        reopened::store().lock().unwrap().mark(&key, chrono::Utc::now());
+        let templates = repo_config.map(|c| c.comments).unwrap_or_default();
+        comment_on_source_pr(webhook_data, platform, &reopened::format_reopened_comment(&branches, &templates), job_id);
+        return ReopenedGate::Hold(format!("PR was reopened after its backport onto {}", branches.join(", ")));
+    }
+
+    if !is_merged(webhook_data, platform) || reopened::store().lock().unwrap().reopened_at(&key).is_none() {
+        return ReopenedGate::Proceed;
+    }
+    if webhook_data.labels.iter().any(|label| label.title == reopened::BACKPORT_AGAIN_LABEL) {
+        info!("PR was reopened after its backport and has the {} label, backporting it again", reopened::BACKPORT_AGAIN_LABEL);
+        return ReopenedGate::Again(key);
+    }
+    ReopenedGate::Hold(format!("PR was reopened after its backport but doesn't have {} label", reopened::BACKPORT_AGAIN_LABEL))
+}
+
+fn backport_merge_request(
+    webhook_data: &ParsedWebhookData,
+    platform: &str,
+    prefetched: Option<&[gitcode::GitCommit]>,
+    again: bool,
+    job_id: Uuid,
+) -> Result<String> {
     match (&webhook_data.action, &webhook_data.state) {
-        (Some(action), Some(state)) if action == "close" && state == "closed" => {
+        (Some(action), Some(state)) if is_merge_event(platform, action, state) => {
             // Check if the label in webhook_data contains a label with title "approval: done"
             if !webhook_data.labels.iter().any(|label| label.title == "approval: done") {
                 return Ok("PR is closed but doesn't have approval: done label".to_string());
             }
 
-            let br_labels: Vec<&Label> = webhook_data.labels.iter()
+            let mut br_labels: Vec<&Label> = webhook_data.labels.iter()
                 .filter(|label| label.title.starts_with("br:"))
                 .collect();
 
@@ -44,74 +298,119 @@ pub fn process_pr(webhook_data: &ParsedWebhookData) -> Result<String, git2::Erro
                 return Ok("No branch labels found".to_string());
             }
 
-            // Get current directory and append repo name
-            let current_dir = std::env::current_dir()
-                .map_err(|e| git2::Error::from_str(&e.to_string()))?;
-            let local_path = current_dir.join("gitcode").join(&webhook_data.repo_name);
+            if let Some(message) = freeze::freeze_gate(&webhook_data.namespace, &webhook_data.repo_name, &SystemClock) {
+                info!("{}", message);
+                return Ok(message);
+            }
+
+            if let Some(message) = access_gate(webhook_data, platform) {
+                info!("{}", message);
+                return Ok(message);
+            }
+
+            let repo_config = match config::load_repo_config_in(&webhook_data.namespace, &webhook_data.repo_name) {
+                Ok(repo_config) => repo_config,
+                Err(e) => {
+                    error!("Failed to read config, using default settings: {}", e);
+                    None
+                }
+            };
+            if let Some(repo_config) = &repo_config {
+                if let Some(message) = label_gate(webhook_data, repo_config, &mut br_labels, platform)? {
+                    info!("{}", message);
+                    return Ok(message);
+                }
+            }
+            let cleanup = repo_config.as_ref().map(|c| c.cleanup).unwrap_or_default();
+            let renames = repo_config.as_ref().map(|c| c.renames).unwrap_or_default();
+            let templates = repo_config.as_ref().map(|c| c.comments.clone()).unwrap_or_default();
+            let guards = repo_config.as_ref().map(|c| c.commit_guards.clone()).unwrap_or_default();
+            // Scanned unless the repository's entry sets `secret_scan: false`
+            let secret_scan = repo_config.as_ref().is_none_or(|c| c.secret_scan);
+
+            let local_path = workspace_path(platform, &webhook_data.repo_name)?;
 
             // Create a new folder at local_path, deleting existing one if present
-            file::create_empty_folder(&local_path)
-                .map_err(|e| git2::Error::from_str(&format!("Failed to prepare directory: {}", e)))?;
+            file::create_empty_folder(&local_path)?;
+            let mut workspace = WorkspaceGuard::new(&local_path, cleanup);
 
             // Clone the repository
-            let repo = clone_repository(&webhook_data.repo_url, &local_path, "gitcode")?;
+            deadline::check("clone")?;
+            let repo = clone_workspace(webhook_data, &local_path, platform)?;
+
+            if let Some(repo_config) = &repo_config {
+                apply_git_config(&repo, &repo_config.git_config)?;
+            }
             
             // Set up Git configuration for the repository
-            let mut config = repo.config()?;
-            let username = env::var("GITCODE_USERNAME").expect("GITCODE_USERNAME not set in environment");
-            let user_email = env::var("GITCODE_USER_EMAIL").expect("GITCODE_USER_EMAIL not set in environment");
-            config.set_str("user.name", &username)?;
-            config.set_str("user.email", &user_email)?;
+            apply_identity(&repo, &resolve_identity(repo_config.as_ref())?)?;
             info!("Repository Git configuration set up successfully");
             
             let iid: u32 = webhook_data.iid.unwrap();
-            // Get the commit list for the PR
-            let commits = match gitcode::get_commit_list_of_pr(
-                "https://api.gitcode.com/api/v5/repos",
-                &webhook_data.namespace,
-                &webhook_data.repo_name,
-                iid,
-                "gitcode"
-            ) {
-                Ok(commits) => commits,
-                Err(e) => return Err(git2::Error::from_str(&e.to_string())),
+            // Get the commit list for the PR, unless the handler already fetched it
+            let commits = match prefetched {
+                Some(commits) => commits.to_vec(),
+                None => gitcode::get_commit_list_of_pr(
+                    api_base_url(platform),
+                    &webhook_data.namespace,
+                    &webhook_data.repo_name,
+                    iid,
+                    platform
+                )?,
             };
             info!("Retrieved commits from MR: {:?}", commits);
             
-            let _result = fetch_merge_request(&local_path, "origin", iid, "gitcode");
+            let _result = fetch_merge_request(&local_path, "origin", iid, platform);
+            let commit_ids: Vec<&str> = commits.iter().map(|commit| commit.sha.as_str()).collect();
+            let stored_patches = patches::store_commit_patches(&PatchStore::from_env(), &local_path, &commit_ids);
+
+            let dco_policy = repo_config.as_ref().map(|c| c.dco).unwrap_or_default();
+            enforce_dco(&local_path, webhook_data, platform, dco_policy, &commits, &templates, job_id)?;
             
             info!("Branch labels: {:?}", br_labels);
-            for br_label in br_labels {
-                info!("Processing branch label - description: {:?}", br_label.description);
-                let branch_name = match br_label.description.as_ref() {
-                    Some(name) => name,
-                    None => {
-                        error!("Failed to get branch name: branch description is None");
-                        return Err(git2::Error::from_str("Branch description is None"));
-                    }
-                };
-                
-                if let Err(e) = switch_branch(&local_path, &branch_name) {
-                    error!("Failed to switch to branch {}: {}", branch_name, e);
-                    return Err(e);
-                }
-                info!("Switching to branch {}", &branch_name);
-                
-                for commit in commits.iter().rev() {
-                    let url = webhook_data.url.as_deref().unwrap_or("unknown");
-                    if let Err(e) = cherry_pick_commit(&local_path, &commit.sha, &branch_name, url) {
-                        error!("Failed to cherry-pick commit {} on branch {}: {}", commit.sha, branch_name, e);
-                        return Err(e);
-                    }
-                }
-                // Push the changes back to origin
-                push_repository(&local_path, "origin", &branch_name)?;
+            let job = BranchJob {
+                local_path: &local_path,
+                webhook_data,
+                platform,
+                commits: &commits,
+                pr_url: webhook_data.url.as_deref().unwrap_or("unknown"),
+                sign_off: dco_policy == DcoPolicy::Add,
+                renames: &renames,
+                guards: &guards,
+                templates: &templates,
+                secret_scan,
+                stored_patches: &stored_patches,
+                branch_map: repo_config.as_ref().map(|c| c.branch_map.as_slice()).unwrap_or_default(),
+                again,
+                job_id,
+            };
+            let results = backport_branches(&job, &br_labels)?;
+            if let Some(message) = canary_dry_run(webhook_data, platform, repo_config.as_ref(), &webhook_data.repo_url, &results, job_id) {
+                results.outcome()?;
+                workspace.succeed();
+                return Ok(message);
             }
+            let updated_branches = results.branch_names();
+            let pushed_ranges = results.ranges();
+            let picked = results.picked();
 
-            // Clean up the local repository
-            if let Err(e) = file::delete_folder(&local_path) {
-                return Err(git2::Error::from_str(&format!("Failed to cleanup repository: {}", e)));
+            // Push all updated branches back to origin in one round trip
+            let atomic = repo_config.as_ref().is_some_and(|c| c.atomic_push);
+            deadline::check("push")?;
+            push_updated_branches(&local_path, "origin", &updated_branches, atomic)?;
+            let verified = verify_pushed_tips(&local_path, "origin", &pushed_ranges, job_id)?;
+            if repo_config.as_ref().is_some_and(|c| c.backport_notes) {
+                publish_notes(&local_path, "origin", platform, &picked);
             }
+            record_backports(webhook_data, platform, picked, &verified, job_id);
+            record_branch_results(webhook_data, platform, &results, job_id);
+            if let Some(trigger) = repo_config.as_ref().and_then(|c| c.ci_trigger.as_ref()) {
+                trigger_ci(trigger, platform, &webhook_data.namespace, &webhook_data.repo_name, &pushed_ranges);
+            }
+            results.outcome()?;
+
+            // The workspace is cleaned up according to the repository's policy when dropped
+            workspace.succeed();
 
             Ok("Successfully processed PR".to_string())
         }
@@ -119,13 +418,41 @@ pub fn process_pr(webhook_data: &ParsedWebhookData) -> Result<String, git2::Erro
     }
 }
 
-pub fn process_github_pr(webhook_data: &ParsedWebhookData) -> Result<String, git2::Error> {
+pub fn process_github_pr(webhook_data: &ParsedWebhookData, job_id: Uuid) -> Result<String> {
+    let expanded = with_configured_branches(webhook_data);
+    let webhook_data = expanded.as_ref().unwrap_or(webhook_data);
+    let _correlation = http_headers::correlate(&job_id.to_string());
+    let _deadline = deadline::start(job_id, deadline::job_timeout());
+    let _outbox = outbox::start(job_id);
+    let again = match reopened_gate(webhook_data, "github", job_id) {
+        ReopenedGate::Proceed => None,
+        ReopenedGate::Again(key) => Some(key),
+        ReopenedGate::Hold(message) => {
+            info!("{}", message);
+            return Ok(message);
+        }
+    };
+    let result = backport_github_pr(webhook_data, again.is_some(), job_id);
+    if let (Ok(_), Some(key)) = (&result, &again) {
+        reopened::store().lock().unwrap().clear(key);
+    }
+    if result.is_err() && !branch_results_recorded(job_id) {
+        status_labels::sync_status_labels(webhook_data, "github", &status_labels::requested_branches(webhook_data), false);
+    }
+    result
+}
+
+fn backport_github_pr(webhook_data: &ParsedWebhookData, again: bool, job_id: Uuid) -> Result<String> {
     info!("Starting GitHub PR processing");
     info!("Webhook data: {:?}", webhook_data);
     
     // Check if action is "merge" and state is "merged"
     match (&webhook_data.action, &webhook_data.state) {
         (Some(action), Some(state)) if action == "closed" && state == "closed" => {
+            if webhook_data.merge.as_ref().is_some_and(|merge| !merge.merged) {
+                info!("PR was closed without merging");
+                return Ok("PR was closed without merging".to_string());
+            }
             info!("PR is closed, checking labels");
             
             // Check if the label in webhook_data contains a label with title "approval: done"
@@ -135,7 +462,7 @@ pub fn process_github_pr(webhook_data: &ParsedWebhookData) -> Result<String, git
             }
             info!("Found approval: done label");
 
-            let br_labels: Vec<&Label> = webhook_data.labels.iter()
+            let mut br_labels: Vec<&Label> = webhook_data.labels.iter()
                 .filter(|label| label.title.starts_with("br:"))
                 .collect();
             info!("Found {} branch labels: {:?}", br_labels.len(), br_labels);
@@ -145,116 +472,139 @@ pub fn process_github_pr(webhook_data: &ParsedWebhookData) -> Result<String, git
                 return Ok("No branch labels found".to_string());
             }
 
-            // Get current directory and append repo name
-            let current_dir = std::env::current_dir()
-                .map_err(|e| git2::Error::from_str(&e.to_string()))?;
-            let local_path = current_dir.join("github").join(&webhook_data.repo_name);
+            if let Some(message) = freeze::freeze_gate(&webhook_data.namespace, &webhook_data.repo_name, &SystemClock) {
+                info!("{}", message);
+                return Ok(message);
+            }
+
+            if let Some(message) = access_gate(webhook_data, "github") {
+                info!("{}", message);
+                return Ok(message);
+            }
+
+            // Read config and get target repo URL
+            let repo_config = config::load_repo_config_in(&webhook_data.namespace, &webhook_data.repo_name)
+                .map_err(|e| Error::Config(format!("Failed to read config: {}", e)))?
+                .ok_or_else(|| Error::Config(format!("Repository {} not found in config", webhook_data.repo_name)))?;
+
+            if let Some(message) = label_gate(webhook_data, &repo_config, &mut br_labels, "github")? {
+                info!("{}", message);
+                return Ok(message);
+            }
+
+            let local_path = workspace_path("github", &webhook_data.repo_name)?;
 
             // Create a new folder at local_path, deleting existing one if present
-            file::create_empty_folder(&local_path)
-                .map_err(|e| git2::Error::from_str(&format!("Failed to prepare directory: {}", e)))?;
+            file::create_empty_folder(&local_path)?;
+            let mut workspace = WorkspaceGuard::new(&local_path, repo_config.cleanup);
 
             // Clone the repository
             info!("Cloning repository from URL: {}", webhook_data.repo_url);
-            let repo = clone_repository(&webhook_data.repo_url, &local_path, "github")?;
+            deadline::check("clone")?;
+            let repo = clone_workspace(webhook_data, &local_path, "github")?;
             info!("Repository cloned successfully");
+
+            apply_git_config(&repo, &repo_config.git_config)?;
             
             // Set up Git configuration for the repository
             info!("Setting up Git configuration");
-            let mut config = repo.config()?;
-            let username = env::var("GITHUB_USERNAME").expect("GITHUB_USERNAME not set in environment");
-            let user_email = env::var("GITHUB_USER_EMAIL").expect("GITHUB_USER_EMAIL not set in environment");
-            config.set_str("user.name", &username)?;
-            config.set_str("user.email", &user_email)?;
+            apply_identity(&repo, &resolve_identity(Some(&repo_config))?)?;
             info!("Repository Git configuration set up successfully");
             
             let iid: u32 = webhook_data.iid.unwrap();
             info!("Processing PR #{}", iid);
             
             // Get the commit list for the PR
-            info!("Fetching commit list from GitHub API");
-            let commits = match gitcode::get_commit_list_of_pr(
+            info!("Fetching PR details from GitHub API");
+            let details = github_graphql::get_pull_request_details(
                 "https://api.github.com/repos",
                 &webhook_data.namespace,
                 &webhook_data.repo_name,
                 iid,
-                "github"
-            ) {
-                Ok(commits) => commits,
-                Err(e) => return Err(git2::Error::from_str(&e.to_string())),
-            };
+            )?;
+            let commits = details.commits;
             info!("Retrieved commits from MR: {:?}", commits);
 
             info!("Fetching merge request");
-            let result = fetch_merge_request(&local_path, "origin", iid, "github");
-            if let Err(e) = result {
+            if let Err(e) = fetch_merge_request(&local_path, "origin", iid, "github") {
                 info!("Failed to fetch merge request: {}", e);
-                return Err(git2::Error::from_str(&format!("Failed to fetch merge request: {}", e)));
+                return Err(e);
             }
             info!("Merge request fetched successfully");
+            let commits = match webhook_data.merge.as_ref() {
+                Some(MergeInfo { queued: true, commit_sha: Some(merge_sha), .. }) => {
+                    info!("PR #{} was merged by a merge queue or auto-merge as {}", iid, merge_sha);
+                    merged_commits(&local_path, "origin", merge_sha, &commits)?
+                }
+                _ => commits,
+            };
+            let commit_ids: Vec<&str> = commits.iter().map(|commit| commit.sha.as_str()).collect();
+            let stored_patches = patches::store_commit_patches(&PatchStore::from_env(), &local_path, &commit_ids);
             
             info!("Adding target remote repository");
-            // Read config and get target repo URL
-            let config = config::read_config("config.yml").map_err(|e| {
-                git2::Error::from_str(&format!("Failed to read config: {}", e))
-            })?;
-            
-            let repo_config = config.repos.get(&webhook_data.repo_name).ok_or_else(|| {
-                git2::Error::from_str(&format!("Repository {} not found in config", webhook_data.repo_name))
-            })?;
-            
+            enforce_dco(&local_path, webhook_data, "github", repo_config.dco, &commits, &repo_config.comments, job_id)?;
+
             match add_remote_repository(&local_path, "target", &repo_config.target_repo) {
                 Ok(_) => info!("Target remote added successfully"),
                 Err(e) => {
                     info!("Failed to add remote repository: {}", e);
-                    return Err(git2::Error::from_str(&format!("Failed to add remote repository: {}", e)));
+                    return Err(e);
                 }
             }
             
+            let pr_url = webhook_data.url.as_deref().ok_or_else(|| {
+                error!("Failed to get webhook URL: url is None");
+                Error::Parse("Webhook URL is None".to_string())
+            })?;
             info!("Branch labels: {:?}", br_labels);
-            for br_label in br_labels {
-                info!("Processing branch label - description: {:?}", br_label.description);
-                let branch_name = match br_label.description.as_ref() {
-                    Some(name) => name,
-                    None => {
-                        error!("Failed to get branch name: branch description is None");
-                        return Err(git2::Error::from_str("Branch description is None"));
-                    }
-                };
-                
-                if let Err(e) = switch_branch(&local_path, &branch_name) {
-                    error!("Failed to switch to branch {}: {}", branch_name, e);
-                    return Err(e);
-                }
-                info!("Switched to branch {}", &branch_name);
-                
-                info!("Cherry-picking commits");
-                for commit in commits.iter().rev() {
-                    info!("Cherry-picking commit: {}", commit.sha);
-                    let url = match webhook_data.url.as_deref() {
-                        Some(u) => u,
-                        None => {
-                            error!("Failed to get webhook URL: url is None");
-                            return Err(git2::Error::from_str("Webhook URL is None"));
-                        }
-                    };
-                    if let Err(e) = cherry_pick_commit(&local_path, &commit.sha, &branch_name, url) {
-                        error!("Failed to cherry-pick commit {} on branch {}: {}", commit.sha, branch_name, e);
-                        return Err(e);
-                    }
-                }
-                
-                info!("Pushing changes to target remote");
-                push_repository(&local_path, "target", &branch_name)?;
-                info!("Successfully pushed to branch {}", branch_name);
+            let job = BranchJob {
+                local_path: &local_path,
+                webhook_data,
+                platform: "github",
+                commits: &commits,
+                pr_url,
+                sign_off: repo_config.dco == DcoPolicy::Add,
+                renames: &repo_config.renames,
+                guards: &repo_config.commit_guards,
+                templates: &repo_config.comments,
+                secret_scan: repo_config.secret_scan,
+                stored_patches: &stored_patches,
+                branch_map: &repo_config.branch_map,
+                again,
+                job_id,
+            };
+            let results = backport_branches(&job, &br_labels)?;
+            if let Some(message) = canary_dry_run(webhook_data, "github", Some(&repo_config), &repo_config.target_repo, &results, job_id) {
+                results.outcome()?;
+                workspace.succeed();
+                return Ok(message);
             }
+            let updated_branches = results.branch_names();
+            let pushed_ranges = results.ranges();
+            let picked = results.picked();
 
-            info!("Cleaning up repository");
-            if let Err(e) = file::delete_folder(&local_path) {
-                info!("Failed to cleanup repository: {}", e);
-                return Err(git2::Error::from_str(&format!("Failed to cleanup repository: {}", e)));
+            info!("Pushing {} branches to target remote", updated_branches.len());
+            deadline::check("push")?;
+            push_updated_branches(&local_path, "target", &updated_branches, repo_config.atomic_push)?;
+            info!("Successfully pushed branches {:?}", updated_branches);
+            let verified = verify_pushed_tips(&local_path, "target", &pushed_ranges, job_id)?;
+            if repo_config.backport_notes {
+                publish_notes(&local_path, "origin", "github", &picked);
+            }
+            record_backports(webhook_data, "github", picked, &verified, job_id);
+            record_branch_results(webhook_data, "github", &results, job_id);
+            if let Some(trigger) = &repo_config.ci_trigger {
+                match RemoteUrl::parse(&repo_config.target_repo) {
+                    Ok(target) => match target.namespace_and_name() {
+                        Some((namespace, name)) => trigger_ci(trigger, target.platform(), namespace, name, &pushed_ranges),
+                        None => warn!("Cannot trigger CI: no repository in {}", repo_config.target_repo),
+                    },
+                    Err(e) => warn!("Cannot trigger CI: {}", e),
+                }
             }
-            info!("Repository cleanup successful");
+            results.outcome()?;
+
+            workspace.succeed();
 
             Ok("Successfully processed PR".to_string())
         }
@@ -266,103 +616,1021 @@ pub fn process_github_pr(webhook_data: &ParsedWebhookData) -> Result<String, git
     }
 }
 
-pub fn process_push_event(push_data: &ParsedPushData) -> Result<String, git2::Error> {
+/// Webhook data equivalent to PR `pr` of the configured repository being
+/// merged with a `br:` label for `branch`, returned with its platform
+///
+/// Lets an operator run the regular pipeline for one PR and branch without a
+/// webhook; the labels count as trusted.
+pub fn operator_backport_request(repo_config: &RepoConfig, pr: u32, branch: &str) -> (&'static str, ParsedWebhookData) {
+    let platform = repo_config.source_platform();
+    let (action, state, repo_url, url) = match platform {
+        "github" => (
+            "closed",
+            "closed",
+            format!("https://github.com/{}/{}.git", repo_config.namespace, repo_config.repo_name),
+            format!("https://github.com/{}/{}/pull/{}", repo_config.namespace, repo_config.repo_name, pr),
+        ),
+        "gitlab" => ("merge", "merged", repo_config.target_repo.clone(), format!("{}/-/merge_requests/{}", repo_config.target_repo.trim_end_matches(".git"), pr)),
+        _ => ("close", "closed", repo_config.target_repo.clone(), format!("{}/pull/{}", repo_config.target_repo.trim_end_matches(".git"), pr)),
+    };
+    let label = |title: &str, description: Option<&str>| Label {
+        title: title.to_string(),
+        description: description.map(str::to_string),
+        r#type: None,
+        source: None,
+    };
+    let data = ParsedWebhookData {
+        labels: vec![label("approval: done", None), label(&format!("br: {}", branch), Some(branch))],
+        event_type: if platform == "github" { "pull_request" } else { "merge_request" }.to_string(),
+        action: Some(action.to_string()),
+        state: Some(state.to_string()),
+        url: Some(url),
+        repo_name: repo_config.repo_name.clone(),
+        repo_url,
+        namespace: repo_config.namespace.clone(),
+        iid: Some(pr),
+        labels_trusted: true,
+        archived: None,
+        merge: None,
+    };
+    (platform, data)
+}
+
+/// Commits of `range` (`<sha1>..<sha2>`) on `branch`, newest first like the
+/// commit lists of the forge APIs
+///
+/// `<sha2>` must be on the branch as cloned from `origin`; like `git log
+/// sha1..sha2`, commits reachable from `<sha1>` are left out.
+pub fn range_commits(repo: &Repository, branch: &str, range: &str) -> Result<Vec<gitcode::GitCommit>> {
+    let (from, to) = range
+        .split_once("..")
+        .filter(|(from, to)| !from.is_empty() && !to.is_empty() && !to.starts_with('.'))
+        .ok_or_else(|| Error::Parse(format!("Invalid commit range {:?}, expected <sha1>..<sha2>", range)))?;
+    let from = repo.revparse_single(from)?.peel_to_commit()?.id();
+    let to = repo.revparse_single(to)?.peel_to_commit()?.id();
+    let tip = repo.find_reference(&format!("refs/remotes/origin/{}", branch))?.peel_to_commit()?.id();
+    if to != tip && !repo.graph_descendant_of(tip, to)? {
+        return Err(Error::Job(format!("Commit {} is not on {}", to, branch)));
+    }
+
+    let mut walk = repo.revwalk()?;
+    walk.set_sorting(git2::Sort::TOPOLOGICAL)?;
+    walk.push(to)?;
+    walk.hide(from)?;
+    let commits = walk
+        .map(|oid| oid.map(|oid| gitcode::GitCommit { sha: oid.to_string() }))
+        .collect::<Result<Vec<_>, git2::Error>>()?;
+    if commits.is_empty() {
+        return Err(Error::Job(format!("Commit range {} of {} is empty", range, branch)));
+    }
+    Ok(commits)
+}
+
+/// Cherry-picks the commits of `range` on `branch` of a configured
+/// repository onto `target_branch`, outside of any PR
+///
+/// For hotfixes that landed on the branch without a PR: the commits go
+/// through the regular pipeline (guards, secret scan, branch map, push
+/// verification), but there is no PR to comment on or to record in the
+/// backport mapping DB.
+pub fn backport_range(repo_config: &RepoConfig, branch: &str, range: &str, target_branch: &str, job_id: Uuid) -> Result<String> {
+    let _deadline = deadline::start(job_id, deadline::job_timeout());
+    let (platform, mut webhook_data) = operator_backport_request(repo_config, 0, target_branch);
+    webhook_data.iid = None;
+    webhook_data.url = None;
+    let webhook_data = &webhook_data;
+
+    if let Some(message) = freeze::freeze_gate(&webhook_data.namespace, &webhook_data.repo_name, &SystemClock) {
+        info!("{}", message);
+        return Ok(message);
+    }
+
+    let local_path = workspace_path(platform, &webhook_data.repo_name)?;
+    file::create_empty_folder(&local_path)?;
+    let mut workspace = WorkspaceGuard::new(&local_path, repo_config.cleanup);
+
+    deadline::check("clone")?;
+    let repo = clone_workspace(webhook_data, &local_path, platform)?;
+    apply_git_config(&repo, &repo_config.git_config)?;
+    apply_identity(&repo, &resolve_identity(Some(repo_config))?)?;
+
+    let commits = range_commits(&repo, branch, range)?;
+    info!("Backporting {} commits of {} {} onto {}", commits.len(), branch, range, target_branch);
+    let commit_ids: Vec<&str> = commits.iter().map(|commit| commit.sha.as_str()).collect();
+    let stored_patches = patches::store_commit_patches(&PatchStore::from_env(), &local_path, &commit_ids);
+    enforce_dco(&local_path, webhook_data, platform, repo_config.dco, &commits, &repo_config.comments, job_id)?;
+
+    // GitHub sources are backported into the target repository, like their PRs
+    let remote = if platform == "github" {
+        add_remote_repository(&local_path, "target", &repo_config.target_repo)?;
+        "target"
+    } else {
+        "origin"
+    };
+    let source = format!("{}@{}", branch, range);
+    let job = BranchJob {
+        local_path: &local_path,
+        webhook_data,
+        platform,
+        commits: &commits,
+        pr_url: &source,
+        sign_off: repo_config.dco == DcoPolicy::Add,
+        renames: &repo_config.renames,
+        guards: &repo_config.commit_guards,
+        templates: &repo_config.comments,
+        secret_scan: repo_config.secret_scan,
+        stored_patches: &stored_patches,
+        branch_map: &repo_config.branch_map,
+        again: false,
+        job_id,
+    };
+    let br_labels: Vec<&Label> = webhook_data.labels.iter().filter(|label| label.title.starts_with("br:")).collect();
+    let results = backport_branches(&job, &br_labels)?;
+    let updated_branches = results.branch_names();
+    let pushed_ranges = results.ranges();
+
+    deadline::check("push")?;
+    push_updated_branches(&local_path, remote, &updated_branches, repo_config.atomic_push)?;
+    verify_pushed_tips(&local_path, remote, &pushed_ranges, job_id)?;
+    record_branch_results(webhook_data, platform, &results, job_id);
+    results.outcome()?;
+
+    workspace.succeed();
+    Ok(format!("Backported {} commits of {} onto {}", results.picked().len(), source, target_branch))
+}
+
+/// Credential callbacks for remotes on `platform`
+pub(crate) fn platform_callbacks(platform: &str) -> RemoteCallbacks<'static> {
+    let mut callbacks = RemoteCallbacks::new();
+    match platform {
+        "github" => callbacks.credentials(github_credentials_callback),
+        "gitlab" => callbacks.credentials(gitlab_credentials_callback),
+        _ => callbacks.credentials(gitcode_credentials_callback),
+    };
+    deadline::watch_transfers(&mut callbacks);
+    callbacks
+}
+
+/// Copies every branch and tag of `source_url` to `target_url` through a bare
+/// repository at `local_path`, returning the references the push changed
+///
+/// Branches matching the `internal` patterns are not pushed and the others are
+/// renamed according to `branch_map`; references that only exist on the
+/// target are left alone. The changes are recorded on job `job_id` before
+/// pushing; with `dry_run` nothing is pushed.
+pub fn mirror_repository(
+    source_url: &str,
+    target_url: &str,
+    local_path: &Path,
+    internal: &[String],
+    branch_map: &[BranchMapping],
+    job_id: Uuid,
+    dry_run: bool,
+) -> Result<Vec<RefChange>> {
+    let platform_of = |url: &str| RemoteUrl::parse(url).map(|remote| remote.platform()).unwrap_or("gitcode");
+    let repo = Repository::init_bare(local_path)?;
+
+    info!("Fetching branches and tags of {}", source_url);
+    let mut source = repo.remote_anonymous(source_url)?;
+    let mut fetch_opts = git2::FetchOptions::new();
+    fetch_opts.remote_callbacks(platform_callbacks(platform_of(source_url)));
+    source.fetch(&["+refs/heads/*:refs/heads/*", "+refs/tags/*:refs/tags/*"], Some(&mut fetch_opts), None)?;
+
+    let names: Vec<String> = repo
+        .references()?
+        .filter_map(|reference| reference.ok()?.name().map(str::to_string))
+        .collect();
+    let refspecs = mirror::mirror_refspecs(names.iter().map(String::as_str), internal, branch_map);
+    if refspecs.is_empty() {
+        return Err(Error::Job(format!("{} has no branches or tags", source_url)));
+    }
+
+    let local: HashMap<String, String> = names
+        .iter()
+        .filter_map(|name| Some((name.clone(), repo.refname_to_id(name).ok()?.to_string())))
+        .collect();
+    let mut target = repo.remote_anonymous(target_url)?;
+    let remote: HashMap<String, String> = {
+        let connection = target.connect_auth(git2::Direction::Push, Some(platform_callbacks(platform_of(target_url))), None)?;
+        connection.list()?.iter().map(|head| (head.name().to_string(), head.oid().to_string())).collect()
+    };
+    let changes = mirror::ref_changes(&refspecs, &local, &remote);
+    info!("Mirror push to {} changes {} references", target_url, changes.len());
+    jobs::store().lock().unwrap().update(job_id, |record| record.ref_changes = changes.clone());
+    if dry_run || changes.is_empty() {
+        return Ok(changes);
+    }
+
+    let rejected = std::cell::RefCell::new(Vec::new());
+    let mut callbacks = platform_callbacks(platform_of(target_url));
+    callbacks.push_update_reference(|refname, status| {
+        if let Some(message) = status {
+            error!("Push of {} rejected: {}", refname, message);
+            rejected.borrow_mut().push(format!("{} ({})", refname, message));
+        }
+        Ok(())
+    });
+    let mut push_options = PushOptions::new();
+    push_options.remote_callbacks(callbacks);
+    info!("Pushing {} references to {}", refspecs.len(), target_url);
+    target.push(&refspecs, Some(&mut push_options))?;
+    drop(push_options);
+
+    let rejected = rejected.into_inner();
+    if !rejected.is_empty() {
+        return Err(Error::Job(format!("Push rejected for: {}", rejected.join(", "))));
+    }
+    Ok(changes)
+}
+
+/// User the bot acts as on `platform`, from `<PLATFORM>_BOT_USERNAME`
+fn bot_username(platform: &str) -> Option<String> {
+    let var = match platform {
+        "github" => "GITHUB_BOT_USERNAME",
+        "gitcode" => "GITCODE_BOT_USERNAME",
+        "gitlab" => "GITLAB_BOT_USERNAME",
+        _ => return None,
+    };
+    env::var(var).ok().filter(|username| !username.is_empty())
+}
+
+/// Queues the comments a bot push produces on the PRs it backported and
+/// returns them leased to the caller, which posts them with
+/// `comment_queue::deliver_leased`
+pub fn process_push_event(push_data: &ParsedPushData, job_id: Uuid) -> Result<Vec<PendingComment>> {
+    let _correlation = http_headers::correlate(&job_id.to_string());
+    let _deadline = deadline::start(job_id, deadline::job_timeout());
     info!("=== Process Push Event Debug ===");
     info!("Processing push event for repository: {}/{}", push_data.namespace, push_data.repo_name);
 
-    // Check if the user_name matches GITCODE_BOT_USERNAME
-    let bot_username = match env::var("GITCODE_BOT_USERNAME") {
-        Ok(username) => {
+    // Check if the user_name matches <PLATFORM>_BOT_USERNAME
+    let bot_username = match bot_username(push_data.platform) {
+        Some(username) => {
             info!("Bot username from env: {}", username);
             username
         },
-        Err(e) => {
-            info!("Failed to get bot username: {}", e);
-            return Err(git2::Error::from_str(&e.to_string()));
+        None => {
+            info!("Failed to get bot username of {}", push_data.platform);
+            return Err(Error::Config(format!("No bot username configured for {}", push_data.platform)));
         }
     };
 
     if push_data.user_name != bot_username {
-        info!("Skipping: User {} is not bot {}", push_data.user_name, bot_username);
-        return Ok("User is not bot, skipping".to_string());
+        info!("Skipping: User {} is not bot {}", redact::user(&push_data.user_name), bot_username);
+        return Ok(Vec::new());
     }
     info!("Verified: Push is from bot user");
 
     // Get comment info from the push data
-    let comments = push_data.get_comment_info();
+    let repo_config = match config::load_repo_config_in(&push_data.namespace, &push_data.repo_name) {
+        Ok(repo_config) => repo_config,
+        Err(e) => {
+            error!("Failed to read config, using default comment templates: {}", e);
+            None
+        }
+    };
+    let templates = repo_config.as_ref().map(|c| c.comments.clone()).unwrap_or_default();
+    let comments = push_data.get_comment_info(&templates, &job_id.to_string());
     info!("Found {} comments to process", comments.len());
 
-    // Post each comment on the corresponding PR
-    for (index, comment) in comments.iter().enumerate() {
-        info!("Processing comment {}/{}", index + 1, comments.len());
-        if let Some(pr_id) = comment.pr_id {
-            info!("Posting comment to PR #{}", pr_id);
-            match gitcode::post_comment_on_pr(
-                "https://api.gitcode.com/api/v5/repos",
-                &push_data.namespace,
-                &push_data.repo_name,
-                pr_id,
-                &comment.message,
-            ) {
-                Ok(_) => info!("Successfully posted comment to PR #{}", pr_id),
-                Err(e) => {
-                    info!("Failed to post comment to PR #{}: {}", pr_id, e);
-                    return Err(git2::Error::from_str(&e.to_string()));
-                }
+    // Queue each comment for its PR first so that a restart or an API outage
+    // does not lose it; the caller posts them right away
+    let mut queued = Vec::new();
+    {
+        let mut queue = comment_queue::queue().lock().unwrap();
+        for comment in &comments {
+            if let Some(pr_id) = comment.pr_id {
+                let body = rewrite_mentions(repo_config.as_ref(), push_data.platform, &comment.message);
+                queued.push(queue.enqueue_leased(job_id, push_data.platform, &push_data.namespace, &push_data.repo_name, pr_id, &body));
             }
         }
     }
 
     info!("=== Push Event Processing Complete ===");
-    Ok("Successfully processed push event".to_string())
+    Ok(queued)
+}
+
+/// Inputs of the CI run for one pushed branch: the commit range plus the configured ones
+pub fn ci_inputs(trigger: &CiTrigger, branch: &str, from: git2::Oid, to: git2::Oid) -> BTreeMap<String, String> {
+    let mut inputs = trigger.inputs.clone();
+    inputs.insert("branch".to_string(), branch.to_string());
+    inputs.insert("from".to_string(), from.to_string());
+    inputs.insert("to".to_string(), to.to_string());
+    inputs
+}
+
+/// Starts the configured workflow (GitHub) or pipeline (GitCode) for every
+/// pushed branch; failures are logged, the backport itself already succeeded
+fn trigger_ci(trigger: &CiTrigger, platform: &str, namespace: &str, repo_name: &str, ranges: &[(String, git2::Oid, git2::Oid)]) {
+    for (branch, from, to) in ranges {
+        let inputs = ci_inputs(trigger, branch, *from, *to);
+        let result = match (platform, trigger.workflow.as_deref()) {
+            ("github", Some(workflow)) => gitcode::trigger_workflow_dispatch(api_base_url(platform), namespace, repo_name, workflow, branch, &inputs),
+            ("github", None) => Err(Error::config("ci_trigger.workflow is required for GitHub targets")),
+            ("gitlab", _) => gitlab::trigger_pipeline(api_base_url(platform), namespace, repo_name, branch, &inputs),
+            _ => gitcode::trigger_pipeline(api_base_url(platform), namespace, repo_name, branch, &inputs),
+        };
+        match result {
+            Ok(()) => info!("Triggered CI on {}/{}@{} for {}..{}", namespace, repo_name, branch, from, to),
+            Err(e) => warn!("Failed to trigger CI on {}/{}@{}: {}", namespace, repo_name, branch, e),
+        }
+    }
+}
+
+/// What the branches of one backport job share
+struct BranchJob<'a> {
+    local_path: &'a PathBuf,
+    webhook_data: &'a ParsedWebhookData,
+    platform: &'a str,
+    commits: &'a [gitcode::GitCommit],
+    pr_url: &'a str,
+    sign_off: bool,
+    renames: &'a RenameDetection,
+    guards: &'a CommitGuards,
+    templates: &'a CommentTemplates,
+    /// Scan each branch's cherry-picked range for secrets before it is pushed
+    secret_scan: bool,
+    stored_patches: &'a [StoredPatch],
+    branch_map: &'a [BranchMapping],
+    /// Backport a reopened PR again: commits already backported, or whose
+    /// change the branch already has, are cherry-picked anyway
+    again: bool,
+    job_id: Uuid,
+}
+
+/// A branch the commits were backported onto, ready to be pushed
+struct BranchBackport {
+    /// Branch named by the PR's label
+    requested: String,
+    /// Its name on the target after `branch_map`
+    branch: String,
+    base: git2::Oid,
+    head: git2::Oid,
+    /// `(branch, original commit, backport commit)` triples
+    picked: Vec<(String, String, git2::Oid)>,
+    /// Commits whose change the branch already had, with the commit carrying it
+    skipped: Vec<(String, git2::Oid)>,
+}
+
+/// Outcome of every branch of a job
+#[derive(Default)]
+struct BranchResults {
+    backported: Vec<BranchBackport>,
+    /// Branch -> why its backport failed
+    failed: Vec<(String, String)>,
+}
+
+impl BranchResults {
+    fn branch_names(&self) -> Vec<String> {
+        self.backported.iter().map(|backport| backport.branch.clone()).collect()
+    }
+
+    fn ranges(&self) -> Vec<(String, git2::Oid, git2::Oid)> {
+        self.backported.iter().map(|backport| (backport.branch.clone(), backport.base, backport.head)).collect()
+    }
+
+    fn picked(&self) -> Vec<(String, String, git2::Oid)> {
+        self.backported.iter().flat_map(|backport| backport.picked.iter().cloned()).collect()
+    }
+
+    /// The job error naming the failed branches, if any
+    fn outcome(&self) -> Result<()> {
+        if self.failed.is_empty() {
+            return Ok(());
+        }
+        let failures: Vec<String> = self.failed.iter().map(|(branch, error)| format!("{}: {}", branch, error)).collect();
+        Err(Error::Job(format!(
+            "Backport failed on {} of {} branches ({})",
+            self.failed.len(),
+            self.failed.len() + self.backported.len(),
+            failures.join("; ")
+        )))
+    }
+}
+
+/// Cherry-picks the job's commits onto the branch of `br_label`
+///
+/// Commits the mapping DB already records on the branch for this PR are left
+/// out, so retrying a partially failed job only redoes the failed branches.
+fn backport_branch(job: &BranchJob, br_label: &Label) -> Result<BranchBackport> {
+    let webhook_data = job.webhook_data;
+    let local_path = job.local_path;
+    let branch_name = br_label.description.as_ref().ok_or_else(|| {
+        error!("Failed to get branch name: branch description is None");
+        Error::Parse("Branch description is None".to_string())
+    })?;
+    let requested = match branch::parse_branch_label(branch_name) {
+        Ok(name) => name,
+        Err(reason) => return Err(report_invalid_branch(webhook_data, job.platform, branch_name, &reason, job.templates, job.job_id)),
+    };
+    let mapped = branch::map_branch(&requested, job.branch_map);
+    let branch_name = &branch::resolve_branch_case(&Repository::open(local_path)?, &mapped);
+    if mapped != requested {
+        info!("Branch {} maps to {} on the target", requested, branch_name);
+        prepare_mapped_branch(local_path, branch_name, &branch::resolve_branch_case(&Repository::open(local_path)?, &requested))?;
+    }
+
+    if let Err(e) = switch_branch(local_path, branch_name) {
+        error!("Failed to switch to branch {}: {}", branch_name, e);
+        return Err(e);
+    }
+    info!("Switched to branch {}", &branch_name);
+    let branch_base = Repository::open(local_path)?.head()?.peel_to_commit()?.id();
+
+    let depth = if job.again { 0 } else { patch_id::search_depth() };
+    let present = patch_id::recent_patch_ids(&Repository::open(local_path)?, branch_base, depth)?;
+
+    let mut picked = Vec::new();
+    let mut skipped = Vec::new();
+    for commit in job.commits.iter().rev() {
+        deadline::check(&format!("cherry-picking {} onto {}", commit.sha, branch_name))?;
+        if !job.again && already_backported(webhook_data, job.platform, branch_name, &commit.sha) {
+            info!("Commit {} was already backported onto {}", commit.sha, branch_name);
+            continue;
+        }
+        let id = patch_id::patch_id(&Repository::open(local_path)?, git2::Oid::from_str(&commit.sha)?)?;
+        if let Some(existing) = id.and_then(|id| present.get(&id)) {
+            info!("Commit {} is already on {} as {}", commit.sha, branch_name, existing);
+            skipped.push((commit.sha.clone(), *existing));
+            continue;
+        }
+        info!("Cherry-picking commit: {}", commit.sha);
+        let decision = guard_commit(local_path, webhook_data, job.platform, &commit.sha, branch_name, job.guards, job.templates, job.job_id)?;
+        if decision == GuardDecision::Skip {
+            continue;
+        }
+        match cherry_pick_commit(local_path, &commit.sha, branch_name, job.pr_url, job.sign_off, job.renames, job.job_id) {
+            Ok(CherryPick::Picked(oid)) => picked.push((branch_name.to_string(), commit.sha.clone(), strip_if_needed(local_path, oid, &decision, job.guards)?)),
+            Ok(CherryPick::Conflicted(report)) => {
+                return Err(report_conflict(webhook_data, job.platform, &report, job.templates, job.stored_patches, job.job_id));
+            }
+            Err(e) => {
+                error!("Failed to cherry-pick commit {} on branch {}: {}", commit.sha, branch_name, e);
+                return Err(e);
+            }
+        }
+    }
+
+    let branch_head = Repository::open(local_path)?.head()?.peel_to_commit()?.id();
+    if job.secret_scan {
+        let findings = secrets::scan_range(&Repository::open(local_path)?, branch_base, branch_head)?;
+        if !findings.is_empty() {
+            return Err(report_secrets(webhook_data, job.platform, branch_name, &findings, job.templates, job.job_id));
+        }
+    }
+    if !skipped.is_empty() {
+        comment_on_source_pr(webhook_data, job.platform, &patch_id::format_already_present_comment(branch_name, &skipped, job.templates), job.job_id);
+    }
+    Ok(BranchBackport { requested, branch: branch_name.to_string(), base: branch_base, head: branch_head, picked, skipped })
+}
+
+/// Creates the local branch `target` a mapped backport is made on, from the
+/// source branch `source` when the cloned repository has no `target` yet
+fn prepare_mapped_branch(repo_path: &PathBuf, target: &str, source: &str) -> Result<()> {
+    let repo = Repository::open(repo_path)?;
+    if repo.find_reference(&format!("refs/remotes/origin/{}", target)).is_ok() {
+        return Ok(());
+    }
+    let start = repo.find_reference(&format!("refs/remotes/origin/{}", source))?.peel_to_commit()?;
+    repo.branch(target, &start, true)?;
+    Ok(())
+}
+
+/// Backports onto every branch of `br_labels`, carrying on past branches that fail
+///
+/// Fails outright when the job runs out of time, and when no branch succeeded
+/// (after recording the per-branch failures).
+fn backport_branches(job: &BranchJob, br_labels: &[&Label]) -> Result<BranchResults> {
+    let mut results = BranchResults::default();
+    for br_label in br_labels {
+        info!("Processing branch label - description: {:?}", br_label.description);
+        match backport_branch(job, br_label) {
+            Ok(backport) => results.backported.push(backport),
+            Err(e) if deadline::expired() => return Err(e),
+            Err(e) => {
+                let label = br_label.description.clone().unwrap_or_else(|| br_label.title.clone());
+                let branch = branch::parse_branch_label(&label).unwrap_or(label);
+                error!("Backport onto {} failed, continuing with the other branches: {}", branch, e);
+                results.failed.push((branch, e.to_string()));
+            }
+        }
+    }
+    if results.backported.is_empty() {
+        record_branch_results(job.webhook_data, job.platform, &results, job.job_id);
+        results.outcome()?;
+    }
+    Ok(results)
+}
+
+/// Runs the job as a canary while a changed configuration with
+/// `canary_jobs` is unconfirmed: what would be pushed to `target` is posted
+/// on the PR instead, and the job's outcome message is returned
+fn canary_dry_run(
+    webhook_data: &ParsedWebhookData,
+    platform: &str,
+    repo_config: Option<&RepoConfig>,
+    target: &str,
+    results: &BranchResults,
+    job_id: Uuid,
+) -> Option<String> {
+    let repo_config = repo_config?;
+    let fingerprint = canary::fingerprint(repo_config);
+    let decision = canary::store().lock().unwrap().begin_job(&webhook_data.repo_name, &fingerprint, repo_config.canary_jobs);
+    let canary::CanaryDecision::DryRun { run, of } = decision else { return None };
+    info!("Canary run {}/{} for {}: not pushing to {}", run, of, webhook_data.repo_name, target);
+    let branches: Vec<(String, usize)> = results.backported.iter().map(|backport| (backport.branch.clone(), backport.picked.len())).collect();
+    comment_on_source_pr(webhook_data, platform, &canary::format_report(run, of, target, &branches, &repo_config.comments), job_id);
+    Some(format!("Canary run {}/{}: nothing was pushed until the configuration is confirmed", run, of))
+}
+
+/// Whether the mapping DB records `commit` of the PR as backported onto `branch`
+fn already_backported(webhook_data: &ParsedWebhookData, platform: &str, branch: &str, commit: &str) -> bool {
+    let Some(source_pr) = webhook_data.iid else { return false };
+    backports::store().lock().unwrap().contains(platform, &webhook_data.namespace, &webhook_data.repo_name, source_pr, branch, commit)
+}
+
+/// Syncs the status labels with the outcome of each branch and stores it on the job
+fn record_branch_results(webhook_data: &ParsedWebhookData, platform: &str, results: &BranchResults, job_id: Uuid) {
+    let failed: Vec<String> = results.failed.iter().map(|(branch, _)| branch.clone()).collect();
+    let succeeded: Vec<String> = results.backported.iter().map(|backport| backport.requested.clone()).collect();
+    status_labels::sync_status_labels(webhook_data, platform, &succeeded, true);
+    status_labels::sync_status_labels(webhook_data, platform, &failed, false);
+
+    let mut branches = BTreeMap::new();
+    for backport in &results.backported {
+        let skipped = backport.skipped.iter().map(|(commit, present)| (commit.clone(), present.to_string())).collect();
+        branches.insert(backport.requested.clone(), jobs::BranchResult { status: jobs::JobStatus::Succeeded, error: None, skipped });
+    }
+    for (branch, error) in &results.failed {
+        branches.insert(branch.clone(), jobs::BranchResult { status: jobs::JobStatus::Failed, error: Some(error.clone()), skipped: BTreeMap::new() });
+    }
+    jobs::store().lock().unwrap().update(job_id, |job| job.branches = branches);
+}
+
+/// Whether the job already stored per-branch results, whose labels are synced
+fn branch_results_recorded(job_id: Uuid) -> bool {
+    jobs::store().lock().unwrap().get(job_id).is_some_and(|job| !job.branches.is_empty())
+}
+
+/// Pushes backport notes for the original commits to the source repository;
+/// the backport itself already succeeded, so failures are only logged
+fn publish_notes(repo_path: &Path, remote_name: &str, platform: &str, picked: &[(String, String, git2::Oid)]) {
+    if let Err(e) = notes::publish(repo_path, remote_name, platform, picked) {
+        error!("Failed to publish backport notes to {}: {}", remote_name, e);
+    }
+}
+
+/// Adds the pushed `(branch, original commit, backport commit)` triples to
+/// the backport mapping DB, with the verified tips of their branches
+fn record_backports(webhook_data: &ParsedWebhookData, platform: &str, picked: Vec<(String, String, git2::Oid)>, verified: &[(String, git2::Oid)], job_id: Uuid) {
+    let Some(source_pr) = webhook_data.iid else { return };
+    let verified_tip = |branch: &str| verified.iter().find(|(pushed, _)| pushed == branch).map(|(_, tip)| tip.to_string());
+    let now = chrono::Utc::now();
+    let records = picked
+        .into_iter()
+        .map(|(branch, original_commit, backport_commit)| BackportRecord {
+            platform: platform.to_string(),
+            namespace: webhook_data.namespace.clone(),
+            repo: webhook_data.repo_name.clone(),
+            source_pr,
+            source_url: webhook_data.url.clone(),
+            verified_tip: verified_tip(&branch),
+            branch,
+            original_commit,
+            backport_commit: backport_commit.to_string(),
+            job_id,
+            created_at: now,
+        })
+        .collect();
+    backports::store().lock().unwrap().record_all(records);
+}
+
+/// Applies the repository's `restrict_labels` setting: keeps the branch
+/// labels applied by trusted users, or returns why the PR is not backported
+/// when its approval or every branch label comes from an untrusted user
+fn label_gate(
+    webhook_data: &ParsedWebhookData,
+    repo_config: &RepoConfig,
+    br_labels: &mut Vec<&Label>,
+    platform: &str,
+) -> Result<Option<String>> {
+    if !repo_config.restrict_labels || webhook_data.labels_trusted {
+        return Ok(None);
+    }
+    let applied = |label: &Label| label.source.clone().unwrap_or_else(|| label.title.clone());
+    let mut required: Vec<String> = br_labels.iter().map(|label| applied(label)).collect();
+    required.push("approval: done".to_string());
+    let required: Vec<&str> = required.iter().map(String::as_str).collect();
+    let trusted = trusted_labels(webhook_data, repo_config, &required, platform)?;
+    if !trusted.contains("approval: done") {
+        return Ok(Some("Approval label not applied by a trusted user".to_string()));
+    }
+    br_labels.retain(|label| trusted.contains(&applied(label)));
+    if br_labels.is_empty() {
+        return Ok(Some("No branch labels applied by trusted users".to_string()));
+    }
+    Ok(None)
+}
+
+/// Returns which of `labels` were last applied by a trusted user
+///
+/// A user is trusted when listed in the repository's `label_allowlist` or when
+/// they hold maintain/admin permission on the source repository.
+fn trusted_labels(
+    webhook_data: &ParsedWebhookData,
+    repo_config: &RepoConfig,
+    labels: &[&str],
+    platform: &str,
+) -> Result<HashSet<String>> {
+    let base_url = api_base_url(platform);
+    let iid = webhook_data.iid.ok_or_else(|| Error::Parse("PR number missing".to_string()))?;
+    let events = gitcode::get_label_events(base_url, &webhook_data.namespace, &webhook_data.repo_name, iid, platform)?;
+
+    // The most recent application of a label determines who is accountable for it
+    let mut applied_by: HashMap<&str, &str> = HashMap::new();
+    for event in &events {
+        applied_by.insert(&event.label, &event.actor);
+    }
+
+    let mut permissions: HashMap<&str, bool> = HashMap::new();
+    let mut trusted = HashSet::new();
+    for label in labels {
+        let actor = match applied_by.get(label) {
+            Some(actor) => *actor,
+            None => {
+                info!("No label event found for {}", label);
+                continue;
+            }
+        };
+        let is_trusted = if repo_config.label_allowlist.iter().any(|user| user == actor) {
+            true
+        } else if let Some(known) = permissions.get(actor) {
+            *known
+        } else {
+            let role = gitcode::get_collaborator_permission(base_url, &webhook_data.namespace, &webhook_data.repo_name, actor, platform)?;
+            let maintainer = role == "admin" || role == "maintain";
+            permissions.insert(actor, maintainer);
+            maintainer
+        };
+        info!("Label {} applied by {} (trusted: {})", label, actor, is_trusted);
+        if is_trusted {
+            trusted.insert(label.to_string());
+        }
+    }
+    Ok(trusted)
+}
+
+/// Applies per-repository `git_config` entries to a freshly cloned repository
+///
+/// Values `true`/`false` and integers are written with their native type so
+/// that settings such as `core.autocrlf` or `merge.renamelimit` behave as if
+/// set with `git config`.
+pub fn apply_git_config(repo: &Repository, entries: &BTreeMap<String, String>) -> Result<()> {
+    if entries.is_empty() {
+        return Ok(());
+    }
+    let mut config = repo.config()?;
+    for (key, value) in entries {
+        info!("Setting git config {} = {}", key, value);
+        match (value.as_str(), value.parse::<i64>()) {
+            ("true", _) => config.set_bool(key, true)?,
+            ("false", _) => config.set_bool(key, false)?,
+            (_, Ok(number)) => config.set_i64(key, number)?,
+            _ => config.set_str(key, value)?,
+        }
+    }
+    Ok(())
+}
+
+/// Identity the bot commits as: the repository's `identity`
+pub fn resolve_identity(repo_config: Option<&RepoConfig>) -> Result<GitIdentity> {
+    repo_config
+        .and_then(|repo_config| repo_config.identity.clone())
+        .ok_or_else(|| Error::config("No git identity: set `identity` (name, email) in the repository config"))
+}
+
+/// Sets `user.name` and `user.email` in the working copy's configuration
+pub fn apply_identity(repo: &Repository, identity: &GitIdentity) -> Result<()> {
+    let mut config = repo.config()?;
+    config.set_str("user.name", &identity.name)?;
+    config.set_str("user.email", &identity.email)?;
+    Ok(())
+}
+
+/// Working directory used to clone `repo_name` for `platform`
+pub fn workspace_path(platform: &str, repo_name: &str) -> Result<PathBuf> {
+    let current_dir = std::env::current_dir()?;
+    Ok(current_dir.join(platform).join(repo_name))
 }
 
 pub fn push_repository(
     repo_path: &PathBuf,
     remote_name: &str,
     branch: &str,
-) -> Result<(), git2::Error> {
+) -> Result<()> {
+    push_branches(repo_path, remote_name, &[branch.to_string()])
+}
+
+/// Pushes several branches with a single `remote.push` call (one connection)
+///
+/// Fails if the server rejects any of the updated references.
+pub fn push_branches(
+    repo_path: &PathBuf,
+    remote_name: &str,
+    branches: &[String],
+) -> Result<()> {
+    if branches.is_empty() {
+        info!("No branches to push to {}", remote_name);
+        return Ok(());
+    }
+
     let repo = Repository::open(repo_path)?;
     let mut remote = repo.find_remote(remote_name)?;
 
-    let mut callbacks = RemoteCallbacks::new();
-    callbacks.credentials(gitcode_credentials_callback);
+    // Ensure we're pushing to the correct refspecs
+    let refspecs: Vec<String> = branches
+        .iter()
+        .map(|branch| format!("+refs/heads/{}:refs/heads/{}", branch, branch))
+        .collect();
 
-    let mut push_options = PushOptions::new();
-    push_options.remote_callbacks(callbacks);
+    let rejected = std::cell::RefCell::new(Vec::new());
+    // Transport failures are retried; rejected references are not
+    RetryPolicy::new().retry_blocking(|attempt| {
+        let mut callbacks = RemoteCallbacks::new();
+        callbacks.credentials(push_credentials_callback);
+        deadline::watch_transfers(&mut callbacks);
+        callbacks.push_update_reference(|refname, status| {
+            if let Some(message) = status {
+                error!("Push of {} rejected: {}", refname, message);
+                rejected.borrow_mut().push(format!("{} ({})", refname, message));
+            }
+            Ok(())
+        });
+
+        let mut push_options = PushOptions::new();
+        push_options.remote_callbacks(callbacks);
+        info!("Pushing refspecs to {} (attempt {}): {:?}", remote_name, attempt, refspecs);
+        remote.push(&refspecs, Some(&mut push_options))
+    }, retry::is_transient_git_error)?;
 
-    // Ensure we're pushing to the correct refspec
-    let refspec = format!("+refs/heads/{}:refs/heads/{}", branch, branch);
-    remote.push(&[&refspec], Some(&mut push_options))?;
+    let rejected = rejected.into_inner();
+    if !rejected.is_empty() {
+        return Err(Error::Job(format!("Push rejected for: {}", rejected.join(", "))));
+    }
 
     Ok(())
 }
 
+/// Credential helper answering with the bot account passed in the command's environment
+const ENV_CREDENTIAL_HELPER: &str =
+    "!f() { echo \"username=$BOT_GIT_USERNAME\"; echo \"password=$BOT_GIT_PASSWORD\"; }; f";
+
+/// Pushes several branches with `git push --atomic`: the server updates every
+/// reference or none of them
+///
+/// libgit2 has no atomic push, so this runs the git CLI with the same account
+/// as `push_branches` (SSH remotes use the agent or `GIT_SSH_KEY_PATH`,
+/// without passphrase). Returns `Ok(false)`, having pushed nothing, when the
+/// server does not support atomic pushes.
+pub fn push_branches_atomic(
+    repo_path: &PathBuf,
+    remote_name: &str,
+    branches: &[String],
+) -> Result<bool> {
+    let refspecs: Vec<String> = branches
+        .iter()
+        .map(|branch| format!("+refs/heads/{}:refs/heads/{}", branch, branch))
+        .collect();
+
+    let repo = Repository::open(repo_path)?;
+    let remote_url = repo.find_remote(remote_name)?.url().unwrap_or_default().to_string();
+    // Local paths, as in tests, and SSH remotes need no account
+    let (username, token) = match RemoteUrl::parse(&remote_url) {
+        Ok(remote) if remote.credential_kind() == CredentialKind::Token => push_account(remote.platform()),
+        _ => Default::default(),
+    };
+
+    let mut git = Command::new("git");
+    git.current_dir(repo_path)
+        .args(["-c", "credential.helper=", "-c"])
+        .arg(format!("credential.helper={}", ENV_CREDENTIAL_HELPER))
+        .args(["push", "--atomic", "--porcelain", remote_name])
+        .args(&refspecs)
+        .env("GIT_TERMINAL_PROMPT", "0")
+        .env("BOT_GIT_USERNAME", username)
+        .env("BOT_GIT_PASSWORD", token);
+    if let Ok(key_path) = env::var("GIT_SSH_KEY_PATH") {
+        git.env("GIT_SSH_COMMAND", format!("ssh -i '{}' -o IdentitiesOnly=yes -o BatchMode=yes", key_path.replace('\'', "'\\''")));
+    }
+
+    info!("Pushing refspecs atomically to {}: {:?}", remote_name, refspecs);
+    let output = command::run_limited(&mut git, &CommandLimits::from_env())
+        .map_err(|e| Error::Job(format!("Failed to run git push: {}", e)))?;
+    if output.success() {
+        return Ok(true);
+    }
+    if output.stderr.contains("does not support --atomic") {
+        warn!("{} does not support atomic pushes", remote_name);
+        return Ok(false);
+    }
+    if output.timed_out {
+        return Err(Error::job("Atomic push timed out"));
+    }
+    error!("Atomic push to {} failed: {}{}", remote_name, output.stdout, output.stderr);
+    Err(Error::Job(format!("Atomic push rejected, no branch was updated: {}", output.stderr.trim())))
+}
+
+/// A pushed branch whose tip on the remote is not the commit the job created
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TipMismatch {
+    pub branch: String,
+    pub expected: git2::Oid,
+    /// `None` when the branch does not exist on the remote
+    pub actual: Option<git2::Oid>,
+}
+
+/// Reads the tips of `branches` on `remote_name` without fetching, like `git ls-remote`
+pub fn remote_branch_tips(repo_path: &PathBuf, remote_name: &str, branches: &[String]) -> Result<HashMap<String, git2::Oid>> {
+    let repo = Repository::open(repo_path)?;
+    let mut remote = repo.find_remote(remote_name)?;
+    let mut callbacks = RemoteCallbacks::new();
+    callbacks.credentials(push_credentials_callback);
+    let connection = remote.connect_auth(git2::Direction::Fetch, Some(callbacks), None)?;
+    let tips = connection
+        .list()?
+        .iter()
+        .filter_map(|head| {
+            let branch = head.name().strip_prefix("refs/heads/")?;
+            branches.iter().any(|b| b == branch).then(|| (branch.to_string(), head.oid()))
+        })
+        .collect();
+    Ok(tips)
+}
+
+/// Pushed branches whose remote tip differs from the expected `(branch, commit)`
+pub fn tip_mismatches(expected: &[(String, git2::Oid)], actual: &HashMap<String, git2::Oid>) -> Vec<TipMismatch> {
+    expected
+        .iter()
+        .filter(|(branch, oid)| actual.get(branch) != Some(oid))
+        .map(|(branch, oid)| TipMismatch { branch: branch.clone(), expected: *oid, actual: actual.get(branch).copied() })
+        .collect()
+}
+
+/// Checks that every branch in `pushed_ranges` ends at the commit the job
+/// created on the remote, and records the verified tips on the job
+///
+/// Reads are retried, as some servers apply pushes asynchronously; a lasting
+/// mismatch (a server-side hook rewriting the commits, a concurrent push)
+/// fails the job.
+fn verify_pushed_tips(
+    repo_path: &PathBuf,
+    remote_name: &str,
+    pushed_ranges: &[(String, git2::Oid, git2::Oid)],
+    job_id: Uuid,
+) -> Result<Vec<(String, git2::Oid)>> {
+    let expected: Vec<(String, git2::Oid)> = pushed_ranges.iter().map(|(branch, _, head)| (branch.clone(), *head)).collect();
+    if expected.is_empty() {
+        return Ok(expected);
+    }
+    let branches: Vec<String> = expected.iter().map(|(branch, _)| branch.clone()).collect();
+    RetryPolicy::new().retry_blocking(|attempt| {
+        let mismatches = tip_mismatches(&expected, &remote_branch_tips(repo_path, remote_name, &branches)?);
+        if mismatches.is_empty() {
+            return Ok(());
+        }
+        let details: Vec<String> = mismatches
+            .iter()
+            .map(|m| format!("{} is at {} instead of {}", m.branch, m.actual.map(|oid| oid.to_string()).unwrap_or_else(|| "nothing".to_string()), m.expected))
+            .collect();
+        warn!("Pushed tips not verified on {} (attempt {}): {}", remote_name, attempt, details.join(", "));
+        Err(Error::Job(format!("Remote branch tips do not match the pushed commits: {}", details.join(", "))))
+    }, |_| true)?;
+
+    info!("Verified pushed tips on {}: {:?}", remote_name, expected);
+    jobs::store().lock().unwrap().update(job_id, |job| {
+        job.verified_tips = expected.iter().map(|(branch, oid)| (branch.clone(), oid.to_string())).collect();
+    });
+    Ok(expected)
+}
+
+/// Pushes the branches updated by a job, atomically when `atomic` is set and
+/// the server supports it
+fn push_updated_branches(
+    repo_path: &PathBuf,
+    remote_name: &str,
+    branches: &[String],
+    atomic: bool,
+) -> Result<()> {
+    if atomic && branches.len() > 1 {
+        if push_branches_atomic(repo_path, remote_name, branches)? {
+            return Ok(());
+        }
+        warn!("Falling back to a non-atomic push of {:?}", branches);
+    }
+    push_branches(repo_path, remote_name, branches)
+}
+
+/// Answers SSH credential requests from the agent or the key in `GIT_SSH_KEY_PATH`
+fn ssh_credentials(user_from_url: Option<&str>) -> Result<git2::Cred, git2::Error> {
+    let user = user_from_url.unwrap_or("git");
+    match env::var("GIT_SSH_KEY_PATH") {
+        Ok(key_path) => {
+            info!("Using SSH key {} for user {}", key_path, user);
+            let passphrase = env::var("GIT_SSH_KEY_PASSPHRASE").ok();
+            git2::Cred::ssh_key(user, None, std::path::Path::new(&key_path), passphrase.as_deref())
+        },
+        Err(_) => {
+            info!("Using SSH agent for user {}", user);
+            git2::Cred::ssh_key_from_agent(user)
+        },
+    }
+}
+
+/// Reads a credential from the environment, failing the git operation
+/// instead of the worker when it is not set
+fn credential_var(name: &str) -> Result<String, git2::Error> {
+    env::var(name).map_err(|_| git2::Error::from_str(&format!("{} not set in environment", name)))
+}
+
 pub fn gitcode_credentials_callback(
     _user: &str,
-    _user_from_url: Option<&str>,
-    _cred: git2::CredentialType,
+    user_from_url: Option<&str>,
+    cred: git2::CredentialType,
 ) -> Result<git2::Cred, git2::Error> {
     info!("GitCode credentials callback triggered");
-    let username = env::var("GITCODE_USERNAME").expect("GITCODE_USERNAME not set in environment");
-    let token = env::var("GITCODE_TOKEN").expect("GITCODE_TOKEN not set in environment");
+    if cred.contains(git2::CredentialType::SSH_KEY) {
+        return ssh_credentials(user_from_url);
+    }
+    let username = credential_var("GITCODE_USERNAME")?;
+    let token = credential_var("GITCODE_TOKEN")?;
     // For HTTP(S) URLs, we need to provide the username and token as password
     git2::Cred::userpass_plaintext(&username, &token)
 }
 
+/// Answers credential requests with `GITLAB_USERNAME` (default `oauth2`)
+/// and `GITLAB_TOKEN`
+pub fn gitlab_credentials_callback(
+    _user: &str,
+    user_from_url: Option<&str>,
+    cred: git2::CredentialType,
+) -> Result<git2::Cred, git2::Error> {
+    info!("GitLab credentials callback triggered");
+    if cred.contains(git2::CredentialType::SSH_KEY) {
+        return ssh_credentials(user_from_url);
+    }
+    let username = env::var("GITLAB_USERNAME").unwrap_or_else(|_| "oauth2".to_string());
+    let token = credential_var("GITLAB_TOKEN")?;
+    git2::Cred::userpass_plaintext(&username, &token)
+}
+
+/// Answers credential requests of pushes with the account of the platform
+/// the remote is on
+fn push_credentials_callback(
+    url: &str,
+    user_from_url: Option<&str>,
+    cred: git2::CredentialType,
+) -> Result<git2::Cred, git2::Error> {
+    let remote = RemoteUrl::parse(url).map_err(|e| git2::Error::from_str(&e.to_string()))?;
+    if remote.credential_kind() == CredentialKind::SshKey {
+        // libgit2 asks for the user name first when the URL carries none
+        if cred == git2::CredentialType::USERNAME {
+            return git2::Cred::username(user_from_url.unwrap_or("git"));
+        }
+        return ssh_credentials(user_from_url);
+    }
+    match remote.platform() {
+        "github" => github_credentials_callback(url, user_from_url, cred),
+        "gitlab" => gitlab_credentials_callback(url, user_from_url, cred),
+        _ => gitcode_credentials_callback(url, user_from_url, cred),
+    }
+}
+
+/// Username and token of the account on `platform`, for pushes made with the
+/// git CLI
+fn push_account(platform: &str) -> (String, String) {
+    match platform {
+        "github" => (env::var("GITHUB_USERNAME").unwrap_or_default(), env::var("GITHUB_TOKEN").unwrap_or_default()),
+        "gitlab" => (env::var("GITLAB_USERNAME").unwrap_or_else(|_| "oauth2".to_string()), env::var("GITLAB_TOKEN").unwrap_or_default()),
+        _ => (env::var("GITCODE_USERNAME").unwrap_or_default(), env::var("GITCODE_TOKEN").unwrap_or_default()),
+    }
+}
+
 pub fn github_credentials_callback(
     _user: &str,
-    _user_from_url: Option<&str>,
-    _cred: git2::CredentialType,
+    user_from_url: Option<&str>,
+    cred: git2::CredentialType,
 ) -> Result<git2::Cred, git2::Error> {
     info!("GitHub credentials callback triggered");
-    let username = env::var("GITHUB_USERNAME").expect("GITHUB_USERNAME not set in environment");
-    let token = env::var("GITHUB_TOKEN").expect("GITHUB_TOKEN not set in environment");
+    if cred.contains(git2::CredentialType::SSH_KEY) {
+        return ssh_credentials(user_from_url);
+    }
+    let username = credential_var("GITHUB_USERNAME")?;
+    let token = credential_var("GITHUB_TOKEN")?;
     // For GitHub, we use the token as the password
     git2::Cred::userpass_plaintext(&username, &token)
 }
 
-pub fn switch_branch(repo_path: &PathBuf, branch_name: &str) -> Result<(), git2::Error> {
+pub fn switch_branch(repo_path: &PathBuf, branch_name: &str) -> Result<()> {
     // Open the repository at the given path
     let repo = Repository::open(repo_path)?;
 
@@ -402,27 +1670,76 @@ pub fn switch_branch(repo_path: &PathBuf, branch_name: &str) -> Result<(), git2:
     Ok(())
 }
 
-pub fn cherry_pick_commit(repo_path: &PathBuf, commit_id: &str, _branch_name: &str, pr_url: &str) -> Result<(), git2::Error> {
+/// Outcome of `cherry_pick_commit`
+#[derive(Debug)]
+pub enum CherryPick {
+    Picked(git2::Oid),
+    /// The changes conflict with the branch; the cherry-pick was aborted and
+    /// the branch is unchanged
+    Conflicted(ConflictReport),
+}
+
+/// Cherry-picks `commit_id` onto HEAD with a three-way merge, committing
+/// the result on the current branch with the original author and the
+/// bot's trailers
+pub fn cherry_pick_commit(
+    repo_path: &PathBuf,
+    commit_id: &str,
+    branch_name: &str,
+    pr_url: &str,
+    sign_off: bool,
+    renames: &RenameDetection,
+    job_id: Uuid,
+) -> Result<CherryPick> {
     let repo = Repository::open(repo_path)?;
 
     // Find the commit to cherry-pick
     let commit = repo.find_commit(repo.revparse_single(commit_id)?.id())?;
     info!("Found commit to cherry-pick: {}", commit_id);
+    let parent_commit = repo.head()?.peel_to_commit()?;
 
-    // Get the tree of the commit
-    let tree = commit.tree()?;
+    // Apply the commit's changes onto HEAD in the index and working tree
+    let mut options = git2::CherrypickOptions::new();
+    options.merge_opts(renames.merge_options());
+    if commit.parent_count() > 1 {
+        options.mainline(1);
+    }
+    repo.cherrypick(&commit, Some(&mut options))?;
 
-    // Get the current HEAD as parent
-    let head = repo.head()?;
-    let parent_commit = head.peel_to_commit()?;
+    let mut index = repo.index()?;
+    if index.has_conflicts() {
+        let report = match conflict::detect_conflicts(&repo, &commit, &parent_commit, branch_name, renames)? {
+            Some(report) => report,
+            None => ConflictReport {
+                commit_sha: commit_id.to_string(),
+                branch: branch_name.to_string(),
+                files: conflicted_paths(&index)?,
+                snippet: String::new(),
+                truncated: false,
+            },
+        };
+        abort_cherry_pick(&repo, &parent_commit)?;
+        return Ok(CherryPick::Conflicted(report));
+    }
+    let tree = repo.find_tree(index.write_tree()?)?;
 
     // Create the new commit with original author and committer information
     let author = commit.author();
     let committer = repo.signature()?;
-    let message = commit.message().unwrap_or("").to_owned() + "\n\nCherry-picked from: " + pr_url;
+    let mut message = format!(
+        "{}\n\nCherry-picked from: {}\n{}{}",
+        commit.message().unwrap_or(""), pr_url, jobs::JOB_TRAILER, job_id
+    );
+    if sign_off {
+        message = dco::add_sign_off(
+            &message,
+            committer.name().unwrap_or("unknown"),
+            committer.email().unwrap_or("unknown"),
+        );
+    }
 
     // Create the cherry-picked commit
-    repo.commit(
+    let oid = repo.commit(
         Some("HEAD"),
         &author,
         &committer,
@@ -430,12 +1747,222 @@ pub fn cherry_pick_commit(repo_path: &PathBuf, commit_id: &str, _branch_name: &s
         &tree,
         &[&parent_commit]
     )?;
+    repo.cleanup_state()?;
 
     info!("Cherry-pick completed successfully");
+    Ok(CherryPick::Picked(oid))
+}
+
+/// Paths with conflict entries in `index`
+fn conflicted_paths(index: &git2::Index) -> Result<Vec<String>> {
+    let mut paths = Vec::new();
+    for conflict in index.conflicts()? {
+        let conflict = conflict?;
+        let entry = conflict.our.or(conflict.their).or(conflict.ancestor);
+        paths.push(entry.map(|entry| String::from_utf8_lossy(&entry.path).to_string()).unwrap_or_else(|| "<unknown>".to_string()));
+    }
+    Ok(paths)
+}
+
+/// Abandons a conflicted cherry-pick, restoring `head` in the index and working tree
+fn abort_cherry_pick(repo: &Repository, head: &git2::Commit) -> Result<()> {
+    repo.cleanup_state()?;
+    repo.reset(head.as_object(), git2::ResetType::Hard, None)?;
+    warn!("Aborted the cherry-pick onto {}", head.id());
     Ok(())
 }
 
-pub fn fetch_merge_request(repo_path: &PathBuf, remote_name: &str, iid: u32, platform: &str) -> Result<(), git2::Error> {
+/// Returns the commits among `commit_ids` whose message lacks a `Signed-off-by:` trailer
+pub fn commits_missing_sign_off(repo_path: &PathBuf, commit_ids: &[String]) -> Result<Vec<String>> {
+    let repo = Repository::open(repo_path)?;
+    let mut missing = Vec::new();
+    for commit_id in commit_ids {
+        let commit = repo.find_commit(repo.revparse_single(commit_id)?.id())?;
+        if !dco::has_sign_off(commit.message().unwrap_or("")) {
+            missing.push(commit_id.clone());
+        }
+    }
+    Ok(missing)
+}
+
+/// Enforces the DCO policy on the PR commits before any branch is touched
+fn enforce_dco(
+    local_path: &PathBuf,
+    webhook_data: &ParsedWebhookData,
+    platform: &str,
+    policy: DcoPolicy,
+    commits: &[gitcode::GitCommit],
+    templates: &CommentTemplates,
+    job_id: Uuid,
+) -> Result<()> {
+    if policy != DcoPolicy::Require {
+        return Ok(());
+    }
+    let commit_ids: Vec<String> = commits.iter().map(|c| c.sha.clone()).collect();
+    let missing = commits_missing_sign_off(local_path, &commit_ids)?;
+    if missing.is_empty() {
+        return Ok(());
+    }
+    error!("Commits without sign-off: {:?}", missing);
+    comment_on_source_pr(webhook_data, platform, &dco::format_missing_sign_off_comment(&missing, templates), job_id);
+    Err(Error::Job(format!("{} commits lack a Signed-off-by trailer", missing.len())))
+}
+
+/// Why a job for an archived or read-only repository is skipped, if it is
+pub fn access_message(repo: &str, access: RepoAccess) -> Option<String> {
+    if access.archived {
+        Some(format!("Repository {} is archived; nothing can be pushed to it", repo))
+    } else if access.read_only {
+        Some(format!("Repository {} is read-only for the service's token; nothing can be pushed to it", repo))
+    } else {
+        None
+    }
+}
+
+/// Skips a job before cloning when its repository is archived or read-only,
+/// rather than failing at push with a permission error
+///
+/// The payload's archived flag is trusted when present; otherwise, and for
+/// write access, the repository API is asked. A failed lookup lets the job run.
+fn access_gate(webhook_data: &ParsedWebhookData, platform: &str) -> Option<String> {
+    let repo = format!("{}/{}", webhook_data.namespace, webhook_data.repo_name);
+    let access = match webhook_data.archived {
+        Some(true) => RepoAccess { archived: true, read_only: false },
+        _ => match gitcode::get_repo_access(api_base_url(platform), &webhook_data.namespace, &webhook_data.repo_name, platform) {
+            Ok(access) => access,
+            Err(e) => {
+                warn!("Failed to check access to {}, proceeding: {}", repo, e);
+                return None;
+            }
+        },
+    };
+    access_message(&repo, access)
+}
+
+/// Returns the REST API base URL of `platform`
+pub fn api_base_url(platform: &str) -> &'static str {
+    match platform {
+        "github" => "https://api.github.com/repos",
+        "gitlab" => gitlab::api_base_url(),
+        _ => "https://api.gitcode.com/api/v5/repos",
+    }
+}
+
+/// Applies the repository's `mentions` setting to a comment posted on `platform`
+fn rewrite_mentions(repo_config: Option<&RepoConfig>, platform: &str, message: &str) -> String {
+    match repo_config {
+        Some(repo_config) => mentions::rewrite_mentions(
+            message,
+            repo_config.mentions,
+            &mentions::mention_map(&repo_config.author_map, platform),
+        ),
+        None => message.to_string(),
+    }
+}
+
+/// Posts `message` on the PR that triggered the webhook, logging failures;
+/// during a job it is posted with the job's other comments when the job ends
+fn comment_on_source_pr(webhook_data: &ParsedWebhookData, platform: &str, message: &str, job_id: Uuid) {
+    let repo_config = config::load_repo_config_in(&webhook_data.namespace, &webhook_data.repo_name).ok().flatten();
+    let message = &rewrite_mentions(repo_config.as_ref(), platform, message);
+    if let Some(pr) = outbox::PullRequestRef::source(webhook_data, platform) {
+        if outbox::defer_comment(pr, message) {
+            return;
+        }
+    }
+    let message = &jobs::with_job_reference(message, &job_id.to_string());
+    if let Some(iid) = webhook_data.iid {
+        let result = RetryPolicy::new().retry_blocking(|_| gitcode::post_comment_on_pr(
+            api_base_url(platform),
+            &webhook_data.namespace,
+            &webhook_data.repo_name,
+            iid,
+            message,
+            platform,
+        ), Error::is_transient);
+        if let Err(e) = result {
+            error!("Failed to post comment to PR #{}: {}", iid, e);
+        }
+    }
+}
+
+/// Posts the conflict report on the originating PR and returns the job error
+fn report_conflict(webhook_data: &ParsedWebhookData, platform: &str, report: &ConflictReport, templates: &CommentTemplates, patches: &[StoredPatch], job_id: Uuid) -> Error {
+    error!("Cherry-pick of {} onto {} conflicts in: {:?}", report.commit_sha, report.branch, report.files);
+    let patch_list = patches::format_patch_list(patches, templates.locale);
+    comment_on_source_pr(webhook_data, platform, &conflict::format_conflict_comment(report, templates, &patch_list), job_id);
+    conflict_owner::assign_conflict(webhook_data, platform, &report.branch);
+    Error::Job(format!(
+        "Cherry-pick of {} onto {} conflicts in {} files",
+        report.commit_sha, report.branch, report.files.len()
+    ))
+}
+
+/// Runs the repository's commit guards on `commit_id` before it is backported
+/// onto `branch`, counting oversized commits; a blocked commit fails the job
+/// with a comment on the originating PR
+#[allow(clippy::too_many_arguments)]
+fn guard_commit(
+    repo_path: &PathBuf,
+    webhook_data: &ParsedWebhookData,
+    platform: &str,
+    commit_id: &str,
+    branch: &str,
+    guards: &CommitGuards,
+    templates: &CommentTemplates,
+    job_id: Uuid,
+) -> Result<GuardDecision> {
+    let repo = Repository::open(repo_path)?;
+    let findings = commit_guard::inspect(&repo, repo.revparse_single(commit_id)?.id(), guards)?;
+    if findings.is_empty() {
+        return Ok(GuardDecision::Pick);
+    }
+    warn!("Commit {} exceeds the size limits: {:?}", commit_id, findings);
+    let kinds: Vec<&'static str> = [(findings.message_bytes.is_some(), "message"), (!findings.large_blobs.is_empty(), "blob")]
+        .into_iter()
+        .filter_map(|(exceeded, kind)| exceeded.then_some(kind))
+        .collect();
+    let labels: Vec<&str> = webhook_data.labels.iter().map(|label| label.title.as_str()).collect();
+    let decision = commit_guard::decide(findings, guards, &labels);
+    for kind in kinds {
+        metrics::record_oversized_commit(&webhook_data.repo_name, kind, decision.action());
+    }
+    info!("Oversized commit {} on {}: {}", commit_id, branch, decision.action());
+    if let GuardDecision::Block(findings) = &decision {
+        comment_on_source_pr(webhook_data, platform, &commit_guard::format_blocked_comment(branch, commit_id, findings, guards, templates), job_id);
+        return Err(Error::Job(format!(
+            "Commit {} exceeds the size limits; add the {} label to backport it", commit_id, guards.override_label
+        )));
+    }
+    Ok(decision)
+}
+
+/// Rewrites the just backported commit `oid` when the guards decided to strip it
+fn strip_if_needed(repo_path: &PathBuf, oid: git2::Oid, decision: &GuardDecision, guards: &CommitGuards) -> Result<git2::Oid> {
+    match decision {
+        GuardDecision::Strip(findings) => Ok(commit_guard::strip(&Repository::open(repo_path)?, oid, findings, guards)?),
+        _ => Ok(oid),
+    }
+}
+
+/// Explains a rejected `br:` label on the originating PR and returns the job error
+fn report_invalid_branch(webhook_data: &ParsedWebhookData, platform: &str, label: &str, reason: &str, templates: &CommentTemplates, job_id: Uuid) -> Error {
+    error!("Invalid branch name {:?} in label: {}", label, reason);
+    comment_on_source_pr(webhook_data, platform, &branch::format_invalid_branch_comment(label, reason, templates), job_id);
+    Error::Job(format!("Invalid branch name {:?}: {}", label, reason))
+}
+
+/// Alerts about secrets found in cherry-picked changes and returns the job error
+fn report_secrets(webhook_data: &ParsedWebhookData, platform: &str, branch: &str, findings: &[secrets::SecretFinding], templates: &CommentTemplates, job_id: Uuid) -> Error {
+    error!("Blocking push to {}: {} possible secrets found: {:?}", branch, findings.len(), findings);
+    comment_on_source_pr(webhook_data, platform, &secrets::format_findings_comment(branch, findings, templates), job_id);
+    Error::Job(format!(
+        "Push to {} blocked: {} possible secrets in cherry-picked changes",
+        branch, findings.len()
+    ))
+}
+
+pub fn fetch_merge_request(repo_path: &PathBuf, remote_name: &str, iid: u32, platform: &str) -> Result<()> {
     info!("Fetching merge request - Path: {:?}, Remote: {}, PR: {}", repo_path, remote_name, iid);
     let repo = Repository::open(repo_path)?;
     info!("Repository opened successfully");
@@ -448,21 +1975,24 @@ pub fn fetch_merge_request(repo_path: &PathBuf, remote_name: &str, iid: u32, pla
         "github" => {
             let mut callbacks = RemoteCallbacks::new();
             callbacks.credentials(github_credentials_callback);
+            deadline::watch_transfers(&mut callbacks);
             callbacks
         },
         "gitcode" => {
             let mut callbacks = RemoteCallbacks::new();
             callbacks.credentials(gitcode_credentials_callback);
+            deadline::watch_transfers(&mut callbacks);
             callbacks
         },
-        _ => return Err(git2::Error::from_str("Unsupported platform")),
+        "gitlab" => platform_callbacks(platform),
+        _ => return Err(Error::Config(format!("Unsupported platform {}", platform))),
     });
 
     // Create the refspec based on platform
     let refspec = match platform {
         "github" => format!("pull/{}/head:refs/remotes/{}/pr/{}", iid, remote_name, iid),
-        "gitcode" => format!("+refs/merge-requests/{}/head:refs/remotes/{}/mr/{}", iid, remote_name, iid),
-        _ => return Err(git2::Error::from_str("Unsupported platform")),
+        "gitcode" | "gitlab" => format!("+refs/merge-requests/{}/head:refs/remotes/{}/mr/{}", iid, remote_name, iid),
+        _ => return Err(Error::Config(format!("Unsupported platform {}", platform))),
     };
     info!("Created refspec: {}", refspec);
 
@@ -478,15 +2008,79 @@ pub fn fetch_merge_request(repo_path: &PathBuf, remote_name: &str, iid: u32, pla
     Ok(())
 }
 
+/// Commits a queued merge landed on the base branch as `merge_sha`, newest
+/// first like `pr_commits`
+///
+/// A merge queue or auto-merge may rebase or squash the PR after its last
+/// head, so the PR's own commits are not what was merged. A merge commit
+/// brings in the commits of its second parent; otherwise the first-parent
+/// chain is the rebased PR when its summaries are the PR commits' ones, and
+/// a single squashed commit when they are not.
+pub fn resolve_merged_commits(repo: &Repository, merge_sha: &str, pr_commits: &[gitcode::GitCommit]) -> Result<Vec<gitcode::GitCommit>> {
+    let merge = repo.find_commit(git2::Oid::from_str(merge_sha)?)?;
+    let as_commits = |oids: Vec<git2::Oid>| oids.into_iter().map(|oid| gitcode::GitCommit { sha: oid.to_string() }).collect::<Vec<_>>();
+    if merge.parent_count() > 1 {
+        let mut walk = repo.revwalk()?;
+        walk.set_sorting(git2::Sort::TOPOLOGICAL)?;
+        walk.push(merge.parent_id(1)?)?;
+        walk.hide(merge.parent_id(0)?)?;
+        return Ok(as_commits(walk.collect::<Result<Vec<_>, _>>()?));
+    }
+
+    let summary = |oid: git2::Oid| repo.find_commit(oid).ok().and_then(|commit| commit.summary().map(str::to_string));
+    let mut expected: Vec<Option<String>> = pr_commits.iter().map(|commit| git2::Oid::from_str(&commit.sha).ok().and_then(summary)).collect();
+    let mut chain = vec![merge.id()];
+    while chain.len() < pr_commits.len() {
+        match repo.find_commit(*chain.last().unwrap())?.parent_ids().next() {
+            Some(parent) => chain.push(parent),
+            None => break,
+        }
+    }
+    let mut landed: Vec<Option<String>> = chain.iter().map(|oid| summary(*oid)).collect();
+    expected.sort();
+    landed.sort();
+    if pr_commits.len() > 1 && expected.iter().all(Option::is_some) && landed == expected {
+        info!("Merge {} rebased the {} PR commits", merge_sha, chain.len());
+        return Ok(as_commits(chain));
+    }
+    info!("Merge {} squashed the PR into one commit", merge_sha);
+    Ok(as_commits(vec![merge.id()]))
+}
+
+/// Fetches `merge_sha` from `remote_name` when the clone lacks it and
+/// resolves the commits it merged
+fn merged_commits(local_path: &Path, remote_name: &str, merge_sha: &str, pr_commits: &[gitcode::GitCommit]) -> Result<Vec<gitcode::GitCommit>> {
+    let repo = Repository::open(local_path)?;
+    if repo.find_commit(git2::Oid::from_str(merge_sha)?).is_err() {
+        info!("Fetching merge commit {}", merge_sha);
+        let mut fetch_options = git2::FetchOptions::new();
+        fetch_options.remote_callbacks(platform_callbacks("github"));
+        repo.find_remote(remote_name)?.fetch(&[merge_sha], Some(&mut fetch_options), None)?;
+    }
+    resolve_merged_commits(&repo, merge_sha, pr_commits)
+}
+
+/// Connects to `url` for pushing without transferring anything
+///
+/// Verifies the URL and push credentials, returning the number of remote heads.
+pub fn probe_push_access(url: &str) -> Result<usize> {
+    let platform = RemoteUrl::parse(url)?.platform();
+    let mut remote = git2::Remote::create_detached(url)?;
+    let connection = remote.connect_auth(git2::Direction::Push, Some(platform_callbacks(platform)), None)?;
+    let heads = connection.list()?.len();
+    info!("Push access to {} verified ({} refs)", url, heads);
+    Ok(heads)
+}
+
 pub fn add_remote_repository(
     repo_path: &PathBuf,
     remote_name: &str,
     remote_url: &str,
-) -> Result<(), git2::Error> {
+) -> Result<()> {
     let repo = Repository::open(repo_path)?;
     
     // Check if remote already exists
-    if let Ok(_) = repo.find_remote(remote_name) {
+    if repo.find_remote(remote_name).is_ok() {
         // If it exists, remove it first
         repo.remote_delete(remote_name)?;
     }
@@ -497,3 +2091,290 @@ pub fn add_remote_repository(
     
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+    use std::os::unix::fs::PermissionsExt;
+
+    fn commit_on(repo: &Repository, branch: &str, parent: Option<git2::Oid>) -> git2::Oid {
+        let tree = repo.find_tree(repo.treebuilder(None).unwrap().write().unwrap()).unwrap();
+        let sig = git2::Signature::now("test", "test@example.com").unwrap();
+        let parents: Vec<git2::Commit> = parent.map(|oid| repo.find_commit(oid).unwrap()).into_iter().collect();
+        let parents: Vec<&git2::Commit> = parents.iter().collect();
+        repo.commit(Some(&format!("refs/heads/{}", branch)), &sig, &sig, branch, &tree, &parents).unwrap()
+    }
+
+    #[test]
+    fn test_cherry_pick_merges_and_aborts_on_conflict() {
+        let dir = tempfile::tempdir().unwrap();
+        let repo = Repository::init(dir.path()).unwrap();
+        let path = dir.path().to_path_buf();
+        let sig = git2::Signature::now("test", "test@example.com").unwrap();
+        repo.config().unwrap().set_str("user.name", "bot").unwrap();
+        repo.config().unwrap().set_str("user.email", "bot@example.com").unwrap();
+        let write = |files: &[(&str, &str)], parent: Option<git2::Oid>, update: Option<&str>| {
+            let mut builder = git2::build::TreeUpdateBuilder::new();
+            for (name, content) in files {
+                builder.upsert(*name, repo.blob(content.as_bytes()).unwrap(), git2::FileMode::Blob);
+            }
+            let base = match parent {
+                Some(parent) => repo.find_commit(parent).unwrap().tree().unwrap(),
+                None => repo.find_tree(repo.treebuilder(None).unwrap().write().unwrap()).unwrap(),
+            };
+            let tree = repo.find_tree(builder.create_updated(&repo, &base).unwrap()).unwrap();
+            let parents: Vec<git2::Commit> = parent.map(|oid| repo.find_commit(oid).unwrap()).into_iter().collect();
+            let parents: Vec<&git2::Commit> = parents.iter().collect();
+            repo.commit(update, &sig, &sig, "change", &tree, &parents).unwrap()
+        };
+        let base = write(&[("a.txt", "one\n"), ("b.txt", "one\n")], None, None);
+        let release = write(&[("b.txt", "release\n")], Some(base), Some("refs/heads/release"));
+        let fix = write(&[("a.txt", "fixed\n")], Some(base), None);
+        let clash = write(&[("b.txt", "main\n")], Some(base), None);
+        repo.set_head("refs/heads/release").unwrap();
+        repo.checkout_head(Some(git2::build::CheckoutBuilder::new().force())).unwrap();
+
+        // The branch keeps its own changes instead of getting the source tree
+        let CherryPick::Picked(oid) = cherry_pick_commit(&path, &fix.to_string(), "release", "url", false, &RenameDetection::default(), Uuid::nil()).unwrap() else {
+            panic!("expected a clean cherry-pick");
+        };
+        let tree = repo.find_commit(oid).unwrap().tree().unwrap();
+        let content = |name: &str| String::from_utf8(repo.find_blob(tree.get_name(name).unwrap().id()).unwrap().content().to_vec()).unwrap();
+        assert_eq!((content("a.txt").as_str(), content("b.txt").as_str()), ("fixed\n", "release\n"));
+        assert_eq!(repo.find_commit(oid).unwrap().parent_id(0).unwrap(), release);
+
+        let CherryPick::Conflicted(report) = cherry_pick_commit(&path, &clash.to_string(), "release", "url", false, &RenameDetection::default(), Uuid::nil()).unwrap() else {
+            panic!("expected a conflict");
+        };
+        assert_eq!(report.files, vec!["b.txt"]);
+        assert_eq!(repo.head().unwrap().target().unwrap(), oid);
+        assert_eq!(repo.state(), git2::RepositoryState::Clean);
+        assert!(!repo.index().unwrap().has_conflicts());
+        assert_eq!(fs::read_to_string(dir.path().join("b.txt")).unwrap(), "release\n");
+    }
+
+    #[test]
+    fn test_ci_inputs() {
+        let trigger = CiTrigger { workflow: Some("release.yml".to_string()), inputs: BTreeMap::from([("suite".to_string(), "full".to_string())]) };
+        let from = git2::Oid::from_str("1111111111111111111111111111111111111111").unwrap();
+        let to = git2::Oid::from_str("2222222222222222222222222222222222222222").unwrap();
+        let inputs = ci_inputs(&trigger, "release-1.2", from, to);
+        assert_eq!(inputs.len(), 4);
+        assert_eq!(inputs["branch"], "release-1.2");
+        assert_eq!(inputs["from"], from.to_string());
+        assert_eq!(inputs["suite"], "full");
+    }
+
+    #[test]
+    fn test_atomic_push_updates_all_or_nothing() {
+        let dir = tempfile::tempdir().unwrap();
+        let remote_path = dir.path().join("remote.git");
+        Repository::init_bare(&remote_path).unwrap();
+        let local_path = dir.path().join("local");
+        let local = Repository::init(&local_path).unwrap();
+        local.remote("origin", remote_path.to_str().unwrap()).unwrap();
+        let base = commit_on(&local, "release-1", None);
+        commit_on(&local, "release-2", Some(base));
+        let branches = vec!["release-1".to_string(), "release-2".to_string()];
+
+        // The server refuses release-2: release-1 must not be updated either
+        let hook = remote_path.join("hooks").join("update");
+        fs::write(&hook, "#!/bin/sh\n[ \"$1\" != refs/heads/release-2 ]\n").unwrap();
+        fs::set_permissions(&hook, fs::Permissions::from_mode(0o755)).unwrap();
+        assert!(push_branches_atomic(&local_path, "origin", &branches).is_err());
+        let remote = Repository::open_bare(&remote_path).unwrap();
+        assert!(remote.find_reference("refs/heads/release-1").is_err());
+
+        fs::remove_file(&hook).unwrap();
+        assert!(push_branches_atomic(&local_path, "origin", &branches).unwrap());
+        assert!(remote.find_reference("refs/heads/release-1").is_ok());
+        assert!(remote.find_reference("refs/heads/release-2").is_ok());
+    }
+
+    #[test]
+    fn test_remote_tips_reveal_rewritten_pushes() {
+        let dir = tempfile::tempdir().unwrap();
+        let remote_path = dir.path().join("remote.git");
+        let remote = Repository::init_bare(&remote_path).unwrap();
+        let local_path = dir.path().join("local");
+        let local = Repository::init(&local_path).unwrap();
+        local.remote("origin", remote_path.to_str().unwrap()).unwrap();
+        let pushed = commit_on(&local, "release-1", None);
+        push_branches(&local_path, "origin", &["release-1".to_string()]).unwrap();
+
+        let branches = vec!["release-1".to_string(), "release-2".to_string()];
+        let tips = remote_branch_tips(&local_path, "origin", &branches).unwrap();
+        assert_eq!(tips.get("release-1"), Some(&pushed));
+        let expected = vec![("release-1".to_string(), pushed), ("release-2".to_string(), pushed)];
+        assert_eq!(tip_mismatches(&expected, &tips), vec![TipMismatch { branch: "release-2".to_string(), expected: pushed, actual: None }]);
+
+        // A hook rewriting the branch on the server
+        let rewritten = commit_on(&remote, "release-1", Some(pushed));
+        let tips = remote_branch_tips(&local_path, "origin", &branches).unwrap();
+        assert_eq!(tip_mismatches(&expected[..1], &tips)[0].actual, Some(rewritten));
+    }
+
+    #[test]
+    fn test_mirror_copies_branches_and_tags() {
+        let dir = tempfile::tempdir().unwrap();
+        let source = Repository::init_bare(dir.path().join("source.git")).unwrap();
+        let main = commit_on(&source, "main", None);
+        commit_on(&source, "release-1", Some(main));
+        source.tag_lightweight("v1.0", &source.find_object(main, None).unwrap(), false).unwrap();
+        let target = Repository::init_bare(dir.path().join("target.git")).unwrap();
+        commit_on(&target, "target-only", None);
+
+        let source_url = dir.path().join("source.git").to_string_lossy().to_string();
+        let target_url = dir.path().join("target.git").to_string_lossy().to_string();
+        commit_on(&source, "security/cve-1", Some(main));
+        let internal = vec!["security/*".to_string()];
+        let dry_run = mirror_repository(&source_url, &target_url, &dir.path().join("dry-run"), &internal, &[], Uuid::new_v4(), true).unwrap();
+        assert_eq!(dry_run.iter().map(|change| change.kind()).collect::<Vec<_>>(), vec!["create"; 3]);
+        assert!(target.find_reference("refs/heads/main").is_err());
+        let changes = mirror_repository(&source_url, &target_url, &dir.path().join("work"), &internal, &[], Uuid::new_v4(), false).unwrap();
+        assert_eq!(changes, dry_run);
+        assert!(mirror_repository(&source_url, &target_url, &dir.path().join("again"), &internal, &[], Uuid::new_v4(), false).unwrap().is_empty());
+        assert!(target.find_reference("refs/heads/security/cve-1").is_err());
+        assert_eq!(target.refname_to_id("refs/heads/main").unwrap(), main);
+        assert!(target.find_reference("refs/heads/release-1").is_ok());
+        assert!(target.find_reference("refs/tags/v1.0").is_ok());
+        assert!(target.find_reference("refs/heads/target-only").is_ok());
+    }
+
+    #[test]
+    fn test_queued_merges_resolve_to_the_landed_commits() {
+        let dir = tempfile::tempdir().unwrap();
+        let repo = Repository::init_bare(dir.path()).unwrap();
+        let commit = |message: &str, parents: &[git2::Oid]| {
+            let tree = repo.find_tree(repo.treebuilder(None).unwrap().write().unwrap()).unwrap();
+            let sig = git2::Signature::now("test", "test@example.com").unwrap();
+            let parents: Vec<git2::Commit> = parents.iter().map(|oid| repo.find_commit(*oid).unwrap()).collect();
+            let parents: Vec<&git2::Commit> = parents.iter().collect();
+            repo.commit(None, &sig, &sig, message, &tree, &parents).unwrap()
+        };
+        let sha = |oids: &[git2::Oid]| oids.iter().map(|oid| gitcode::GitCommit { sha: oid.to_string() }).collect::<Vec<_>>();
+        let resolve = |merge: git2::Oid, pr: &[gitcode::GitCommit]| {
+            resolve_merged_commits(&repo, &merge.to_string(), pr).unwrap().into_iter().map(|commit| commit.sha).collect::<Vec<_>>()
+        };
+        let shas = |oids: &[git2::Oid]| oids.iter().map(git2::Oid::to_string).collect::<Vec<_>>();
+        let base = commit("base", &[]);
+        let first = commit("fix parser", &[base]);
+        let second = commit("add test", &[first]);
+        // Newest first, as the backport loop expects
+        let pr = sha(&[second, first]);
+        let main = commit("unrelated", &[base]);
+
+        // Merge commit: the queue's updated PR commits come from its second parent
+        let requeued = commit("add test", &[first]);
+        let merge = commit("Merge pull request #1", &[main, requeued]);
+        assert_eq!(resolve(merge, &pr), shas(&[requeued, first]));
+
+        // Rebase: the first-parent chain carries the PR's summaries
+        let rebased_first = commit("fix parser", &[main]);
+        let rebased_second = commit("add test", &[rebased_first]);
+        assert_eq!(resolve(rebased_second, &pr), shas(&[rebased_second, rebased_first]));
+
+        // Squash: one commit replacing the PR
+        let squashed = commit("Fix parser (#1)", &[main]);
+        assert_eq!(resolve(squashed, &pr), shas(&[squashed]));
+    }
+
+    #[test]
+    fn test_archived_and_read_only_repositories_are_skipped() {
+        let github = serde_json::json!({ "archived": false, "permissions": { "admin": false, "push": false, "pull": true } });
+        assert_eq!(gitcode::repo_access(&github), RepoAccess { archived: false, read_only: true });
+        let gitcode_repo = serde_json::json!({ "archived": true, "permission": { "push": true } });
+        assert_eq!(gitcode::repo_access(&gitcode_repo), RepoAccess { archived: true, read_only: false });
+        // Unknown permissions do not block
+        assert_eq!(gitcode::repo_access(&serde_json::json!({ "name": "repo" })), RepoAccess::default());
+
+        assert!(access_message("org/repo", RepoAccess { archived: true, read_only: true }).unwrap().contains("is archived"));
+        assert!(access_message("org/repo", RepoAccess { archived: false, read_only: true }).unwrap().contains("read-only"));
+        assert_eq!(access_message("org/repo", RepoAccess::default()), None);
+
+        let payload = r#"{"action":"closed","pull_request":{"url":"u","state":"closed","number":1,"labels":[],"html_url":"h"},
+            "repository":{"name":"repo","clone_url":"c","full_name":"org/repo","archived":true}}"#;
+        assert_eq!(crate::utils::parser::parse_github_pr_data(payload).unwrap().archived, Some(true));
+    }
+
+    #[test]
+    fn test_operator_backport_request_targets_the_source_platform() {
+        let github = RepoConfig::new("https://gitcode.com/mirror/repo.git", "org", "repo");
+        let (platform, data) = operator_backport_request(&github, 12, "release-1.0");
+        assert_eq!(platform, "github");
+        assert_eq!(data.repo_url, "https://github.com/org/repo.git");
+        assert_eq!(data.action.as_deref(), Some("closed"));
+        assert!(data.labels_trusted);
+        assert_eq!(status_labels::requested_branches(&data), vec!["release-1.0"]);
+
+        let gitcode = RepoConfig::new("https://gitcode.com/org/repo.git", "org", "repo");
+        let (platform, data) = operator_backport_request(&gitcode, 12, "release-1.0");
+        assert_eq!(platform, "gitcode");
+        assert_eq!(data.action.as_deref(), Some("close"));
+        assert_eq!(data.url.as_deref(), Some("https://gitcode.com/org/repo/pull/12"));
+    }
+
+    #[test]
+    fn test_commits_are_prefetched_for_approved_merges_only() {
+        let repo_config = RepoConfig::new("https://gitcode.com/org/repo.git", "org", "repo");
+        let (platform, mut data) = operator_backport_request(&repo_config, 12, "release-1.0");
+        assert!(needs_commits(&data, platform));
+        data.labels.retain(|label| label.title != "approval: done");
+        assert!(!needs_commits(&data, platform));
+        let (_, mut data) = operator_backport_request(&repo_config, 12, "release-1.0");
+        data.action = Some("update".to_string());
+        assert!(!needs_commits(&data, platform));
+    }
+
+    #[test]
+    fn test_missing_credentials_fail_the_git_operation() {
+        let error = credential_var("WEBHOOK_SERVICE_TEST_UNSET_TOKEN").unwrap_err();
+        assert_eq!(error.message(), "WEBHOOK_SERVICE_TEST_UNSET_TOKEN not set in environment");
+    }
+
+    #[test]
+    fn test_pushes_to_github_use_the_github_account() {
+        if env::var("GITHUB_USERNAME").is_ok() {
+            return;
+        }
+        let result = push_credentials_callback("https://github.com/org/repo.git", None, git2::CredentialType::USER_PASS_PLAINTEXT);
+        assert_eq!(result.err().map(|e| e.message().to_string()).as_deref(), Some("GITHUB_USERNAME not set in environment"));
+    }
+
+    #[test]
+    fn test_restricted_labels_fail_closed_where_they_cannot_be_checked() {
+        let mut repo_config = RepoConfig::new("https://gitcode.com/org/repo.git", "org", "repo");
+        let (platform, mut data) = operator_backport_request(&repo_config, 12, "release-1.0");
+        let mut br_labels: Vec<&Label> = data.labels.iter().filter(|label| label.title.starts_with("br:")).collect();
+        assert_eq!(label_gate(&data, &repo_config, &mut br_labels, platform).unwrap(), None);
+
+        repo_config.restrict_labels = true;
+        assert_eq!(label_gate(&data, &repo_config, &mut br_labels, platform).unwrap(), None);
+        data.labels_trusted = false;
+        let mut br_labels: Vec<&Label> = data.labels.iter().filter(|label| label.title.starts_with("br:")).collect();
+        let error = label_gate(&data, &repo_config, &mut br_labels, platform).unwrap_err();
+        assert_eq!(error.to_string(), "Label events are not supported on gitcode");
+    }
+
+    #[test]
+    fn test_range_commits_lists_the_hotfixes_newest_first() {
+        let dir = tempfile::tempdir().unwrap();
+        let repo = Repository::init(dir.path()).unwrap();
+        let base = commit_on(&repo, "main", None);
+        let fix = commit_on(&repo, "main", Some(base));
+        let follow_up = commit_on(&repo, "main", Some(fix));
+        let other = commit_on(&repo, "feature", Some(base));
+        repo.reference("refs/remotes/origin/main", follow_up, true, "test").unwrap();
+
+        let shas = |commits: Vec<gitcode::GitCommit>| commits.into_iter().map(|commit| commit.sha).collect::<Vec<_>>();
+        let range = format!("{}..{}", base, follow_up);
+        assert_eq!(shas(range_commits(&repo, "main", &range).unwrap()), vec![follow_up.to_string(), fix.to_string()]);
+        let short = format!("{}..{}", &base.to_string()[..7], &fix.to_string()[..7]);
+        assert_eq!(shas(range_commits(&repo, "main", &short).unwrap()), vec![fix.to_string()]);
+
+        assert_eq!(range_commits(&repo, "main", &base.to_string()).unwrap_err().kind(), "parse");
+        assert_eq!(range_commits(&repo, "main", &format!("{}..{}", base, other)).unwrap_err().kind(), "job");
+        assert_eq!(range_commits(&repo, "main", &format!("{}..{}", fix, fix)).unwrap_err().kind(), "job");
+    }
+}
@@ -1,6 +1,10 @@
 use serde::{Deserialize, Serialize};
 use reqwest::header::{HeaderMap, HeaderValue, AUTHORIZATION, USER_AGENT};
 use log::{info, error};
+use std::collections::{BTreeMap, HashMap};
+
+use crate::error::{Error, Result};
+use crate::utils::{gitlab, http_client, http_headers, metrics};
 
 #[derive(Debug, Serialize, Deserialize)]
 pub struct GitAuthor {
@@ -8,7 +12,7 @@ pub struct GitAuthor {
     pub email: String,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct GitCommit {
     pub sha: String,
 }
@@ -18,7 +22,669 @@ struct CommentRequest {
     body: String,
 }
 
-pub fn get_commit_list_of_pr(base_url: &str, namespace: &str, repo_name: &str, pull_id: u32, platform: &str) -> Result<Vec<GitCommit>, Box<dyn std::error::Error>> {
+#[derive(Debug, Serialize, Deserialize)]
+pub struct RepoLabel {
+    pub name: String,
+    #[serde(default)]
+    pub color: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+struct CreateLabelRequest<'a> {
+    name: &'a str,
+    color: &'a str,
+    description: &'a str,
+}
+
+/// Color used for bot-created labels without a configured color
+pub const DEFAULT_LABEL_COLOR: &str = "ededed";
+
+/// Builds the authorization headers for API calls to `platform`
+pub(crate) fn api_headers(platform: &str) -> Result<HeaderMap> {
+    let token = match platform {
+        "github" => std::env::var("GITHUB_TOKEN").map_err(|_| Error::config("GITHUB_TOKEN not set"))?,
+        "gitcode" => std::env::var("GITCODE_TOKEN").map_err(|_| Error::config("GITCODE_TOKEN not set"))?,
+        "gitlab" => std::env::var("GITLAB_TOKEN").map_err(|_| Error::config("GITLAB_TOKEN not set"))?,
+        _ => return Err(Error::config(format!("Unsupported platform {}", platform))),
+    };
+
+    let mut headers = HeaderMap::new();
+    headers.insert(AUTHORIZATION, HeaderValue::from_str(&format!("Bearer {}", token))?);
+    headers.insert(USER_AGENT, HeaderValue::from_static("HiTLS_GIT_BOT"));
+    if platform == "github" {
+        headers.insert("X-GitHub-Api-Version", HeaderValue::from_static("2022-11-28"));
+    }
+    http_headers::apply_extra_headers(platform, &mut headers);
+    Ok(headers)
+}
+
+/// Returns an error carrying the response body when the request was not successful
+pub(crate) fn check_response(response: reqwest::blocking::Response) -> Result<reqwest::blocking::Response> {
+    metrics::record_http_response(response.url().host_str().unwrap_or("unknown"), response.version());
+    let status = response.status();
+    info!("Response status: {}", status);
+    if !status.is_success() {
+        let error_text = response.text()?;
+        error!("Error response body: {}", error_text);
+        return Err(Error::api_status(status, &error_text));
+    }
+    Ok(response)
+}
+
+/// `check_response` for the async client
+pub(crate) async fn check_response_async(response: reqwest::Response) -> Result<reqwest::Response> {
+    metrics::record_http_response(response.url().host_str().unwrap_or("unknown"), response.version());
+    let status = response.status();
+    info!("Response status: {}", status);
+    if !status.is_success() {
+        let error_text = response.text().await?;
+        error!("Error response body: {}", error_text);
+        return Err(Error::api_status(status, &error_text));
+    }
+    Ok(response)
+}
+
+/// Authorization headers for the async client, which cannot carry the
+/// error type of `api_headers` across an await
+pub(crate) fn api_headers_async(platform: &str) -> Result<HeaderMap> {
+    api_headers(platform)
+}
+
+/// Fetches repository metadata, verifying the token can access the repository
+pub fn get_repository(
+    base_url: &str,
+    namespace: &str,
+    repo_name: &str,
+    platform: &str,
+) -> Result<serde_json::Value> {
+    let url = format!("{}/{}/{}", base_url, namespace, repo_name);
+    info!("Fetching repository: {}", url);
+    let client = http_client::blocking();
+    let response = client.get(&url)
+        .headers(api_headers(platform)?)
+        .send()?;
+    Ok(check_response(response)?.json()?)
+}
+
+/// Same as `get_repository`, also returning the token's `X-OAuth-Scopes`
+/// header (only sent for GitHub classic tokens)
+pub fn get_repository_with_scopes(
+    base_url: &str,
+    namespace: &str,
+    repo_name: &str,
+    platform: &str,
+) -> Result<(serde_json::Value, Option<String>)> {
+    let url = match platform {
+        "gitlab" => gitlab::project_url(base_url, namespace, repo_name),
+        _ => format!("{}/{}/{}", base_url, namespace, repo_name),
+    };
+    info!("Fetching repository: {}", url);
+    let client = http_client::blocking();
+    let response = check_response(client.get(&url).headers(api_headers(platform)?).send()?)?;
+    let scopes = response
+        .headers()
+        .get("x-oauth-scopes")
+        .and_then(|value| value.to_str().ok())
+        .map(str::to_string);
+    Ok((response.json()?, scopes))
+}
+
+/// Metadata of a repository to create
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct NewRepository {
+    pub name: String,
+    pub description: String,
+    pub private: bool,
+    pub default_branch: Option<String>,
+}
+
+/// Creates a repository under `namespace`, an organization or the token's own account
+///
+/// `base_url` is the `.../repos` API base as for the other calls.
+pub fn create_repository(
+    base_url: &str,
+    namespace: &str,
+    repository: &NewRepository,
+    platform: &str,
+) -> Result<serde_json::Value> {
+    let root = base_url.strip_suffix("/repos").unwrap_or(base_url);
+    let client = http_client::blocking();
+    let user: serde_json::Value = check_response(client.get(format!("{}/user", root)).headers(api_headers(platform)?).send()?)?.json()?;
+    let url = if user["login"].as_str().is_some_and(|login| login.eq_ignore_ascii_case(namespace)) {
+        format!("{}/user/repos", root)
+    } else {
+        format!("{}/orgs/{}/repos", root, namespace)
+    };
+    let mut body = serde_json::json!({
+        "name": repository.name,
+        "description": repository.description,
+        "private": repository.private,
+    });
+    // GitHub only accepts a default branch once the branch exists
+    if let (Some(branch), "gitcode") = (&repository.default_branch, platform) {
+        body["default_branch"] = serde_json::json!(branch);
+    }
+    info!("Creating repository {}/{} (private: {})", namespace, repository.name, repository.private);
+    let response = client.post(&url)
+        .headers(api_headers(platform)?)
+        .json(&body)
+        .send()?;
+    Ok(check_response(response)?.json()?)
+}
+
+/// Registers a webhook delivering PR and push events to `hook_url`
+pub fn create_webhook(
+    base_url: &str,
+    namespace: &str,
+    repo_name: &str,
+    hook_url: &str,
+    secret: &str,
+    platform: &str,
+) -> Result<serde_json::Value> {
+    let url = format!("{}/{}/{}/hooks", base_url, namespace, repo_name);
+    info!("Registering webhook on {}/{} for {}", namespace, repo_name, hook_url);
+    let body = match platform {
+        "github" => serde_json::json!({
+            "name": "web",
+            "active": true,
+            "events": ["pull_request", "push"],
+            "config": { "url": hook_url, "content_type": "json", "secret": secret },
+        }),
+        "gitcode" => serde_json::json!({
+            "url": hook_url,
+            "encryption_type": 1,
+            "password": secret,
+            "push_events": true,
+            "merge_requests_events": true,
+        }),
+        _ => return Err(Error::config(format!("Unsupported platform {}", platform))),
+    };
+    let client = http_client::blocking();
+    let response = client.post(&url)
+        .headers(api_headers(platform)?)
+        .json(&body)
+        .send()?;
+    Ok(check_response(response)?.json()?)
+}
+
+/// A webhook registered on a repository
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RepoWebhook {
+    pub id: u64,
+    pub url: String,
+}
+
+/// Lists the webhooks of a repository
+pub fn list_webhooks(
+    base_url: &str,
+    namespace: &str,
+    repo_name: &str,
+    platform: &str,
+) -> Result<Vec<RepoWebhook>> {
+    let url = format!("{}/{}/{}/hooks", base_url, namespace, repo_name);
+    info!("Listing webhooks: {}", url);
+    let client = http_client::blocking();
+    let response = client.get(&url)
+        .headers(api_headers(platform)?)
+        .send()?;
+    let hooks: Vec<serde_json::Value> = check_response(response)?.json()?;
+    Ok(hooks
+        .iter()
+        .filter_map(|hook| {
+            let id = hook["id"].as_u64()?;
+            // GitHub nests the delivery URL under `config`
+            let url = hook["config"]["url"].as_str().or_else(|| hook["url"].as_str())?;
+            Some(RepoWebhook { id, url: url.to_string() })
+        })
+        .collect())
+}
+
+/// Replaces the secret of an existing webhook, keeping its URL and events
+pub fn update_webhook_secret(
+    base_url: &str,
+    namespace: &str,
+    repo_name: &str,
+    hook: &RepoWebhook,
+    secret: &str,
+    platform: &str,
+) -> Result<()> {
+    let url = format!("{}/{}/{}/hooks/{}", base_url, namespace, repo_name, hook.id);
+    info!("Updating secret of webhook {} on {}/{}", hook.id, namespace, repo_name);
+    let body = match platform {
+        "github" => serde_json::json!({
+            "config": { "url": hook.url, "content_type": "json", "secret": secret },
+        }),
+        "gitcode" => serde_json::json!({
+            "url": hook.url,
+            "encryption_type": 1,
+            "password": secret,
+        }),
+        _ => return Err(Error::config(format!("Unsupported platform {}", platform))),
+    };
+    let client = http_client::blocking();
+    let response = client.patch(&url)
+        .headers(api_headers(platform)?)
+        .json(&body)
+        .send()?;
+    check_response(response)?;
+    Ok(())
+}
+
+/// Starts a `workflow_dispatch` run of `workflow` (file name or ID) on `git_ref` (GitHub)
+///
+/// The workflow must declare every key of `inputs` as a `workflow_dispatch` input.
+pub fn trigger_workflow_dispatch(
+    base_url: &str,
+    namespace: &str,
+    repo_name: &str,
+    workflow: &str,
+    git_ref: &str,
+    inputs: &BTreeMap<String, String>,
+) -> Result<()> {
+    let url = format!("{}/{}/{}/actions/workflows/{}/dispatches", base_url, namespace, repo_name, workflow);
+    info!("Dispatching workflow {} on {}/{}@{}", workflow, namespace, repo_name, git_ref);
+    let client = http_client::blocking();
+    let response = client.post(&url)
+        .headers(api_headers("github")?)
+        .json(&serde_json::json!({ "ref": git_ref, "inputs": inputs }))
+        .send()?;
+    check_response(response)?;
+    Ok(())
+}
+
+/// Starts a pipeline on `git_ref` with `variables` (GitCode, GitLab-compatible pipeline API)
+pub fn trigger_pipeline(
+    base_url: &str,
+    namespace: &str,
+    repo_name: &str,
+    git_ref: &str,
+    variables: &BTreeMap<String, String>,
+) -> Result<()> {
+    let url = format!("{}/{}/{}/pipeline", base_url, namespace, repo_name);
+    info!("Starting pipeline on {}/{}@{}", namespace, repo_name, git_ref);
+    let variables: Vec<serde_json::Value> = variables
+        .iter()
+        .map(|(key, value)| serde_json::json!({ "key": key, "value": value }))
+        .collect();
+    let client = http_client::blocking();
+    let response = client.post(&url)
+        .headers(api_headers("gitcode")?)
+        .json(&serde_json::json!({ "ref": git_ref, "variables": variables }))
+        .send()?;
+    check_response(response)?;
+    Ok(())
+}
+
+/// Lists the labels defined on a repository
+pub fn list_repo_labels(
+    base_url: &str,
+    namespace: &str,
+    repo_name: &str,
+    platform: &str,
+) -> Result<Vec<RepoLabel>> {
+    let url = format!("{}/{}/{}/labels", base_url, namespace, repo_name);
+    info!("Listing labels: {}", url);
+    let client = http_client::blocking();
+    let response = client.get(&url)
+        .headers(api_headers(platform)?)
+        .query(&[("per_page", "100")])
+        .send()?;
+    Ok(check_response(response)?.json()?)
+}
+
+/// Creates a label on a repository
+pub fn create_repo_label(
+    base_url: &str,
+    namespace: &str,
+    repo_name: &str,
+    name: &str,
+    color: &str,
+    platform: &str,
+) -> Result<()> {
+    let url = format!("{}/{}/{}/labels", base_url, namespace, repo_name);
+    info!("Creating label {} ({}) on {}/{}", name, color, namespace, repo_name);
+    let request = CreateLabelRequest {
+        name,
+        color: color.trim_start_matches('#'),
+        description: "Created by the backport bot",
+    };
+    let client = http_client::blocking();
+    let response = client.post(&url)
+        .headers(api_headers(platform)?)
+        .json(&request)
+        .send()?;
+    check_response(response)?;
+    Ok(())
+}
+
+/// Creates the labels in `labels` that do not exist on the repository yet
+///
+/// `colors` maps label names to colors; unmapped labels get `DEFAULT_LABEL_COLOR`.
+pub fn ensure_repo_labels(
+    base_url: &str,
+    namespace: &str,
+    repo_name: &str,
+    labels: &[String],
+    colors: &HashMap<String, String>,
+    platform: &str,
+) -> Result<()> {
+    let existing = list_repo_labels(base_url, namespace, repo_name, platform)?;
+    for label in labels {
+        if existing.iter().any(|l| l.name.eq_ignore_ascii_case(label)) {
+            continue;
+        }
+        let color = colors.get(label).map(String::as_str).unwrap_or(DEFAULT_LABEL_COLOR);
+        create_repo_label(base_url, namespace, repo_name, label, color, platform)?;
+    }
+    Ok(())
+}
+
+/// Applies labels to a PR, creating any label missing on the repository first
+pub fn add_labels_to_pr(
+    base_url: &str,
+    namespace: &str,
+    repo_name: &str,
+    pull_id: u32,
+    labels: &[String],
+    colors: &HashMap<String, String>,
+    platform: &str,
+) -> Result<()> {
+    if platform == "gitlab" {
+        return gitlab::add_merge_request_labels(base_url, namespace, repo_name, pull_id, labels);
+    }
+    ensure_repo_labels(base_url, namespace, repo_name, labels, colors, platform)?;
+
+    let client = http_client::blocking();
+    let request = match platform {
+        "github" => client
+            .post(format!("{}/{}/{}/issues/{}/labels", base_url, namespace, repo_name, pull_id))
+            .json(&serde_json::json!({ "labels": labels })),
+        "gitcode" => client
+            .post(format!("{}/{}/{}/pulls/{}/labels", base_url, namespace, repo_name, pull_id))
+            .json(labels),
+        _ => return Err(Error::config(format!("Unsupported platform {}", platform))),
+    };
+    info!("Applying labels {:?} to PR #{}", labels, pull_id);
+    check_response(request.headers(api_headers(platform)?).send()?)?;
+    Ok(())
+}
+
+/// Removes a label from a PR
+pub fn remove_label_from_pr(
+    base_url: &str,
+    namespace: &str,
+    repo_name: &str,
+    pull_id: u32,
+    label: &str,
+    platform: &str,
+) -> Result<()> {
+    let kind = match platform {
+        "github" => "issues",
+        "gitcode" => "pulls",
+        "gitlab" => return gitlab::remove_merge_request_label(base_url, namespace, repo_name, pull_id, label),
+        _ => return Err(Error::config(format!("Unsupported platform {}", platform))),
+    };
+    // Status labels contain ':' and spaces, so the name goes in as an encoded path segment
+    let mut url = reqwest::Url::parse(&format!("{}/{}/{}/{}/{}/labels", base_url, namespace, repo_name, kind, pull_id)).map_err(|e| Error::config(format!("Invalid API URL: {}", e)))?;
+    url.path_segments_mut().map_err(|_| Error::config("Invalid API base URL"))?.push(label);
+    info!("Removing label {} from PR #{}", label, pull_id);
+    let client = http_client::blocking();
+    check_response(client.delete(url).headers(api_headers(platform)?).send()?)?;
+    Ok(())
+}
+
+/// Whether the service can still push to a repository
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct RepoAccess {
+    pub archived: bool,
+    /// The token's user lacks push permission
+    pub read_only: bool,
+}
+
+/// Reads the access flags from a repository API response; GitHub reports
+/// `permissions`, GitCode `permission`
+pub fn repo_access(repo: &serde_json::Value) -> RepoAccess {
+    let push = repo["permissions"]["push"].as_bool().or_else(|| repo["permission"]["push"].as_bool());
+    RepoAccess {
+        archived: repo["archived"].as_bool().unwrap_or(false) || repo["disabled"].as_bool().unwrap_or(false),
+        read_only: push == Some(false),
+    }
+}
+
+/// Fetches the archive and write-access state of a repository
+pub fn get_repo_access(
+    base_url: &str,
+    namespace: &str,
+    repo_name: &str,
+    platform: &str,
+) -> Result<RepoAccess> {
+    if platform == "gitlab" {
+        return gitlab::get_project_access(base_url, namespace, repo_name);
+    }
+    Ok(repo_access(&get_repository(base_url, namespace, repo_name, platform)?))
+}
+
+/// Description, homepage, topics and default branch of a repository, the
+/// metadata a mirror keeps in sync with its source
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RepoMetadata {
+    pub description: String,
+    pub homepage: String,
+    /// Sorted, so that metadata compare equal whatever order the forge uses
+    pub topics: Vec<String>,
+    pub default_branch: Option<String>,
+}
+
+/// Reads the metadata from a repository or GitLab project API response;
+/// older GitLab releases call the topics `tag_list`, and GitLab projects
+/// have no homepage
+pub fn repo_metadata(repo: &serde_json::Value) -> RepoMetadata {
+    let topics = repo["topics"].as_array().or_else(|| repo["tag_list"].as_array());
+    let mut topics: Vec<String> = topics
+        .into_iter()
+        .flatten()
+        .filter_map(|topic| topic.as_str().map(str::to_string))
+        .collect();
+    topics.sort();
+    RepoMetadata {
+        description: repo["description"].as_str().unwrap_or_default().to_string(),
+        homepage: repo["homepage"].as_str().unwrap_or_default().to_string(),
+        topics,
+        default_branch: repo["default_branch"].as_str().map(str::to_string),
+    }
+}
+
+/// Fetches the metadata of a repository
+pub fn get_repo_metadata(
+    base_url: &str,
+    namespace: &str,
+    repo_name: &str,
+    platform: &str,
+) -> Result<RepoMetadata> {
+    let (repo, _) = get_repository_with_scopes(base_url, namespace, repo_name, platform)?;
+    Ok(repo_metadata(&repo))
+}
+
+/// Replaces the description, homepage, topics and, when set, the default
+/// branch of a repository
+pub fn update_repo_metadata(
+    base_url: &str,
+    namespace: &str,
+    repo_name: &str,
+    metadata: &RepoMetadata,
+    platform: &str,
+) -> Result<()> {
+    match platform {
+        "github" | "gitcode" => {}
+        "gitlab" => return gitlab::update_project_metadata(base_url, namespace, repo_name, metadata),
+        _ => return Err(Error::config(format!("Unsupported platform {}", platform))),
+    }
+    let url = format!("{}/{}/{}", base_url, namespace, repo_name);
+    // GitCode requires the name on every repository update
+    let mut body = serde_json::json!({
+        "name": repo_name,
+        "description": metadata.description,
+        "homepage": metadata.homepage,
+    });
+    if let Some(branch) = &metadata.default_branch {
+        body["default_branch"] = serde_json::json!(branch);
+    }
+    info!("Updating metadata of {}/{}", namespace, repo_name);
+    let client = http_client::blocking();
+    check_response(client.patch(&url).headers(api_headers(platform)?).json(&body).send()?)?;
+    check_response(
+        client
+            .put(format!("{}/topics", url))
+            .headers(api_headers(platform)?)
+            .json(&serde_json::json!({ "names": metadata.topics }))
+            .send()?,
+    )?;
+    Ok(())
+}
+
+/// Returns the login of the user who opened a PR
+pub fn get_pr_author(
+    base_url: &str,
+    namespace: &str,
+    repo_name: &str,
+    pull_id: u32,
+    platform: &str,
+) -> Result<String> {
+    let url = format!("{}/{}/{}/pulls/{}", base_url, namespace, repo_name, pull_id);
+    info!("Fetching PR: {}", url);
+    let client = http_client::blocking();
+    let pull: serde_json::Value = check_response(client.get(&url).headers(api_headers(platform)?).send()?)?.json()?;
+    let author = pull["user"]["login"].as_str().ok_or_else(|| Error::Parse("PR author missing from response".to_string()))?;
+    Ok(author.to_string())
+}
+
+/// Adds assignees to a PR
+///
+/// GitCode PR assignees are its reviewers, so this also requests their review there.
+pub fn add_assignees_to_pr(
+    base_url: &str,
+    namespace: &str,
+    repo_name: &str,
+    pull_id: u32,
+    assignees: &[String],
+    platform: &str,
+) -> Result<()> {
+    let client = http_client::blocking();
+    let request = match platform {
+        "github" => client
+            .post(format!("{}/{}/{}/issues/{}/assignees", base_url, namespace, repo_name, pull_id))
+            .json(&serde_json::json!({ "assignees": assignees })),
+        "gitcode" => client
+            .post(format!("{}/{}/{}/pulls/{}/assignees", base_url, namespace, repo_name, pull_id))
+            .json(&serde_json::json!({ "assignees": assignees.join(",") })),
+        _ => return Err(Error::config(format!("Unsupported platform {}", platform))),
+    };
+    info!("Assigning {:?} to PR #{}", assignees, pull_id);
+    check_response(request.headers(api_headers(platform)?).send()?)?;
+    Ok(())
+}
+
+/// Requests a review of a PR from `reviewers` (GitHub only, see `add_assignees_to_pr`)
+pub fn request_pr_reviewers(
+    base_url: &str,
+    namespace: &str,
+    repo_name: &str,
+    pull_id: u32,
+    reviewers: &[String],
+    platform: &str,
+) -> Result<()> {
+    if platform != "github" {
+        return Err(Error::config(format!("Review requests are not supported on {}", platform)));
+    }
+    let url = format!("{}/{}/{}/pulls/{}/requested_reviewers", base_url, namespace, repo_name, pull_id);
+    info!("Requesting review of PR #{} from {:?}", pull_id, reviewers);
+    let client = http_client::blocking();
+    let response = client.post(&url)
+        .headers(api_headers(platform)?)
+        .json(&serde_json::json!({ "reviewers": reviewers }))
+        .send()?;
+    check_response(response)?;
+    Ok(())
+}
+
+/// A label being applied to an issue or PR, as recorded in its event timeline
+#[derive(Debug, Clone)]
+pub struct LabelEvent {
+    pub label: String,
+    pub actor: String,
+}
+
+/// Label events per page of the GitHub issue events API (its maximum)
+const EVENTS_PER_PAGE: usize = 100;
+
+/// Lists the `labeled` events of a PR in chronological order, following
+/// every page (GitHub and GitLab)
+pub fn get_label_events(
+    base_url: &str,
+    namespace: &str,
+    repo_name: &str,
+    pull_id: u32,
+    platform: &str,
+) -> Result<Vec<LabelEvent>> {
+    match platform {
+        "github" => {}
+        "gitlab" => return gitlab::get_merge_request_label_events(base_url, namespace, repo_name, pull_id),
+        _ => return Err(Error::config(format!("Label events are not supported on {}", platform))),
+    }
+    let url = format!("{}/{}/{}/issues/{}/events", base_url, namespace, repo_name, pull_id);
+    let client = http_client::blocking();
+    let mut labeled = Vec::new();
+    for page in 1.. {
+        info!("Fetching label events: {} (page {})", url, page);
+        let response = client.get(&url)
+            .headers(api_headers(platform)?)
+            .query(&[("per_page", EVENTS_PER_PAGE), ("page", page)])
+            .send()?;
+        let events: Vec<serde_json::Value> = check_response(response)?.json()?;
+        labeled.extend(events
+            .iter()
+            .filter(|event| event["event"] == "labeled")
+            .filter_map(|event| {
+                Some(LabelEvent {
+                    label: event["label"]["name"].as_str()?.to_string(),
+                    actor: event["actor"]["login"].as_str()?.to_string(),
+                })
+            }));
+        if events.len() < EVENTS_PER_PAGE {
+            break;
+        }
+    }
+    Ok(labeled)
+}
+
+/// Returns the role of `user` on a repository (e.g. `admin`, `maintain`, `write`, `read`);
+/// GitLab members with Maintainer access or more are reported as `maintain`
+pub fn get_collaborator_permission(
+    base_url: &str,
+    namespace: &str,
+    repo_name: &str,
+    user: &str,
+    platform: &str,
+) -> Result<String> {
+    if platform == "gitlab" {
+        let level = gitlab::member_access_level(base_url, namespace, repo_name, user)?;
+        return Ok(if level >= gitlab::MAINTAINER_ACCESS { "maintain" } else { "write" }.to_string());
+    }
+    let url = format!("{}/{}/{}/collaborators/{}/permission", base_url, namespace, repo_name, user);
+    info!("Fetching permission of {}: {}", user, url);
+    let client = http_client::blocking();
+    let response = client.get(&url)
+        .headers(api_headers(platform)?)
+        .send()?;
+    let body: serde_json::Value = check_response(response)?.json()?;
+    // `role_name` distinguishes maintain/triage, `permission` only has admin/write/read/none
+    let role = body["role_name"].as_str()
+        .or_else(|| body["permission"].as_str())
+        .ok_or_else(|| Error::Parse("Permission missing from response".to_string()))?;
+    Ok(role.to_string())
+}
+
+pub fn get_commit_list_of_pr(base_url: &str, namespace: &str, repo_name: &str, pull_id: u32, platform: &str) -> Result<Vec<GitCommit>> {
     info!("Getting commit list for PR:");
     info!("  Platform: {}", platform);
     info!("  Base URL: {}", base_url);
@@ -26,20 +692,24 @@ pub fn get_commit_list_of_pr(base_url: &str, namespace: &str, repo_name: &str, p
     info!("  Repo: {}", repo_name);
     info!("  PR ID: {}", pull_id);
 
+    if platform == "gitlab" {
+        return gitlab::get_merge_request_commits(base_url, namespace, repo_name, pull_id);
+    }
+
     let token = match platform {
         "github" => {
             let token = std::env::var("GITHUB_TOKEN")
-                .map_err(|_| "GITHUB_TOKEN not set")?;
+                .map_err(|_| Error::config("GITHUB_TOKEN not set"))?;
             info!("Using GitHub token: {}...", &token[..10]);
             token
         },
         "gitcode" => {
             let token = std::env::var("GITCODE_TOKEN")
-                .map_err(|_| "GITCODE_TOKEN not set")?;
+                .map_err(|_| Error::config("GITCODE_TOKEN not set"))?;
             info!("Using GitCode token: {}...", &token[..10]);
             token
         },
-        _ => return Err("Unsupported platform".into()),
+        _ => return Err(Error::config(format!("Unsupported platform {}", platform))),
     };
     
     let url = format!(
@@ -70,22 +740,17 @@ pub fn get_commit_list_of_pr(base_url: &str, namespace: &str, repo_name: &str, p
         );
     }
 
+    http_headers::apply_extra_headers(platform, &mut headers);
+
     info!("Making HTTP request...");
-    let client = reqwest::blocking::Client::new();
+    let client = http_client::blocking();
     let response = client.get(&url)
         .headers(headers)
+        .timeout(http_client::timeout_for("commits"))
         .send()?;
-    
-    let status = response.status();
-    info!("Response status: {}", status);
-    if !status.is_success() {
-        let error_text = response.text()?;
-        error!("Error response body: {}", error_text);
-        return Err(format!("Request failed with status {}: {}", status, error_text).into());
-    }
 
     info!("Parsing response body...");
-    let commits: Vec<GitCommit> = response.json()?;
+    let commits: Vec<GitCommit> = check_response(response)?.json()?;
     info!("Found {} commits", commits.len());
     
     Ok(commits)
@@ -97,21 +762,35 @@ pub fn post_comment_on_pr(
     repo_name: &str,
     pull_id: u32,
     message: &str,
-) -> Result<(), Box<dyn std::error::Error>> {
+    platform: &str,
+) -> Result<()> {
     info!("Posting comment on PR:");
+    info!("  Platform: {}", platform);
     info!("  Base URL: {}", base_url);
     info!("  Namespace: {}", namespace);
     info!("  Repo: {}", repo_name);
     info!("  PR ID: {}", pull_id);
 
-    let token = std::env::var("GITCODE_TOKEN")
-        .map_err(|_| "GITCODE_TOKEN not set")?;
-    info!("Using GitCode token: {}...", &token[..10]);
+    if platform == "gitlab" {
+        return gitlab::post_merge_request_note(base_url, namespace, repo_name, pull_id, message);
+    }
 
-    let url = format!(
-        "{}/{}/{}/pulls/{}/comments",
-        base_url, namespace, repo_name, pull_id
-    );
+    let (token, url) = match platform {
+        "github" => {
+            let token = std::env::var("GITHUB_TOKEN")
+                .map_err(|_| Error::config("GITHUB_TOKEN not set"))?;
+            info!("Using GitHub token: {}...", &token[..10]);
+            // GitHub exposes PR conversation comments through the issues API
+            (token, format!("{}/{}/{}/issues/{}/comments", base_url, namespace, repo_name, pull_id))
+        },
+        "gitcode" => {
+            let token = std::env::var("GITCODE_TOKEN")
+                .map_err(|_| Error::config("GITCODE_TOKEN not set"))?;
+            info!("Using GitCode token: {}...", &token[..10]);
+            (token, format!("{}/{}/{}/pulls/{}/comments", base_url, namespace, repo_name, pull_id))
+        },
+        _ => return Err(Error::config(format!("Unsupported platform {}", platform))),
+    };
     info!("Request URL: {}", url);
 
     let mut headers = HeaderMap::new();
@@ -132,21 +811,74 @@ pub fn post_comment_on_pr(
         body: message.to_string(),
     };
 
+    http_headers::apply_extra_headers(platform, &mut headers);
+
     info!("Making HTTP request...");
-    let client = reqwest::blocking::Client::new();
+    let client = http_client::blocking();
     let response = client.post(&url)
         .headers(headers)
         .json(&comment)
         .send()?;
+    check_response(response)?;
 
-    let status = response.status();
-    info!("Response status: {}", status);
-    if !status.is_success() {
-        let error_text = response.text()?;
-        error!("Error response body: {}", error_text);
-        return Err(format!("Request failed with status {}: {}", status, error_text).into());
-    }
+    info!("Comment posted successfully");
+    Ok(())
+}
 
+/// `get_commit_list_of_pr` on the async `client`, for callers on the async
+/// runtime that must not hold a blocking thread while waiting on the API
+pub async fn get_commit_list_of_pr_async(
+    client: &reqwest::Client,
+    base_url: &str,
+    namespace: &str,
+    repo_name: &str,
+    pull_id: u32,
+    platform: &str,
+) -> Result<Vec<GitCommit>> {
+    let url = match platform {
+        "github" | "gitcode" => format!("{}/{}/{}/pulls/{}/commits", base_url, namespace, repo_name, pull_id),
+        "gitlab" => return gitlab::get_merge_request_commits_async(client, base_url, namespace, repo_name, pull_id).await,
+        _ => return Err(Error::config(format!("Unsupported platform {}", platform))),
+    };
+    info!("Fetching commits of PR #{} of {}/{}", pull_id, namespace, repo_name);
+    let response = client
+        .get(&url)
+        .headers(api_headers_async(platform)?)
+        .timeout(http_client::timeout_for("commits"))
+        .send()
+        .await?;
+    let commits: Vec<GitCommit> = check_response_async(response).await?.json().await?;
+    info!("Found {} commits", commits.len());
+    Ok(commits)
+}
+
+/// `post_comment_on_pr` on the async `client`
+pub async fn post_comment_on_pr_async(
+    client: &reqwest::Client,
+    base_url: &str,
+    namespace: &str,
+    repo_name: &str,
+    pull_id: u32,
+    message: &str,
+    platform: &str,
+) -> Result<()> {
+    let url = match platform {
+        // GitHub exposes PR conversation comments through the issues API
+        "github" => format!("{}/{}/{}/issues/{}/comments", base_url, namespace, repo_name, pull_id),
+        "gitcode" => format!("{}/{}/{}/pulls/{}/comments", base_url, namespace, repo_name, pull_id),
+        "gitlab" => return gitlab::post_merge_request_note_async(client, base_url, namespace, repo_name, pull_id, message).await,
+        _ => return Err(Error::config(format!("Unsupported platform {}", platform))),
+    };
+    info!("Posting comment on PR #{} of {}/{}", pull_id, namespace, repo_name);
+    let comment = CommentRequest { body: message.to_string() };
+    let response = client
+        .post(&url)
+        .headers(api_headers_async(platform)?)
+        .timeout(http_client::timeout_for("comments"))
+        .json(&comment)
+        .send()
+        .await?;
+    check_response_async(response).await?;
     info!("Comment posted successfully");
     Ok(())
 }
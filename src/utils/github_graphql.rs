@@ -0,0 +1,125 @@
+use serde_json::{json, Value};
+use log::{info, error};
+
+use crate::error::{Error, Result};
+use crate::utils::gitcode::{self, api_headers, check_response, GitCommit};
+use crate::utils::http_client;
+
+/// Everything the GitHub processor needs to know about a pull request
+#[derive(Debug, Default)]
+pub struct PullRequestDetails {
+    pub commits: Vec<GitCommit>,
+}
+
+const PULL_REQUEST_QUERY: &str = r#"
+query($owner: String!, $name: String!, $number: Int!) {
+  repository(owner: $owner, name: $name) {
+    pullRequest(number: $number) {
+      commits(first: 100) {
+        pageInfo { hasNextPage }
+        nodes { commit { oid } }
+      }
+    }
+  }
+}
+"#;
+
+/// GraphQL endpoint of the REST API at `base_url`
+///
+/// `https://api.github.com/repos` maps to `https://api.github.com/graphql`,
+/// GitHub Enterprise Server's `https://host/api/v3/repos` to `https://host/api/graphql`.
+pub fn graphql_url(base_url: &str) -> String {
+    let api = base_url.trim_end_matches('/').trim_end_matches("/repos");
+    format!("{}/graphql", api.strip_suffix("/v3").unwrap_or(api))
+}
+
+/// Extracts the pull request details from a GraphQL response body
+///
+/// PRs with more commits than one page holds are reported as errors so the
+/// caller falls back to the paginated REST path.
+pub fn parse_pull_request(body: &Value) -> Result<PullRequestDetails> {
+    if let Some(errors) = body["errors"].as_array().filter(|errors| !errors.is_empty()) {
+        return Err(Error::HttpApi { status: None, message: format!("GraphQL errors: {}", Value::Array(errors.clone())) });
+    }
+    let pr = &body["data"]["repository"]["pullRequest"];
+    if pr.is_null() {
+        return Err(Error::Parse("Pull request missing from GraphQL response".to_string()));
+    }
+    if pr["commits"]["pageInfo"]["hasNextPage"].as_bool().unwrap_or(false) {
+        return Err(Error::Parse("Pull request has more commits than one GraphQL page".to_string()));
+    }
+
+    Ok(PullRequestDetails {
+        commits: pr["commits"]["nodes"]
+            .as_array()
+            .into_iter()
+            .flatten()
+            .filter_map(|node| node["commit"]["oid"].as_str())
+            .map(|sha| GitCommit { sha: sha.to_string() })
+            .collect(),
+    })
+}
+
+/// Fetches the commits of a pull request in one GraphQL query
+pub fn fetch_pull_request(base_url: &str, namespace: &str, repo_name: &str, pull_id: u32) -> Result<PullRequestDetails> {
+    let url = graphql_url(base_url);
+    info!("Fetching PR #{} of {}/{} via GraphQL: {}", pull_id, namespace, repo_name, url);
+    let client = http_client::blocking();
+    let response = client.post(&url)
+        .headers(api_headers("github")?)
+        .timeout(http_client::timeout_for("graphql"))
+        .json(&json!({
+            "query": PULL_REQUEST_QUERY,
+            "variables": { "owner": namespace, "name": repo_name, "number": pull_id },
+        }))
+        .send()?;
+    let body: Value = check_response(response)?.json()?;
+    parse_pull_request(&body)
+}
+
+/// Fetches PR details with GraphQL, falling back to the paginated REST
+/// commit list when GraphQL is unavailable (e.g. older GitHub Enterprise
+/// Server) or the query fails
+pub fn get_pull_request_details(base_url: &str, namespace: &str, repo_name: &str, pull_id: u32) -> Result<PullRequestDetails> {
+    match fetch_pull_request(base_url, namespace, repo_name, pull_id) {
+        Ok(details) => Ok(details),
+        Err(e) => {
+            error!("GraphQL query for PR #{} failed, falling back to REST: {}", pull_id, e);
+            Ok(PullRequestDetails {
+                commits: gitcode::get_commit_list_of_pr(base_url, namespace, repo_name, pull_id, "github")?,
+            })
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_pull_request() {
+        let body = json!({
+            "data": { "repository": { "pullRequest": {
+                "commits": { "pageInfo": { "hasNextPage": false }, "nodes": [{ "commit": { "oid": "abc123" } }] }
+            }}}
+        });
+        let details = parse_pull_request(&body).unwrap();
+        assert_eq!(details.commits.len(), 1);
+        assert_eq!(details.commits[0].sha, "abc123");
+    }
+
+    #[test]
+    fn test_graphql_url_from_rest_base() {
+        assert_eq!(graphql_url("https://api.github.com/repos"), "https://api.github.com/graphql");
+        assert_eq!(graphql_url("https://ghe.example.com/api/v3/repos"), "https://ghe.example.com/api/graphql");
+    }
+
+    #[test]
+    fn test_parse_pull_request_errors_trigger_fallback() {
+        assert!(parse_pull_request(&json!({ "errors": [{ "message": "Not found" }] })).is_err());
+        let paged = json!({ "data": { "repository": { "pullRequest": {
+            "commits": { "pageInfo": { "hasNextPage": true }, "nodes": [] }
+        }}}});
+        assert!(parse_pull_request(&paged).is_err());
+    }
+}
@@ -0,0 +1,312 @@
+use std::collections::BTreeMap;
+use std::env;
+use std::sync::OnceLock;
+use serde::Deserialize;
+use serde_json::Value;
+use log::info;
+
+use crate::error::Result;
+use crate::utils::gitcode::{api_headers, api_headers_async, check_response, check_response_async, GitCommit, LabelEvent, RepoAccess, RepoMetadata};
+use crate::utils::{hmac, http_client};
+use crate::utils::remote_url::RemoteUrl;
+
+/// Reporter access level, the lowest one allowed to label merge requests
+pub const REPORTER_ACCESS: u64 = 20;
+
+/// Developer access level, the lowest one allowed to push
+pub const DEVELOPER_ACCESS: u64 = 30;
+
+/// Maintainer access level, the lowest one trusted to apply restricted labels
+pub const MAINTAINER_ACCESS: u64 = 40;
+
+/// Commits per page of the merge request commits API (its maximum)
+const PER_PAGE: usize = 100;
+
+/// URL of the self-hosted GitLab instance from `GITLAB_URL` (default `https://gitlab.com`)
+pub fn instance_url() -> &'static str {
+    static URL: OnceLock<String> = OnceLock::new();
+    URL.get_or_init(|| {
+        env::var("GITLAB_URL")
+            .map(|url| url.trim_end_matches('/').to_string())
+            .unwrap_or_else(|_| "https://gitlab.com".to_string())
+    })
+}
+
+/// Base URL of the REST API of the GitLab instance
+pub fn api_base_url() -> &'static str {
+    static URL: OnceLock<String> = OnceLock::new();
+    URL.get_or_init(|| format!("{}/api/v4", instance_url()))
+}
+
+/// Host name of the GitLab instance
+pub fn instance_host() -> &'static str {
+    static HOST: OnceLock<String> = OnceLock::new();
+    HOST.get_or_init(|| {
+        RemoteUrl::parse(&format!("{}/_", instance_url()))
+            .map(|instance| instance.host().to_string())
+            .unwrap_or_default()
+    })
+}
+
+/// Whether `host` is the configured GitLab instance
+pub fn is_gitlab_host(host: &str) -> bool {
+    instance_host().eq_ignore_ascii_case(host)
+}
+
+/// Whether the git remote `url` is on the configured GitLab instance
+pub fn is_gitlab_url(url: &str) -> bool {
+    RemoteUrl::parse(url).is_ok_and(|remote| is_gitlab_host(remote.host()))
+}
+
+/// API URL of a project; GitLab addresses projects by their URL-encoded
+/// full path, which may include subgroups
+pub fn project_url(base_url: &str, namespace: &str, repo_name: &str) -> String {
+    format!("{}/projects/{}%2F{}", base_url, namespace.replace('/', "%2F"), repo_name)
+}
+
+/// Checks the `X-Gitlab-Token` of a webhook against the accepted secrets;
+/// GitLab sends the secret itself instead of signing the body
+pub fn verify_token(keys: &[String], token: &str) -> bool {
+    keys.iter().any(|key| !key.is_empty() && hmac::constant_time_eq(key.as_bytes(), token.as_bytes()))
+}
+
+#[derive(Debug, Deserialize)]
+struct MergeRequestCommit {
+    id: String,
+}
+
+/// Commits of a merge request, newest first like the GitCode API
+pub fn get_merge_request_commits(base_url: &str, namespace: &str, repo_name: &str, iid: u32) -> Result<Vec<GitCommit>> {
+    let url = format!("{}/merge_requests/{}/commits", project_url(base_url, namespace, repo_name), iid);
+    let client = http_client::blocking();
+    let mut commits = Vec::new();
+    for page in 1.. {
+        info!("Fetching commits of merge request !{} (page {})", iid, page);
+        let response = client
+            .get(&url)
+            .query(&[("per_page", PER_PAGE), ("page", page)])
+            .headers(api_headers("gitlab")?)
+            .timeout(http_client::timeout_for("commits"))
+            .send()?;
+        let batch: Vec<MergeRequestCommit> = check_response(response)?.json()?;
+        let last = batch.len() < PER_PAGE;
+        commits.extend(batch.into_iter().map(|commit| GitCommit { sha: commit.id }));
+        if last {
+            break;
+        }
+    }
+    info!("Found {} commits", commits.len());
+    Ok(commits)
+}
+
+/// Posts a note (comment) on a merge request
+pub fn post_merge_request_note(base_url: &str, namespace: &str, repo_name: &str, iid: u32, body: &str) -> Result<()> {
+    let url = format!("{}/merge_requests/{}/notes", project_url(base_url, namespace, repo_name), iid);
+    info!("Posting note on merge request !{}", iid);
+    let client = http_client::blocking();
+    check_response(client.post(&url).headers(api_headers("gitlab")?).json(&serde_json::json!({ "body": body })).send()?)?;
+    Ok(())
+}
+
+/// `get_merge_request_commits` on the async `client`
+pub async fn get_merge_request_commits_async(
+    client: &reqwest::Client,
+    base_url: &str,
+    namespace: &str,
+    repo_name: &str,
+    iid: u32,
+) -> Result<Vec<GitCommit>> {
+    let url = format!("{}/merge_requests/{}/commits", project_url(base_url, namespace, repo_name), iid);
+    let mut commits = Vec::new();
+    for page in 1.. {
+        info!("Fetching commits of merge request !{} (page {})", iid, page);
+        let response = client
+            .get(&url)
+            .query(&[("per_page", PER_PAGE), ("page", page)])
+            .headers(api_headers_async("gitlab")?)
+            .timeout(http_client::timeout_for("commits"))
+            .send()
+            .await?;
+        let batch: Vec<MergeRequestCommit> = check_response_async(response).await?.json().await?;
+        let last = batch.len() < PER_PAGE;
+        commits.extend(batch.into_iter().map(|commit| GitCommit { sha: commit.id }));
+        if last {
+            break;
+        }
+    }
+    info!("Found {} commits", commits.len());
+    Ok(commits)
+}
+
+/// `post_merge_request_note` on the async `client`
+pub async fn post_merge_request_note_async(
+    client: &reqwest::Client,
+    base_url: &str,
+    namespace: &str,
+    repo_name: &str,
+    iid: u32,
+    body: &str,
+) -> Result<()> {
+    let url = format!("{}/merge_requests/{}/notes", project_url(base_url, namespace, repo_name), iid);
+    info!("Posting note on merge request !{}", iid);
+    let response = client.post(&url).headers(api_headers_async("gitlab")?).json(&serde_json::json!({ "body": body })).send().await?;
+    check_response_async(response).await?;
+    Ok(())
+}
+
+/// Applies labels to a merge request; GitLab creates missing labels itself
+pub fn add_merge_request_labels(base_url: &str, namespace: &str, repo_name: &str, iid: u32, labels: &[String]) -> Result<()> {
+    update_merge_request_labels(base_url, namespace, repo_name, iid, "add_labels", labels)
+}
+
+/// Removes a label from a merge request
+pub fn remove_merge_request_label(base_url: &str, namespace: &str, repo_name: &str, iid: u32, label: &str) -> Result<()> {
+    update_merge_request_labels(base_url, namespace, repo_name, iid, "remove_labels", &[label.to_string()])
+}
+
+fn update_merge_request_labels(base_url: &str, namespace: &str, repo_name: &str, iid: u32, field: &str, labels: &[String]) -> Result<()> {
+    let url = format!("{}/merge_requests/{}", project_url(base_url, namespace, repo_name), iid);
+    info!("Updating labels of merge request !{} ({} {:?})", iid, field, labels);
+    let client = http_client::blocking();
+    let body = serde_json::json!({ field: labels.join(",") });
+    check_response(client.put(&url).headers(api_headers("gitlab")?).json(&body).send()?)?;
+    Ok(())
+}
+
+/// Starts a pipeline on `git_ref` with `variables`
+pub fn trigger_pipeline(
+    base_url: &str,
+    namespace: &str,
+    repo_name: &str,
+    git_ref: &str,
+    variables: &BTreeMap<String, String>,
+) -> Result<()> {
+    let url = format!("{}/pipeline", project_url(base_url, namespace, repo_name));
+    info!("Starting pipeline on {}/{}@{}", namespace, repo_name, git_ref);
+    let variables: Vec<Value> = variables
+        .iter()
+        .map(|(key, value)| serde_json::json!({ "key": key, "value": value }))
+        .collect();
+    let client = http_client::blocking();
+    let body = serde_json::json!({ "ref": git_ref, "variables": variables });
+    check_response(client.post(&url).headers(api_headers("gitlab")?).json(&body).send()?)?;
+    Ok(())
+}
+
+/// `add` events of the labels of a merge request in chronological order,
+/// following every page
+pub fn get_merge_request_label_events(base_url: &str, namespace: &str, repo_name: &str, iid: u32) -> Result<Vec<LabelEvent>> {
+    let url = format!("{}/merge_requests/{}/resource_label_events", project_url(base_url, namespace, repo_name), iid);
+    let client = http_client::blocking();
+    let mut labeled = Vec::new();
+    for page in 1.. {
+        info!("Fetching label events of merge request !{} (page {})", iid, page);
+        let response = client
+            .get(&url)
+            .query(&[("per_page", PER_PAGE), ("page", page)])
+            .headers(api_headers("gitlab")?)
+            .send()?;
+        let events: Vec<Value> = check_response(response)?.json()?;
+        labeled.extend(label_events(&events));
+        if events.len() < PER_PAGE {
+            break;
+        }
+    }
+    Ok(labeled)
+}
+
+/// Reads the label additions from a page of `resource_label_events`
+pub fn label_events(events: &[Value]) -> Vec<LabelEvent> {
+    events
+        .iter()
+        .filter(|event| event["action"] == "add")
+        .filter_map(|event| {
+            Some(LabelEvent {
+                label: event["label"]["name"].as_str()?.to_string(),
+                actor: event["user"]["username"].as_str()?.to_string(),
+            })
+        })
+        .collect()
+}
+
+/// Access level of `username` on a project, inherited memberships
+/// included; 0 when the user is not a member
+pub fn member_access_level(base_url: &str, namespace: &str, repo_name: &str, username: &str) -> Result<u64> {
+    let url = format!("{}/members/all", project_url(base_url, namespace, repo_name));
+    info!("Fetching access level of {} on {}/{}", username, namespace, repo_name);
+    let client = http_client::blocking();
+    let response = client.get(&url).query(&[("query", username)]).headers(api_headers("gitlab")?).send()?;
+    let members: Vec<Value> = check_response(response)?.json()?;
+    Ok(members
+        .iter()
+        .filter(|member| member["username"].as_str().is_some_and(|name| name.eq_ignore_ascii_case(username)))
+        .filter_map(|member| member["access_level"].as_u64())
+        .max()
+        .unwrap_or(0))
+}
+
+/// Highest access level of the token's user on a project, through the
+/// project itself or its group; `None` when the response reports neither
+pub fn access_level(project: &Value) -> Option<u64> {
+    ["project_access", "group_access"]
+        .iter()
+        .filter_map(|scope| project["permissions"][scope]["access_level"].as_u64())
+        .max()
+}
+
+/// Reads the access flags from a project API response: archived, or the
+/// token's user below Developer in both the project and its group
+pub fn project_access(project: &Value) -> RepoAccess {
+    RepoAccess {
+        archived: project["archived"].as_bool().unwrap_or(false),
+        read_only: access_level(project).is_some_and(|level| level < DEVELOPER_ACCESS),
+    }
+}
+
+/// Fetches the archive and write-access state of a project
+pub fn get_project_access(base_url: &str, namespace: &str, repo_name: &str) -> Result<RepoAccess> {
+    let client = http_client::blocking();
+    let response = client.get(project_url(base_url, namespace, repo_name)).headers(api_headers("gitlab")?).send()?;
+    let project: Value = check_response(response)?.json()?;
+    Ok(project_access(&project))
+}
+
+/// Replaces the description, topics and, when set, the default branch of a
+/// project; GitLab projects have no homepage
+pub fn update_project_metadata(base_url: &str, namespace: &str, repo_name: &str, metadata: &RepoMetadata) -> Result<()> {
+    let mut body = serde_json::json!({ "description": metadata.description, "topics": metadata.topics });
+    if let Some(branch) = &metadata.default_branch {
+        body["default_branch"] = serde_json::json!(branch);
+    }
+    info!("Updating metadata of project {}/{}", namespace, repo_name);
+    let client = http_client::blocking();
+    check_response(client.put(project_url(base_url, namespace, repo_name)).headers(api_headers("gitlab")?).json(&body).send()?)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_projects_and_access() {
+        assert_eq!(project_url("https://gitlab.example.com/api/v4", "group/sub", "repo"), "https://gitlab.example.com/api/v4/projects/group%2Fsub%2Frepo");
+        assert!(verify_token(&["old".to_string(), "new".to_string()], "new"));
+        assert!(!verify_token(&["".to_string()], ""));
+
+        let reporter = json!({ "archived": false, "permissions": { "project_access": { "access_level": 20 }, "group_access": null } });
+        assert_eq!(project_access(&reporter), RepoAccess { archived: false, read_only: true });
+        let maintainer = json!({ "archived": true, "permissions": { "project_access": { "access_level": 20 }, "group_access": { "access_level": 40 } } });
+        assert_eq!(project_access(&maintainer), RepoAccess { archived: true, read_only: false });
+        assert_eq!(project_access(&json!({})), RepoAccess::default());
+
+        let events = label_events(&[
+            json!({ "action": "add", "label": { "name": "br:release-1.0" }, "user": { "username": "alice" } }),
+            json!({ "action": "remove", "label": { "name": "br:release-1.0" }, "user": { "username": "bob" } }),
+            json!({ "action": "add", "label": null, "user": { "username": "bob" } }),
+        ]);
+        assert_eq!(events.len(), 1);
+        assert_eq!((events[0].label.as_str(), events[0].actor.as_str()), ("br:release-1.0", "alice"));
+    }
+}
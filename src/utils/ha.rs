@@ -0,0 +1,344 @@
+use std::collections::{BTreeMap, BTreeSet};
+use std::env;
+use std::sync::{Mutex, OnceLock};
+use std::time::{Duration, Instant};
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+use log::{info, error};
+
+use crate::error::{Error, Result};
+use crate::utils::{hmac, http_client};
+use crate::utils::body::WebhookBody;
+
+/// Header carrying the HMAC of forwarded HA messages
+pub const HA_SIGNATURE_HEADER: &str = "X-HA-Signature";
+
+/// Upper bound of forwarded events kept by a standby
+const MAX_PENDING: usize = 10_000;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HaRole {
+    /// Single instance, no forwarding
+    Off,
+    /// Processes webhooks and forwards them to the standby
+    Active,
+    /// Buffers forwarded webhooks and takes over when the active goes silent
+    Standby,
+}
+
+#[derive(Debug, Clone)]
+pub struct HaSettings {
+    pub role: HaRole,
+    /// Base URL of the standby (set on the active)
+    pub peer_url: Option<String>,
+    /// Key signing the messages exchanged between the instances
+    pub secret: String,
+    pub heartbeat: Duration,
+    /// Silence after which the standby takes over
+    pub failover_after: Duration,
+}
+
+fn env_secs(name: &str, default: u64) -> Duration {
+    Duration::from_secs(env::var(name).ok().and_then(|v| v.parse().ok()).unwrap_or(default))
+}
+
+impl HaSettings {
+    /// Loads `HA_ROLE` (`off`, `active`, `standby`), `HA_PEER_URL`, `HA_SHARED_SECRET`,
+    /// `HA_HEARTBEAT_SECS` (default 10) and `HA_FAILOVER_SECS` (default 30)
+    pub fn from_env() -> Self {
+        let role = match env::var("HA_ROLE").unwrap_or_default().to_lowercase().as_str() {
+            "active" => HaRole::Active,
+            "standby" => HaRole::Standby,
+            _ => HaRole::Off,
+        };
+        let secret = env::var("HA_SHARED_SECRET").unwrap_or_default();
+        if role != HaRole::Off && secret.is_empty() {
+            panic!("HA_SHARED_SECRET must be set when HA_ROLE is active or standby");
+        }
+        HaSettings {
+            role,
+            peer_url: env::var("HA_PEER_URL").ok().map(|url| url.trim_end_matches('/').to_string()),
+            secret,
+            heartbeat: env_secs("HA_HEARTBEAT_SECS", 10),
+            failover_after: env_secs("HA_FAILOVER_SECS", 30),
+        }
+    }
+}
+
+/// The process-wide HA settings
+pub fn settings() -> &'static HaSettings {
+    static SETTINGS: OnceLock<HaSettings> = OnceLock::new();
+    SETTINGS.get_or_init(HaSettings::from_env)
+}
+
+/// A verified webhook forwarded from the active to the standby
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct ForwardedEvent {
+    /// Run of the active that numbered the event
+    #[serde(default)]
+    pub epoch: String,
+    pub seq: u64,
+    pub platform: String,
+    pub event: String,
+    pub body: String,
+}
+
+/// Liveness signal of the active; events up to `completed_seq` need no replay
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct Heartbeat {
+    /// Run of the active, see `ForwardedEvent::epoch`
+    #[serde(default)]
+    pub epoch: String,
+    pub last_seq: u64,
+    pub completed_seq: u64,
+}
+
+/// Replication state of either side
+#[derive(Debug, Default)]
+pub struct HaState {
+    // Active side
+    /// Identifies this run, whose sequence numbers start over at 1
+    epoch: String,
+    next_seq: u64,
+    in_flight: BTreeSet<u64>,
+    // Standby side
+    /// Run of the active the buffered events belong to
+    active_epoch: Option<String>,
+    last_seq: u64,
+    last_heartbeat: Option<Instant>,
+    pending: BTreeMap<u64, ForwardedEvent>,
+    took_over: bool,
+}
+
+impl HaState {
+    /// State of an active whose run is identified by `epoch`
+    pub fn with_epoch(epoch: &str) -> Self {
+        HaState { epoch: epoch.to_string(), ..HaState::default() }
+    }
+
+    /// Allocates the sequence number of a forwarded event (active)
+    pub fn begin(&mut self) -> u64 {
+        self.next_seq += 1;
+        self.in_flight.insert(self.next_seq);
+        self.next_seq
+    }
+
+    /// Marks a forwarded event as processed (active)
+    pub fn complete(&mut self, seq: u64) {
+        self.in_flight.remove(&seq);
+    }
+
+    /// Heartbeat describing the active's progress: everything below the oldest
+    /// in-flight event is done
+    pub fn heartbeat(&self) -> Heartbeat {
+        let completed_seq = match self.in_flight.iter().next() {
+            Some(oldest) => oldest - 1,
+            None => self.next_seq,
+        };
+        Heartbeat { epoch: self.epoch.clone(), last_seq: self.next_seq, completed_seq }
+    }
+
+    /// Starts over when the active restarted: its sequence numbers restart at
+    /// 1, and the events of its previous run can no longer be matched
+    /// against its progress (standby)
+    fn follow_epoch(&mut self, epoch: &str) {
+        if self.active_epoch.as_deref() == Some(epoch) {
+            return;
+        }
+        if self.active_epoch.is_some() {
+            info!("HA: active instance restarted, dropping {} events of its previous run", self.pending.len());
+            self.pending.clear();
+            self.last_seq = 0;
+        }
+        self.active_epoch = Some(epoch.to_string());
+    }
+
+    /// Buffers a forwarded event (standby)
+    pub fn record_event(&mut self, event: ForwardedEvent, now: Instant) {
+        self.follow_epoch(&event.epoch);
+        if self.last_seq != 0 && event.seq > self.last_seq + 1 {
+            error!("HA: missed forwarded events {}..{}", self.last_seq + 1, event.seq - 1);
+        }
+        self.last_seq = self.last_seq.max(event.seq);
+        self.last_heartbeat = Some(now);
+        self.pending.insert(event.seq, event);
+        while self.pending.len() > MAX_PENDING {
+            self.pending.pop_first();
+        }
+    }
+
+    /// Records a heartbeat and drops events the active has finished (standby)
+    pub fn record_heartbeat(&mut self, heartbeat: &Heartbeat, now: Instant) {
+        if self.took_over {
+            info!("HA: active instance is back, returning to standby");
+            self.took_over = false;
+        }
+        self.follow_epoch(&heartbeat.epoch);
+        self.last_heartbeat = Some(now);
+        self.pending.retain(|seq, _| *seq > heartbeat.completed_seq);
+    }
+
+    /// Whether the active has been silent for longer than `failover_after`
+    pub fn active_missing(&self, failover_after: Duration, now: Instant) -> bool {
+        match self.last_heartbeat {
+            Some(last) => now.duration_since(last) > failover_after,
+            // No contact since startup: behave as if the active were down
+            None => true,
+        }
+    }
+
+    /// On the first check after the active went silent, returns the events it
+    /// may not have finished so the standby can replay them
+    pub fn take_over(&mut self, failover_after: Duration, now: Instant) -> Option<Vec<ForwardedEvent>> {
+        if self.took_over || !self.active_missing(failover_after, now) {
+            return None;
+        }
+        self.took_over = true;
+        Some(std::mem::take(&mut self.pending).into_values().collect())
+    }
+}
+
+/// The process-wide HA state
+pub fn state() -> &'static Mutex<HaState> {
+    static STATE: OnceLock<Mutex<HaState>> = OnceLock::new();
+    STATE.get_or_init(|| Mutex::new(HaState::with_epoch(&uuid::Uuid::new_v4().to_string())))
+}
+
+pub fn sign(secret: &str, payload: &str) -> String {
+    hmac::compute_hmac_sha256(payload.as_bytes(), secret)
+}
+
+/// Whether this instance processes the webhooks it receives
+///
+/// A standby only processes once the active has missed its heartbeats.
+pub fn should_process_locally(settings: &HaSettings) -> bool {
+    match settings.role {
+        HaRole::Off | HaRole::Active => true,
+        HaRole::Standby => state().lock().unwrap().active_missing(settings.failover_after, Instant::now()),
+    }
+}
+
+async fn post_signed(settings: &HaSettings, path: &str, payload: String) -> Result<()> {
+    let peer = settings.peer_url.as_ref().ok_or_else(|| Error::config("HA_PEER_URL not set"))?;
+    let response = http_client::shared()
+        .post(format!("{}{}", peer, path))
+        .header(HA_SIGNATURE_HEADER, sign(&settings.secret, &payload))
+        .header("Content-Type", "application/json")
+        .timeout(settings.heartbeat)
+        .body(payload)
+        .send()
+        .await?;
+    if !response.status().is_success() {
+        return Err(Error::HttpApi { status: Some(response.status().as_u16()), message: format!("standby answered {}", response.status()) });
+    }
+    Ok(())
+}
+
+/// Forwards a verified webhook to the standby in the background (active only)
+///
+/// Returns the event's sequence number, to be passed to `complete` once the
+/// event is processed. The webhook is processed without waiting for the
+/// standby; forwarding failures are logged, never fatal.
+pub fn forward(settings: &'static HaSettings, platform: &str, event: &str, body: &WebhookBody) -> Option<u64> {
+    if settings.role != HaRole::Active {
+        return None;
+    }
+    let body = match body.text() {
+        Ok(body) => body,
+        Err(e) => {
+            error!("HA: failed to read {} event body, not forwarding: {}", platform, e);
+            return None;
+        }
+    };
+    let (epoch, seq) = {
+        let mut state = state().lock().unwrap();
+        (state.epoch.clone(), state.begin())
+    };
+    let forwarded = ForwardedEvent {
+        epoch,
+        seq,
+        platform: platform.to_string(),
+        event: event.to_string(),
+        body,
+    };
+    let payload = serde_json::to_string(&forwarded).expect("forwarded event serializes");
+    tokio::spawn(async move {
+        if let Err(e) = post_signed(settings, "/ha/event", payload).await {
+            error!("HA: failed to forward event {} to standby: {}", seq, e);
+        }
+    });
+    Some(seq)
+}
+
+pub fn complete(seq: u64) {
+    state().lock().unwrap().complete(seq);
+}
+
+/// Sends heartbeats to the standby every `heartbeat` interval (active only)
+pub fn spawn_heartbeat_task(settings: &'static HaSettings) {
+    if settings.role != HaRole::Active {
+        return;
+    }
+    info!("HA: active, sending heartbeats to {:?} every {:?}", settings.peer_url, settings.heartbeat);
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(settings.heartbeat);
+        loop {
+            interval.tick().await;
+            let heartbeat = state().lock().unwrap().heartbeat();
+            let payload = serde_json::to_string(&heartbeat).expect("heartbeat serializes");
+            if let Err(e) = post_signed(settings, "/ha/heartbeat", payload).await {
+                error!("HA: heartbeat to standby failed: {}", e);
+            }
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn event(seq: u64) -> ForwardedEvent {
+        ForwardedEvent { epoch: "first".to_string(), seq, platform: "github".to_string(), event: "pull_request".to_string(), body: "{}".to_string() }
+    }
+
+    #[test]
+    fn test_heartbeat_reports_contiguous_progress() {
+        let mut active = HaState::default();
+        let first = active.begin();
+        let second = active.begin();
+        active.complete(second);
+        assert_eq!(active.heartbeat().completed_seq, 0);
+        active.complete(first);
+        assert_eq!(active.heartbeat().completed_seq, 2);
+    }
+
+    #[test]
+    fn test_standby_takes_over_unfinished_events() {
+        let failover = Duration::from_secs(30);
+        let start = Instant::now();
+        let mut standby = HaState::default();
+        standby.record_event(event(1), start);
+        standby.record_event(event(2), start);
+        standby.record_heartbeat(&Heartbeat { epoch: "first".to_string(), last_seq: 2, completed_seq: 1 }, start);
+
+        assert!(standby.take_over(failover, start + Duration::from_secs(10)).is_none());
+        let replay = standby.take_over(failover, start + Duration::from_secs(31)).unwrap();
+        assert_eq!(replay.iter().map(|e| e.seq).collect::<Vec<_>>(), vec![2]);
+        // Takeover happens once
+        assert!(standby.take_over(failover, start + Duration::from_secs(40)).is_none());
+    }
+
+    #[test]
+    fn test_standby_forgets_events_of_a_restarted_active() {
+        let start = Instant::now();
+        let mut standby = HaState::default();
+        standby.record_event(event(7), start);
+        standby.record_event(event(8), start);
+
+        let restarted = HaState::with_epoch("second");
+        standby.record_heartbeat(&restarted.heartbeat(), start);
+        let replay = standby.take_over(Duration::from_secs(30), start + Duration::from_secs(31)).unwrap();
+        assert!(replay.is_empty());
+        assert_eq!(standby.last_seq, 0);
+    }
+}
@@ -11,7 +11,7 @@ use hex;
 /// 
 /// # Example
 /// ```
-/// use crate::utils::hash::sha256_hex;
+/// use webhook_service::utils::hash::sha256_hex;
 /// 
 /// let hash = sha256_hex("Hello, World!");
 /// assert_eq!(hash.len(), 64); // SHA-256 hash is 32 bytes (64 hex chars)
@@ -50,7 +50,7 @@ mod tests {
             ),
             (
                 "你好，世界！", // Unicode test
-                "5f2b4d25d103ce72d11d0734ab76f81458ef3e3c78bc5d6664275f845c484d8c"
+                "fa65d94b3532d83fd24ada92dadecfc7ae5370e6dbf762133027a89c2e7202f1"
             ),
         ];
 
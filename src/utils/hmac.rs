@@ -1,12 +1,48 @@
+use std::fmt;
 use hmac::{Hmac, Mac};
 use sha2::Sha256;
 
+use crate::utils::body::WebhookBody;
+
 type HmacSha256 = Hmac<Sha256>;
 
+/// Why a webhook signature was rejected
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SignatureError {
+    /// The signature is not a hex-encoded SHA-256 MAC
+    Malformed,
+    /// None of the keys produced the signature
+    Mismatch,
+    /// The body could not be read to compute its MAC
+    Unreadable(String),
+}
+
+impl fmt::Display for SignatureError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SignatureError::Malformed => write!(f, "malformed signature"),
+            SignatureError::Mismatch => write!(f, "signature mismatch"),
+            SignatureError::Unreadable(e) => write!(f, "failed to read the signed body: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for SignatureError {}
+
+fn decode_signature(signature: &str) -> Result<Vec<u8>, SignatureError> {
+    match hex::decode(signature.trim()) {
+        Ok(bytes) if bytes.len() == 32 => Ok(bytes),
+        _ => Err(SignatureError::Malformed),
+    }
+}
+
+fn new_mac(key: &str) -> HmacSha256 {
+    HmacSha256::new_from_slice(key.as_bytes()).expect("HMAC can take key of any size")
+}
+
 pub fn compute_hmac_sha256(input: &[u8], key: &str) -> String {
     // Create HMAC-SHA256 instance
-    let mut mac = HmacSha256::new_from_slice(key.as_bytes())
-        .expect("HMAC can take key of any size");
+    let mut mac = new_mac(key);
 
     // Add input data
     mac.update(input);
@@ -17,6 +53,34 @@ pub fn compute_hmac_sha256(input: &[u8], key: &str) -> String {
     hex::encode(bytes)
 }
 
+/// Checks a hex HMAC-SHA256 `signature` of `input` in constant time
+pub fn verify_hmac_sha256(input: &[u8], key: &str, signature: &str) -> Result<(), SignatureError> {
+    let expected = decode_signature(signature)?;
+    let mut mac = new_mac(key);
+    mac.update(input);
+    mac.verify_slice(&expected).map_err(|_| SignatureError::Mismatch)
+}
+
+/// Checks the hex HMAC-SHA256 `signature` of a webhook body against each of
+/// `keys`, comparing in constant time; keys are tried in order and the body
+/// is streamed, so spooled bodies are never loaded
+pub fn verify_webhook_signature(body: &WebhookBody, keys: &[String], signature: &str) -> Result<(), SignatureError> {
+    let expected = decode_signature(signature)?;
+    for key in keys {
+        let mac = body.mac(new_mac(key)).map_err(|e| SignatureError::Unreadable(e.to_string()))?;
+        if mac.verify_slice(&expected).is_ok() {
+            return Ok(());
+        }
+    }
+    Err(SignatureError::Mismatch)
+}
+
+/// Compares two secrets without returning early on the first difference;
+/// only their lengths leak
+pub fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0u8, |diff, (x, y)| diff | (x ^ y)) == 0
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -28,4 +92,20 @@ mod tests {
         let result = compute_hmac_sha256(test_input, test_key);
         assert!(!result.is_empty());
     }
+
+    #[test]
+    fn test_verify_webhook_signature() {
+        let body = WebhookBody::from("{\"number\": 1}".to_string());
+        let signature = compute_hmac_sha256(b"{\"number\": 1}", "new");
+        let keys = vec!["old".to_string(), "new".to_string()];
+        assert_eq!(verify_webhook_signature(&body, &keys, &signature), Ok(()));
+        assert_eq!(verify_webhook_signature(&body, &keys[..1], &signature), Err(SignatureError::Mismatch));
+        assert_eq!(verify_webhook_signature(&body, &keys, "zz"), Err(SignatureError::Malformed));
+        assert_eq!(verify_webhook_signature(&body, &keys, &signature[..62]), Err(SignatureError::Malformed));
+        assert_eq!(verify_hmac_sha256(b"{\"number\": 2}", "new", &signature), Err(SignatureError::Mismatch));
+
+        assert!(constant_time_eq(b"token", b"token"));
+        assert!(!constant_time_eq(b"token", b"tokem"));
+        assert!(!constant_time_eq(b"token", b"token2"));
+    }
 }
\ No newline at end of file
@@ -0,0 +1,169 @@
+use std::collections::HashMap;
+use std::env;
+use std::sync::OnceLock;
+use std::time::Duration;
+use log::{info, warn};
+
+/// Timeouts and connection pool settings shared by the outgoing HTTP clients
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct HttpSettings {
+    pub connect_timeout: Duration,
+    /// Whole-request timeout, covering slow or stalled reads
+    pub timeout: Duration,
+    /// Idle pooled connections are closed after this; `None` keeps them
+    pub pool_idle_timeout: Option<Duration>,
+    pub pool_max_idle_per_host: usize,
+    pub tcp_keepalive: Option<Duration>,
+    /// Negotiate HTTP/2 (ALPN) so that concurrent calls to a host share one
+    /// connection; off forces HTTP/1.1
+    pub http2: bool,
+    /// Interval of HTTP/2 pings keeping idle connections alive
+    pub http2_keepalive: Option<Duration>,
+    /// Call name -> timeout replacing `timeout` for that call
+    pub overrides: HashMap<String, Duration>,
+}
+
+impl Default for HttpSettings {
+    fn default() -> Self {
+        HttpSettings {
+            connect_timeout: Duration::from_secs(10),
+            timeout: Duration::from_secs(60),
+            pool_idle_timeout: Some(Duration::from_secs(90)),
+            pool_max_idle_per_host: 8,
+            tcp_keepalive: Some(Duration::from_secs(60)),
+            http2: true,
+            http2_keepalive: Some(Duration::from_secs(30)),
+            overrides: HashMap::new(),
+        }
+    }
+}
+
+/// Parses `call=secs` pairs separated by commas, skipping malformed entries
+pub fn parse_overrides(value: &str) -> HashMap<String, Duration> {
+    value
+        .split(',')
+        .filter(|entry| !entry.trim().is_empty())
+        .filter_map(|entry| {
+            let parsed = entry.split_once('=').and_then(|(call, secs)| Some((call.trim().to_string(), secs.trim().parse::<u64>().ok()?)));
+            if parsed.is_none() {
+                warn!("Ignoring malformed HTTP timeout override {:?}", entry);
+            }
+            parsed.map(|(call, secs)| (call, Duration::from_secs(secs)))
+        })
+        .collect()
+}
+
+impl HttpSettings {
+    /// Reads `HTTP_CONNECT_TIMEOUT_SECS`, `HTTP_TIMEOUT_SECS`,
+    /// `HTTP_POOL_IDLE_SECS`, `HTTP_POOL_MAX_IDLE_PER_HOST`,
+    /// `HTTP_TCP_KEEPALIVE_SECS`, `HTTP2_KEEPALIVE_SECS` (`0` disables these
+    /// three durations), `HTTP2` (`false` forces HTTP/1.1) and
+    /// `HTTP_TIMEOUT_OVERRIDES` (`commits=120,freeze_calendar=30`)
+    pub fn from_env() -> Self {
+        let defaults = HttpSettings::default();
+        let secs = |name: &str| env::var(name).ok().and_then(|value| value.parse::<u64>().ok());
+        let optional = |name: &str, default: Option<Duration>| match secs(name) {
+            Some(0) => None,
+            Some(value) => Some(Duration::from_secs(value)),
+            None => default,
+        };
+        HttpSettings {
+            connect_timeout: secs("HTTP_CONNECT_TIMEOUT_SECS").map(Duration::from_secs).unwrap_or(defaults.connect_timeout),
+            timeout: secs("HTTP_TIMEOUT_SECS").map(Duration::from_secs).unwrap_or(defaults.timeout),
+            pool_idle_timeout: optional("HTTP_POOL_IDLE_SECS", defaults.pool_idle_timeout),
+            pool_max_idle_per_host: secs("HTTP_POOL_MAX_IDLE_PER_HOST").map(|value| value as usize).unwrap_or(defaults.pool_max_idle_per_host),
+            tcp_keepalive: optional("HTTP_TCP_KEEPALIVE_SECS", defaults.tcp_keepalive),
+            http2: env::var("HTTP2").map(|value| value != "false" && value != "0").unwrap_or(defaults.http2),
+            http2_keepalive: optional("HTTP2_KEEPALIVE_SECS", defaults.http2_keepalive),
+            overrides: env::var("HTTP_TIMEOUT_OVERRIDES").map(|value| parse_overrides(&value)).unwrap_or_default(),
+        }
+    }
+
+    /// Timeout of the call named `call`
+    pub fn timeout_for(&self, call: &str) -> Duration {
+        self.overrides.get(call).copied().unwrap_or(self.timeout)
+    }
+}
+
+/// The process-wide HTTP settings
+pub fn settings() -> &'static HttpSettings {
+    static SETTINGS: OnceLock<HttpSettings> = OnceLock::new();
+    SETTINGS.get_or_init(|| {
+        let settings = HttpSettings::from_env();
+        info!(
+            "HTTP clients: connect timeout {:?}, timeout {:?}, HTTP/2 {}",
+            settings.connect_timeout,
+            settings.timeout,
+            if settings.http2 { "enabled" } else { "disabled" }
+        );
+        settings
+    })
+}
+
+/// Timeout of the call named `call`, for `RequestBuilder::timeout`
+pub fn timeout_for(call: &str) -> Duration {
+    settings().timeout_for(call)
+}
+
+/// Applies the shared settings to a blocking client builder
+pub fn configure_blocking(builder: reqwest::blocking::ClientBuilder) -> reqwest::blocking::ClientBuilder {
+    let settings = settings();
+    let builder = builder
+        .connect_timeout(settings.connect_timeout)
+        .timeout(settings.timeout)
+        .pool_idle_timeout(settings.pool_idle_timeout)
+        .pool_max_idle_per_host(settings.pool_max_idle_per_host)
+        .tcp_keepalive(settings.tcp_keepalive);
+    if !settings.http2 {
+        return builder.http1_only();
+    }
+    // The blocking builder has no HTTP/2 keep-alive pings
+    builder.http2_adaptive_window(true)
+}
+
+/// Applies the shared settings to an async client builder
+pub fn configure_async(builder: reqwest::ClientBuilder) -> reqwest::ClientBuilder {
+    let settings = settings();
+    let builder = builder
+        .connect_timeout(settings.connect_timeout)
+        .timeout(settings.timeout)
+        .pool_idle_timeout(settings.pool_idle_timeout)
+        .pool_max_idle_per_host(settings.pool_max_idle_per_host)
+        .tcp_keepalive(settings.tcp_keepalive);
+    if !settings.http2 {
+        return builder.http1_only();
+    }
+    let builder = builder.http2_adaptive_window(true);
+    match settings.http2_keepalive {
+        Some(interval) => builder.http2_keep_alive_interval(interval).http2_keep_alive_while_idle(true),
+        None => builder,
+    }
+}
+
+/// The shared blocking client, reusing connections across API calls
+pub fn blocking() -> &'static reqwest::blocking::Client {
+    static CLIENT: OnceLock<reqwest::blocking::Client> = OnceLock::new();
+    CLIENT.get_or_init(|| configure_blocking(reqwest::blocking::Client::builder()).build().expect("Failed to build HTTP client"))
+}
+
+/// The shared async client, for async API calls made outside of a webhook
+/// handler (replays, HA), which use the client of their platform's state
+pub fn shared() -> &'static reqwest::Client {
+    static CLIENT: OnceLock<reqwest::Client> = OnceLock::new();
+    CLIENT.get_or_init(|| configure_async(reqwest::Client::builder()).build().expect("Failed to build HTTP client"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_timeout_overrides() {
+        let settings = HttpSettings { overrides: parse_overrides("commits=120, broken, freeze_calendar = 30,x=y"), ..HttpSettings::default() };
+        assert_eq!(settings.overrides.len(), 2);
+        assert_eq!(settings.timeout_for("commits"), Duration::from_secs(120));
+        assert_eq!(settings.timeout_for("freeze_calendar"), Duration::from_secs(30));
+        assert_eq!(settings.timeout_for("labels"), Duration::from_secs(60));
+        assert!(settings.http2);
+    }
+}
@@ -0,0 +1,187 @@
+use std::cell::RefCell;
+use std::env;
+use std::sync::{OnceLock, RwLock};
+use rand::RngCore;
+use reqwest::header::{HeaderMap, HeaderName, HeaderValue};
+use log::warn;
+
+use crate::utils::hash;
+
+/// Header carrying the job ID of the request
+pub const CORRELATION_HEADER: &str = "x-correlation-id";
+
+thread_local! {
+    static CORRELATION_ID: RefCell<Option<String>> = const { RefCell::new(None) };
+}
+
+/// Restores the previous correlation ID of the thread when dropped
+pub struct CorrelationScope {
+    previous: Option<String>,
+}
+
+impl Drop for CorrelationScope {
+    fn drop(&mut self) {
+        let previous = self.previous.take();
+        CORRELATION_ID.with(|id| *id.borrow_mut() = previous);
+    }
+}
+
+/// Tags the API requests made by this thread with `id` (a job ID) until the
+/// returned scope is dropped
+///
+/// Jobs run on their own blocking thread, so a thread-local reaches every
+/// API call of the job without threading the ID through each function.
+pub fn correlate(id: &str) -> CorrelationScope {
+    let previous = CORRELATION_ID.with(|current| current.borrow_mut().replace(id.to_string()));
+    CorrelationScope { previous }
+}
+
+/// Correlation ID of the current thread, if any
+pub fn correlation_id() -> Option<String> {
+    CORRELATION_ID.with(|id| id.borrow().clone())
+}
+
+/// Whether OpenTelemetry is configured (`OTEL_EXPORTER_OTLP_ENDPOINT` set and
+/// `OTEL_SDK_DISABLED` not `true`), in which case `traceparent` is sent too
+pub fn otel_enabled() -> bool {
+    let disabled = env::var("OTEL_SDK_DISABLED").is_ok_and(|value| value.eq_ignore_ascii_case("true"));
+    !disabled && env::var("OTEL_EXPORTER_OTLP_ENDPOINT").is_ok_and(|value| !value.is_empty())
+}
+
+/// W3C `traceparent` for a request of the job `correlation_id`
+///
+/// The trace ID is derived from the correlation ID so that every request of a
+/// job shares it; each request gets its own span ID.
+pub fn traceparent(correlation_id: &str) -> String {
+    let trace_id = match uuid::Uuid::parse_str(correlation_id) {
+        Ok(uuid) => uuid.simple().to_string(),
+        Err(_) => hash::sha256_hex(correlation_id)[..32].to_string(),
+    };
+    let mut span_id = [0u8; 8];
+    rand::thread_rng().fill_bytes(&mut span_id);
+    format!("00-{}-{}-01", trace_id, hex::encode(span_id))
+}
+
+/// Sets the correlation headers of the current thread's job, if any
+fn apply_correlation_headers(headers: &mut HeaderMap) {
+    let Some(id) = correlation_id() else { return };
+    if let Ok(value) = HeaderValue::from_str(&id) {
+        headers.insert(CORRELATION_HEADER, value);
+    }
+    if otel_enabled() {
+        if let Ok(value) = HeaderValue::from_str(&traceparent(&id)) {
+            headers.insert("traceparent", value);
+        }
+    }
+}
+
+/// Computes headers at request time (e.g. a short-lived gateway token)
+pub type HeaderHook = Box<dyn Fn(&str, &mut HeaderMap) + Send + Sync>;
+
+fn hooks() -> &'static RwLock<Vec<HeaderHook>> {
+    static HOOKS: OnceLock<RwLock<Vec<HeaderHook>>> = OnceLock::new();
+    HOOKS.get_or_init(|| RwLock::new(Vec::new()))
+}
+
+/// Registers a hook called with the platform name for every API request,
+/// after the static headers are set
+pub fn register_header_hook(hook: HeaderHook) {
+    hooks().write().unwrap().push(hook);
+}
+
+/// Parses `Name: value` entries separated by newlines or `;`
+///
+/// Invalid entries are skipped with a warning rather than failing every API call.
+pub fn parse_headers(spec: &str) -> HeaderMap {
+    let mut headers = HeaderMap::new();
+    for entry in spec.split(['\n', ';']).map(str::trim).filter(|entry| !entry.is_empty()) {
+        let parsed = entry.split_once(':').and_then(|(name, value)| {
+            let name = HeaderName::from_bytes(name.trim().as_bytes()).ok()?;
+            let value = HeaderValue::from_str(value.trim()).ok()?;
+            Some((name, value))
+        });
+        match parsed {
+            Some((name, value)) => {
+                headers.append(name, value);
+            }
+            None => warn!("Ignoring invalid extra header entry {:?}", entry.split(':').next().unwrap_or("")),
+        }
+    }
+    headers
+}
+
+/// Static headers for `platform` from `<PLATFORM>_EXTRA_HEADERS`
+/// (e.g. `GITCODE_EXTRA_HEADERS="X-Gateway-Key: abc; X-Tenant: backport"`)
+pub fn static_headers(platform: &str) -> HeaderMap {
+    env::var(format!("{}_EXTRA_HEADERS", platform.to_uppercase()))
+        .map(|spec| parse_headers(&spec))
+        .unwrap_or_default()
+}
+
+/// Adds the correlation headers, the configured static headers and the
+/// registered hooks' headers to an API request for `platform`; they override
+/// headers set by the caller
+pub fn apply_extra_headers(platform: &str, headers: &mut HeaderMap) {
+    apply_correlation_headers(headers);
+    let extra = static_headers(platform);
+    for name in extra.keys() {
+        headers.remove(name);
+    }
+    for (name, value) in extra.iter() {
+        headers.append(name.clone(), value.clone());
+    }
+    for hook in hooks().read().unwrap().iter() {
+        hook(platform, headers);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_headers() {
+        let headers = parse_headers("X-Gateway-Key: abc:def; X-Tenant: backport\n bad header\n");
+        assert_eq!(headers.len(), 2);
+        assert_eq!(headers["x-gateway-key"], "abc:def");
+        assert_eq!(headers["x-tenant"], "backport");
+    }
+
+    #[test]
+    fn test_hooks_see_the_platform() {
+        register_header_hook(Box::new(|platform, headers| {
+            if platform == "test-platform" {
+                headers.insert("x-dynamic", HeaderValue::from_static("1"));
+            }
+        }));
+        let mut headers = HeaderMap::new();
+        apply_extra_headers("test-platform", &mut headers);
+        assert_eq!(headers["x-dynamic"], "1");
+        let mut other = HeaderMap::new();
+        apply_extra_headers("other-platform", &mut other);
+        assert!(other.is_empty());
+    }
+
+    #[test]
+    fn test_correlation_scope_tags_requests() {
+        let job_id = "0b6f5c1e-8d1f-4a8e-9a57-3f4c1b2d7e90";
+        {
+            let _scope = correlate(job_id);
+            let mut headers = HeaderMap::new();
+            apply_extra_headers("correlation-platform", &mut headers);
+            assert_eq!(headers[CORRELATION_HEADER], job_id);
+            {
+                let _nested = correlate("other");
+                assert_eq!(correlation_id().as_deref(), Some("other"));
+            }
+            assert_eq!(correlation_id().as_deref(), Some(job_id));
+        }
+        assert_eq!(correlation_id(), None);
+
+        let parent = traceparent(job_id);
+        let parts: Vec<&str> = parent.split('-').collect();
+        assert_eq!(parts, vec!["00", "0b6f5c1e8d1f4a8e9a573f4c1b2d7e90", parts[2], "01"]);
+        assert_eq!(parts[2].len(), 16);
+        assert_eq!(traceparent("not-a-uuid").len(), 55);
+    }
+}
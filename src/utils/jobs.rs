@@ -0,0 +1,281 @@
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex, OnceLock};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+use uuid::Uuid;
+use log::error;
+
+use crate::utils::clock::{self, Clock};
+use crate::utils::mirror::RefChange;
+use crate::utils::{state, state_store};
+
+/// Number of most recent jobs kept in the store
+const MAX_JOBS: usize = 1000;
+
+/// Commit trailer linking a backported commit to the job that created it
+pub const JOB_TRAILER: &str = "Backport-Job: ";
+
+/// Appends the job reference footer to a PR comment
+pub fn with_job_reference(message: &str, job_id: &str) -> String {
+    format!("{}\n\n<sub>Backport job: `{}`</sub>", message.trim_end(), job_id)
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum JobStatus {
+    Running,
+    Succeeded,
+    Failed,
+    /// Some branches were backported and others failed
+    Partial,
+}
+
+impl JobStatus {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            JobStatus::Running => "running",
+            JobStatus::Succeeded => "succeeded",
+            JobStatus::Failed => "failed",
+            JobStatus::Partial => "partial",
+        }
+    }
+}
+
+/// Outcome of a backport job on one target branch
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+pub struct BranchResult {
+    pub status: JobStatus,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+    /// Commits left out because the branch already had the same change
+    /// (same patch-id) -> the commit of the branch carrying it
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub skipped: BTreeMap<String, String>,
+}
+
+/// Filters for `JobStore::search`; unset fields match every job
+#[derive(Debug, Default)]
+pub struct JobQuery<'a> {
+    /// Full job ID or a prefix of it (e.g. from a commit trailer or comment)
+    pub id: Option<&'a str>,
+    pub repo: Option<&'a str>,
+    pub status: Option<&'a str>,
+    pub limit: usize,
+}
+
+/// One webhook processing attempt
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct JobRecord {
+    pub id: Uuid,
+    pub platform: String,
+    pub repo: String,
+    pub event: String,
+    pub status: JobStatus,
+    pub created_at: DateTime<Utc>,
+    pub finished_at: Option<DateTime<Utc>>,
+    pub error: Option<String>,
+    /// Layer the error of a failed job comes from (`git`, `http_api`, ...)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error_kind: Option<String>,
+    /// Archived workspace of a failed job
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    #[schema(value_type = Option<String>)]
+    pub artifact: Option<PathBuf>,
+    /// Pushed branch -> its tip on the remote, read back after the push
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub verified_tips: BTreeMap<String, String>,
+    /// Target branch -> its outcome, for backport jobs
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub branches: BTreeMap<String, BranchResult>,
+    /// References a mirror job's push changes on the target, computed before
+    /// pushing
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub ref_changes: Vec<RefChange>,
+}
+
+/// Job records persisted as JSON in `jobs.json` under the state directory
+pub struct JobStore {
+    path: PathBuf,
+    jobs: Vec<JobRecord>,
+    clock: Arc<dyn Clock>,
+}
+
+impl JobStore {
+    pub fn open(path: &Path) -> JobStore {
+        let jobs = match state_store::read_document(path) {
+            Ok(Some(contents)) => serde_json::from_str(&contents).unwrap_or_else(|e| {
+                error!("Failed to parse job store {:?}, starting empty: {}", path, e);
+                Vec::new()
+            }),
+            _ => Vec::new(),
+        };
+        JobStore { path: path.to_path_buf(), jobs, clock: clock::system() }
+    }
+
+    /// Uses `clock` for job timestamps (a `MockClock` in tests)
+    pub fn with_clock(mut self, clock: Arc<dyn Clock>) -> Self {
+        self.clock = clock;
+        self
+    }
+
+    fn save(&self) {
+        let result = serde_json::to_string_pretty(&self.jobs)
+            .map_err(std::io::Error::from)
+            .and_then(|contents| state_store::write_document(&self.path, &contents));
+        if let Err(e) = result {
+            error!("Failed to persist job store {:?}: {}", self.path, e);
+        }
+    }
+
+    pub fn start(&mut self, platform: &str, repo: &str, event: &str) -> Uuid {
+        let record = JobRecord {
+            id: Uuid::new_v4(),
+            platform: platform.to_string(),
+            repo: repo.to_string(),
+            event: event.to_string(),
+            status: JobStatus::Running,
+            created_at: self.clock.now(),
+            finished_at: None,
+            error: None,
+            error_kind: None,
+            artifact: None,
+            verified_tips: BTreeMap::new(),
+            branches: BTreeMap::new(),
+            ref_changes: Vec::new(),
+        };
+        let id = record.id;
+        self.jobs.push(record);
+        if self.jobs.len() > MAX_JOBS {
+            let excess = self.jobs.len() - MAX_JOBS;
+            self.jobs.drain(..excess);
+        }
+        self.save();
+        id
+    }
+
+    pub fn update<F: FnOnce(&mut JobRecord)>(&mut self, id: Uuid, f: F) {
+        if let Some(record) = self.jobs.iter_mut().find(|job| job.id == id) {
+            f(record);
+            self.save();
+        }
+    }
+
+    /// Marks the job finished; a failed job whose branch results include a
+    /// success is `Partial`
+    pub fn finish(&mut self, id: Uuid, result: Result<(), String>) {
+        let now = self.clock.now();
+        self.update(id, |record| {
+            record.finished_at = Some(now);
+            match result {
+                Ok(()) => record.status = JobStatus::Succeeded,
+                Err(e) => {
+                    let any_succeeded = record.branches.values().any(|branch| branch.status == JobStatus::Succeeded);
+                    record.status = if any_succeeded { JobStatus::Partial } else { JobStatus::Failed };
+                    record.error = Some(e);
+                }
+            }
+        });
+    }
+
+    pub fn get(&self, id: Uuid) -> Option<JobRecord> {
+        self.jobs.iter().find(|job| job.id == id).cloned()
+    }
+
+    /// Returns the jobs matching `query`, most recent first
+    pub fn search(&self, query: &JobQuery) -> Vec<JobRecord> {
+        let id = query.id.map(str::to_lowercase);
+        self.jobs
+            .iter()
+            .rev()
+            .filter(|job| id.as_ref().is_none_or(|id| job.id.to_string().starts_with(id.as_str())))
+            .filter(|job| query.repo.is_none_or(|repo| job.repo == repo))
+            .filter(|job| query.status.is_none_or(|status| job.status.as_str() == status))
+            .take(if query.limit == 0 { MAX_JOBS } else { query.limit })
+            .cloned()
+            .collect()
+    }
+
+    /// Returns up to `limit` jobs, most recent first
+    pub fn recent(&self, limit: usize) -> Vec<JobRecord> {
+        self.jobs.iter().rev().take(limit).cloned().collect()
+    }
+}
+
+/// The process-wide job store
+pub fn store() -> &'static Mutex<JobStore> {
+    static STORE: OnceLock<Mutex<JobStore>> = OnceLock::new();
+    STORE.get_or_init(|| Mutex::new(JobStore::open(&state::state_dir().join("jobs.json"))))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::clock::MockClock;
+
+    #[test]
+    fn test_job_timestamps_use_injected_clock() {
+        let dir = tempfile::tempdir().unwrap();
+        let start = "2024-06-01T08:00:00Z".parse::<DateTime<Utc>>().unwrap();
+        let clock = MockClock::new(start);
+        let mut store = JobStore::open(&dir.path().join("jobs.json")).with_clock(Arc::new(clock.clone()));
+
+        let id = store.start("github", "repo", "pull_request");
+        clock.advance(std::time::Duration::from_secs(90));
+        store.finish(id, Ok(()));
+
+        let job = store.get(id).unwrap();
+        assert_eq!(job.created_at, start);
+        assert_eq!(job.finished_at.unwrap() - job.created_at, chrono::Duration::seconds(90));
+    }
+
+    #[test]
+    fn test_failed_branches_make_a_job_partial() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut store = JobStore::open(&dir.path().join("jobs.json"));
+        let result = |status, error: Option<&str>| BranchResult { status, error: error.map(str::to_string), skipped: BTreeMap::new() };
+
+        let partial = store.start("gitcode", "repo", "merge_request");
+        store.update(partial, |job| {
+            job.branches.insert("release-1.0".to_string(), result(JobStatus::Succeeded, None));
+            job.branches.insert("release-2.0".to_string(), result(JobStatus::Failed, Some("conflict")));
+        });
+        store.finish(partial, Err("Backport failed on 1 of 2 branches".to_string()));
+        assert_eq!(store.get(partial).unwrap().status, JobStatus::Partial);
+
+        let failed = store.start("gitcode", "repo", "merge_request");
+        store.update(failed, |job| {
+            job.branches.insert("release-2.0".to_string(), result(JobStatus::Failed, Some("conflict")));
+        });
+        store.finish(failed, Err("Backport failed on 1 of 1 branches".to_string()));
+        assert_eq!(store.get(failed).unwrap().status, JobStatus::Failed);
+        assert_eq!(store.search(&JobQuery { status: Some("partial"), ..Default::default() }).len(), 1);
+    }
+
+    #[test]
+    fn test_job_lifecycle_is_persisted() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("jobs.json");
+
+        let mut store = JobStore::open(&path);
+        let ok = store.start("github", "repo", "pull_request");
+        let failed = store.start("gitcode", "repo", "merge_request");
+        store.finish(ok, Ok(()));
+        store.finish(failed, Err("boom".to_string()));
+
+        let reopened = JobStore::open(&path);
+        assert_eq!(reopened.get(ok).unwrap().status, JobStatus::Succeeded);
+        let failed = reopened.get(failed).unwrap();
+        assert_eq!(failed.status, JobStatus::Failed);
+        assert_eq!(failed.error.as_deref(), Some("boom"));
+        assert_eq!(reopened.recent(1)[0].id, failed.id);
+
+        let prefix = failed.id.to_string()[..8].to_string();
+        let found = reopened.search(&JobQuery { id: Some(&prefix), ..Default::default() });
+        assert_eq!(found.len(), 1);
+        assert_eq!(found[0].id, failed.id);
+        let succeeded = reopened.search(&JobQuery { status: Some("succeeded"), ..Default::default() });
+        assert_eq!(succeeded[0].id, ok);
+    }
+}
@@ -1,26 +1,160 @@
+use std::env;
 use std::fs::{self, OpenOptions};
-use std::io::Write;
+use std::io::{self, BufWriter, Write};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::mpsc::{self, Receiver, SyncSender, TrySendError};
+use std::process;
+use std::sync::{Arc, Mutex, OnceLock};
+use std::thread::{self, JoinHandle};
 use env_logger::Builder;
 use log::LevelFilter;
 
-pub fn init_production_logger() {
-    let log_dir = "logs";
-    let log_file = format!("{}/webhook_service.log", log_dir);
-    
-    // Create logs directory if it doesn't exist
-    fs::create_dir_all(log_dir).expect("Failed to create log directory");
-    
-    // Configure env_logger with custom format
+/// Log lines dropped by the production logger because its buffer was full
+static DROPPED: OnceLock<Arc<AtomicU64>> = OnceLock::new();
+
+/// Writer thread of the production logger, stopped by `shutdown`
+static WRITER: Mutex<Option<WriterHandle>> = Mutex::new(None);
+
+/// Number of log lines buffered for the writer thread, from
+/// `LOG_BUFFER_LINES` (default 8192); lines logged while it is full are
+/// dropped and counted instead of blocking the caller
+pub fn buffer_lines() -> usize {
+    env::var("LOG_BUFFER_LINES")
+        .ok()
+        .and_then(|value| value.parse().ok())
+        .filter(|&lines| lines > 0)
+        .unwrap_or(8192)
+}
+
+/// Log lines dropped so far by the production logger
+pub fn dropped_lines() -> u64 {
+    DROPPED.get().map(|dropped| dropped.load(Ordering::Relaxed)).unwrap_or(0)
+}
+
+/// What the writer thread receives
+enum Message {
+    Line(Vec<u8>),
+    /// Write what was queued before, report dropped lines and exit
+    Shutdown,
+}
+
+/// Hands log lines to a dedicated writer thread through a bounded channel,
+/// so that workers never wait on file I/O; lines that do not fit in the
+/// channel are dropped and counted
+pub struct NonBlockingWriter {
+    sender: SyncSender<Message>,
+    dropped: Arc<AtomicU64>,
+}
+
+/// Writer thread of a `NonBlockingWriter`
+pub struct WriterHandle {
+    sender: SyncSender<Message>,
+    thread: JoinHandle<()>,
+}
+
+impl WriterHandle {
+    /// Waits until the lines queued so far are written and flushed, then
+    /// stops the thread; lines written afterwards are dropped
+    pub fn shutdown(self) {
+        let _ = self.sender.send(Message::Shutdown);
+        let _ = self.thread.join();
+    }
+}
+
+impl NonBlockingWriter {
+    /// Starts the writer thread appending to `sink`; it runs until the
+    /// handle is shut down
+    pub fn spawn<W: Write + Send + 'static>(sink: W, capacity: usize) -> io::Result<(NonBlockingWriter, WriterHandle)> {
+        let (sender, receiver) = mpsc::sync_channel(capacity);
+        let dropped = Arc::new(AtomicU64::new(0));
+        let counter = dropped.clone();
+        let thread = thread::Builder::new()
+            .name("log-writer".to_string())
+            .spawn(move || write_lines(receiver, BufWriter::new(sink), counter))?;
+        let handle = WriterHandle { sender: sender.clone(), thread };
+        Ok((NonBlockingWriter { sender, dropped }, handle))
+    }
+
+    /// Counter of the lines dropped by this writer
+    pub fn dropped(&self) -> Arc<AtomicU64> {
+        self.dropped.clone()
+    }
+}
+
+impl Write for NonBlockingWriter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        match self.sender.try_send(Message::Line(buf.to_vec())) {
+            Ok(()) => {}
+            Err(TrySendError::Full(_)) | Err(TrySendError::Disconnected(_)) => {
+                self.dropped.fetch_add(1, Ordering::Relaxed);
+            }
+        }
+        Ok(buf.len())
+    }
+
+    /// Lines are flushed by the writer thread whenever the channel runs empty
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// Writes lines in batches, flushing when the channel runs empty and noting
+/// in the log how many lines were dropped since the last batch
+fn write_lines(receiver: Receiver<Message>, mut sink: impl Write, dropped: Arc<AtomicU64>) {
+    let mut reported = 0;
+    let mut running = true;
+    while running {
+        let Ok(mut message) = receiver.recv() else { break };
+        loop {
+            match message {
+                Message::Line(line) => {
+                    let _ = sink.write_all(&line);
+                }
+                Message::Shutdown => {
+                    running = false;
+                    break;
+                }
+            }
+            match receiver.try_recv() {
+                Ok(next) => message = next,
+                Err(_) => break,
+            }
+        }
+        report_dropped(&mut sink, &dropped, &mut reported);
+        let _ = sink.flush();
+    }
+    report_dropped(&mut sink, &dropped, &mut reported);
+    let _ = sink.flush();
+}
+
+/// Notes the lines dropped since `reported` in the log
+fn report_dropped(sink: &mut impl Write, dropped: &AtomicU64, reported: &mut u64) {
+    let total = dropped.load(Ordering::Relaxed);
+    if total > *reported {
+        let _ = writeln!(
+            sink,
+            "{} [WARN] logging - {} log lines dropped, the log buffer was full",
+            chrono::Utc::now().format("%Y-%m-%dT%H:%M:%SZ"),
+            total - *reported
+        );
+        *reported = total;
+    }
+}
+
+/// Logger writing `level` and above to `log_file` through a `NonBlockingWriter`
+fn file_logger(log_file: &str, level: LevelFilter) -> (Arc<AtomicU64>, WriterHandle) {
     let mut builder = Builder::new();
-    builder.filter_level(LevelFilter::Info);
-    
+    builder.filter_level(level);
+
     // Create or append to log file
     let file = OpenOptions::new()
         .create(true)
         .append(true)
-        .open(&log_file)
+        .open(log_file)
         .expect("Failed to open log file");
-    
+    let (writer, handle) = NonBlockingWriter::spawn(file, buffer_lines()).expect("Failed to start log writer");
+    let dropped = writer.dropped();
+
     // Set custom format
     builder.format(|buf, record| {
         writeln!(
@@ -32,52 +166,97 @@ pub fn init_production_logger() {
             record.args()
         )
     });
-    
-    // Set file as output
-    builder.target(env_logger::Target::Pipe(Box::new(file)));
-    
-    // Initialize the logger
+
+    builder.target(env_logger::Target::Pipe(Box::new(writer)));
     builder.init();
-    
+    (dropped, handle)
+}
+
+pub fn init_production_logger() {
+    let log_dir = "logs";
+    let log_file = format!("{}/webhook_service.log", log_dir);
+
+    // Create logs directory if it doesn't exist
+    fs::create_dir_all(log_dir).expect("Failed to create log directory");
+
+    let (dropped, handle) = file_logger(&log_file, LevelFilter::Info);
+    let _ = DROPPED.set(dropped);
+    *WRITER.lock().unwrap() = Some(handle);
+
     log::info!("Logger initialized - logging to {}", log_file);
 }
 
+/// Writes out the lines queued for the production logger and stops its
+/// writer thread; a no-op when the logger was never started
+pub fn shutdown() {
+    if let Some(handle) = WRITER.lock().unwrap().take() {
+        handle.shutdown();
+    }
+}
+
+/// `process::exit`, after the queued log lines are written; exiting skips
+/// destructors, so the last lines (usually the reason) would be lost
+pub fn exit(code: i32) -> ! {
+    shutdown();
+    process::exit(code)
+}
+
 #[cfg(test)]
 pub fn init_test_logger() {
     let log_dir = "logs/test";
     let log_file = format!("{}/test.log", log_dir);
-    
+
     // Create logs directory if it doesn't exist
     fs::create_dir_all(log_dir).expect("Failed to create test log directory");
-    
-    // Configure env_logger with custom format
-    let mut builder = Builder::new();
-    builder.filter_level(LevelFilter::Debug);
-    
-    // Create or append to log file
-    let file = OpenOptions::new()
-        .create(true)
-        .append(true)
-        .open(&log_file)
-        .expect("Failed to open test log file");
-    
-    // Set custom format
-    builder.format(|buf, record| {
-        writeln!(
-            buf,
-            "{} [{}] {} - {}",
-            buf.timestamp(),
-            record.level(),
-            record.target(),
-            record.args()
-        )
-    });
-    
-    // Set file as output
-    builder.target(env_logger::Target::Pipe(Box::new(file)));
-    
-    // Initialize the logger
-    builder.init();
-    
+
+    file_logger(&log_file, LevelFilter::Debug);
+
     log::info!("Test logger initialized - logging to {}", log_file);
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Mutex;
+
+    /// Sink that waits for `gate` before every write
+    struct GatedSink {
+        gate: Arc<Mutex<()>>,
+        output: Arc<Mutex<Vec<u8>>>,
+    }
+
+    impl Write for GatedSink {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            let _open = self.gate.lock().unwrap();
+            self.output.lock().unwrap().extend_from_slice(buf);
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_lines_over_the_buffer_are_dropped_and_reported() {
+        let gate = Arc::new(Mutex::new(()));
+        let output = Arc::new(Mutex::new(Vec::new()));
+        let closed = gate.lock().unwrap();
+        let sink = GatedSink { gate: gate.clone(), output: output.clone() };
+        let (mut writer, handle) = NonBlockingWriter::spawn(sink, 1).unwrap();
+
+        for line in 0..4 {
+            writer.write_all(format!("line {}\n", line).as_bytes()).unwrap();
+        }
+        let dropped = writer.dropped().load(Ordering::Relaxed);
+        assert!(dropped >= 2, "dropped {}", dropped);
+
+        drop(closed);
+        handle.shutdown();
+        // Lines written after the shutdown are dropped, not blocked on
+        writer.write_all(b"late\n").unwrap();
+        let output = String::from_utf8(output.lock().unwrap().clone()).unwrap();
+        assert_eq!(output.matches("line ").count() as u64 + dropped, 4);
+        assert!(output.contains(&format!("{} log lines dropped", dropped)));
+    }
+}
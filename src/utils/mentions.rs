@@ -0,0 +1,123 @@
+use std::collections::HashMap;
+use serde::{Deserialize, Serialize};
+
+/// How @-mentions are rewritten in comments the bot posts
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum MentionMode {
+    /// Comments are posted as they are
+    #[default]
+    Keep,
+    /// Mentions of users in `author_map` are rewritten to the account on the
+    /// platform the comment is posted to; others become plain text
+    Translate,
+    /// Every mention becomes plain text
+    Strip,
+}
+
+/// Account names on `platform` for the users of the other platform
+///
+/// `author_map` maps GitHub logins to GitCode logins; it is inverted for
+/// comments posted on GitHub.
+pub fn mention_map(author_map: &HashMap<String, String>, platform: &str) -> HashMap<String, String> {
+    let mut map: HashMap<String, String> = author_map
+        .iter()
+        .map(|(github, gitcode)| match platform {
+            "github" => (gitcode.to_lowercase(), github.clone()),
+            _ => (github.to_lowercase(), gitcode.clone()),
+        })
+        .collect();
+    // Users already named by their account on `platform` stay as they are
+    for name in map.values().cloned().collect::<Vec<_>>() {
+        map.entry(name.to_lowercase()).or_insert(name);
+    }
+    map
+}
+
+/// Rewrites the @-mentions of `text` according to `mode`
+///
+/// `map` is keyed by lowercase login (see `mention_map`). Mentions inside
+/// code spans and fenced code blocks are left alone, as are e-mail addresses.
+pub fn rewrite_mentions(text: &str, mode: MentionMode, map: &HashMap<String, String>) -> String {
+    if mode == MentionMode::Keep {
+        return text.to_string();
+    }
+    let mut output = String::with_capacity(text.len());
+    let mut in_fence = false;
+    for line in text.split_inclusive('\n') {
+        if line.trim_start().starts_with("```") {
+            in_fence = !in_fence;
+            output.push_str(line);
+        } else if in_fence {
+            output.push_str(line);
+        } else {
+            rewrite_line(line, mode, map, &mut output);
+        }
+    }
+    output
+}
+
+fn is_login_char(c: char) -> bool {
+    c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '.' | '/')
+}
+
+fn rewrite_line(line: &str, mode: MentionMode, map: &HashMap<String, String>, output: &mut String) {
+    let mut in_code = false;
+    let mut previous: Option<char> = None;
+    let mut rest = line;
+    while let Some(c) = rest.chars().next() {
+        rest = &rest[c.len_utf8()..];
+        let starts_mention = c == '@'
+            && !in_code
+            && !previous.is_some_and(|p| is_login_char(p) || p == '`' || p == '@');
+        if !starts_mention {
+            if c == '`' {
+                in_code = !in_code;
+            }
+            output.push(c);
+            previous = Some(c);
+            continue;
+        }
+        let end = rest.find(|c: char| !is_login_char(c)).unwrap_or(rest.len());
+        // A sentence may end right after the name
+        let login = rest[..end].trim_end_matches(['.', '/']);
+        if login.is_empty() {
+            output.push(c);
+            previous = Some(c);
+            continue;
+        }
+        match (mode, map.get(&login.to_lowercase())) {
+            (MentionMode::Translate, Some(target)) => {
+                output.push('@');
+                output.push_str(target);
+            }
+            _ => output.push_str(login),
+        }
+        rest = &rest[login.len()..];
+        previous = login.chars().next_back();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn author_map() -> HashMap<String, String> {
+        HashMap::from([("Alice".to_string(), "alice_gc".to_string())])
+    }
+
+    #[test]
+    fn test_translate_mentions() {
+        let map = mention_map(&author_map(), "gitcode");
+        let text = "Thanks @alice and @bob, cc @alice_gc. Mail alice@example.com\n`@alice` stays\n```\n@bob\n```\n";
+        assert_eq!(
+            rewrite_mentions(text, MentionMode::Translate, &map),
+            "Thanks @alice_gc and bob, cc @alice_gc. Mail alice@example.com\n`@alice` stays\n```\n@bob\n```\n"
+        );
+        assert_eq!(rewrite_mentions("@org/team, @Alice", MentionMode::Strip, &map), "org/team, Alice");
+        assert_eq!(rewrite_mentions("@bob", MentionMode::Keep, &map), "@bob");
+
+        let reverse = mention_map(&author_map(), "github");
+        assert_eq!(rewrite_mentions("@alice_gc", MentionMode::Translate, &reverse), "@Alice");
+    }
+}
@@ -0,0 +1,196 @@
+use std::collections::BTreeMap;
+use std::fmt;
+use std::sync::{Mutex, OnceLock};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Coarse failure categories used to label job failure metrics
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum FailureClass {
+    Auth,
+    Network,
+    Conflict,
+    PushRejected,
+    Parse,
+    Disk,
+    Other,
+}
+
+impl FailureClass {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            FailureClass::Auth => "auth",
+            FailureClass::Network => "network",
+            FailureClass::Conflict => "conflict",
+            FailureClass::PushRejected => "push_rejected",
+            FailureClass::Parse => "parse",
+            FailureClass::Disk => "disk",
+            FailureClass::Other => "other",
+        }
+    }
+}
+
+impl fmt::Display for FailureClass {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// Classifies a failure from its error message
+///
+/// Errors are still mostly plain strings from git2, reqwest and serde, so the
+/// classification works on well-known fragments of their messages.
+pub fn classify(message: &str) -> FailureClass {
+    let message = message.to_lowercase();
+    let matches = |needles: &[&str]| needles.iter().any(|needle| message.contains(needle));
+
+    if matches(&["conflict"]) {
+        FailureClass::Conflict
+    } else if matches(&["401", "403", "unauthorized", "forbidden", "authentication", "credentials", "token not set", "bad credentials"]) {
+        FailureClass::Auth
+    } else if matches(&["rejected", "non-fast-forward", "failed to push", "pre-receive hook", "protected branch"]) {
+        FailureClass::PushRejected
+    } else if matches(&["timed out", "timeout", "connection", "dns", "resolve", "network", "tls", "ssl", "502", "503", "504"]) {
+        FailureClass::Network
+    } else if matches(&["parse", "expected value", "missing field", "invalid type", "eof while", "json", "yaml"]) {
+        FailureClass::Parse
+    } else if matches(&["no space", "disk", "directory", "permission denied", "read-only file system", "os error"]) {
+        FailureClass::Disk
+    } else {
+        FailureClass::Other
+    }
+}
+
+#[derive(Default)]
+struct Registry {
+    failures: BTreeMap<(String, FailureClass), u64>,
+    last_failure: BTreeMap<String, u64>,
+    payload_drift: BTreeMap<(String, String, &'static str), u64>,
+    oversized_commits: BTreeMap<(String, &'static str, &'static str), u64>,
+    http_responses: BTreeMap<(String, String), u64>,
+}
+
+fn registry() -> &'static Mutex<Registry> {
+    static REGISTRY: OnceLock<Mutex<Registry>> = OnceLock::new();
+    REGISTRY.get_or_init(|| Mutex::new(Registry::default()))
+}
+
+/// Records a job failure for `repo`, classified from its error message
+pub fn record_failure(repo: &str, message: &str) -> FailureClass {
+    let class = classify(message);
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0);
+
+    let mut registry = registry().lock().unwrap();
+    *registry.failures.entry((repo.to_string(), class)).or_insert(0) += 1;
+    registry.last_failure.insert(repo.to_string(), now);
+    class
+}
+
+/// Counts `count` drifted fields of `kind` (`missing` or `unknown`) in a webhook payload
+pub fn record_payload_drift(platform: &str, event: &str, kind: &'static str, count: u64) {
+    let mut registry = registry().lock().unwrap();
+    *registry.payload_drift.entry((platform.to_string(), event.to_string(), kind)).or_insert(0) += count;
+}
+
+/// Counts a backported commit over a size limit of `kind` (`message` or
+/// `blob`) and what the guards did with it
+pub fn record_oversized_commit(repo: &str, kind: &'static str, action: &'static str) {
+    let mut registry = registry().lock().unwrap();
+    *registry.oversized_commits.entry((repo.to_string(), kind, action)).or_insert(0) += 1;
+}
+
+/// Counts a forge API response from `host` by the HTTP version it came
+/// over; with HTTP/2 a host's calls share one multiplexed connection
+pub fn record_http_response(host: &str, version: reqwest::Version) {
+    let mut registry = registry().lock().unwrap();
+    *registry.http_responses.entry((host.to_string(), format!("{:?}", version))).or_insert(0) += 1;
+}
+
+fn escape_label(value: &str) -> String {
+    value.replace('\\', "\\\\").replace('"', "\\\"").replace('\n', "\\n")
+}
+
+/// Renders all metrics in the Prometheus text exposition format
+pub fn render_prometheus() -> String {
+    let registry = registry().lock().unwrap();
+    let mut output = String::new();
+
+    output.push_str("# HELP webhook_job_failures_total Failed webhook jobs by repository and error class.\n");
+    output.push_str("# TYPE webhook_job_failures_total counter\n");
+    for ((repo, class), count) in &registry.failures {
+        output.push_str(&format!(
+            "webhook_job_failures_total{{repo=\"{}\",class=\"{}\"}} {}\n",
+            escape_label(repo), class, count
+        ));
+    }
+
+    output.push_str("# HELP webhook_job_last_failure_timestamp_seconds Unix time of the last failed job per repository.\n");
+    output.push_str("# TYPE webhook_job_last_failure_timestamp_seconds gauge\n");
+    for (repo, timestamp) in &registry.last_failure {
+        output.push_str(&format!(
+            "webhook_job_last_failure_timestamp_seconds{{repo=\"{}\"}} {}\n",
+            escape_label(repo), timestamp
+        ));
+    }
+
+    output.push_str("# HELP webhook_payload_drift_total Webhook payload fields missing from or new to the recorded schema.\n");
+    output.push_str("# TYPE webhook_payload_drift_total counter\n");
+    for ((platform, event, kind), count) in &registry.payload_drift {
+        output.push_str(&format!(
+            "webhook_payload_drift_total{{platform=\"{}\",event=\"{}\",kind=\"{}\"}} {}\n",
+            escape_label(platform), escape_label(event), kind, count
+        ));
+    }
+
+    output.push_str("# HELP webhook_oversized_commits_total Backported commits over the message or file size limits, by guard action.\n");
+    output.push_str("# TYPE webhook_oversized_commits_total counter\n");
+    for ((repo, kind, action), count) in &registry.oversized_commits {
+        output.push_str(&format!(
+            "webhook_oversized_commits_total{{repo=\"{}\",kind=\"{}\",action=\"{}\"}} {}\n",
+            escape_label(repo), kind, action, count
+        ));
+    }
+
+    output.push_str("# HELP webhook_forge_http_responses_total Forge API responses by host and HTTP version.\n");
+    output.push_str("# TYPE webhook_forge_http_responses_total counter\n");
+    for ((host, version), count) in &registry.http_responses {
+        output.push_str(&format!(
+            "webhook_forge_http_responses_total{{host=\"{}\",version=\"{}\"}} {}\n",
+            escape_label(host), escape_label(version), count
+        ));
+    }
+
+    output.push_str("# HELP webhook_log_lines_dropped_total Log lines dropped because the log writer fell behind.\n");
+    output.push_str("# TYPE webhook_log_lines_dropped_total counter\n");
+    output.push_str(&format!("webhook_log_lines_dropped_total {}\n", crate::utils::logging::dropped_lines()));
+
+    output
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_classify() {
+        assert_eq!(classify("Request failed with status 401 Unauthorized: Bad credentials"), FailureClass::Auth);
+        assert_eq!(classify("Cherry-pick of abc onto main conflicts in 2 files"), FailureClass::Conflict);
+        assert_eq!(classify("failed to push some refs: non-fast-forward"), FailureClass::PushRejected);
+        assert_eq!(classify("error sending request: operation timed out"), FailureClass::Network);
+        assert_eq!(classify("missing field `repository` at line 1 column 2"), FailureClass::Parse);
+        assert_eq!(classify("No space left on device (os error 28)"), FailureClass::Disk);
+        assert_eq!(classify("something odd"), FailureClass::Other);
+    }
+
+    #[test]
+    fn test_render_prometheus() {
+        record_failure("metrics-test-repo", "Bad credentials");
+        record_http_response("api.metrics-test.example", reqwest::Version::HTTP_2);
+        let output = render_prometheus();
+        assert!(output.contains("webhook_forge_http_responses_total{host=\"api.metrics-test.example\",version=\"HTTP/2.0\"} 1"));
+        assert!(output.contains("webhook_job_failures_total{repo=\"metrics-test-repo\",class=\"auth\"} 1"));
+        assert!(output.contains("webhook_job_last_failure_timestamp_seconds{repo=\"metrics-test-repo\"}"));
+    }
+}
@@ -0,0 +1,129 @@
+use std::fs;
+use std::io;
+use std::path::Path;
+use chrono::Utc;
+use log::info;
+
+use crate::utils::state;
+use crate::utils::state_store::StateStore;
+
+/// State document recording the schema version of the state
+pub const VERSION_FILE: &str = "schema_version";
+
+/// One upgrade step of the persistent state
+pub struct Migration {
+    /// Version the state is at after this migration
+    pub version: u32,
+    pub description: &'static str,
+    /// Rewrites the documents of the state directory through the backend
+    pub apply: fn(&dyn StateStore, &Path) -> io::Result<()>,
+}
+
+fn baseline(_store: &dyn StateStore, _state_dir: &Path) -> io::Result<()> {
+    // State written before versioning already matches schema 1
+    Ok(())
+}
+
+/// Migrations in ascending version order; append new steps, never edit shipped ones
+pub const MIGRATIONS: &[Migration] = &[
+    Migration { version: 1, description: "baseline: version the state directory", apply: baseline },
+];
+
+/// Version the current binary writes
+pub fn current_version() -> u32 {
+    MIGRATIONS.last().map(|m| m.version).unwrap_or(0)
+}
+
+/// Reads the recorded schema version, `0` for unversioned state
+pub fn read_version(store: &dyn StateStore, state_dir: &Path) -> io::Result<u32> {
+    match store.load(&state_dir.join(VERSION_FILE))? {
+        Some(contents) => contents.trim().parse().map_err(|_| {
+            io::Error::new(io::ErrorKind::InvalidData, format!("Invalid {}: {:?}", VERSION_FILE, contents.trim()))
+        }),
+        None => Ok(0),
+    }
+}
+
+fn write_version(store: &dyn StateStore, state_dir: &Path, version: u32) -> io::Result<()> {
+    store.save(&state_dir.join(VERSION_FILE), &format!("{}\n", version))
+}
+
+/// Applies every pending migration in `migrations` to the state of
+/// `state_dir` held by `store`
+///
+/// The existing state is backed up into `<state_dir>/backups/` with
+/// `export_state` before the first step runs. The version is recorded after each step, so an
+/// interrupted upgrade resumes where it stopped. State written by a newer
+/// version of the service is refused rather than silently downgraded.
+///
+/// # Returns
+/// * `io::Result<u32>` - The schema version of the state after migrating
+pub fn run_migrations(store: &dyn StateStore, state_dir: &Path, migrations: &[Migration]) -> io::Result<u32> {
+    fs::create_dir_all(state_dir)?;
+    let mut version = read_version(store, state_dir)?;
+    let target = migrations.last().map(|m| m.version).unwrap_or(0);
+
+    if version > target {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("State schema version {} is newer than supported version {}", version, target),
+        ));
+    }
+    let pending: Vec<&Migration> = migrations.iter().filter(|m| m.version > version).collect();
+    if pending.is_empty() {
+        info!("State schema is up to date (version {})", version);
+        return Ok(version);
+    }
+
+    let backups = state_dir.join("backups");
+    fs::create_dir_all(&backups)?;
+    let backup = backups.join(format!("state-v{}-{}.tar.gz", version, Utc::now().format("%Y%m%dT%H%M%SZ")));
+    state::export_state(store, state_dir, &backup)?;
+    info!("Backed up state to {:?} before migrating", backup);
+
+    for migration in pending {
+        info!("Migrating state to version {}: {}", migration.version, migration.description);
+        (migration.apply)(store, state_dir)?;
+        write_version(store, state_dir, migration.version)?;
+        version = migration.version;
+    }
+    Ok(version)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::state_store::FileStore;
+
+    fn rename_jobs(store: &dyn StateStore, state_dir: &Path) -> io::Result<()> {
+        let jobs = store.load(&state_dir.join("jobs.json"))?.unwrap_or_default();
+        store.save(&state_dir.join("jobs.json"), &jobs.replace("old", "new"))
+    }
+
+    const TEST_MIGRATIONS: &[Migration] = &[
+        Migration { version: 1, description: "baseline", apply: baseline },
+        Migration { version: 2, description: "rename", apply: rename_jobs },
+    ];
+
+    #[test]
+    fn test_run_migrations_upgrades_and_backs_up() {
+        let dir = tempfile::tempdir().unwrap();
+        fs::write(dir.path().join("jobs.json"), "old").unwrap();
+
+        assert_eq!(run_migrations(&FileStore, dir.path(), TEST_MIGRATIONS).unwrap(), 2);
+        assert_eq!(fs::read_to_string(dir.path().join("jobs.json")).unwrap(), "new");
+        assert_eq!(read_version(&FileStore, dir.path()).unwrap(), 2);
+        assert_eq!(fs::read_dir(dir.path().join("backups")).unwrap().count(), 1);
+
+        // Up-to-date state is left alone
+        assert_eq!(run_migrations(&FileStore, dir.path(), TEST_MIGRATIONS).unwrap(), 2);
+        assert_eq!(fs::read_dir(dir.path().join("backups")).unwrap().count(), 1);
+    }
+
+    #[test]
+    fn test_run_migrations_refuses_newer_state() {
+        let dir = tempfile::tempdir().unwrap();
+        write_version(&FileStore, dir.path(), 5).unwrap();
+        assert!(run_migrations(&FileStore, dir.path(), TEST_MIGRATIONS).is_err());
+    }
+}
@@ -0,0 +1,122 @@
+use std::collections::HashMap;
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+
+use crate::utils::branch::{self, BranchMapping};
+
+/// A reference a mirror push creates, moves or deletes on the target
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+pub struct RefChange {
+    /// Reference on the target
+    #[serde(rename = "ref")]
+    pub refname: String,
+    /// Tip on the target before the push, `None` when the push creates it
+    pub old: Option<String>,
+    /// Tip after the push, `None` when the push deletes it
+    pub new: Option<String>,
+}
+
+impl RefChange {
+    pub fn kind(&self) -> &'static str {
+        match (&self.old, &self.new) {
+            (None, _) => "create",
+            (_, None) => "delete",
+            _ => "update",
+        }
+    }
+}
+
+/// Changes `refspecs` make on a target whose references point at `remote`,
+/// given the local references they push; references already at the pushed
+/// commit are left out
+pub fn ref_changes(refspecs: &[String], local: &HashMap<String, String>, remote: &HashMap<String, String>) -> Vec<RefChange> {
+    refspecs
+        .iter()
+        .filter_map(|refspec| {
+            let (source, destination) = refspec.trim_start_matches('+').split_once(':')?;
+            let new = if source.is_empty() { None } else { Some(local.get(source)?.clone()) };
+            let old = remote.get(destination).cloned();
+            (old != new).then(|| RefChange { refname: destination.to_string(), old, new })
+        })
+        .collect()
+}
+
+/// Whether `text` matches `pattern`, where `*` matches any run of characters
+/// (including `/`) and everything else matches literally
+pub fn glob_match(pattern: &str, text: &str) -> bool {
+    let Some((first, rest)) = pattern.split_once('*') else { return pattern == text };
+    let Some(mut remaining) = text.strip_prefix(first) else { return false };
+    let mut parts: Vec<&str> = rest.split('*').collect();
+    let last = parts.pop().unwrap_or("");
+    for part in parts {
+        match remaining.find(part) {
+            Some(index) => remaining = &remaining[index + part.len()..],
+            None => return false,
+        }
+    }
+    remaining.len() >= last.len() && remaining.ends_with(last)
+}
+
+/// Whether `branch` is internal-only according to `patterns` (e.g. `security/*`)
+pub fn is_internal(branch: &str, patterns: &[String]) -> bool {
+    patterns.iter().any(|pattern| glob_match(pattern.trim(), branch))
+}
+
+/// Push refspecs mirroring the branches and tags in `refs`, renaming
+/// branches according to `branch_map`
+///
+/// Every mirror push goes through here, so internal-only branches are never
+/// sent to a target, whatever wildcard selected them.
+pub fn mirror_refspecs<'a>(refs: impl IntoIterator<Item = &'a str>, internal: &[String], branch_map: &[BranchMapping]) -> Vec<String> {
+    refs.into_iter()
+        .filter(|name| match name.strip_prefix("refs/heads/") {
+            Some(branch) => !is_internal(branch, internal),
+            None => name.starts_with("refs/tags/"),
+        })
+        .map(|name| match name.strip_prefix("refs/heads/") {
+            Some(branch) => format!("+{}:refs/heads/{}", name, branch::map_branch(branch, branch_map)),
+            None => format!("+{}:{}", name, name),
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_internal_branches_are_left_out() {
+        assert!(glob_match("security/*", "security/cve-1"));
+        assert!(glob_match("*-internal", "release-1.0-internal"));
+        assert!(glob_match("a*b*c", "a-b-b-c"));
+        assert!(!glob_match("a*b*c", "a-c-b"));
+        assert!(!glob_match("release", "release-1.0"));
+
+        let internal = vec!["security/*".to_string(), "*-internal".to_string()];
+        let refs = ["refs/heads/main", "refs/heads/security/cve-1", "refs/heads/release-internal", "refs/tags/v1.0", "refs/remotes/origin/main"];
+        assert_eq!(mirror_refspecs(refs, &internal, &[]), vec!["+refs/heads/main:refs/heads/main", "+refs/tags/v1.0:refs/tags/v1.0"]);
+
+        let branch_map = vec![BranchMapping { source: "release/*".to_string(), target: "rel-*".to_string() }];
+        assert_eq!(
+            mirror_refspecs(["refs/heads/release/1.2", "refs/tags/release/1.2"], &[], &branch_map),
+            vec!["+refs/heads/release/1.2:refs/heads/rel-1.2", "+refs/tags/release/1.2:refs/tags/release/1.2"]
+        );
+    }
+
+    #[test]
+    fn test_ref_changes_of_a_push() {
+        let map = |pairs: &[(&str, &str)]| pairs.iter().map(|(name, oid)| (name.to_string(), oid.to_string())).collect::<HashMap<_, _>>();
+        let local = map(&[("refs/heads/main", "b"), ("refs/heads/dev", "c"), ("refs/tags/v1", "d")]);
+        let remote = map(&[("refs/heads/main", "a"), ("refs/tags/v1", "d"), ("refs/heads/old", "e")]);
+        let refspecs: Vec<String> = ["+refs/heads/main:refs/heads/main", "+refs/heads/dev:refs/heads/dev", "+refs/tags/v1:refs/tags/v1", ":refs/heads/old"]
+            .iter()
+            .map(|refspec| refspec.to_string())
+            .collect();
+
+        let changes = ref_changes(&refspecs, &local, &remote);
+        let summary: Vec<(&str, &str)> = changes.iter().map(|change| (change.kind(), change.refname.as_str())).collect();
+        assert_eq!(summary, vec![("update", "refs/heads/main"), ("create", "refs/heads/dev"), ("delete", "refs/heads/old")]);
+        assert_eq!(changes[0].old.as_deref(), Some("a"));
+        assert_eq!(changes[0].new.as_deref(), Some("b"));
+    }
+}
@@ -0,0 +1,414 @@
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use std::sync::{Mutex, OnceLock};
+use std::time::Duration;
+use chrono::{DateTime, Utc};
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+use uuid::Uuid;
+use log::{error, info};
+
+use crate::error::{Error, Result};
+use crate::utils::config::{self, RepoConfig};
+use crate::utils::gitcode::{self, RepoMetadata};
+use crate::utils::remote_url::RemoteUrl;
+use crate::utils::{branch, git, ha, jobs, mirror, state, state_store};
+
+/// Shortest accepted `interval_secs`
+pub const MIN_INTERVAL_SECS: u64 = 60;
+
+/// Full mirror of a repository to its `target_repo` on a fixed interval, so
+/// that the target catches up on pushes whose webhooks were missed
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct MirrorSchedule {
+    /// Seconds between two syncs, plus up to a tenth of it of jitter
+    pub interval_secs: u64,
+    /// Repository mirrored (default: the GitHub repository of the entry)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub source: Option<String>,
+    /// Also copy the description, homepage, topics and default branch of
+    /// the source to the target after each sync
+    #[serde(default = "config::default_true")]
+    pub metadata: bool,
+}
+
+impl MirrorSchedule {
+    pub fn interval(&self) -> Duration {
+        Duration::from_secs(self.interval_secs)
+    }
+}
+
+/// Repository mirrored to the `target_repo` of `repo_config`: the `source`
+/// of its schedule, or its GitHub repository
+pub fn source_url(repo_config: &RepoConfig) -> String {
+    repo_config
+        .mirror
+        .as_ref()
+        .and_then(|schedule| schedule.source.clone())
+        .unwrap_or_else(|| format!("https://github.com/{}/{}.git", repo_config.namespace, repo_config.repo_name))
+}
+
+/// Random delay of up to a tenth of `interval`, so that repositories sharing
+/// an interval do not all sync at once
+pub fn jitter(interval: Duration) -> Duration {
+    interval.mul_f64(rand::thread_rng().gen_range(0.0..=0.1))
+}
+
+/// Last scheduled sync of one repository
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+pub struct SyncStatus {
+    pub interval_secs: u64,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub next_sync: Option<DateTime<Utc>>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub last_started: Option<DateTime<Utc>>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub last_finished: Option<DateTime<Utc>>,
+    /// Job of the last sync
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub last_job: Option<Uuid>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub succeeded: Option<bool>,
+    /// References the last successful sync changed on the target
+    #[serde(default)]
+    pub changed: usize,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+    /// Last time the target metadata was checked against the source
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub metadata_synced: Option<DateTime<Utc>>,
+    /// Why the last metadata sync failed; it does not fail the mirror
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub metadata_error: Option<String>,
+}
+
+/// Sync status of every scheduled repository, persisted as JSON in
+/// `mirror_sync.json`
+pub struct SyncStore {
+    path: PathBuf,
+    repos: BTreeMap<String, SyncStatus>,
+}
+
+impl SyncStore {
+    pub fn open(path: &Path) -> SyncStore {
+        let repos = match state_store::read_document(path) {
+            Ok(Some(contents)) => serde_json::from_str(&contents).unwrap_or_else(|e| {
+                error!("Failed to parse mirror sync status {:?}, starting empty: {}", path, e);
+                BTreeMap::new()
+            }),
+            _ => BTreeMap::new(),
+        };
+        let mut store = SyncStore { path: path.to_path_buf(), repos };
+        // Syncs that were running when the service stopped will never finish
+        let interrupted: Vec<String> = store.repos.keys().filter(|repo| store.running(repo).is_some()).cloned().collect();
+        for repo in interrupted {
+            let started = store.repos[&repo].last_started.unwrap_or_default();
+            store.finish(&repo, Err("Interrupted by a restart".to_string()), started);
+        }
+        store
+    }
+
+    fn save(&self) {
+        let result = serde_json::to_string_pretty(&self.repos)
+            .map_err(std::io::Error::from)
+            .and_then(|contents| state_store::write_document(&self.path, &contents));
+        if let Err(e) = result {
+            error!("Failed to persist mirror sync status {:?}: {}", self.path, e);
+        }
+    }
+
+    /// Records when the next sync of `repo` is due
+    pub fn schedule(&mut self, repo: &str, interval: Duration, next_sync: DateTime<Utc>) {
+        let status = self.repos.entry(repo.to_string()).or_default();
+        status.interval_secs = interval.as_secs();
+        status.next_sync = Some(next_sync);
+        self.save();
+    }
+
+    pub fn begin(&mut self, repo: &str, job_id: Uuid, now: DateTime<Utc>) {
+        let status = self.repos.entry(repo.to_string()).or_default();
+        status.next_sync = None;
+        status.last_started = Some(now);
+        status.last_job = Some(job_id);
+        self.save();
+    }
+
+    /// Records the outcome of the sync of `repo`: the number of references
+    /// it changed, or why it failed
+    pub fn finish(&mut self, repo: &str, result: Result<usize, String>, now: DateTime<Utc>) {
+        let status = self.repos.entry(repo.to_string()).or_default();
+        status.last_finished = Some(now);
+        status.succeeded = Some(result.is_ok());
+        match result {
+            Ok(changed) => {
+                status.changed = changed;
+                status.error = None;
+            }
+            Err(e) => status.error = Some(e),
+        }
+        self.save();
+    }
+
+    /// Records the outcome of the metadata sync of `repo`
+    pub fn finish_metadata(&mut self, repo: &str, result: std::result::Result<(), String>, now: DateTime<Utc>) {
+        let status = self.repos.entry(repo.to_string()).or_default();
+        status.metadata_synced = Some(now);
+        status.metadata_error = result.err();
+        self.save();
+    }
+
+    /// Job of the sync of `repo` that is still running, if any
+    pub fn running(&self, repo: &str) -> Option<Uuid> {
+        let status = self.repos.get(repo)?;
+        let started = status.last_started?;
+        status.last_finished.is_none_or(|finished| finished < started).then_some(status.last_job?)
+    }
+
+    /// Stops tracking a repository whose schedule was removed
+    pub fn remove(&mut self, repo: &str) {
+        if self.repos.remove(repo).is_some() {
+            self.save();
+        }
+    }
+
+    pub fn list(&self) -> &BTreeMap<String, SyncStatus> {
+        &self.repos
+    }
+}
+
+/// The process-wide mirror sync status
+pub fn store() -> &'static Mutex<SyncStore> {
+    static STORE: OnceLock<Mutex<SyncStore>> = OnceLock::new();
+    STORE.get_or_init(|| Mutex::new(SyncStore::open(&state::state_dir().join("mirror_sync.json"))))
+}
+
+/// Current schedule of `repo`, read from the config file so that changes
+/// apply from the next sync on
+fn current_schedule(repo: &str) -> Result<Option<(RepoConfig, MirrorSchedule)>> {
+    Ok(config::load_repo_config(repo)?.and_then(|repo_config| {
+        let schedule = repo_config.mirror.clone()?;
+        Some((repo_config, schedule))
+    }))
+}
+
+/// Metadata the target should have: that of the source, with the default
+/// branch renamed by `branch_map` and left alone when it is internal
+pub fn target_metadata(source: &RepoMetadata, repo_config: &RepoConfig) -> RepoMetadata {
+    let default_branch = source
+        .default_branch
+        .as_ref()
+        .filter(|name| !mirror::is_internal(name, &repo_config.internal_branches))
+        .map(|name| branch::map_branch(name, &repo_config.branch_map));
+    RepoMetadata { default_branch, ..source.clone() }
+}
+
+/// `(platform, namespace, repo_name)` of a remote, for the forge APIs
+fn api_repo(url: &str) -> Result<(&'static str, String, String)> {
+    let remote = RemoteUrl::parse(url)?;
+    let (namespace, name) = remote
+        .namespace_and_name()
+        .ok_or_else(|| Error::config(format!("{} has no namespace", url)))?;
+    Ok((remote.platform(), namespace.to_string(), name.to_string()))
+}
+
+/// Copies the description, homepage, topics and default branch of the
+/// source of `repo_config` to its target, only writing when they differ;
+/// returns whether the target was updated
+pub fn sync_metadata(repo_config: &RepoConfig) -> Result<bool> {
+    let (source_platform, source_namespace, source_name) = api_repo(&source_url(repo_config))?;
+    let (target_platform, target_namespace, target_name) = api_repo(&repo_config.target_repo)?;
+    let source = gitcode::get_repo_metadata(git::api_base_url(source_platform), &source_namespace, &source_name, source_platform)?;
+    let current = gitcode::get_repo_metadata(git::api_base_url(target_platform), &target_namespace, &target_name, target_platform)?;
+    let mut wanted = target_metadata(&source, repo_config);
+    if wanted.default_branch.is_none() {
+        wanted.default_branch = current.default_branch.clone();
+    }
+    if target_platform == "gitlab" {
+        // GitLab projects have no homepage to compare
+        wanted.homepage = current.homepage.clone();
+    }
+    if wanted == current {
+        return Ok(false);
+    }
+    gitcode::update_repo_metadata(git::api_base_url(target_platform), &target_namespace, &target_name, &wanted, target_platform)?;
+    Ok(true)
+}
+
+/// Records a mirror job of `repo` as started, in the job store and the
+/// sync status
+pub fn start_job(repo: &str, repo_config: &RepoConfig) -> Uuid {
+    let platform = crate::utils::remote_url::RemoteUrl::parse(&repo_config.target_repo).map(|remote| remote.platform()).unwrap_or("gitcode");
+    let job_id = jobs::store().lock().unwrap().start(platform, repo, "mirror");
+    store().lock().unwrap().begin(repo, job_id, Utc::now());
+    job_id
+}
+
+/// Mirrors `repo` as the job `job_id` from `start_job`, recording the
+/// outcome in the sync status
+pub fn sync(repo: &str, repo_config: &RepoConfig, job_id: Uuid) {
+    let source = source_url(repo_config);
+    info!("Mirroring {} from {} to {} (job {})", repo, source, repo_config.target_repo, job_id);
+
+    let result = tempfile::tempdir().map_err(Into::into).and_then(|workdir| {
+        git::mirror_repository(&source, &repo_config.target_repo, workdir.path(), &repo_config.internal_branches, &repo_config.branch_map, job_id, false)
+    });
+    let result = match result {
+        Ok(changes) => {
+            info!("Mirror of {} changed {} references (job {})", repo, changes.len(), job_id);
+            jobs::store().lock().unwrap().finish(job_id, Ok(()));
+            Ok(changes.len())
+        }
+        Err(e) => {
+            error!("Mirror of {} failed (job {}): {}", repo, job_id, e.details());
+            let mut jobs = jobs::store().lock().unwrap();
+            jobs.update(job_id, |job| job.error_kind = Some(e.kind().to_string()));
+            jobs.finish(job_id, Err(e.to_string()));
+            Err(e.to_string())
+        }
+    };
+    let synced = result.is_ok();
+    store().lock().unwrap().finish(repo, result, Utc::now());
+
+    // The default branch must exist on the target before it can be selected
+    if synced && repo_config.mirror.as_ref().is_none_or(|schedule| schedule.metadata) {
+        let result = match sync_metadata(repo_config) {
+            Ok(updated) => {
+                if updated {
+                    info!("Updated the metadata of {} from {}", repo_config.target_repo, source);
+                }
+                Ok(())
+            }
+            Err(e) => {
+                error!("Metadata sync of {} failed (job {}): {}", repo, job_id, e.details());
+                Err(e.to_string())
+            }
+        };
+        store().lock().unwrap().finish_metadata(repo, result, Utc::now());
+    }
+}
+
+/// Starts one task per repository with a `mirror` schedule in the config
+/// file; a task ends when its schedule is removed, and repositories given
+/// a schedule later start syncing after a restart
+///
+/// Disabled repositories, and standby instances of an HA pair, skip their
+/// syncs.
+pub fn spawn_scheduler() {
+    let config = match config::read_config(config::CONFIG_FILE) {
+        Ok(config) => config,
+        Err(e) => {
+            error!("Scheduled mirrors disabled, failed to read {}: {}", config::CONFIG_FILE, e);
+            return;
+        }
+    };
+    for (repo, repo_config) in config.repos {
+        let Some(schedule) = repo_config.mirror.clone() else { continue };
+        info!("Mirroring {} every {}s", repo, schedule.interval_secs);
+        tokio::spawn(async move {
+            let mut current = (repo_config, schedule);
+            let mut delay = jitter(current.1.interval());
+            loop {
+                let next_sync = Utc::now() + chrono::Duration::from_std(delay).unwrap_or_default();
+                store().lock().unwrap().schedule(&repo, current.1.interval(), next_sync);
+                tokio::time::sleep(delay).await;
+
+                match current_schedule(&repo) {
+                    Ok(Some(latest)) => current = latest,
+                    Ok(None) => {
+                        info!("Mirror schedule of {} removed, stopping", repo);
+                        store().lock().unwrap().remove(&repo);
+                        return;
+                    }
+                    // A config being edited keeps the previous schedule
+                    Err(e) => error!("Failed to read config for the mirror of {}: {}", repo, e),
+                }
+                let (repo_config, schedule) = current.clone();
+                delay = schedule.interval() + jitter(schedule.interval());
+                if !repo_config.enabled || !ha::should_process_locally(ha::settings()) {
+                    continue;
+                }
+                if let Some(running) = store().lock().unwrap().running(&repo) {
+                    info!("Mirror job {} of {} still running, skipping the scheduled sync", running, repo);
+                    continue;
+                }
+                let name = repo.clone();
+                let job_id = start_job(&repo, &repo_config);
+                if let Err(e) = tokio::task::spawn_blocking(move || sync(&name, &repo_config, job_id)).await {
+                    error!("Mirror worker of {} failed: {}", repo, e);
+                }
+            }
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_sync_status_is_kept_across_restarts() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("mirror_sync.json");
+        let now: DateTime<Utc> = "2024-06-01T08:00:00Z".parse().unwrap();
+        let job = Uuid::new_v4();
+
+        let mut store = SyncStore::open(&path);
+        store.schedule("repo", Duration::from_secs(3600), now);
+        store.begin("repo", job, now);
+        store.finish("repo", Ok(3), now);
+        let later = now + chrono::Duration::minutes(1);
+        store.begin("repo", job, later);
+        assert_eq!(store.running("repo"), Some(job));
+        assert_eq!(SyncStore::open(&path).list()["repo"].error.as_deref(), Some("Interrupted by a restart"));
+        store.finish("repo", Err("Push rejected for: main".to_string()), later);
+
+        assert_eq!(store.running("repo"), None);
+        let status = SyncStore::open(&path).list()["repo"].clone();
+        assert_eq!(status.interval_secs, 3600);
+        assert_eq!(status.last_job, Some(job));
+        assert_eq!(status.succeeded, Some(false));
+        assert_eq!(status.changed, 3);
+        assert_eq!(status.error.as_deref(), Some("Push rejected for: main"));
+
+        let interval = Duration::from_secs(600);
+        assert!((0..100).map(|_| jitter(interval)).all(|delay| delay <= Duration::from_secs(60)));
+
+        let mut repo_config = RepoConfig::new("https://gitcode.com/mirror/repo.git", "org", "repo");
+        assert_eq!(source_url(&repo_config), "https://github.com/org/repo.git");
+        repo_config.mirror = Some(MirrorSchedule { interval_secs: 600, source: Some("https://gitlab.com/org/repo.git".to_string()), metadata: true });
+        assert_eq!(source_url(&repo_config), "https://gitlab.com/org/repo.git");
+        let schedule: MirrorSchedule = serde_json::from_str(r#"{"interval_secs": 600}"#).unwrap();
+        assert!(schedule.metadata);
+    }
+
+    #[test]
+    fn test_target_metadata_follows_the_branch_map() {
+        let source = gitcode::repo_metadata(&serde_json::json!({
+            "description": "TLS library",
+            "homepage": "https://example.org",
+            "topics": ["tls", "crypto"],
+            "default_branch": "main",
+        }));
+        assert_eq!(source.topics, vec!["crypto", "tls"]);
+        let gitlab = gitcode::repo_metadata(&serde_json::json!({ "description": null, "tag_list": ["tls"] }));
+        assert_eq!(gitlab, RepoMetadata { topics: vec!["tls".to_string()], ..RepoMetadata::default() });
+
+        let mut repo_config = RepoConfig::new("https://gitcode.com/mirror/repo.git", "org", "repo");
+        assert_eq!(target_metadata(&source, &repo_config), source);
+        repo_config.branch_map = vec![branch::BranchMapping { source: "main".to_string(), target: "master".to_string() }];
+        assert_eq!(target_metadata(&source, &repo_config).default_branch.as_deref(), Some("master"));
+        repo_config.internal_branches = vec!["ma*".to_string()];
+        let metadata = target_metadata(&source, &repo_config);
+        assert_eq!(metadata.default_branch, None);
+        assert_eq!(metadata.description, "TLS library");
+
+        let dir = tempfile::tempdir().unwrap();
+        let mut store = SyncStore::open(&dir.path().join("mirror_sync.json"));
+        let now: DateTime<Utc> = "2024-06-01T08:00:00Z".parse().unwrap();
+        store.finish_metadata("repo", Err("Request failed with status 403 Forbidden".to_string()), now);
+        store.finish_metadata("repo", Ok(()), now);
+        assert_eq!(store.list()["repo"].metadata_synced, Some(now));
+        assert_eq!(store.list()["repo"].metadata_error, None);
+    }
+}
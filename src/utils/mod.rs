@@ -5,5 +5,61 @@ pub mod file;
 pub mod config;
 pub mod hmac;
 pub mod aes_cbc;
+pub mod aes_gcm;
 pub mod hash;
 pub mod logging;
+pub mod state;
+pub mod freeze;
+pub mod conflict;
+pub mod metrics;
+pub mod secrets;
+pub mod dco;
+pub mod remote_url;
+pub mod onboard;
+pub mod service_key;
+pub mod jobs;
+pub mod archive;
+pub mod templates;
+pub mod command;
+pub mod retry;
+pub mod migrations;
+pub mod github_graphql;
+pub mod repo_cache;
+pub mod ha;
+pub mod dlq;
+pub mod privileges;
+pub mod patches;
+pub mod branch;
+pub mod paused;
+pub mod webhook_secrets;
+pub mod clock;
+pub mod body;
+pub mod mentions;
+pub mod stats;
+pub mod backports;
+pub mod http_headers;
+pub mod status_labels;
+pub mod comment_queue;
+pub mod connectivity;
+pub mod repo_health;
+pub mod payload_drift;
+pub mod token_scopes;
+pub mod state_store;
+pub mod conflict_owner;
+pub mod commit_guard;
+pub mod mirror;
+pub mod s3_export;
+pub mod deadline;
+pub mod notes;
+pub mod http_client;
+pub mod payload_signing;
+pub mod gitlab;
+pub mod api_tokens;
+pub mod audit;
+pub mod outbox;
+pub mod deliveries;
+pub mod redact;
+pub mod patch_id;
+pub mod reopened;
+pub mod canary;
+pub mod mirror_schedule;
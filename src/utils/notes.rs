@@ -0,0 +1,136 @@
+use std::path::Path;
+use git2::{FetchOptions, Oid, PushOptions, Repository};
+use log::{info, warn};
+
+use crate::utils::git;
+
+/// Notes ref holding the backport status of original commits
+pub const NOTES_REF: &str = "refs/notes/backports";
+
+/// Attempts at publishing the notes when another job pushed them concurrently
+const MAX_ATTEMPTS: u32 = 3;
+
+/// Adds the line recording the backport of a commit onto `branch` as
+/// `commit` to its note; `None` when the note already has it
+pub fn append_backport(existing: Option<&str>, branch: &str, commit: &str) -> Option<String> {
+    let line = format!("Backported to {}: {}", branch, commit);
+    let existing = existing.unwrap_or("").trim_end();
+    if existing.lines().any(|existing_line| existing_line.trim() == line) {
+        return None;
+    }
+    Some(if existing.is_empty() { format!("{}\n", line) } else { format!("{}\n{}\n", existing, line) })
+}
+
+/// Records the `(branch, original commit, backport commit)` triples in the
+/// notes of the original commits, returning the number of notes written
+pub fn annotate(repo: &Repository, picked: &[(String, String, Oid)]) -> Result<usize, git2::Error> {
+    let signature = repo.signature()?;
+    let mut written = 0;
+    for (branch, original, backport) in picked {
+        let original = Oid::from_str(original)?;
+        let existing = repo.find_note(Some(NOTES_REF), original).ok();
+        let Some(note) = append_backport(existing.as_ref().and_then(|note| note.message()), branch, &backport.to_string()) else { continue };
+        repo.note(&signature, &signature, Some(NOTES_REF), original, &note, true)?;
+        written += 1;
+    }
+    Ok(written)
+}
+
+/// Annotates the original commits and pushes `refs/notes/backports` to
+/// `remote_name`, so `git log --notes=backports` shows where they went
+///
+/// The remote's notes are fetched first and the push is retried on top of
+/// them when another job updated them in between.
+pub fn publish(repo_path: &Path, remote_name: &str, platform: &str, picked: &[(String, String, Oid)]) -> Result<usize, git2::Error> {
+    let repo = Repository::open(repo_path)?;
+    let mut remote = repo.find_remote(remote_name)?;
+    let refspec = format!("{}:{}", NOTES_REF, NOTES_REF);
+    for attempt in 1..=MAX_ATTEMPTS {
+        let mut fetch_options = FetchOptions::new();
+        fetch_options.remote_callbacks(git::platform_callbacks(platform));
+        if let Err(e) = remote.fetch(&[format!("+{}", refspec)], Some(&mut fetch_options), None) {
+            warn!("Failed to fetch {} from {}, starting new notes: {}", NOTES_REF, remote_name, e);
+        }
+        let written = annotate(&repo, picked)?;
+        if written == 0 {
+            return Ok(0);
+        }
+
+        let rejected = std::cell::RefCell::new(None);
+        let mut callbacks = git::platform_callbacks(platform);
+        callbacks.push_update_reference(|_, status| {
+            *rejected.borrow_mut() = status.map(str::to_string);
+            Ok(())
+        });
+        let mut push_options = PushOptions::new();
+        push_options.remote_callbacks(callbacks);
+        let pushed = remote.push(&[refspec.as_str()], Some(&mut push_options));
+        drop(push_options);
+        let rejected = rejected.into_inner();
+        match pushed {
+            Ok(()) if rejected.is_none() => {
+                info!("Pushed backport notes for {} commits to {}", written, remote_name);
+                return Ok(written);
+            }
+            Ok(()) => warn!("Push of {} rejected (attempt {}): {}", NOTES_REF, attempt, rejected.unwrap_or_default()),
+            Err(e) if e.code() == git2::ErrorCode::NotFastForward => warn!("Push of {} rejected (attempt {}): {}", NOTES_REF, attempt, e),
+            Err(e) => return Err(e),
+        }
+    }
+    Err(git2::Error::from_str(&format!("{} kept changing on {}; notes not pushed", NOTES_REF, remote_name)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn commit(repo: &Repository, message: &str) -> Oid {
+        let tree = repo.find_tree(repo.index().unwrap().write_tree().unwrap()).unwrap();
+        let sig = repo.signature().unwrap();
+        let parents: Vec<git2::Commit> = repo.head().ok().and_then(|head| head.peel_to_commit().ok()).into_iter().collect();
+        let parents: Vec<&git2::Commit> = parents.iter().collect();
+        repo.commit(Some("HEAD"), &sig, &sig, message, &tree, &parents).unwrap()
+    }
+
+    fn clone(origin: &Path, path: &Path) -> Repository {
+        let repo = Repository::clone(origin.to_str().unwrap(), path).unwrap();
+        let mut config = repo.config().unwrap();
+        config.set_str("user.name", "bot").unwrap();
+        config.set_str("user.email", "bot@example.com").unwrap();
+        repo
+    }
+
+    #[test]
+    fn test_notes_accumulate_on_the_remote() {
+        assert_eq!(append_backport(Some("Backported to a: 1\n"), "a", "1"), None);
+        assert_eq!(append_backport(None, "a", "1").unwrap(), "Backported to a: 1\n");
+
+        let dir = tempfile::tempdir().unwrap();
+        let origin_path = dir.path().join("origin.git");
+        let seed = Repository::init(dir.path().join("seed")).unwrap();
+        seed.config().unwrap().set_str("user.name", "bot").unwrap();
+        seed.config().unwrap().set_str("user.email", "bot@example.com").unwrap();
+        let original = commit(&seed, "fix");
+        Repository::init_bare(&origin_path).unwrap();
+        seed.remote("origin", origin_path.to_str().unwrap()).unwrap()
+            .push(&["refs/heads/master:refs/heads/master"], None).unwrap();
+
+        let first = clone(&origin_path, &dir.path().join("first"));
+        let backport = commit(&first, "fix (backport)");
+        let picked = vec![("release-1.0".to_string(), original.to_string(), backport)];
+        assert_eq!(publish(first.path().parent().unwrap(), "origin", "gitcode", &picked).unwrap(), 1);
+        // Nothing new to record
+        assert_eq!(publish(first.path().parent().unwrap(), "origin", "gitcode", &picked).unwrap(), 0);
+
+        let second = clone(&origin_path, &dir.path().join("second"));
+        let picked = vec![("release-2.0".to_string(), original.to_string(), backport)];
+        assert_eq!(publish(second.path().parent().unwrap(), "origin", "gitcode", &picked).unwrap(), 1);
+
+        let origin = Repository::open_bare(&origin_path).unwrap();
+        let note = origin.find_note(Some(NOTES_REF), original).unwrap();
+        assert_eq!(
+            note.message().unwrap(),
+            format!("Backported to release-1.0: {}\nBackported to release-2.0: {}\n", backport, backport)
+        );
+    }
+}
@@ -0,0 +1,214 @@
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+use serde_json::Value;
+use log::{info, error};
+
+use crate::utils::{config, git, gitcode, webhook_secrets};
+use crate::utils::config::{GitIdentity, RepoConfig};
+use crate::utils::gitcode::NewRepository;
+use crate::utils::remote_url::RemoteUrl;
+
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct OnboardRequest {
+    /// Platform hosting the source repository (`github` or `gitcode`)
+    pub platform: String,
+    pub namespace: String,
+    pub repo_name: String,
+    pub target_repo: String,
+    /// Public URL of this service's webhook endpoint for the source platform
+    pub webhook_url: String,
+    /// Create the target repository when it does not exist yet
+    #[serde(default)]
+    pub create_target: Option<TargetRepoSpec>,
+    /// Identity the bot commits as; required unless the repository's group sets one
+    #[serde(default)]
+    pub identity: Option<GitIdentity>,
+}
+
+/// Metadata of a target repository created during onboarding; unset fields
+/// are copied from the source repository
+#[derive(Debug, Clone, Default, Deserialize, ToSchema)]
+pub struct TargetRepoSpec {
+    pub description: Option<String>,
+    pub private: Option<bool>,
+    pub default_branch: Option<String>,
+}
+
+#[derive(Debug, Default, Serialize, ToSchema)]
+pub struct OnboardReport {
+    pub source_access: bool,
+    pub target_access: bool,
+    pub target_created: bool,
+    pub webhook_registered: bool,
+    pub config_written: bool,
+    pub dry_run_refs: Option<usize>,
+    pub errors: Vec<String>,
+    /// Problems that did not stop onboarding
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub warnings: Vec<String>,
+}
+
+impl OnboardReport {
+    pub fn is_success(&self) -> bool {
+        self.errors.is_empty()
+    }
+}
+
+/// Metadata of the target repository: `spec` where set, the source's otherwise
+pub fn new_target_repository(name: &str, spec: &TargetRepoSpec, source: &Value) -> NewRepository {
+    NewRepository {
+        name: name.to_string(),
+        description: spec
+            .description
+            .clone()
+            .or_else(|| source["description"].as_str().map(str::to_string))
+            .unwrap_or_default(),
+        private: spec.private.or_else(|| source["private"].as_bool()).unwrap_or(true),
+        default_branch: spec
+            .default_branch
+            .clone()
+            .or_else(|| source["default_branch"].as_str().map(str::to_string)),
+    }
+}
+
+/// Onboards a repository in one call
+///
+/// Verifies token access to the source and target repositories, creating the
+/// target first when requested, registers the webhook on the source with a
+/// new random secret kept in the webhook secret store, writes
+/// the config entry and dry-runs a push connection to the target. Stops at the
+/// first failing step.
+pub fn onboard(request: &OnboardRequest) -> OnboardReport {
+    let mut report = OnboardReport::default();
+    info!("Onboarding {}/{} ({}) -> {}", request.namespace, request.repo_name, request.platform, request.target_repo);
+
+    if request.platform != "github" && request.platform != "gitcode" {
+        report.errors.push(format!("Unsupported platform {}", request.platform));
+        return report;
+    }
+
+    let target = match RemoteUrl::parse(&request.target_repo) {
+        Ok(target) => target,
+        Err(e) => {
+            report.errors.push(e.to_string());
+            return report;
+        }
+    };
+
+    // 1. Token access to the source repository
+    let source_base = git::api_base_url(&request.platform);
+    let source = match gitcode::get_repository(source_base, &request.namespace, &request.repo_name, &request.platform) {
+        Ok(source) => {
+            report.source_access = true;
+            source
+        }
+        Err(e) => {
+            report.errors.push(format!("Source repository not accessible: {}", e));
+            return report;
+        }
+    };
+
+    // 2. Token access to the target repository
+    let (target_namespace, target_name) = match target.namespace_and_name() {
+        Some(parts) => parts,
+        None => {
+            report.errors.push(format!("Cannot derive namespace from {}", target));
+            return report;
+        }
+    };
+    let target_platform = target.platform();
+    let target_base = git::api_base_url(target_platform);
+    match gitcode::get_repository(target_base, target_namespace, target_name, target_platform) {
+        Ok(_) => report.target_access = true,
+        Err(e) => match &request.create_target {
+            Some(spec) => {
+                let repository = new_target_repository(target_name, spec, &source);
+                if let Err(e) = gitcode::create_repository(target_base, target_namespace, &repository, target_platform) {
+                    report.errors.push(format!("Failed to create target repository: {}", e));
+                    return report;
+                }
+                report.target_created = true;
+                report.target_access = true;
+                if let (Some(branch), "github") = (&repository.default_branch, target_platform) {
+                    // The branch only exists after the first mirror push, which then becomes the default
+                    report.warnings.push(format!("Default branch {} is set by the first push of that branch", branch));
+                }
+            }
+            None => {
+                report.errors.push(format!("Target repository not accessible: {}", e));
+                return report;
+            }
+        },
+    }
+
+    // 3. Webhook registration on the source, with a secret of its own
+    let key = match webhook_secrets::storage_key() {
+        Ok(key) => key,
+        Err(e) => {
+            report.errors.push(format!("Failed to derive the webhook secret key: {}", e));
+            return report;
+        }
+    };
+    let repo = webhook_secrets::repo_key(&request.platform, &request.namespace, &request.repo_name);
+    let registered = webhook_secrets::provision(&repo, key, |secret| {
+        gitcode::create_webhook(source_base, &request.namespace, &request.repo_name, &request.webhook_url, secret, &request.platform)
+            .map(|_| ())
+    });
+    if let Err(e) = registered {
+        report.errors.push(format!("Failed to register webhook: {}", e));
+        return report;
+    }
+    report.webhook_registered = true;
+
+    // 4. Config entry
+    let result = config::update_config(config::CONFIG_FILE, |config| {
+        let existing = config.repos.get(&request.repo_name).cloned();
+        let mut entry = existing.unwrap_or_else(|| RepoConfig::new(&request.target_repo, &request.namespace, &request.repo_name));
+        entry.target_repo = request.target_repo.clone();
+        if request.identity.is_some() {
+            entry.identity = request.identity.clone();
+        }
+        config.repos.insert(request.repo_name.clone(), entry);
+    });
+    match result {
+        Ok(()) => report.config_written = true,
+        Err(e) => {
+            report.errors.push(format!("Failed to write config: {}", e));
+            return report;
+        }
+    }
+
+    // 5. Dry-run: connect to the target for pushing without sending anything
+    match git::probe_push_access(&request.target_repo) {
+        Ok(refs) => report.dry_run_refs = Some(refs),
+        Err(e) => report.errors.push(format!("Dry-run push connection failed: {}", e)),
+    }
+
+    if !report.is_success() {
+        error!("Onboarding of {} incomplete: {:?}", request.repo_name, report.errors);
+    }
+    report
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_target_metadata_defaults_to_the_source() {
+        let source = serde_json::json!({ "description": "TLS library", "private": false, "default_branch": "main" });
+        let copied = new_target_repository("mirror", &TargetRepoSpec::default(), &source);
+        assert_eq!(copied, NewRepository {
+            name: "mirror".to_string(),
+            description: "TLS library".to_string(),
+            private: false,
+            default_branch: Some("main".to_string()),
+        });
+
+        let spec = TargetRepoSpec { private: Some(true), default_branch: Some("master".to_string()), ..TargetRepoSpec::default() };
+        let overridden = new_target_repository("mirror", &spec, &serde_json::json!({}));
+        assert!(overridden.private);
+        assert_eq!(overridden.description, "");
+        assert_eq!(overridden.default_branch.as_deref(), Some("master"));
+    }
+}
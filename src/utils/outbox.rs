@@ -0,0 +1,248 @@
+use std::cell::RefCell;
+use std::collections::{BTreeMap, HashMap};
+use std::env;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+use uuid::Uuid;
+use log::{info, error};
+
+use crate::models::webhook::ParsedWebhookData;
+use crate::error::{Error, Result};
+use crate::utils::{comment_queue, gitcode, jobs};
+use crate::utils::git::api_base_url;
+use crate::utils::retry::RetryPolicy;
+
+/// Separates the comments of one job when they are posted together
+const COMMENT_SEPARATOR: &str = "\n\n---\n\n";
+
+/// Minimum spacing between two forge API writes from `API_WRITE_INTERVAL_MS`
+/// (default 250)
+pub fn write_interval() -> Duration {
+    let millis = env::var("API_WRITE_INTERVAL_MS")
+        .ok()
+        .and_then(|value| value.parse::<u64>().ok())
+        .unwrap_or(250);
+    Duration::from_millis(millis)
+}
+
+/// The PR a deferred update is for
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub struct PullRequestRef {
+    pub platform: String,
+    pub namespace: String,
+    pub repo: String,
+    pub pr_id: u32,
+}
+
+impl PullRequestRef {
+    /// The PR that triggered the webhook, when it has one
+    pub fn source(webhook_data: &ParsedWebhookData, platform: &str) -> Option<PullRequestRef> {
+        Some(PullRequestRef {
+            platform: platform.to_string(),
+            namespace: webhook_data.namespace.clone(),
+            repo: webhook_data.repo_name.clone(),
+            pr_id: webhook_data.iid?,
+        })
+    }
+}
+
+/// Updates collected for one PR
+#[derive(Debug, Clone, Default)]
+struct PendingUpdates {
+    comments: Vec<String>,
+    /// Whether each label ends up on the PR; a later change replaces an earlier one
+    labels: BTreeMap<String, bool>,
+    label_colors: HashMap<String, String>,
+}
+
+/// One API write of a flushed outbox
+#[derive(Debug, Clone, PartialEq)]
+pub enum Write {
+    Comment { pr: PullRequestRef, body: String },
+    AddLabels { pr: PullRequestRef, labels: Vec<String>, colors: HashMap<String, String> },
+    RemoveLabel { pr: PullRequestRef, label: String },
+}
+
+/// Comments and label updates produced by one job, written when it ends
+#[derive(Debug, Default)]
+pub struct Outbox {
+    job_id: Uuid,
+    updates: BTreeMap<PullRequestRef, PendingUpdates>,
+}
+
+impl Outbox {
+    pub fn new(job_id: Uuid) -> Outbox {
+        Outbox { job_id, updates: BTreeMap::new() }
+    }
+
+    pub fn comment(&mut self, pr: PullRequestRef, body: &str) {
+        self.updates.entry(pr).or_default().comments.push(body.trim_end().to_string());
+    }
+
+    /// Records label changes; a label both added and removed during the job
+    /// keeps only its last change, and `retract` drops earlier additions
+    /// that no longer apply without removing anything from the PR
+    pub fn labels(&mut self, pr: PullRequestRef, add: &[String], remove: &[String], retract: &[String], colors: &HashMap<String, String>) {
+        let pending = self.updates.entry(pr).or_default();
+        for label in retract {
+            if pending.labels.get(label) == Some(&true) {
+                pending.labels.remove(label);
+            }
+        }
+        for label in remove {
+            pending.labels.insert(label.clone(), false);
+        }
+        for label in add {
+            pending.labels.insert(label.clone(), true);
+        }
+        pending.label_colors.extend(colors.iter().map(|(label, color)| (label.clone(), color.clone())));
+    }
+
+    /// The coalesced writes: per PR, one comment carrying every message and
+    /// the job reference, one call adding labels and one per removed label
+    pub fn writes(&self) -> Vec<Write> {
+        let mut writes = Vec::new();
+        for (pr, pending) in &self.updates {
+            if !pending.comments.is_empty() {
+                let body = jobs::with_job_reference(&pending.comments.join(COMMENT_SEPARATOR), &self.job_id.to_string());
+                writes.push(Write::Comment { pr: pr.clone(), body });
+            }
+            let add: Vec<String> = pending.labels.iter().filter(|(_, applied)| **applied).map(|(label, _)| label.clone()).collect();
+            if !add.is_empty() {
+                writes.push(Write::AddLabels { pr: pr.clone(), labels: add, colors: pending.label_colors.clone() });
+            }
+            for (label, _) in pending.labels.iter().filter(|(_, applied)| !**applied) {
+                writes.push(Write::RemoveLabel { pr: pr.clone(), label: label.clone() });
+            }
+        }
+        writes
+    }
+
+    /// Sends the writes, spaced by `write_interval` across all jobs; comments
+    /// that still fail after retries go to the comment queue
+    pub fn flush(&self) {
+        for write in self.writes() {
+            pace(write_interval());
+            let result = RetryPolicy::new().retry_blocking(|_| send(&write), Error::is_transient);
+            let Err(e) = result else { continue };
+            match write {
+                Write::Comment { pr, body } => {
+                    error!("Failed to post comment to PR #{}, queueing it: {}", pr.pr_id, e);
+                    comment_queue::queue().lock().unwrap().enqueue(self.job_id, &pr.platform, &pr.namespace, &pr.repo, pr.pr_id, &body);
+                }
+                Write::AddLabels { pr, labels, .. } => error!("Failed to add labels {:?} to PR #{}: {}", labels, pr.pr_id, e),
+                Write::RemoveLabel { pr, label } => error!("Failed to remove label {} from PR #{}: {}", label, pr.pr_id, e),
+            }
+        }
+    }
+}
+
+fn send(write: &Write) -> Result<()> {
+    match write {
+        Write::Comment { pr, body } => {
+            gitcode::post_comment_on_pr(api_base_url(&pr.platform), &pr.namespace, &pr.repo, pr.pr_id, body, &pr.platform)
+        }
+        Write::AddLabels { pr, labels, colors } => {
+            gitcode::add_labels_to_pr(api_base_url(&pr.platform), &pr.namespace, &pr.repo, pr.pr_id, labels, colors, &pr.platform)
+        }
+        Write::RemoveLabel { pr, label } => {
+            gitcode::remove_label_from_pr(api_base_url(&pr.platform), &pr.namespace, &pr.repo, pr.pr_id, label, &pr.platform)
+        }
+    }
+}
+
+/// Waits until `interval` has passed since the previous write of the process
+fn pace(interval: Duration) {
+    static LAST_WRITE: Mutex<Option<Instant>> = Mutex::new(None);
+    let mut last = LAST_WRITE.lock().unwrap();
+    if let Some(wait) = last.and_then(|at| (at + interval).checked_duration_since(Instant::now())) {
+        std::thread::sleep(wait);
+    }
+    *last = Some(Instant::now());
+}
+
+thread_local! {
+    static CURRENT: RefCell<Option<Outbox>> = const { RefCell::new(None) };
+}
+
+/// Collects a job's updates on the current thread and flushes them when dropped
+pub struct OutboxScope {
+    previous: Option<Outbox>,
+}
+
+impl Drop for OutboxScope {
+    fn drop(&mut self) {
+        let previous = self.previous.take();
+        let outbox = CURRENT.with(|current| std::mem::replace(&mut *current.borrow_mut(), previous));
+        if let Some(outbox) = outbox {
+            info!("Flushing {} deferred updates of job {}", outbox.writes().len(), outbox.job_id);
+            outbox.flush();
+        }
+    }
+}
+
+/// Defers the comments and label updates of `job_id` until the returned
+/// scope is dropped, so a job writes to the forge once per PR at its end
+pub fn start(job_id: Uuid) -> OutboxScope {
+    let previous = CURRENT.with(|current| current.borrow_mut().replace(Outbox::new(job_id)));
+    OutboxScope { previous }
+}
+
+/// Adds a comment to the current job's outbox; false outside a job, where
+/// the caller posts it right away
+pub fn defer_comment(pr: PullRequestRef, body: &str) -> bool {
+    CURRENT.with(|current| match current.borrow_mut().as_mut() {
+        Some(outbox) => {
+            outbox.comment(pr, body);
+            true
+        }
+        None => false,
+    })
+}
+
+/// Adds label changes to the current job's outbox; false outside a job
+pub fn defer_labels(pr: PullRequestRef, add: &[String], remove: &[String], retract: &[String], colors: &HashMap<String, String>) -> bool {
+    CURRENT.with(|current| match current.borrow_mut().as_mut() {
+        Some(outbox) => {
+            outbox.labels(pr, add, remove, retract, colors);
+            true
+        }
+        None => false,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_updates_are_coalesced_per_pr() {
+        let pr = PullRequestRef { platform: "github".to_string(), namespace: "org".to_string(), repo: "repo".to_string(), pr_id: 7 };
+        let job_id = Uuid::new_v4();
+        let mut outbox = Outbox::new(job_id);
+        outbox.comment(pr.clone(), "DCO check failed\n");
+        outbox.comment(pr.clone(), "Conflict on release-1.0");
+        let colors = HashMap::new();
+        outbox.labels(pr.clone(), &["backported: release-1.0".to_string()], &["backport-failed: release-1.0".to_string()], &[], &colors);
+        outbox.labels(pr.clone(), &["backport-failed: release-1.0".to_string()], &["backported: release-1.0".to_string()], &[], &colors);
+        outbox.labels(pr.clone(), &["backport-failed: release-1.2".to_string()], &[], &[], &colors);
+        outbox.labels(pr.clone(), &["backported: release-1.2".to_string()], &[], &["backport-failed: release-1.2".to_string()], &colors);
+
+        let writes = outbox.writes();
+        assert_eq!(writes.len(), 3);
+        assert_eq!(writes[0], Write::Comment {
+            pr: pr.clone(),
+            body: jobs::with_job_reference("DCO check failed\n\n---\n\nConflict on release-1.0", &job_id.to_string()),
+        });
+        assert_eq!(writes[1], Write::AddLabels {
+            pr: pr.clone(),
+            labels: vec!["backport-failed: release-1.0".to_string(), "backported: release-1.2".to_string()],
+            colors,
+        });
+        assert_eq!(writes[2], Write::RemoveLabel { pr, label: "backported: release-1.0".to_string() });
+
+        assert!(!defer_comment(PullRequestRef { platform: String::new(), namespace: String::new(), repo: String::new(), pr_id: 1 }, "x"));
+        let _scope = start(Uuid::new_v4());
+        assert!(defer_labels(PullRequestRef { platform: String::new(), namespace: String::new(), repo: String::new(), pr_id: 1 }, &[], &[], &[], &HashMap::new()));
+    }
+}
@@ -1,8 +1,13 @@
 use crate::models::webhook::{
-    WebhookPayload, ParsedWebhookData, Label, GitHubWebhookPayload,
-    GitCodePushPayload, ParsedPushData
+    WebhookPayload, ParsedWebhookData, Label, GitHubWebhookPayload, GitHubPullRequest,
+    GitLabWebhookPayload, GitCodePushPayload, GitHubPushPayload, ParsedPushData, MergeInfo
 };
+
+/// Login of the bot that merges PRs leaving a GitHub merge queue
+const MERGE_QUEUE_BOT: &str = "github-merge-queue[bot]";
+use serde::Deserialize;
 use serde_json;
+use std::io::Read;
 
 pub fn parse_gitcode_pr_data(json_str: &str) -> Result<ParsedWebhookData, serde_json::Error> {
     // Parse the JSON string into our struct
@@ -14,6 +19,7 @@ pub fn parse_gitcode_pr_data(json_str: &str) -> Result<ParsedWebhookData, serde_
             title: label.title,
             description: label.description,
             r#type: None,
+            source: None,
         }).collect())
         .unwrap_or_default();
     
@@ -26,15 +32,29 @@ pub fn parse_gitcode_pr_data(json_str: &str) -> Result<ParsedWebhookData, serde_
         url: payload.object_attributes.as_ref().and_then(|attrs| attrs.url.clone()),
         repo_name: payload.repository.name,
         repo_url: payload.repository.git_http_url,
-        namespace: payload.project.namespace,
+        namespace: gitcode_namespace(payload.project.namespace, payload.project.path_with_namespace.as_deref()),
         iid: payload.object_attributes.as_ref().and_then(|attrs| attrs.iid),
+        labels_trusted: false,
+        archived: payload.project.archived,
+        merge: None,
     })
 }
 
+/// Namespace of a GitCode project, from its full path when the payload has
+/// one: group-level webhooks report the group's display name as `namespace`
+fn gitcode_namespace(namespace: String, path_with_namespace: Option<&str>) -> String {
+    path_with_namespace
+        .and_then(|path| path.rsplit_once('/'))
+        .map(|(namespace, _)| namespace.to_string())
+        .unwrap_or(namespace)
+}
+
 pub fn parse_github_pr_data(json_str: &str) -> Result<ParsedWebhookData, serde_json::Error> {
     // Parse the JSON string into our GitHub-specific struct
     let payload: GitHubWebhookPayload = serde_json::from_str(json_str)?;
     
+    let merge = merge_info(&payload.pull_request);
+
     // Extract labels with titles and descriptions
     let labels: Vec<Label> = payload.pull_request.labels
         .into_iter()
@@ -42,6 +62,7 @@ pub fn parse_github_pr_data(json_str: &str) -> Result<ParsedWebhookData, serde_j
             title: label.name,
             description: label.description,
             r#type: None,
+            source: None,
         })
         .collect();
     
@@ -63,23 +84,162 @@ pub fn parse_github_pr_data(json_str: &str) -> Result<ParsedWebhookData, serde_j
         repo_url: payload.repository.clone_url,
         namespace,
         iid: payload.pull_request.number,
+        labels_trusted: false,
+        archived: match (payload.repository.archived, payload.repository.disabled) {
+            (None, None) => None,
+            (archived, disabled) => Some(archived.unwrap_or(false) || disabled.unwrap_or(false)),
+        },
+        merge,
+    })
+}
+
+/// Merge state of a GitHub PR; `None` for payloads without a `merged` field
+///
+/// A PR merged by the merge queue bot, or by auto-merge once its checks
+/// passed, counts as queued: its commits may have been rebased or squashed
+/// after the head the PR shows.
+pub fn merge_info(pull_request: &GitHubPullRequest) -> Option<MergeInfo> {
+    let merged = pull_request.merged?;
+    let by_queue = pull_request.merged_by.as_ref().is_some_and(|user| user.login == MERGE_QUEUE_BOT);
+    let auto_merged = pull_request.auto_merge.as_ref().is_some_and(|auto_merge| !auto_merge.is_null());
+    Some(MergeInfo {
+        merged,
+        commit_sha: pull_request.merge_commit_sha.clone(),
+        queued: merged && (by_queue || auto_merged),
+    })
+}
+
+pub fn parse_gitlab_pr_data(json_str: &str) -> Result<ParsedWebhookData, serde_json::Error> {
+    let payload: GitLabWebhookPayload = serde_json::from_str(json_str)?;
+
+    let labels: Vec<Label> = payload.labels
+        .map(|labels| labels.into_iter().map(|label| Label {
+            title: label.title,
+            description: label.description,
+            r#type: None,
+            source: None,
+        }).collect())
+        .unwrap_or_default();
+
+    // The namespace keeps the subgroups of the project path
+    let (namespace, repo_name) = payload.project.path_with_namespace
+        .rsplit_once('/')
+        .unwrap_or(("", &payload.project.path_with_namespace));
+
+    Ok(ParsedWebhookData {
+        labels,
+        event_type: payload.object_kind,
+        action: payload.object_attributes.as_ref().and_then(|attrs| attrs.action.clone()),
+        state: payload.object_attributes.as_ref().and_then(|attrs| attrs.state.clone()),
+        url: payload.object_attributes.as_ref().and_then(|attrs| attrs.url.clone()),
+        repo_name: repo_name.to_string(),
+        repo_url: payload.project.git_http_url,
+        namespace: namespace.to_string(),
+        iid: payload.object_attributes.as_ref().and_then(|attrs| attrs.iid),
+        labels_trusted: false,
+        archived: payload.project.archived,
+        merge: None,
     })
 }
 
 pub fn parse_gitcode_push_data(json_str: &str) -> Result<ParsedPushData, serde_json::Error> {
     // Parse the JSON string into our struct
     let payload: GitCodePushPayload = serde_json::from_str(json_str)?;
-    
+    Ok(push_data_from_payload(payload))
+}
+
+/// Parses a push payload straight from `reader`, without buffering the JSON text
+pub fn parse_gitcode_push_reader<R: Read>(reader: R) -> Result<ParsedPushData, serde_json::Error> {
+    let payload: GitCodePushPayload = serde_json::from_reader(reader)?;
+    Ok(push_data_from_payload(payload))
+}
+
+fn push_data_from_payload(payload: GitCodePushPayload) -> ParsedPushData {
     // Create the parsed data struct
-    Ok(ParsedPushData {
+    ParsedPushData {
+        platform: "gitcode",
         user_name: payload.user_name,
         user_email: payload.user_email,
         commits: payload.commits,
         repo_name: payload.repository.name,
         project_name: payload.project.name,
-        namespace: payload.project.namespace,
+        namespace: gitcode_namespace(payload.project.namespace, payload.project.path_with_namespace.as_deref()),
         branch: payload.git_branch,
-    })
+    }
+}
+
+pub fn parse_github_push_data(json_str: &str) -> Result<ParsedPushData, serde_json::Error> {
+    let payload: GitHubPushPayload = serde_json::from_str(json_str)?;
+    Ok(github_push_data_from_payload(payload))
+}
+
+/// `parse_github_push_data` reading the payload from `reader`
+pub fn parse_github_push_reader<R: Read>(reader: R) -> Result<ParsedPushData, serde_json::Error> {
+    let payload: GitHubPushPayload = serde_json::from_reader(reader)?;
+    Ok(github_push_data_from_payload(payload))
+}
+
+fn github_push_data_from_payload(payload: GitHubPushPayload) -> ParsedPushData {
+    let namespace = payload.repository.full_name
+        .split_once('/')
+        .map(|(namespace, _)| namespace.to_string())
+        .unwrap_or_default();
+    let branch = payload.git_ref.strip_prefix("refs/heads/").unwrap_or(&payload.git_ref).to_string();
+    ParsedPushData {
+        platform: "github",
+        user_name: payload.pusher.name,
+        user_email: payload.pusher.email.unwrap_or_default(),
+        commits: payload.commits,
+        project_name: payload.repository.name.clone(),
+        repo_name: payload.repository.name,
+        namespace,
+        branch,
+    }
+}
+
+pub fn repo_identity(platform: &str, json_str: &str) -> Option<(String, String)> {
+    repo_identity_reader(platform, json_str.as_bytes())
+}
+
+/// Only the fields `repo_identity` needs; everything else is skipped while reading
+#[derive(Deserialize)]
+struct IdentityPayload {
+    repository: Option<IdentityRepository>,
+    project: Option<IdentityProject>,
+}
+
+#[derive(Deserialize)]
+struct IdentityRepository {
+    full_name: Option<String>,
+    name: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct IdentityProject {
+    namespace: Option<String>,
+    path_with_namespace: Option<String>,
+}
+
+/// Same as `repo_identity`, reading the payload from `reader`
+pub fn repo_identity_reader<R: Read>(platform: &str, reader: R) -> Option<(String, String)> {
+    let payload: IdentityPayload = serde_json::from_reader(reader).ok()?;
+    if platform == "gitlab" {
+        let path = payload.project?.path_with_namespace?;
+        let (namespace, repo) = path.rsplit_once('/')?;
+        return Some((namespace.to_string(), repo.to_string()));
+    }
+    let repository = payload.repository?;
+    match platform {
+        "github" => {
+            let full_name = repository.full_name?;
+            let (namespace, repo) = full_name.split_once('/')?;
+            Some((namespace.to_string(), repo.to_string()))
+        }
+        _ => {
+            let project = payload.project?;
+            Some((gitcode_namespace(project.namespace?, project.path_with_namespace.as_deref()), repository.name?))
+        }
+    }
 }
 
 #[cfg(test)]
@@ -125,6 +285,42 @@ mod tests {
         assert_eq!(result.labels[0].description.as_ref().unwrap(), "feature/test-branch");
     }
 
+    #[test]
+    fn test_parse_gitlab_pr_data() {
+        let json_str = r#"{
+            "object_kind": "merge_request",
+            "event_type": "merge_request",
+            "object_attributes": {
+                "state": "merged",
+                "action": "merge",
+                "url": "https://gitlab.example.com/group/sub/test-repo/-/merge_requests/7",
+                "iid": 7
+            },
+            "project": {
+                "path_with_namespace": "group/sub/test-repo",
+                "git_http_url": "https://gitlab.example.com/group/sub/test-repo.git"
+            },
+            "labels": [
+                {
+                    "title": "br: release-1.0",
+                    "description": "release-1.0",
+                    "type": "ProjectLabel"
+                }
+            ]
+        }"#;
+
+        let result = parse_gitlab_pr_data(json_str).unwrap();
+        assert_eq!(result.event_type, "merge_request");
+        assert_eq!(result.action.unwrap(), "merge");
+        assert_eq!(result.state.unwrap(), "merged");
+        assert_eq!(result.repo_name, "test-repo");
+        assert_eq!(result.namespace, "group/sub");
+        assert_eq!(result.repo_url, "https://gitlab.example.com/group/sub/test-repo.git");
+        assert_eq!(result.iid.unwrap(), 7);
+        assert_eq!(result.labels[0].title, "br: release-1.0");
+        assert_eq!(repo_identity("gitlab", json_str), Some(("group/sub".to_string(), "test-repo".to_string())));
+    }
+
     #[test]
     fn test_parse_github_pr_data() {
         let json_str = r#"{
@@ -137,28 +333,28 @@ mod tests {
                 "html_url": "https://github.com/test-org/test-repo/pull/1",
                 "state": "closed",
                 "number": 1,
-                "title": "Test pull request"
+                "title": "Test pull request",
+                "labels": [
+                    {
+                        "name": "type: feature",
+                        "description": ""
+                    },
+                    {
+                        "name": "version: 1.0",
+                        "description": "version-1.0"
+                    },
+                    {
+                        "name": "branch: main",
+                        "description": "main"
+                    }
+                ]
             },
             "repository": {
                 "id": 987654321,
                 "name": "test-repo",
                 "full_name": "test-org/test-repo",
                 "clone_url": "https://github.com/test-org/test-repo.git"
-            },
-            "labels": [
-                {
-                    "name": "type: feature",
-                    "description": ""
-                },
-                {
-                    "name": "version: 1.0",
-                    "description": "version-1.0"
-                },
-                {
-                    "name": "branch: main",
-                    "description": "main"
-                }
-            ]
+            }
         }"#;
 
         let result = parse_github_pr_data(json_str).unwrap();
@@ -189,6 +385,52 @@ mod tests {
         assert_eq!(result.labels[2].description, Some("main".to_string()));
     }
 
+    /// A PR closed by the merge queue bot; the head it shows is not what landed
+    const MERGE_QUEUE_PAYLOAD: &str = r#"{
+        "action": "closed",
+        "pull_request": {
+            "url": "https://api.github.com/repos/org/repo/pulls/7", "html_url": "https://github.com/org/repo/pull/7",
+            "state": "closed", "number": 7, "labels": [],
+            "merged": true, "merge_commit_sha": "4f1c2d", "merged_by": { "login": "github-merge-queue[bot]" },
+            "auto_merge": null, "head": { "sha": "9a8b7c" }
+        },
+        "repository": { "name": "repo", "clone_url": "https://github.com/org/repo.git", "full_name": "org/repo" }
+    }"#;
+
+    /// A PR merged by auto-merge once its checks passed, credited to its author
+    const AUTO_MERGE_PAYLOAD: &str = r#"{
+        "action": "closed",
+        "pull_request": {
+            "url": "https://api.github.com/repos/org/repo/pulls/8", "html_url": "https://github.com/org/repo/pull/8",
+            "state": "closed", "number": 8, "labels": [],
+            "merged": true, "merge_commit_sha": "5e6f7a", "merged_by": { "login": "alice" },
+            "auto_merge": { "enabled_by": { "login": "alice" }, "merge_method": "squash" }
+        },
+        "repository": { "name": "repo", "clone_url": "https://github.com/org/repo.git", "full_name": "org/repo" }
+    }"#;
+
+    /// A PR closed without merging
+    const CLOSED_UNMERGED_PAYLOAD: &str = r#"{
+        "action": "closed",
+        "pull_request": {
+            "url": "https://api.github.com/repos/org/repo/pulls/9", "html_url": "https://github.com/org/repo/pull/9",
+            "state": "closed", "number": 9, "labels": [],
+            "merged": false, "merge_commit_sha": null, "merged_by": null, "auto_merge": null
+        },
+        "repository": { "name": "repo", "clone_url": "https://github.com/org/repo.git", "full_name": "org/repo" }
+    }"#;
+
+    #[test]
+    fn test_merge_queue_and_auto_merge_payloads() {
+        let queued = parse_github_pr_data(MERGE_QUEUE_PAYLOAD).unwrap().merge.unwrap();
+        assert_eq!(queued, MergeInfo { merged: true, commit_sha: Some("4f1c2d".to_string()), queued: true });
+        let auto_merged = parse_github_pr_data(AUTO_MERGE_PAYLOAD).unwrap().merge.unwrap();
+        assert!(auto_merged.queued);
+        assert_eq!(auto_merged.commit_sha.as_deref(), Some("5e6f7a"));
+        let unmerged = parse_github_pr_data(CLOSED_UNMERGED_PAYLOAD).unwrap().merge.unwrap();
+        assert_eq!(unmerged, MergeInfo { merged: false, commit_sha: None, queued: false });
+    }
+
     #[test]
     fn test_parse_gitcode_push_data() {
         let json_str = r#"{
@@ -232,5 +474,55 @@ mod tests {
         assert_eq!(commit.message, "test commit message");
         assert_eq!(commit.author.name, "Test Author");
         assert_eq!(commit.author.email, "author@example.com");
+
+        let streamed = parse_gitcode_push_reader(json_str.as_bytes()).unwrap();
+        assert_eq!(streamed.branch, result.branch);
+        assert_eq!(streamed.commits.len(), 1);
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn test_parse_github_push_data() {
+        let json_str = r#"{
+            "ref": "refs/heads/release-1.0",
+            "pusher": { "name": "backport-bot", "email": null },
+            "repository": { "name": "test-repo", "full_name": "test-org/test-repo" },
+            "commits": [
+                {
+                    "id": "abcdef1234567890abcdef1234567890abcdef12",
+                    "message": "Fix bug\n\nCherry-picked from: https://github.com/test-org/test-repo/pull/7",
+                    "timestamp": "2024-01-01T00:00:00Z",
+                    "url": "https://github.com/test-org/test-repo/commit/abcdef1234567890abcdef1234567890abcdef12",
+                    "author": { "name": "Test Author", "email": "author@example.com", "username": "author" }
+                }
+            ]
+        }"#;
+
+        let result = parse_github_push_data(json_str).unwrap();
+        assert_eq!(result.platform, "github");
+        assert_eq!(result.user_name, "backport-bot");
+        assert_eq!(result.user_email, "");
+        assert_eq!(result.namespace, "test-org");
+        assert_eq!(result.repo_name, "test-repo");
+        assert_eq!(result.branch, "release-1.0");
+        assert_eq!(result.commits[0].get_original_pr_number("github.com"), Some(7));
+        assert_eq!(result.commits[0].get_original_pr_number("gitcode.com"), None);
+
+        let comments = result.get_comment_info(&Default::default(), "job");
+        assert_eq!(comments.len(), 1);
+        assert_eq!(comments[0].pr_id, Some(7));
+        assert_eq!(parse_github_push_reader(json_str.as_bytes()).unwrap().branch, "release-1.0");
+    }
+
+    #[test]
+    fn test_repo_identity() {
+        let github = r#"{"repository": {"full_name": "test-org/test-repo"}}"#;
+        assert_eq!(repo_identity("github", github), Some(("test-org".to_string(), "test-repo".to_string())));
+        let gitcode = r#"{"project": {"namespace": "test"}, "repository": {"name": "repo"}}"#;
+        assert_eq!(repo_identity("gitcode", gitcode), Some(("test".to_string(), "repo".to_string())));
+        assert_eq!(repo_identity("github", "not json"), None);
+
+        // Group-level webhooks name the group by its display name
+        let group = r#"{"project": {"namespace": "Open HiTLS", "path_with_namespace": "openHiTLS/crypto/repo"}, "repository": {"name": "repo"}}"#;
+        assert_eq!(repo_identity("gitcode", group), Some(("openHiTLS/crypto".to_string(), "repo".to_string())));
+    }
+}
@@ -0,0 +1,96 @@
+use std::collections::HashMap;
+use std::env;
+use git2::{Oid, Repository, Sort};
+
+use crate::utils::templates::{CommentTemplates, MessageKind};
+
+/// Number of commits of a target branch whose patch-ids are compared with
+/// the commits to backport, from `PATCH_ID_DEPTH` (default 500; 0 disables
+/// the check)
+pub fn search_depth() -> usize {
+    env::var("PATCH_ID_DEPTH")
+        .ok()
+        .and_then(|value| value.parse().ok())
+        .unwrap_or(500)
+}
+
+/// Patch-id of a commit: a hash of its diff against its parent that ignores
+/// line numbers and whitespace, so the same change has the same patch-id
+/// wherever it was applied; `None` for merges, root and empty commits
+pub fn patch_id(repo: &Repository, commit: Oid) -> Result<Option<Oid>, git2::Error> {
+    let commit = repo.find_commit(commit)?;
+    if commit.parent_count() != 1 {
+        return Ok(None);
+    }
+    let diff = repo.diff_tree_to_tree(Some(&commit.parent(0)?.tree()?), Some(&commit.tree()?), None)?;
+    if diff.deltas().len() == 0 {
+        return Ok(None);
+    }
+    diff.patchid(None).map(Some)
+}
+
+/// Patch-ids of the last `depth` commits reachable from `head`, mapped to
+/// the commit carrying each
+pub fn recent_patch_ids(repo: &Repository, head: Oid, depth: usize) -> Result<HashMap<Oid, Oid>, git2::Error> {
+    let mut walk = repo.revwalk()?;
+    walk.set_sorting(Sort::TOPOLOGICAL)?;
+    walk.push(head)?;
+    let mut ids = HashMap::new();
+    for commit in walk.take(depth) {
+        let commit = commit?;
+        if let Some(id) = patch_id(repo, commit)? {
+            ids.entry(id).or_insert(commit);
+        }
+    }
+    Ok(ids)
+}
+
+/// Comment listing the commits left out of the backport onto `branch`, as
+/// `(commit, commit of the branch with the same change)` pairs
+pub fn format_already_present_comment(branch: &str, skipped: &[(String, Oid)], templates: &CommentTemplates) -> String {
+    let commits: String = skipped.iter().map(|(commit, present)| format!("- {} (as {})\n", commit, present)).collect();
+    templates.render(MessageKind::AlreadyPresent, &[("branch", branch), ("commits", &commits)])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::Path;
+    use git2::Signature;
+
+    fn commit(repo: &Repository, file: &str, contents: &str, message: &str, parent: Option<Oid>) -> Oid {
+        std::fs::write(repo.workdir().unwrap().join(file), contents).unwrap();
+        let mut index = repo.index().unwrap();
+        match parent {
+            Some(oid) => index.read_tree(&repo.find_commit(oid).unwrap().tree().unwrap()).unwrap(),
+            None => index.clear().unwrap(),
+        }
+        index.add_path(Path::new(file)).unwrap();
+        let tree = repo.find_tree(index.write_tree().unwrap()).unwrap();
+        let signature = Signature::now("Test", "test@example.com").unwrap();
+        let parents: Vec<git2::Commit> = parent.map(|oid| repo.find_commit(oid).unwrap()).into_iter().collect();
+        let parents: Vec<&git2::Commit> = parents.iter().collect();
+        repo.commit(None, &signature, &signature, message, &tree, &parents).unwrap()
+    }
+
+    #[test]
+    fn test_manual_backports_share_the_patch_id() {
+        let dir = tempfile::tempdir().unwrap();
+        let repo = Repository::init(dir.path()).unwrap();
+        let base = commit(&repo, "a.txt", "one\ntwo\n", "base", None);
+        let fix = commit(&repo, "a.txt", "one\ntwo\nthree\n", "Fix", Some(base));
+
+        let release = commit(&repo, "b.txt", "other\n", "release only", Some(base));
+        let manual = commit(&repo, "a.txt", "one\ntwo\nthree\n", "Fix (manual backport)", Some(release));
+        let unrelated = commit(&repo, "a.txt", "one\ntwo\nfour\n", "Something else", Some(base));
+
+        let fix_id = patch_id(&repo, fix).unwrap().unwrap();
+        assert_eq!(patch_id(&repo, manual).unwrap(), Some(fix_id));
+        assert_ne!(patch_id(&repo, unrelated).unwrap(), Some(fix_id));
+        assert_eq!(patch_id(&repo, base).unwrap(), None);
+
+        assert_eq!(recent_patch_ids(&repo, manual, 10).unwrap().get(&fix_id), Some(&manual));
+        assert_eq!(recent_patch_ids(&repo, manual, 1).unwrap().len(), 1);
+        assert!(!recent_patch_ids(&repo, unrelated, 10).unwrap().contains_key(&fix_id));
+    }
+}
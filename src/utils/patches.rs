@@ -0,0 +1,253 @@
+use std::env;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime};
+use git2::{EmailCreateOptions, Oid, Repository};
+use sha2::{Digest, Sha256};
+use log::{info, error};
+
+use crate::utils::templates::Locale;
+
+/// A `format-patch` file kept in the patch store
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct StoredPatch {
+    pub commit: String,
+    /// SHA-256 of the patch contents, which is also its name in the store
+    pub digest: String,
+}
+
+impl StoredPatch {
+    /// Download link under `PATCH_BASE_URL`, if one is configured
+    pub fn url(&self) -> Option<String> {
+        let base = env::var("PATCH_BASE_URL").ok().filter(|base| !base.is_empty())?;
+        Some(format!("{}/patches/{}.patch", base.trim_end_matches('/'), self.digest))
+    }
+}
+
+/// Content-addressed directory of generated patches
+///
+/// Patches are stored as `<root>/<first two digest chars>/<digest>.patch`,
+/// so identical patches from different jobs share one file. Patches not
+/// stored again for `retention` are removed by [`PatchStore::prune`].
+pub struct PatchStore {
+    root: PathBuf,
+    retention: Option<Duration>,
+}
+
+fn is_digest(digest: &str) -> bool {
+    digest.len() == 64 && digest.chars().all(|c| c.is_ascii_digit() || ('a'..='f').contains(&c))
+}
+
+impl PatchStore {
+    pub fn new(root: &Path) -> Self {
+        PatchStore { root: root.to_path_buf(), retention: None }
+    }
+
+    pub fn with_retention(mut self, retention: Duration) -> Self {
+        self.retention = Some(retention);
+        self
+    }
+
+    /// Store rooted at `PATCH_STORE_DIR`, defaulting to `patches`, keeping
+    /// patches for `PATCH_RETENTION_DAYS` (default 30, 0 keeps them forever)
+    pub fn from_env() -> Self {
+        let root = env::var("PATCH_STORE_DIR").unwrap_or_else(|_| "patches".to_string());
+        let days = env::var("PATCH_RETENTION_DAYS").ok().and_then(|value| value.parse::<u64>().ok()).unwrap_or(30);
+        let store = PatchStore::new(Path::new(&root));
+        match days {
+            0 => store,
+            days => store.with_retention(Duration::from_secs(days * 24 * 60 * 60)),
+        }
+    }
+
+    pub fn root(&self) -> &Path {
+        &self.root
+    }
+
+    fn path(&self, digest: &str) -> PathBuf {
+        self.root.join(&digest[..2]).join(format!("{}.patch", digest))
+    }
+
+    /// Writes `contents` unless already present and returns its digest
+    pub fn put(&self, contents: &[u8]) -> io::Result<String> {
+        let digest = hex::encode(Sha256::digest(contents));
+        let path = self.path(&digest);
+        if path.exists() {
+            // Restart the retention period of a patch stored again
+            fs::File::options().write(true).open(&path)?.set_modified(SystemTime::now())?;
+        } else {
+            let dir = path.parent().expect("patch path has a parent");
+            fs::create_dir_all(dir)?;
+            // Write then rename so readers never see a partial patch
+            let tmp = dir.join(format!(".{}.tmp", digest));
+            fs::write(&tmp, contents)?;
+            fs::rename(&tmp, &path)?;
+        }
+        Ok(digest)
+    }
+
+    /// Removes patches last stored before the retention period and returns
+    /// how many were removed; does nothing without a retention
+    pub fn prune(&self, now: SystemTime) -> io::Result<usize> {
+        let Some(cutoff) = self.retention.and_then(|retention| now.checked_sub(retention)) else {
+            return Ok(0);
+        };
+        let shards = match fs::read_dir(&self.root) {
+            Ok(shards) => shards,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(0),
+            Err(e) => return Err(e),
+        };
+        let mut removed = 0;
+        for shard in shards {
+            let shard = shard?;
+            if !shard.file_type()?.is_dir() {
+                continue;
+            }
+            for entry in fs::read_dir(shard.path())? {
+                let entry = entry?;
+                if entry.metadata()?.modified()? < cutoff {
+                    fs::remove_file(entry.path())?;
+                    removed += 1;
+                }
+            }
+            // Fails, harmlessly, while the shard still holds patches
+            let _ = fs::remove_dir(shard.path());
+        }
+        Ok(removed)
+    }
+
+    /// Reads the patch named `digest`; malformed digests are treated as missing
+    pub fn get(&self, digest: &str) -> io::Result<Option<Vec<u8>>> {
+        if !is_digest(digest) {
+            return Ok(None);
+        }
+        match fs::read(self.path(digest)) {
+            Ok(contents) => Ok(Some(contents)),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(e),
+        }
+    }
+}
+
+/// Periodically prunes the patch store configured in the environment
+pub fn spawn_prune_task(interval: Duration) {
+    tokio::spawn(async move {
+        let mut ticker = tokio::time::interval(interval);
+        loop {
+            ticker.tick().await;
+            let result = tokio::task::spawn_blocking(|| {
+                let store = PatchStore::from_env();
+                match store.prune(SystemTime::now()) {
+                    Ok(0) => {}
+                    Ok(removed) => info!("Pruned {} expired patches from {:?}", removed, store.root),
+                    Err(e) => error!("Failed to prune patches in {:?}: {}", store.root, e),
+                }
+            }).await;
+            if let Err(e) = result {
+                error!("Patch prune task failed: {}", e);
+            }
+        }
+    });
+}
+
+/// Renders `commit` the way `git format-patch` does
+pub fn format_patch(repo: &Repository, commit_id: &str) -> Result<Vec<u8>, git2::Error> {
+    let commit = repo.find_commit(Oid::from_str(commit_id)?)?;
+    let email = git2::Email::from_commit(&commit, &mut EmailCreateOptions::new())?;
+    Ok(email.as_slice().to_vec())
+}
+
+/// Stores the patch of every commit about to be cherry-picked
+///
+/// Failures are logged and skipped: missing patches must not fail a backport.
+pub fn store_commit_patches(store: &PatchStore, repo_path: &Path, commit_ids: &[&str]) -> Vec<StoredPatch> {
+    let repo = match Repository::open(repo_path) {
+        Ok(repo) => repo,
+        Err(e) => {
+            error!("Failed to open {:?} to store patches: {}", repo_path, e);
+            return Vec::new();
+        }
+    };
+    let mut stored = Vec::new();
+    for commit in commit_ids {
+        let result = format_patch(&repo, commit)
+            .map_err(|e| e.to_string())
+            .and_then(|patch| store.put(&patch).map_err(|e| e.to_string()));
+        match result {
+            Ok(digest) => stored.push(StoredPatch { commit: commit.to_string(), digest }),
+            Err(e) => error!("Failed to store patch of {}: {}", commit, e),
+        }
+    }
+    info!("Stored {} of {} patches in {:?}", stored.len(), commit_ids.len(), store.root);
+    stored
+}
+
+/// Renders the list of downloadable patches for a PR comment, or an empty
+/// string when the store has no base URL
+pub fn format_patch_list(patches: &[StoredPatch], locale: Locale) -> String {
+    let links: String = patches
+        .iter()
+        .filter_map(|patch| {
+            let short = &patch.commit[..patch.commit.len().min(8)];
+            patch.url().map(|url| format!("- [{}.patch]({})\n", short, url))
+        })
+        .collect();
+    if links.is_empty() {
+        return String::new();
+    }
+    let header = match locale {
+        Locale::En => "Patches attempted by the bot:",
+        Locale::ZhCn => "机器人尝试应用的补丁：",
+    };
+    format!("\n{}\n{}", header, links)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_patch_store_is_content_addressed() {
+        let dir = tempfile::tempdir().unwrap();
+        let store = PatchStore::new(dir.path());
+
+        let digest = store.put(b"patch").unwrap();
+        assert_eq!(store.put(b"patch").unwrap(), digest);
+        assert!(dir.path().join(&digest[..2]).join(format!("{}.patch", digest)).exists());
+        assert_eq!(store.get(&digest).unwrap().unwrap(), b"patch");
+        assert!(store.get("../../etc/passwd").unwrap().is_none());
+        assert!(store.get(&"0".repeat(64)).unwrap().is_none());
+    }
+
+    #[test]
+    fn test_prune_removes_expired_patches() {
+        let dir = tempfile::tempdir().unwrap();
+        let day = Duration::from_secs(24 * 60 * 60);
+        let store = PatchStore::new(dir.path()).with_retention(day);
+        let old = store.put(b"old").unwrap();
+        let kept = store.put(b"kept").unwrap();
+        let now = SystemTime::now() + 2 * day;
+        fs::File::options().write(true).open(store.path(&kept)).unwrap().set_modified(now).unwrap();
+
+        assert_eq!(store.prune(now).unwrap(), 1);
+        assert!(store.get(&old).unwrap().is_none());
+        assert!(store.get(&kept).unwrap().is_some());
+        assert_eq!(PatchStore::new(dir.path()).prune(now + 10 * day).unwrap(), 0);
+    }
+
+    #[test]
+    fn test_format_patch() {
+        let dir = tempfile::tempdir().unwrap();
+        let repo = Repository::init(dir.path()).unwrap();
+        let mut builder = repo.treebuilder(None).unwrap();
+        builder.insert("file.txt", repo.blob(b"hello\n").unwrap(), 0o100644).unwrap();
+        let tree = repo.find_tree(builder.write().unwrap()).unwrap();
+        let sig = git2::Signature::now("test", "test@example.com").unwrap();
+        let oid = repo.commit(None, &sig, &sig, "Add file", &tree, &[]).unwrap();
+
+        let patch = String::from_utf8(format_patch(&repo, &oid.to_string()).unwrap()).unwrap();
+        assert!(patch.contains("Subject: [PATCH] Add file"));
+        assert!(patch.contains("+hello"));
+    }
+}
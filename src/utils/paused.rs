@@ -0,0 +1,255 @@
+use std::collections::BTreeSet;
+use std::path::{Path, PathBuf};
+use std::sync::{Mutex, OnceLock};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use log::error;
+
+use crate::error::Result;
+use crate::utils::dlq::Delivery;
+use crate::utils::{payload_signing, redact, state, state_store};
+
+/// Number of held events kept per repository; the oldest are dropped beyond this
+const MAX_EVENTS_PER_REPO: usize = 200;
+
+/// Marker for a paused platform in `PlatformPauses`: every platform is paused
+pub const ALL_PLATFORMS: &str = "*";
+
+/// Why an event is held
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum HeldFor {
+    /// Its repository is disabled
+    #[default]
+    Repo,
+    /// Its platform is paused
+    Platform,
+}
+
+/// A webhook received while its repository was disabled or its platform paused
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PausedEvent {
+    pub repo: String,
+    pub platform: String,
+    pub event: String,
+    pub received_at: DateTime<Utc>,
+    #[serde(default)]
+    pub held_for: HeldFor,
+    pub body: String,
+    /// Original body encrypted with the service key when `body` is redacted
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub sealed_body: Option<String>,
+    /// Signature of the original payload, checked before it is replayed
+    #[serde(default)]
+    pub signature: Option<String>,
+}
+
+impl PausedEvent {
+    /// Fails when a spooled body cannot be read back
+    pub fn new(repo: &str, delivery: Delivery, held_for: HeldFor) -> std::io::Result<Self> {
+        let body = delivery.body.text()?;
+        let (stored, sealed_body) = redact::for_storage(body.clone());
+        Ok(PausedEvent {
+            repo: repo.to_string(),
+            platform: delivery.platform.to_string(),
+            event: delivery.event.to_string(),
+            received_at: Utc::now(),
+            held_for,
+            signature: payload_signing::sign(delivery.platform, delivery.event, &body),
+            body: stored,
+            sealed_body,
+        })
+    }
+
+    /// Checks that the stored payload was not modified since it was held and
+    /// returns the original body to replay
+    pub fn verify(&self) -> Result<String> {
+        let body = redact::original(&self.body, self.sealed_body.as_deref())?;
+        payload_signing::verify(&self.platform, &self.event, &body, self.signature.as_deref())?;
+        Ok(body)
+    }
+}
+
+/// Events of disabled repositories, persisted as JSON in `paused.json`
+pub struct PausedEvents {
+    path: PathBuf,
+    events: Vec<PausedEvent>,
+}
+
+impl PausedEvents {
+    pub fn open(path: &Path) -> PausedEvents {
+        let events = match state_store::read_document(path) {
+            Ok(Some(contents)) => serde_json::from_str(&contents).unwrap_or_else(|e| {
+                error!("Failed to parse paused events {:?}, starting empty: {}", path, e);
+                Vec::new()
+            }),
+            _ => Vec::new(),
+        };
+        PausedEvents { path: path.to_path_buf(), events }
+    }
+
+    fn save(&self) {
+        let result = serde_json::to_string_pretty(&self.events)
+            .map_err(std::io::Error::from)
+            .and_then(|contents| state_store::write_document(&self.path, &contents));
+        if let Err(e) = result {
+            error!("Failed to persist paused events {:?}: {}", self.path, e);
+        }
+    }
+
+    pub fn push(&mut self, event: PausedEvent) {
+        let held = self.events.iter().filter(|held| held.repo == event.repo).count();
+        if held >= MAX_EVENTS_PER_REPO {
+            if let Some(oldest) = self.events.iter().position(|held| held.repo == event.repo) {
+                error!("Dropping oldest held event of {}: limit of {} reached", event.repo, MAX_EVENTS_PER_REPO);
+                self.events.remove(oldest);
+            }
+        }
+        self.events.push(event);
+        self.save();
+    }
+
+    /// Number of events held because `repo` is disabled
+    pub fn count(&self, repo: &str) -> usize {
+        self.events.iter().filter(|event| event.held_for == HeldFor::Repo && event.repo == repo).count()
+    }
+
+    fn take_where<F: Fn(&PausedEvent) -> bool>(&mut self, predicate: F) -> Vec<PausedEvent> {
+        let (taken, kept) = std::mem::take(&mut self.events)
+            .into_iter()
+            .partition(|event| predicate(event));
+        self.events = kept;
+        self.save();
+        taken
+    }
+
+    /// Removes and returns the events held because `repo` was disabled, oldest first
+    pub fn take_repo(&mut self, repo: &str) -> Vec<PausedEvent> {
+        self.take_where(|event| event.held_for == HeldFor::Repo && event.repo == repo)
+    }
+
+    /// Removes and returns the events held for a paused platform that
+    /// `resumed` accepts, oldest first
+    pub fn take_platforms<F: Fn(&str) -> bool>(&mut self, resumed: F) -> Vec<PausedEvent> {
+        self.take_where(|event| event.held_for == HeldFor::Platform && resumed(&event.platform))
+    }
+}
+
+/// The process-wide store of held events
+pub fn store() -> &'static Mutex<PausedEvents> {
+    static STORE: OnceLock<Mutex<PausedEvents>> = OnceLock::new();
+    STORE.get_or_init(|| Mutex::new(PausedEvents::open(&state::state_dir().join("paused.json"))))
+}
+
+/// Paused platforms, persisted as JSON in `paused_platforms.json`
+///
+/// Webhooks of a paused platform are still accepted and verified, but held
+/// instead of starting jobs. `ALL_PLATFORMS` pauses every platform.
+pub struct PlatformPauses {
+    path: PathBuf,
+    paused: BTreeSet<String>,
+}
+
+impl PlatformPauses {
+    pub fn open(path: &Path) -> PlatformPauses {
+        let paused = match state_store::read_document(path) {
+            Ok(Some(contents)) => serde_json::from_str(&contents).unwrap_or_else(|e| {
+                error!("Failed to parse paused platforms {:?}, starting with none: {}", path, e);
+                BTreeSet::new()
+            }),
+            _ => BTreeSet::new(),
+        };
+        PlatformPauses { path: path.to_path_buf(), paused }
+    }
+
+    fn save(&self) {
+        let result = serde_json::to_string_pretty(&self.paused)
+            .map_err(std::io::Error::from)
+            .and_then(|contents| state_store::write_document(&self.path, &contents));
+        if let Err(e) = result {
+            error!("Failed to persist paused platforms {:?}: {}", self.path, e);
+        }
+    }
+
+    pub fn pause(&mut self, platform: &str) {
+        self.paused.insert(platform.to_string());
+        self.save();
+    }
+
+    /// Resumes `platform`, or every platform for `ALL_PLATFORMS`
+    pub fn resume(&mut self, platform: &str) {
+        if platform == ALL_PLATFORMS {
+            self.paused.clear();
+        } else {
+            self.paused.remove(platform);
+        }
+        self.save();
+    }
+
+    pub fn is_paused(&self, platform: &str) -> bool {
+        self.paused.contains(ALL_PLATFORMS) || self.paused.contains(platform)
+    }
+
+    pub fn list(&self) -> Vec<String> {
+        self.paused.iter().cloned().collect()
+    }
+}
+
+/// The process-wide set of paused platforms
+pub fn platforms() -> &'static Mutex<PlatformPauses> {
+    static PAUSES: OnceLock<Mutex<PlatformPauses>> = OnceLock::new();
+    PAUSES.get_or_init(|| Mutex::new(PlatformPauses::open(&state::state_dir().join("paused_platforms.json"))))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::body::WebhookBody;
+
+    #[test]
+    fn test_events_are_held_per_repo() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("paused.json");
+        let body = WebhookBody::from("{}".to_string());
+        let delivery = Delivery { platform: "github", event: "pull_request", body: &body };
+
+        let mut store = PausedEvents::open(&path);
+        store.push(PausedEvent::new("a", delivery, HeldFor::Repo).unwrap());
+        store.push(PausedEvent::new("b", delivery, HeldFor::Repo).unwrap());
+        store.push(PausedEvent::new("a", delivery, HeldFor::Repo).unwrap());
+
+        let mut reopened = PausedEvents::open(&path);
+        assert_eq!(reopened.count("a"), 2);
+        assert_eq!(reopened.take_repo("a").len(), 2);
+        assert_eq!(reopened.count("a"), 0);
+        assert_eq!(PausedEvents::open(&path).count("b"), 1);
+    }
+
+    #[test]
+    fn test_platform_pause_holds_events_until_resumed() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut pauses = PlatformPauses::open(&dir.path().join("paused_platforms.json"));
+        pauses.pause("gitcode");
+        assert!(pauses.is_paused("gitcode") && !pauses.is_paused("github"));
+        pauses.pause(ALL_PLATFORMS);
+        pauses.resume("gitcode");
+        assert!(pauses.is_paused("gitcode"));
+        pauses.resume(ALL_PLATFORMS);
+        assert!(PlatformPauses::open(&dir.path().join("paused_platforms.json")).list().is_empty());
+
+        let body = WebhookBody::from("{}".to_string());
+        let mut store = PausedEvents::open(&dir.path().join("paused.json"));
+        let github = Delivery { platform: "github", event: "pull_request", body: &body };
+        let gitcode = Delivery { platform: "gitcode", event: "Push Hook", body: &body };
+        store.push(PausedEvent::new("a", github, HeldFor::Platform).unwrap());
+        store.push(PausedEvent::new("a", gitcode, HeldFor::Platform).unwrap());
+        store.push(PausedEvent::new("a", github, HeldFor::Repo).unwrap());
+
+        assert_eq!(store.count("a"), 1);
+        let resumed = store.take_platforms(|platform| platform == "github");
+        assert_eq!(resumed.len(), 1);
+        assert_eq!(resumed[0].held_for, HeldFor::Platform);
+        assert_eq!(store.take_repo("a").len(), 1);
+        assert_eq!(store.take_platforms(|_| true).len(), 1);
+    }
+}
@@ -0,0 +1,203 @@
+use std::collections::{BTreeMap, BTreeSet};
+use std::path::{Path, PathBuf};
+use std::sync::{Mutex, OnceLock};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use log::{warn, error};
+
+use crate::utils::body::WebhookBody;
+use crate::utils::{metrics, state, state_store};
+
+/// Nesting depth up to which field paths are tracked
+const MAX_DEPTH: usize = 4;
+
+/// Fields the parsers read for each `(platform, event)`; a payload without
+/// one of them no longer parses or loses information
+pub fn required_fields(platform: &str, event: &str) -> &'static [&'static str] {
+    match (platform, event) {
+        ("gitcode", "Merge Request Hook") => &[
+            "object_attributes.action", "object_attributes.state", "object_attributes.url", "object_attributes.iid",
+            "repository.name", "repository.git_http_url", "project.namespace",
+        ],
+        ("gitcode", "Push Hook") => &[
+            "user_name", "user_email", "git_branch", "repository.name", "project.name", "project.namespace",
+            "commits[].id", "commits[].message", "commits[].timestamp", "commits[].url",
+            "commits[].author.name", "commits[].author.email",
+        ],
+        ("github", "push") => &[
+            "ref", "pusher.name", "repository.name", "repository.full_name",
+            "commits[].id", "commits[].message", "commits[].timestamp", "commits[].url",
+            "commits[].author.name", "commits[].author.email",
+        ],
+        ("github", "pull_request") => &[
+            "action", "pull_request.url", "pull_request.state", "pull_request.number", "pull_request.html_url",
+            "pull_request.labels", "repository.name", "repository.clone_url", "repository.full_name",
+        ],
+        _ => &[],
+    }
+}
+
+/// Field paths of `value`: object keys joined with `.`, array elements as `[]`
+pub fn field_paths(value: &Value) -> BTreeSet<String> {
+    fn walk(value: &Value, prefix: &str, depth: usize, paths: &mut BTreeSet<String>) {
+        if depth >= MAX_DEPTH {
+            return;
+        }
+        match value {
+            Value::Object(map) => {
+                for (key, child) in map {
+                    let path = if prefix.is_empty() { key.clone() } else { format!("{}.{}", prefix, key) };
+                    paths.insert(path.clone());
+                    walk(child, &path, depth + 1, paths);
+                }
+            }
+            Value::Array(items) => {
+                let path = format!("{}[]", prefix);
+                for item in items {
+                    walk(item, &path, depth, paths);
+                }
+            }
+            _ => {}
+        }
+    }
+    let mut paths = BTreeSet::new();
+    walk(value, "", 0, &mut paths);
+    paths
+}
+
+/// Differences between a payload and the recorded schema of its event type
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct DriftReport {
+    /// Required fields the payload lacks
+    pub missing: Vec<String>,
+    /// Fields never seen before in this event type
+    pub unknown: Vec<String>,
+    /// Schema version after this payload
+    pub version: u32,
+}
+
+/// Field paths seen so far for one event type; the version increases each
+/// time new fields appear
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct EventSchema {
+    pub version: u32,
+    pub fields: BTreeSet<String>,
+    pub updated_at: Option<DateTime<Utc>>,
+}
+
+/// Recorded payload schemas persisted as JSON in `payload_schemas.json`
+///
+/// The first payload of an event type becomes its baseline; later payloads
+/// are compared against it and new fields are added with a version bump, so
+/// each change is reported once.
+pub struct SchemaStore {
+    path: PathBuf,
+    schemas: BTreeMap<String, EventSchema>,
+}
+
+impl SchemaStore {
+    pub fn open(path: &Path) -> SchemaStore {
+        let schemas = match state_store::read_document(path) {
+            Ok(Some(contents)) => serde_json::from_str(&contents).unwrap_or_else(|e| {
+                error!("Failed to parse payload schemas {:?}, starting empty: {}", path, e);
+                BTreeMap::new()
+            }),
+            _ => BTreeMap::new(),
+        };
+        SchemaStore { path: path.to_path_buf(), schemas }
+    }
+
+    fn save(&self) {
+        let result = serde_json::to_string_pretty(&self.schemas)
+            .map_err(std::io::Error::from)
+            .and_then(|contents| state_store::write_document(&self.path, &contents));
+        if let Err(e) = result {
+            error!("Failed to persist payload schemas {:?}: {}", self.path, e);
+        }
+    }
+
+    /// Compares `payload` with the schema of `(platform, event)` and records its new fields
+    pub fn check(&mut self, platform: &str, event: &str, payload: &Value) -> DriftReport {
+        let paths = field_paths(payload);
+        let missing = required_fields(platform, event)
+            .iter()
+            .filter(|field| !paths.contains(**field))
+            .map(|field| field.to_string())
+            .collect();
+
+        let schema = self.schemas.entry(format!("{}/{}", platform, event)).or_default();
+        let baseline = schema.version == 0;
+        let unknown: Vec<String> = if baseline {
+            Vec::new()
+        } else {
+            paths.difference(&schema.fields).cloned().collect()
+        };
+        if baseline || !unknown.is_empty() {
+            schema.version += 1;
+            schema.fields.extend(paths);
+            schema.updated_at = Some(Utc::now());
+            let version = schema.version;
+            self.save();
+            return DriftReport { missing, unknown, version };
+        }
+        DriftReport { missing, unknown, version: schema.version }
+    }
+}
+
+/// The process-wide payload schema store
+pub fn store() -> &'static Mutex<SchemaStore> {
+    static STORE: OnceLock<Mutex<SchemaStore>> = OnceLock::new();
+    STORE.get_or_init(|| Mutex::new(SchemaStore::open(&state::state_dir().join("payload_schemas.json"))))
+}
+
+/// Checks a verified webhook for payload drift, logging and counting it
+///
+/// Purely advisory: parsing stays permissive and processing continues whatever
+/// the outcome.
+pub fn inspect(platform: &str, event: &str, body: &WebhookBody) {
+    let payload: Value = match body.reader().map(serde_json::from_reader) {
+        Ok(Ok(payload)) => payload,
+        _ => return,
+    };
+    let report = store().lock().unwrap().check(platform, event, &payload);
+    if !report.missing.is_empty() {
+        warn!("Payload drift in {} {}: missing required fields {:?}", platform, event, report.missing);
+        metrics::record_payload_drift(platform, event, "missing", report.missing.len() as u64);
+    }
+    if !report.unknown.is_empty() {
+        warn!("Payload drift in {} {}: new fields {:?} (schema version {})", platform, event, report.unknown, report.version);
+        metrics::record_payload_drift(platform, event, "unknown", report.unknown.len() as u64);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_drift_is_reported_once_per_change() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("payload_schemas.json");
+        let mut store = SchemaStore::open(&path);
+        let payload = json!({
+            "user_name": "bot", "user_email": "bot@example.com", "git_branch": "main",
+            "repository": { "name": "repo" }, "project": { "name": "repo", "namespace": "org" },
+            "commits": [{ "id": "1", "message": "m", "timestamp": "t", "url": "u", "author": { "name": "a", "email": "e" } }],
+        });
+        assert_eq!(store.check("gitcode", "Push Hook", &payload), DriftReport { version: 1, ..DriftReport::default() });
+
+        let mut drifted = payload.clone();
+        drifted["project"] = json!({ "name": "repo", "path_with_namespace": "org/repo" });
+        let report = SchemaStore::open(&path).check("gitcode", "Push Hook", &drifted);
+        assert_eq!(report.missing, vec!["project.namespace"]);
+        assert_eq!(report.unknown, vec!["project.path_with_namespace"]);
+        assert_eq!(report.version, 2);
+
+        let again = SchemaStore::open(&path).check("gitcode", "Push Hook", &drifted);
+        assert!(again.unknown.is_empty());
+        assert_eq!(again.version, 2);
+        assert!(field_paths(&payload).contains("commits[].author.email"));
+    }
+}
@@ -0,0 +1,72 @@
+use std::sync::OnceLock;
+use log::error;
+
+use crate::error::{Error, Result};
+use crate::utils::hmac::{self, compute_hmac_sha256};
+use crate::utils::service_key;
+
+/// Context mixed into the service key so payload signatures never double as
+/// another use of the key
+const KEY_CONTEXT: &[u8] = b"stored-webhook-payload";
+
+/// Signing key of stored payloads, derived from the service key
+fn signing_key() -> Result<&'static str> {
+    static KEY: OnceLock<String> = OnceLock::new();
+    if let Some(key) = KEY.get() {
+        return Ok(key);
+    }
+    let password = service_key::get_service_key().map_err(|e| Error::config(format!("Failed to read the service key: {}", e)))?;
+    Ok(KEY.get_or_init(|| compute_hmac_sha256(KEY_CONTEXT, &password)))
+}
+
+/// The signed text of a stored webhook: its platform, event and body
+fn signed_message(platform: &str, event: &str, body: &str) -> String {
+    format!("{}\n{}\n{}", platform, event, body)
+}
+
+/// Hex HMAC-SHA256 with `key` over the platform, event and body of a stored webhook
+pub fn signature_with(key: &str, platform: &str, event: &str, body: &str) -> String {
+    compute_hmac_sha256(signed_message(platform, event, body).as_bytes(), key)
+}
+
+/// Checks `signature` against the stored webhook with `key`
+pub fn verify_with(key: &str, platform: &str, event: &str, body: &str, signature: Option<&str>) -> Result<()> {
+    match signature {
+        None => Err(Error::crypto("stored payload is not signed")),
+        Some(signature) => hmac::verify_hmac_sha256(signed_message(platform, event, body).as_bytes(), key, signature)
+            .map_err(|_| Error::crypto("stored payload signature mismatch")),
+    }
+}
+
+/// Signs a webhook before it is stored for a later replay; `None` when the
+/// service key is unavailable, which makes the replay refuse it
+pub fn sign(platform: &str, event: &str, body: &str) -> Option<String> {
+    match signing_key() {
+        Ok(key) => Some(signature_with(key, platform, event, body)),
+        Err(e) => {
+            error!("Failed to sign stored {} payload, it cannot be replayed: {}", platform, e);
+            None
+        }
+    }
+}
+
+/// Verifies a stored webhook before it is replayed, so an edited state file
+/// cannot inject payloads into the pipeline
+pub fn verify(platform: &str, event: &str, body: &str, signature: Option<&str>) -> Result<()> {
+    verify_with(signing_key()?, platform, event, body, signature)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_tampered_payloads_are_rejected() {
+        let signature = signature_with("key", "github", "pull_request", "{\"number\": 1}");
+        assert!(verify_with("key", "github", "pull_request", "{\"number\": 1}", Some(&signature)).is_ok());
+        assert!(verify_with("key", "github", "pull_request", "{\"number\": 2}", Some(&signature)).is_err());
+        assert!(verify_with("key", "gitcode", "pull_request", "{\"number\": 1}", Some(&signature)).is_err());
+        assert!(verify_with("other", "github", "pull_request", "{\"number\": 1}", Some(&signature)).is_err());
+        assert_eq!(verify_with("key", "github", "pull_request", "{}", None).unwrap_err().to_string(), "stored payload is not signed");
+    }
+}
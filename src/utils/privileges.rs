@@ -0,0 +1,183 @@
+use std::env;
+use std::ffi::{CStr, CString};
+use std::io;
+use std::os::unix::fs::MetadataExt;
+use std::path::{Path, PathBuf};
+use log::info;
+
+use crate::utils::{archive, patches::PatchStore, repo_cache::RepoCache, state};
+
+/// Account looked up in the password database
+#[derive(Debug, Clone)]
+pub struct User {
+    pub name: String,
+    pub uid: u32,
+    pub gid: u32,
+    pub home: PathBuf,
+}
+
+/// User to switch to after binding, from `RUN_AS_USER`
+pub fn run_as_user() -> Option<String> {
+    env::var("RUN_AS_USER").ok().filter(|user| !user.is_empty())
+}
+
+pub fn is_root() -> bool {
+    unsafe { libc::geteuid() == 0 }
+}
+
+/// Refuses to start as root unless privileges will be dropped after binding
+///
+/// The service runs git on repository content controlled by whoever opens a
+/// pull request, so it must never do that work as root.
+pub fn check_startup_user() -> io::Result<()> {
+    if !is_root() {
+        return Ok(());
+    }
+    match run_as_user() {
+        Some(name) => lookup_user(&name).map(|_| ()),
+        None => Err(io::Error::new(
+            io::ErrorKind::PermissionDenied,
+            "refusing to run as root; start as an unprivileged user or set RUN_AS_USER",
+        )),
+    }
+}
+
+pub fn lookup_user(name: &str) -> io::Result<User> {
+    let c_name = CString::new(name).map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))?;
+    let mut passwd: libc::passwd = unsafe { std::mem::zeroed() };
+    let mut result: *mut libc::passwd = std::ptr::null_mut();
+    let mut buffer = vec![0 as libc::c_char; 16 * 1024];
+    let code = unsafe {
+        libc::getpwnam_r(c_name.as_ptr(), &mut passwd, buffer.as_mut_ptr(), buffer.len(), &mut result)
+    };
+    if code != 0 {
+        return Err(io::Error::from_raw_os_error(code));
+    }
+    if result.is_null() {
+        return Err(io::Error::new(io::ErrorKind::NotFound, format!("user {} does not exist", name)));
+    }
+    let home = unsafe { CStr::from_ptr(passwd.pw_dir) }.to_string_lossy().to_string();
+    Ok(User { name: name.to_string(), uid: passwd.pw_uid, gid: passwd.pw_gid, home: PathBuf::from(home) })
+}
+
+/// Switches the effective ids to `user` while keeping root as the real id
+///
+/// Files created during startup (logs, state, workspaces) then belong to the
+/// target user; `regain_root` restores the ids needed to bind the port.
+pub fn assume_user(user: &User) -> io::Result<()> {
+    unsafe {
+        if libc::setegid(user.gid) != 0 || libc::seteuid(user.uid) != 0 {
+            return Err(io::Error::last_os_error());
+        }
+    }
+    Ok(())
+}
+
+pub fn regain_root() -> io::Result<()> {
+    unsafe {
+        if libc::seteuid(0) != 0 || libc::setegid(0) != 0 {
+            return Err(io::Error::last_os_error());
+        }
+    }
+    Ok(())
+}
+
+/// Points `HOME` and `USER` at `user`, so that git and the keyring read its
+/// configuration
+///
+/// Changing the environment is only sound while the process has a single
+/// thread, so this runs before the async runtime starts.
+pub fn set_user_env(user: &User) {
+    env::set_var("HOME", &user.home);
+    env::set_var("USER", &user.name);
+}
+
+/// Switches the whole process to `user` (supplementary groups, group, then user)
+///
+/// glibc applies the id changes to every thread, so this is safe to call
+/// once the async runtime is running.
+pub fn drop_privileges(user: &User) -> io::Result<()> {
+    if user.uid == 0 {
+        return Err(io::Error::new(io::ErrorKind::InvalidInput, "RUN_AS_USER must not be root"));
+    }
+    unsafe {
+        if libc::setgroups(1, &user.gid) != 0
+            || libc::setgid(user.gid) != 0
+            || libc::setuid(user.uid) != 0
+        {
+            return Err(io::Error::last_os_error());
+        }
+        // Make sure root cannot be regained
+        if libc::setuid(0) == 0 {
+            return Err(io::Error::other("privileges could be regained after dropping them"));
+        }
+    }
+    info!("Dropped privileges to {} (uid {}, gid {})", user.name, user.uid, user.gid);
+    Ok(())
+}
+
+/// Directories the service writes to: workspaces, logs, state, artifacts, the repository cache and patches
+pub fn service_dirs() -> Vec<PathBuf> {
+    let current_dir = env::current_dir().unwrap_or_default();
+    vec![
+        current_dir.join("github"),
+        current_dir.join("gitcode"),
+        PathBuf::from("logs"),
+        state::state_dir(),
+        archive::artifacts_dir(),
+        RepoCache::from_env().root().to_path_buf(),
+        PatchStore::from_env().root().to_path_buf(),
+    ]
+}
+
+/// Checks that every existing directory is owned by `uid` and not world-writable
+///
+/// Directories owned by someone else could hold planted hooks or git config
+/// that the service would execute.
+pub fn verify_ownership(dirs: &[PathBuf], uid: u32) -> io::Result<()> {
+    let problems: Vec<String> = dirs
+        .iter()
+        .filter_map(|dir| ownership_problem(dir, uid))
+        .collect();
+    if problems.is_empty() {
+        Ok(())
+    } else {
+        Err(io::Error::new(io::ErrorKind::PermissionDenied, problems.join("; ")))
+    }
+}
+
+fn ownership_problem(dir: &Path, uid: u32) -> Option<String> {
+    let metadata = std::fs::metadata(dir).ok()?;
+    if metadata.uid() != uid {
+        Some(format!("{} is owned by uid {}, expected {}", dir.display(), metadata.uid(), uid))
+    } else if metadata.mode() & 0o002 != 0 {
+        Some(format!("{} is world-writable", dir.display()))
+    } else {
+        None
+    }
+}
+
+/// Verifies the service directories against the current effective user
+pub fn verify_service_dirs() -> io::Result<()> {
+    verify_ownership(&service_dirs(), unsafe { libc::geteuid() })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::os::unix::fs::PermissionsExt;
+
+    #[test]
+    fn test_verify_ownership() {
+        let dir = tempfile::tempdir().unwrap();
+        let uid = unsafe { libc::geteuid() };
+        let dirs = vec![dir.path().to_path_buf(), dir.path().join("missing")];
+
+        assert!(verify_ownership(&dirs, uid).is_ok());
+        assert!(verify_ownership(&dirs, uid + 1).is_err());
+
+        std::fs::set_permissions(dir.path(), std::fs::Permissions::from_mode(0o777)).unwrap();
+        let error = verify_ownership(&dirs, uid).unwrap_err();
+        assert!(error.to_string().contains("world-writable"));
+    }
+}
@@ -0,0 +1,159 @@
+use std::sync::OnceLock;
+use serde_json::Value;
+use log::error;
+
+use crate::error::{Error, Result};
+use crate::utils::hmac::compute_hmac_sha256;
+use crate::utils::{service_key, webhook_secrets};
+
+/// Context mixed into the service key so pseudonyms never double as another
+/// use of the key
+const KEY_CONTEXT: &[u8] = b"payload-pseudonym";
+
+/// Replaces commit messages and values that cannot be pseudonymized
+pub const REDACTED: &str = "[redacted]";
+
+/// Fields holding a user name or email anywhere in a payload
+const IDENTITY_FIELDS: &[&str] = &[
+    "login", "username", "user_name", "user_username", "email", "user_email",
+    "author_name", "author_email", "committer_name", "committer_email",
+];
+
+/// Objects describing a person, whose `name` is a user name as well
+const PERSON_OBJECTS: &[&str] = &["author", "committer", "pusher", "user", "sender"];
+
+/// Whether stored payloads and logs are anonymized, from `REDACT_PAYLOADS`
+/// (default off)
+pub fn enabled() -> bool {
+    crate::api::platform::env_flag("REDACT_PAYLOADS", false)
+}
+
+/// Pseudonym key derived from the service key
+fn pseudonym_key() -> Result<&'static str> {
+    static KEY: OnceLock<String> = OnceLock::new();
+    if let Some(key) = KEY.get() {
+        return Ok(key);
+    }
+    let password = service_key::get_service_key().map_err(|e| Error::config(format!("Failed to read the service key: {}", e)))?;
+    Ok(KEY.get_or_init(|| compute_hmac_sha256(KEY_CONTEXT, &password)))
+}
+
+/// Stable pseudonym of a user name or email under `key`; case is ignored so
+/// that a user keeps one pseudonym across forges
+pub fn pseudonym_with(key: &str, value: &str) -> String {
+    let digest = compute_hmac_sha256(value.trim().to_lowercase().as_bytes(), key);
+    format!("anon-{}", &digest[..12])
+}
+
+/// Pseudonym of `value` keyed by the service key; without the key nothing
+/// identifies the user at all
+pub fn pseudonym(value: &str) -> String {
+    match pseudonym_key() {
+        Ok(key) => pseudonym_with(key, value),
+        Err(_) => REDACTED.to_string(),
+    }
+}
+
+/// A user name as it may appear in logs
+pub fn user(name: &str) -> String {
+    if enabled() {
+        pseudonym(name)
+    } else {
+        name.to_string()
+    }
+}
+
+fn redact_value(value: &mut Value, anonymize: &dyn Fn(&str) -> String) {
+    match value {
+        Value::Object(fields) => {
+            for (field, value) in fields.iter_mut() {
+                match value {
+                    Value::String(text) if IDENTITY_FIELDS.contains(&field.as_str()) => *text = anonymize(text),
+                    Value::String(text) if field == "message" => *text = REDACTED.to_string(),
+                    Value::Object(person) if PERSON_OBJECTS.contains(&field.as_str()) => {
+                        if let Some(Value::String(name)) = person.get_mut("name") {
+                            *name = anonymize(name);
+                        }
+                        redact_value(value, anonymize);
+                    }
+                    _ => redact_value(value, anonymize),
+                }
+            }
+        }
+        Value::Array(items) => items.iter_mut().for_each(|item| redact_value(item, anonymize)),
+        _ => {}
+    }
+}
+
+/// `body` with user names and emails replaced by pseudonyms under `key` and
+/// commit messages removed; a body that is not JSON is dropped entirely
+pub fn redact_payload_with(key: &str, body: &str) -> String {
+    match serde_json::from_str::<Value>(body) {
+        Ok(mut payload) => {
+            redact_value(&mut payload, &|value| pseudonym_with(key, value));
+            payload.to_string()
+        }
+        Err(_) => REDACTED.to_string(),
+    }
+}
+
+/// `redact_payload_with` keyed by the service key
+pub fn redact_payload(body: &str) -> String {
+    match pseudonym_key() {
+        Ok(key) => redact_payload_with(key, body),
+        Err(e) => {
+            error!("Failed to derive the pseudonym key, dropping the payload: {}", e);
+            REDACTED.to_string()
+        }
+    }
+}
+
+/// The form of a webhook body to persist: unchanged when anonymization is
+/// off, otherwise the redacted body plus the original encrypted with the
+/// service key, which only this instance can read back for a replay
+pub fn for_storage(body: String) -> (String, Option<String>) {
+    if !enabled() {
+        return (body, None);
+    }
+    let sealed = webhook_secrets::storage_key().and_then(|key| webhook_secrets::encrypt(key, &body));
+    let sealed = sealed
+        .map_err(|e| error!("Failed to encrypt the original payload, it cannot be replayed: {}", e))
+        .ok();
+    (redact_payload(&body), sealed)
+}
+
+/// The original webhook body of a stored `body` and its encrypted copy
+pub fn original(body: &str, sealed: Option<&str>) -> Result<String> {
+    match sealed {
+        None => Ok(body.to_string()),
+        Some(sealed) => webhook_secrets::decrypt(webhook_secrets::storage_key()?, sealed),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_payloads_are_anonymized() {
+        let body = r#"{
+            "repository": { "full_name": "org/repo" },
+            "sender": { "login": "Alice", "id": 7 },
+            "user_name": "alice",
+            "commits": [{ "id": "abc", "message": "Fix the thing\n\nSigned-off-by: Alice <alice@example.com>",
+                          "author": { "name": "Alice Doe", "email": "alice@example.com" } }]
+        }"#;
+        let redacted: Value = serde_json::from_str(&redact_payload_with("key", body)).unwrap();
+        assert_eq!(redacted["repository"]["full_name"], "org/repo");
+        assert_eq!(redacted["sender"]["id"], 7);
+        assert_eq!(redacted["sender"]["login"], pseudonym_with("key", "alice"));
+        assert_eq!(redacted["user_name"], redacted["sender"]["login"]);
+        assert_eq!(redacted["commits"][0]["id"], "abc");
+        assert_eq!(redacted["commits"][0]["message"], REDACTED);
+        assert_eq!(redacted["commits"][0]["author"]["name"], pseudonym_with("key", "Alice Doe"));
+        assert_eq!(redacted["commits"][0]["author"]["email"], pseudonym_with("key", "alice@example.com"));
+        assert!(!redacted.to_string().contains("alice"));
+        assert_ne!(pseudonym_with("other", "alice"), pseudonym_with("key", "alice"));
+        assert_eq!(redact_payload_with("key", "payload=alice"), REDACTED);
+    }
+}
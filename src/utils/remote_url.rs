@@ -0,0 +1,195 @@
+use std::fmt;
+
+use crate::utils::gitlab;
+
+/// Credential mechanism needed to talk to a remote
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CredentialKind {
+    /// Username and token sent over HTTP(S)
+    Token,
+    /// SSH key (agent or key file)
+    SshKey,
+}
+
+/// A git remote URL in one of the syntaxes git accepts
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum RemoteUrl {
+    /// `https://host[:port]/path` (or `http://`)
+    Http { scheme: String, host: String, port: Option<u16>, path: String },
+    /// `ssh://[user@]host[:port]/path`
+    Ssh { user: Option<String>, host: String, port: Option<u16>, path: String },
+    /// `[user@]host:path`
+    Scp { user: Option<String>, host: String, path: String },
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RemoteUrlError(pub String);
+
+impl fmt::Display for RemoteUrlError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Invalid remote URL: {}", self.0)
+    }
+}
+
+impl std::error::Error for RemoteUrlError {}
+
+/// Splits `host[:port]` into its parts
+fn split_host_port(authority: &str, url: &str) -> Result<(String, Option<u16>), RemoteUrlError> {
+    let (host, port) = match authority.rsplit_once(':') {
+        Some((host, port)) => {
+            let port = port
+                .parse::<u16>()
+                .map_err(|_| RemoteUrlError(format!("{} (bad port)", url)))?;
+            (host, Some(port))
+        }
+        None => (authority, None),
+    };
+    if host.is_empty() {
+        return Err(RemoteUrlError(format!("{} (missing host)", url)));
+    }
+    Ok((host.to_string(), port))
+}
+
+fn check_path(path: &str, url: &str) -> Result<String, RemoteUrlError> {
+    let path = path.trim_start_matches('/');
+    if path.is_empty() {
+        return Err(RemoteUrlError(format!("{} (missing repository path)", url)));
+    }
+    Ok(path.to_string())
+}
+
+impl RemoteUrl {
+    pub fn parse(url: &str) -> Result<RemoteUrl, RemoteUrlError> {
+        let url = url.trim();
+        if url.chars().any(char::is_whitespace) {
+            return Err(RemoteUrlError(format!("{} (contains whitespace)", url)));
+        }
+
+        if let Some((scheme, rest)) = url.split_once("://") {
+            let (authority, path) = rest.split_once('/').unwrap_or((rest, ""));
+            let (user, host_port) = match authority.rsplit_once('@') {
+                Some((user, host_port)) => (Some(user.to_string()), host_port),
+                None => (None, authority),
+            };
+            let (host, port) = split_host_port(host_port, url)?;
+            let path = check_path(path, url)?;
+
+            return match scheme {
+                "https" | "http" => Ok(RemoteUrl::Http { scheme: scheme.to_string(), host, port, path }),
+                "ssh" | "git+ssh" => Ok(RemoteUrl::Ssh { user, host, port, path }),
+                _ => Err(RemoteUrlError(format!("{} (unsupported scheme {})", url, scheme))),
+            };
+        }
+
+        // scp-like syntax: [user@]host:path (a colon before any slash)
+        match url.split_once(':') {
+            Some((authority, path)) if !authority.contains('/') => {
+                let (user, host) = match authority.split_once('@') {
+                    Some((user, host)) => (Some(user.to_string()), host.to_string()),
+                    None => (None, authority.to_string()),
+                };
+                if host.is_empty() {
+                    return Err(RemoteUrlError(format!("{} (missing host)", url)));
+                }
+                Ok(RemoteUrl::Scp { user, host, path: check_path(path, url)? })
+            }
+            _ => Err(RemoteUrlError(format!("{} (unrecognized syntax)", url))),
+        }
+    }
+
+    pub fn host(&self) -> &str {
+        match self {
+            RemoteUrl::Http { host, .. } | RemoteUrl::Ssh { host, .. } | RemoteUrl::Scp { host, .. } => host,
+        }
+    }
+
+    pub fn path(&self) -> &str {
+        match self {
+            RemoteUrl::Http { path, .. } | RemoteUrl::Ssh { path, .. } | RemoteUrl::Scp { path, .. } => path,
+        }
+    }
+
+    /// Forge hosting this remote: `github` for github.com, `gitlab` for the
+    /// configured GitLab instance, `gitcode` otherwise
+    pub fn platform(&self) -> &'static str {
+        if self.host().eq_ignore_ascii_case("github.com") {
+            "github"
+        } else if gitlab::is_gitlab_host(self.host()) {
+            "gitlab"
+        } else {
+            "gitcode"
+        }
+    }
+
+    /// `(namespace, repo_name)` of the remote path, without a `.git` suffix
+    pub fn namespace_and_name(&self) -> Option<(&str, &str)> {
+        let path = self.path().trim_end_matches('/');
+        let path = path.strip_suffix(".git").unwrap_or(path);
+        path.rsplit_once('/')
+    }
+
+    /// Credential mechanism pushes to this remote use
+    pub fn credential_kind(&self) -> CredentialKind {
+        match self {
+            RemoteUrl::Http { .. } => CredentialKind::Token,
+            RemoteUrl::Ssh { .. } | RemoteUrl::Scp { .. } => CredentialKind::SshKey,
+        }
+    }
+}
+
+impl fmt::Display for RemoteUrl {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RemoteUrl::Http { scheme, host, port, path } => {
+                let port = port.map(|p| format!(":{}", p)).unwrap_or_default();
+                write!(f, "{}://{}{}/{}", scheme, host, port, path)
+            }
+            RemoteUrl::Ssh { user, host, port, path } => {
+                let user = user.as_ref().map(|u| format!("{}@", u)).unwrap_or_default();
+                let port = port.map(|p| format!(":{}", p)).unwrap_or_default();
+                write!(f, "ssh://{}{}{}/{}", user, host, port, path)
+            }
+            RemoteUrl::Scp { user, host, path } => {
+                let user = user.as_ref().map(|u| format!("{}@", u)).unwrap_or_default();
+                write!(f, "{}{}:{}", user, host, path)
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_supported_syntaxes() {
+        let https = RemoteUrl::parse("https://gitcode.com/openHiTLS/openhitls.git").unwrap();
+        assert_eq!(https.host(), "gitcode.com");
+        assert_eq!(https.path(), "openHiTLS/openhitls.git");
+        assert_eq!(https.credential_kind(), CredentialKind::Token);
+        assert_eq!(https.namespace_and_name(), Some(("openHiTLS", "openhitls")));
+        assert_eq!(https.platform(), "gitcode");
+
+        let ssh = RemoteUrl::parse("ssh://git@gitcode.com:2222/openHiTLS/openhitls.git").unwrap();
+        assert_eq!(ssh, RemoteUrl::Ssh {
+            user: Some("git".to_string()),
+            host: "gitcode.com".to_string(),
+            port: Some(2222),
+            path: "openHiTLS/openhitls.git".to_string(),
+        });
+        assert_eq!(ssh.credential_kind(), CredentialKind::SshKey);
+
+        let scp = RemoteUrl::parse("git@github.com:openHiTLS/openhitls.git").unwrap();
+        assert_eq!(scp.host(), "github.com");
+        assert_eq!(scp.to_string(), "git@github.com:openHiTLS/openhitls.git");
+    }
+
+    #[test]
+    fn test_parse_rejects_invalid_urls() {
+        assert!(RemoteUrl::parse("ftp://example.com/repo.git").is_err());
+        assert!(RemoteUrl::parse("https://gitcode.com").is_err());
+        assert!(RemoteUrl::parse("https://:443/repo.git").is_err());
+        assert!(RemoteUrl::parse("/local/path/repo.git").is_err());
+        assert!(RemoteUrl::parse("git@github.com:").is_err());
+    }
+}
@@ -0,0 +1,126 @@
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use std::sync::{Mutex, OnceLock};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use log::error;
+
+use crate::utils::templates::{CommentTemplates, MessageKind};
+use crate::utils::{state, state_store};
+
+/// Label required to backport a PR again after it was reopened
+pub const BACKPORT_AGAIN_LABEL: &str = "backport: again";
+
+/// How a PR that is reopened after it was backported is handled when it is
+/// merged again
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum ReopenedPolicy {
+    /// The reopen is flagged and the re-merge is only backported again with
+    /// the `backport: again` label
+    #[default]
+    RequireLabel,
+    /// Reopens are not tracked; commits already backported are skipped
+    Ignore,
+}
+
+/// Whether a PR webhook action reopens the PR (`reopened` on GitHub,
+/// `reopen` on GitCode and GitLab)
+pub fn is_reopen_action(action: &str) -> bool {
+    action == "reopened" || action == "reopen"
+}
+
+/// PRs reopened after their backport, persisted as JSON in `reopened.json`
+/// until they are backported again
+pub struct ReopenedStore {
+    path: PathBuf,
+    /// PR key -> when it was reopened
+    reopened: BTreeMap<String, DateTime<Utc>>,
+}
+
+impl ReopenedStore {
+    pub fn open(path: &Path) -> ReopenedStore {
+        let reopened = match state_store::read_document(path) {
+            Ok(Some(contents)) => serde_json::from_str(&contents).unwrap_or_else(|e| {
+                error!("Failed to parse reopened PRs {:?}, starting empty: {}", path, e);
+                BTreeMap::new()
+            }),
+            _ => BTreeMap::new(),
+        };
+        ReopenedStore { path: path.to_path_buf(), reopened }
+    }
+
+    fn save(&self) {
+        let result = serde_json::to_string_pretty(&self.reopened)
+            .map_err(std::io::Error::from)
+            .and_then(|contents| state_store::write_document(&self.path, &contents));
+        if let Err(e) = result {
+            error!("Failed to persist reopened PRs {:?}: {}", self.path, e);
+        }
+    }
+
+    /// Flags the PR `key` as reopened; the first reopen is kept
+    pub fn mark(&mut self, key: &str, at: DateTime<Utc>) {
+        if !self.reopened.contains_key(key) {
+            self.reopened.insert(key.to_string(), at);
+            self.save();
+        }
+    }
+
+    /// When the PR `key` was reopened, if it was not backported again since
+    pub fn reopened_at(&self, key: &str) -> Option<DateTime<Utc>> {
+        self.reopened.get(key).copied()
+    }
+
+    /// Clears the flag once the PR was backported again
+    pub fn clear(&mut self, key: &str) {
+        if self.reopened.remove(key).is_some() {
+            self.save();
+        }
+    }
+}
+
+/// Key of a PR: numbers are only unique per forge and repository
+pub fn pr_key(platform: &str, namespace: &str, repo_name: &str, pr: u32) -> String {
+    format!("{}:{}/{}#{}", platform, namespace, repo_name, pr)
+}
+
+/// Comment warning that a PR backported onto `branches` was reopened
+pub fn format_reopened_comment(branches: &[String], templates: &CommentTemplates) -> String {
+    let branches: String = branches.iter().map(|branch| format!("- `{}`\n", branch)).collect();
+    templates.render(MessageKind::ReopenedAfterBackport, &[("branches", &branches), ("label", BACKPORT_AGAIN_LABEL)])
+}
+
+/// The process-wide reopened PR flags
+pub fn store() -> &'static Mutex<ReopenedStore> {
+    static STORE: OnceLock<Mutex<ReopenedStore>> = OnceLock::new();
+    STORE.get_or_init(|| Mutex::new(ReopenedStore::open(&state::state_dir().join("reopened.json"))))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_reopened_prs_are_flagged_until_cleared() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("reopened.json");
+        let key = pr_key("gitcode", "org", "repo", 42);
+        let first: DateTime<Utc> = "2024-06-01T08:00:00Z".parse().unwrap();
+
+        let mut store = ReopenedStore::open(&path);
+        store.mark(&key, first);
+        store.mark(&key, "2024-06-02T08:00:00Z".parse().unwrap());
+        assert_eq!(store.reopened_at(&pr_key("gitlab", "org", "repo", 42)), None);
+
+        let mut store = ReopenedStore::open(&path);
+        assert_eq!(store.reopened_at(&key), Some(first));
+        store.clear(&key);
+        assert_eq!(ReopenedStore::open(&path).reopened_at(&key), None);
+
+        assert!(is_reopen_action("reopened") && is_reopen_action("reopen"));
+        assert!(!is_reopen_action("closed"));
+        let comment = format_reopened_comment(&["release-1.0".to_string()], &CommentTemplates::default());
+        assert!(comment.contains("`release-1.0`") && comment.contains(BACKPORT_AGAIN_LABEL));
+    }
+}
@@ -0,0 +1,229 @@
+use std::env;
+use std::fs::{File, OpenOptions};
+use std::os::unix::io::AsRawFd;
+use std::path::{Path, PathBuf};
+use git2::{FetchOptions, RemoteCallbacks, Repository};
+use log::info;
+
+use crate::error::{Error, Result};
+use crate::utils::{deadline, git};
+
+/// Bare clones of source repositories, kept warm between jobs
+pub struct RepoCache {
+    root: PathBuf,
+}
+
+/// Exclusive `flock` on `<cache>.lock`, released when dropped
+///
+/// Every acquisition opens the file anew, so the lock excludes other jobs of
+/// this process as well as other instances sharing the cache directory.
+struct CacheLock {
+    _file: File,
+}
+
+impl CacheLock {
+    fn acquire(path: &Path) -> Result<CacheLock> {
+        let mut name = path.file_name().unwrap_or_default().to_os_string();
+        name.push(".lock");
+        let lock_path = path.with_file_name(name);
+        let lock_error = |e: std::io::Error| Error::Io(std::io::Error::new(e.kind(), format!("Failed to lock repository cache {:?}: {}", lock_path, e)));
+        if let Some(parent) = lock_path.parent() {
+            std::fs::create_dir_all(parent).map_err(lock_error)?;
+        }
+        let file = OpenOptions::new().create(true).truncate(false).write(true).open(&lock_path).map_err(lock_error)?;
+        // SAFETY: flock on a descriptor owned by `file`, which outlives the lock
+        if unsafe { libc::flock(file.as_raw_fd(), libc::LOCK_EX) } != 0 {
+            return Err(lock_error(std::io::Error::last_os_error()));
+        }
+        Ok(CacheLock { _file: file })
+    }
+}
+
+fn fetch_options(platform: &str) -> FetchOptions<'static> {
+    let mut callbacks = RemoteCallbacks::new();
+    match platform {
+        "github" => callbacks.credentials(git::github_credentials_callback),
+        "gitlab" => callbacks.credentials(git::gitlab_credentials_callback),
+        _ => callbacks.credentials(git::gitcode_credentials_callback),
+    };
+    deadline::watch_transfers(&mut callbacks);
+    let mut options = FetchOptions::new();
+    options.remote_callbacks(callbacks);
+    options
+}
+
+/// Refspec fetching the head of PR `number` into the same ref in the cache
+pub fn pr_refspec(platform: &str, number: u32) -> String {
+    match platform {
+        "github" => format!("+refs/pull/{}/head:refs/pull/{}/head", number, number),
+        _ => format!("+refs/merge-requests/{}/head:refs/merge-requests/{}/head", number, number),
+    }
+}
+
+impl RepoCache {
+    pub fn new(root: &Path) -> Self {
+        RepoCache { root: root.to_path_buf() }
+    }
+
+    /// Cache rooted at `REPO_CACHE_DIR`, defaulting to `cache`
+    pub fn from_env() -> Self {
+        let root = env::var("REPO_CACHE_DIR").unwrap_or_else(|_| "cache".to_string());
+        RepoCache::new(Path::new(&root))
+    }
+
+    pub fn root(&self) -> &Path {
+        &self.root
+    }
+
+    /// Cache of the repository `full_name` (`owner/repo`, with any subgroups)
+    /// on `platform`
+    pub fn path(&self, platform: &str, full_name: &str) -> PathBuf {
+        self.root.join(platform).join(format!("{}.git", full_name))
+    }
+
+    /// Creates or updates the bare clone of `repo_url`, fetching all branches
+    /// plus `extra_refspecs`; its HEAD follows the default branch of `repo_url`
+    pub fn update(&self, platform: &str, repo_url: &str, full_name: &str, extra_refspecs: &[String]) -> Result<PathBuf> {
+        let path = self.path(platform, full_name);
+        let _lock = CacheLock::acquire(&path)?;
+
+        let repo = match Repository::open_bare(&path) {
+            Ok(repo) => repo,
+            Err(_) => {
+                info!("Creating repository cache at {:?}", path);
+                std::fs::create_dir_all(&path)?;
+                Repository::init_bare(&path)?
+            }
+        };
+        let mut remote = match repo.find_remote("origin") {
+            Ok(_) => {
+                repo.remote_set_url("origin", repo_url)?;
+                repo.find_remote("origin")?
+            }
+            Err(_) => repo.remote("origin", repo_url)?,
+        };
+
+        let mut refspecs = vec!["+refs/heads/*:refs/heads/*".to_string()];
+        refspecs.extend(extra_refspecs.iter().cloned());
+        info!("Updating repository cache {:?} with {:?}", path, refspecs);
+        remote.fetch(&refspecs, Some(&mut fetch_options(platform)), None)?;
+        // A fresh bare repository points HEAD at an unborn `master`
+        if let Ok(default_branch) = remote.default_branch() {
+            if let Some(default_branch) = default_branch.as_str() {
+                repo.set_head(default_branch)?;
+            }
+        }
+        Ok(path)
+    }
+
+    /// Fetches a PR head into the cache ahead of the backport job
+    pub fn prewarm_pr(&self, platform: &str, repo_url: &str, full_name: &str, number: u32) -> Result<PathBuf> {
+        self.update(platform, repo_url, full_name, &[pr_refspec(platform, number)])
+    }
+
+    /// Creates a job workspace at `local_path` that borrows the objects of
+    /// the cache, then updates it from `repo_url`
+    ///
+    /// Like a `git worktree` nothing is copied out of the cache, but the
+    /// workspace has its own refs, so concurrent jobs on the same repository
+    /// can check out and move branches independently. Only objects missing
+    /// from the cache are downloaded. Returns `Ok(None)` when there is no
+    /// cache for the repository yet.
+    pub fn clone_workspace(&self, platform: &str, repo_url: &str, full_name: &str, local_path: &Path) -> Result<Option<Repository>> {
+        let path = self.path(platform, full_name);
+        let Ok(cache) = Repository::open_bare(&path) else {
+            return Ok(None);
+        };
+
+        let repo = Repository::init(local_path)?;
+        let objects = std::fs::canonicalize(path.join("objects"))?;
+        std::fs::write(repo.path().join("objects/info/alternates"), format!("{}\n", objects.display()))
+            .map_err(|e| Error::Job(format!("Failed to link workspace to the cache: {}", e)))?;
+        // Reopen so the object database picks up the alternates
+        let repo = Repository::open(local_path)?;
+        {
+            let _lock = CacheLock::acquire(&path)?;
+            info!("Creating workspace {:?} from cache {:?}", local_path, path);
+            repo.remote("origin", &path.to_string_lossy())?.fetch(&["+refs/heads/*:refs/remotes/origin/*"], None, None)?;
+        }
+
+        repo.remote_set_url("origin", repo_url)?;
+        repo.find_remote("origin")?.fetch(
+            &["+refs/heads/*:refs/remotes/origin/*"],
+            Some(&mut fetch_options(platform)),
+            None,
+        )?;
+
+        // Check out the default branch of the cache at the fresh remote tip
+        let default_branch = cache.find_reference("HEAD").ok()
+            .and_then(|head| head.symbolic_target().and_then(|target| target.strip_prefix("refs/heads/")).map(str::to_string));
+        if let Some(branch) = default_branch {
+            if let Ok(upstream) = repo.find_reference(&format!("refs/remotes/origin/{}", branch)).and_then(|r| r.peel_to_commit()) {
+                let mut local = repo.branch(&branch, &upstream, true)?;
+                local.set_upstream(Some(&format!("origin/{}", branch)))?;
+                repo.set_head(&format!("refs/heads/{}", branch))?;
+                repo.checkout_head(Some(git2::build::CheckoutBuilder::new().force()))?;
+            }
+        }
+        Ok(Some(repo))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn commit(repo: &Repository, message: &str) -> git2::Oid {
+        let sig = git2::Signature::now("test", "test@example.com").unwrap();
+        let mut index = repo.index().unwrap();
+        std::fs::write(repo.workdir().unwrap().join("file.txt"), message).unwrap();
+        index.add_path(Path::new("file.txt")).unwrap();
+        let tree = repo.find_tree(index.write_tree().unwrap()).unwrap();
+        let parent = repo.head().ok().and_then(|h| h.peel_to_commit().ok());
+        let parents: Vec<&git2::Commit> = parent.iter().collect();
+        repo.commit(Some("HEAD"), &sig, &sig, message, &tree, &parents).unwrap()
+    }
+
+    #[test]
+    fn test_clone_workspace_from_warm_cache() {
+        let dir = tempfile::tempdir().unwrap();
+        let origin = Repository::init(dir.path().join("origin")).unwrap();
+        commit(&origin, "first");
+        let url = dir.path().join("origin").to_string_lossy().to_string();
+
+        let cache = RepoCache::new(&dir.path().join("cache"));
+        assert!(cache.clone_workspace("github", &url, "org/repo", &dir.path().join("none")).unwrap().is_none());
+        cache.update("github", &url, "org/repo", &[]).unwrap();
+
+        // New upstream commit after the cache was warmed
+        let latest = commit(&origin, "second");
+        let workspace = cache.clone_workspace("github", &url, "org/repo", &dir.path().join("ws")).unwrap().unwrap();
+        assert_eq!(workspace.head().unwrap().peel_to_commit().unwrap().id(), latest);
+        assert_eq!(std::fs::read_to_string(dir.path().join("ws/file.txt")).unwrap(), "second");
+        assert!(dir.path().join("ws/.git/objects/info/alternates").exists());
+
+        // A second workspace moves its branches without affecting the first
+        let other = cache.clone_workspace("github", &url, "org/repo", &dir.path().join("ws2")).unwrap().unwrap();
+        commit(&other, "third");
+        assert_eq!(workspace.head().unwrap().peel_to_commit().unwrap().id(), latest);
+    }
+
+    #[test]
+    fn test_cache_follows_a_main_default_branch_per_owner() {
+        let dir = tempfile::tempdir().unwrap();
+        let origin = Repository::init(dir.path().join("origin")).unwrap();
+        origin.set_head("refs/heads/main").unwrap();
+        let tip = commit(&origin, "on main");
+        let url = dir.path().join("origin").to_string_lossy().to_string();
+
+        let cache = RepoCache::new(&dir.path().join("cache"));
+        let path = cache.update("gitcode", &url, "a/foo", &[]).unwrap();
+        assert_ne!(path, cache.path("gitcode", "b/foo"));
+        assert_eq!(Repository::open_bare(&path).unwrap().find_reference("HEAD").unwrap().symbolic_target(), Some("refs/heads/main"));
+        assert!(cache.clone_workspace("gitcode", &url, "b/foo", &dir.path().join("other")).unwrap().is_none());
+
+        let workspace = cache.clone_workspace("gitcode", &url, "a/foo", &dir.path().join("ws")).unwrap().unwrap();
+        assert_eq!(workspace.head().unwrap().name(), Some("refs/heads/main"));
+        assert_eq!(workspace.head().unwrap().peel_to_commit().unwrap().id(), tip);
+    }
+}
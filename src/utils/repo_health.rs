@@ -0,0 +1,145 @@
+use std::collections::BTreeMap;
+use std::env;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex, OnceLock};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use log::error;
+
+use crate::utils::clock::{self, Clock};
+use crate::utils::{state, state_store};
+use crate::utils::stats::JobOutcome;
+
+/// Consecutive failed jobs after which a repository is marked unhealthy,
+/// from `REPO_FAILURE_BUDGET` (default 5, `0` disables the budget)
+pub fn failure_budget() -> u32 {
+    env::var("REPO_FAILURE_BUDGET")
+        .ok()
+        .and_then(|value| value.parse::<u32>().ok())
+        .unwrap_or(5)
+}
+
+/// Failure streak of one repository
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct RepoHealth {
+    pub consecutive_failures: u32,
+    /// Set once the failure budget is exhausted; cleared by an explicit resume
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub unhealthy_since: Option<DateTime<Utc>>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub last_error: Option<String>,
+}
+
+/// Per-repository failure streaks persisted as JSON in `repo_health.json`
+///
+/// Conflicts do not count: they are about one PR, not about the repository.
+pub struct HealthStore {
+    path: PathBuf,
+    repos: BTreeMap<String, RepoHealth>,
+    clock: Arc<dyn Clock>,
+}
+
+impl HealthStore {
+    pub fn open(path: &Path) -> HealthStore {
+        let repos = match state_store::read_document(path) {
+            Ok(Some(contents)) => serde_json::from_str(&contents).unwrap_or_else(|e| {
+                error!("Failed to parse repository health {:?}, starting empty: {}", path, e);
+                BTreeMap::new()
+            }),
+            _ => BTreeMap::new(),
+        };
+        HealthStore { path: path.to_path_buf(), repos, clock: clock::system() }
+    }
+
+    /// Uses `clock` to date unhealthy marks (a `MockClock` in tests)
+    pub fn with_clock(mut self, clock: Arc<dyn Clock>) -> Self {
+        self.clock = clock;
+        self
+    }
+
+    fn save(&self) {
+        let result = serde_json::to_string_pretty(&self.repos)
+            .map_err(std::io::Error::from)
+            .and_then(|contents| state_store::write_document(&self.path, &contents));
+        if let Err(e) = result {
+            error!("Failed to persist repository health {:?}: {}", self.path, e);
+        }
+    }
+
+    /// Counts a finished job of `repo`; returns true when this job exhausted
+    /// the failure budget and the repository just became unhealthy
+    pub fn record(&mut self, repo: &str, outcome: JobOutcome, error: Option<&str>, budget: u32) -> bool {
+        let now = self.clock.now();
+        let health = self.repos.entry(repo.to_string()).or_default();
+        let tripped = match outcome {
+            JobOutcome::Failed => {
+                health.consecutive_failures += 1;
+                health.last_error = error.map(str::to_string);
+                let tripped = budget > 0 && health.consecutive_failures >= budget && health.unhealthy_since.is_none();
+                if tripped {
+                    health.unhealthy_since = Some(now);
+                }
+                tripped
+            }
+            JobOutcome::Succeeded => {
+                health.consecutive_failures = 0;
+                health.last_error = None;
+                false
+            }
+            JobOutcome::Conflicted => false,
+        };
+        self.save();
+        tripped
+    }
+
+    pub fn is_unhealthy(&self, repo: &str) -> bool {
+        self.repos.get(repo).is_some_and(|health| health.unhealthy_since.is_some())
+    }
+
+    pub fn get(&self, repo: &str) -> Option<RepoHealth> {
+        self.repos.get(repo).cloned()
+    }
+
+    /// Clears the unhealthy mark and the failure streak; returns whether the
+    /// repository was unhealthy
+    pub fn resume(&mut self, repo: &str) -> bool {
+        let Some(health) = self.repos.remove(repo) else { return false };
+        self.save();
+        health.unhealthy_since.is_some()
+    }
+}
+
+/// The process-wide repository health store
+pub fn store() -> &'static Mutex<HealthStore> {
+    static STORE: OnceLock<Mutex<HealthStore>> = OnceLock::new();
+    STORE.get_or_init(|| Mutex::new(HealthStore::open(&state::state_dir().join("repo_health.json"))))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_failure_budget_trips_once_until_resumed() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("repo_health.json");
+        let mut store = HealthStore::open(&path);
+
+        assert!(!store.record("repo", JobOutcome::Failed, Some("401 Unauthorized"), 2));
+        // Conflicts neither count nor reset the streak
+        assert!(!store.record("repo", JobOutcome::Conflicted, None, 2));
+        assert!(store.record("repo", JobOutcome::Failed, Some("401 Unauthorized"), 2));
+        assert!(!store.record("repo", JobOutcome::Failed, Some("401 Unauthorized"), 2));
+
+        let mut reopened = HealthStore::open(&path);
+        assert!(reopened.is_unhealthy("repo"));
+        assert_eq!(reopened.get("repo").unwrap().consecutive_failures, 3);
+        assert!(reopened.resume("repo"));
+        assert!(!reopened.is_unhealthy("repo"));
+        assert!(!reopened.resume("repo"));
+
+        assert!(!reopened.record("other", JobOutcome::Failed, None, 0));
+        assert!(!reopened.record("other", JobOutcome::Failed, None, 0));
+        assert!(!reopened.is_unhealthy("other"));
+    }
+}
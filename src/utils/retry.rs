@@ -0,0 +1,247 @@
+use std::future::Future;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use log::info;
+use rand::Rng;
+
+use crate::utils::clock::{self, Clock};
+use crate::utils::deadline;
+
+/// Retry policy with exponential backoff
+///
+/// ```
+/// use std::time::Duration;
+/// use webhook_service::utils::retry::RetryPolicy;
+///
+/// let policy = RetryPolicy::new()
+///     .max_attempts(5)
+///     .backoff(Duration::from_millis(1), Duration::from_millis(10))
+///     .deadline(Duration::from_secs(1));
+/// let result: Result<u32, &str> = policy.retry_blocking(|attempt| {
+///     if attempt < 3 { Err("transient") } else { Ok(attempt) }
+/// }, |_| true);
+/// assert_eq!(result, Ok(3));
+/// ```
+#[derive(Debug, Clone)]
+pub struct RetryPolicy {
+    max_attempts: u32,
+    initial_backoff: Duration,
+    max_backoff: Duration,
+    multiplier: f64,
+    jitter: bool,
+    deadline: Option<Duration>,
+    clock: Arc<dyn Clock>,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        RetryPolicy {
+            max_attempts: 3,
+            initial_backoff: Duration::from_millis(500),
+            max_backoff: Duration::from_secs(30),
+            multiplier: 2.0,
+            jitter: true,
+            deadline: None,
+            clock: clock::system(),
+        }
+    }
+}
+
+impl RetryPolicy {
+    pub fn new() -> Self {
+        RetryPolicy::default()
+    }
+
+    /// Total number of attempts, including the first one
+    pub fn max_attempts(mut self, attempts: u32) -> Self {
+        self.max_attempts = attempts.max(1);
+        self
+    }
+
+    /// Delay before the first retry and the cap for later ones
+    pub fn backoff(mut self, initial: Duration, max: Duration) -> Self {
+        self.initial_backoff = initial;
+        self.max_backoff = max.max(initial);
+        self
+    }
+
+    pub fn multiplier(mut self, multiplier: f64) -> Self {
+        self.multiplier = multiplier.max(1.0);
+        self
+    }
+
+    /// Randomize each delay between 50% and 100% of its nominal value
+    pub fn jitter(mut self, jitter: bool) -> Self {
+        self.jitter = jitter;
+        self
+    }
+
+    /// Stop retrying once the next attempt would start after `deadline`
+    pub fn deadline(mut self, deadline: Duration) -> Self {
+        self.deadline = Some(deadline);
+        self
+    }
+
+    /// Clock used for sleeping and the deadline (a `MockClock` in tests)
+    pub fn clock(mut self, clock: Arc<dyn Clock>) -> Self {
+        self.clock = clock;
+        self
+    }
+
+    /// Delay after the given (1-based) failed attempt
+    pub fn delay_for(&self, attempt: u32) -> Duration {
+        let factor = self.multiplier.powi(attempt.saturating_sub(1) as i32);
+        let nominal = self.initial_backoff.mul_f64(factor).min(self.max_backoff);
+        if self.jitter {
+            nominal.mul_f64(rand::thread_rng().gen_range(0.5..=1.0))
+        } else {
+            nominal
+        }
+    }
+
+    /// Returns the delay before the next attempt, or `None` to give up
+    fn next_delay(&self, attempt: u32, started: Instant) -> Option<Duration> {
+        if attempt >= self.max_attempts {
+            return None;
+        }
+        let delay = self.delay_for(attempt);
+        match self.deadline {
+            Some(deadline) if self.clock.instant().duration_since(started) + delay > deadline => None,
+            _ => Some(delay),
+        }
+    }
+
+    /// Runs `operation` until it succeeds, `retry_on` rejects its error or the
+    /// policy is exhausted; the operation receives the 1-based attempt number
+    pub fn retry_blocking<T, E, F, P>(&self, mut operation: F, retry_on: P) -> Result<T, E>
+    where
+        F: FnMut(u32) -> Result<T, E>,
+        P: Fn(&E) -> bool,
+        E: std::fmt::Display,
+    {
+        let started = self.clock.instant();
+        let mut attempt = 1;
+        loop {
+            match operation(attempt) {
+                Ok(value) => return Ok(value),
+                Err(e) => {
+                    let delay = match retry_on(&e).then(|| self.next_delay(attempt, started)).flatten() {
+                        Some(delay) => delay,
+                        None => return Err(e),
+                    };
+                    info!("Attempt {}/{} failed: {}; retrying in {:?}", attempt, self.max_attempts, e, delay);
+                    self.clock.sleep(delay);
+                    attempt += 1;
+                }
+            }
+        }
+    }
+
+    /// Async variant of `retry_blocking`, sleeping with `tokio::time::sleep`
+    pub async fn retry_async<T, E, F, Fut, P>(&self, mut operation: F, retry_on: P) -> Result<T, E>
+    where
+        F: FnMut(u32) -> Fut,
+        Fut: Future<Output = Result<T, E>>,
+        P: Fn(&E) -> bool,
+        E: std::fmt::Display,
+    {
+        let started = self.clock.instant();
+        let mut attempt = 1;
+        loop {
+            match operation(attempt).await {
+                Ok(value) => return Ok(value),
+                Err(e) => {
+                    let delay = match retry_on(&e).then(|| self.next_delay(attempt, started)).flatten() {
+                        Some(delay) => delay,
+                        None => return Err(e),
+                    };
+                    info!("Attempt {}/{} failed: {}; retrying in {:?}", attempt, self.max_attempts, e, delay);
+                    self.clock.sleep_async(delay).await;
+                    attempt += 1;
+                }
+            }
+        }
+    }
+}
+
+/// Policy of a whole job failing transiently: `JOB_MAX_ATTEMPTS` attempts
+/// (default 3) between 5s and 60s apart
+pub fn job_policy() -> RetryPolicy {
+    let attempts = std::env::var("JOB_MAX_ATTEMPTS")
+        .ok()
+        .and_then(|value| value.parse::<u32>().ok())
+        .unwrap_or(3);
+    RetryPolicy::new().max_attempts(attempts).backoff(Duration::from_secs(5), Duration::from_secs(60))
+}
+
+/// Whether a git2 error is a transient transport failure worth retrying
+pub fn is_transient_git_error(error: &git2::Error) -> bool {
+    // A transfer aborted by the job deadline is not worth another attempt
+    matches!(
+        error.class(),
+        git2::ErrorClass::Net | git2::ErrorClass::Http | git2::ErrorClass::Ssh | git2::ErrorClass::Os
+    ) && !deadline::expired()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::clock::MockClock;
+
+    fn fast() -> RetryPolicy {
+        RetryPolicy::new().backoff(Duration::from_millis(1), Duration::from_millis(2)).jitter(false)
+    }
+
+    #[test]
+    fn test_retry_blocking_stops_on_non_retryable_error() {
+        let mut calls = 0;
+        let result: Result<(), String> = fast().max_attempts(5).retry_blocking(|_| {
+            calls += 1;
+            Err(if calls == 2 { "fatal".to_string() } else { "transient".to_string() })
+        }, |e| e == "transient");
+        assert_eq!(result, Err("fatal".to_string()));
+        assert_eq!(calls, 2);
+    }
+
+    #[test]
+    fn test_retry_blocking_gives_up_after_max_attempts() {
+        let mut calls = 0;
+        let result: Result<(), &str> = fast().max_attempts(3).retry_blocking(|_| {
+            calls += 1;
+            Err("transient")
+        }, |_| true);
+        assert!(result.is_err());
+        assert_eq!(calls, 3);
+    }
+
+    #[test]
+    fn test_delay_for_is_capped() {
+        let policy = RetryPolicy::new().backoff(Duration::from_millis(100), Duration::from_millis(250)).jitter(false);
+        assert_eq!(policy.delay_for(1), Duration::from_millis(100));
+        assert_eq!(policy.delay_for(2), Duration::from_millis(200));
+        assert_eq!(policy.delay_for(3), Duration::from_millis(250));
+    }
+
+    #[test]
+    fn test_deadline_with_mock_clock() {
+        let clock = MockClock::new(chrono::Utc::now());
+        let policy = RetryPolicy::new()
+            .max_attempts(10)
+            .backoff(Duration::from_secs(10), Duration::from_secs(60))
+            .jitter(false)
+            .deadline(Duration::from_secs(60))
+            .clock(Arc::new(clock.clone()));
+        let result: Result<(), &str> = policy.retry_blocking(|_| Err("transient"), |_| true);
+        assert!(result.is_err());
+        // 10s + 20s elapsed; a further 40s would pass the 60s deadline
+        assert_eq!(clock.sleeps(), vec![Duration::from_secs(10), Duration::from_secs(20)]);
+    }
+
+    #[tokio::test]
+    async fn test_retry_async() {
+        let result: Result<u32, &str> = fast().max_attempts(4).retry_async(|attempt| async move {
+            if attempt < 3 { Err("transient") } else { Ok(attempt) }
+        }, |_| true).await;
+        assert_eq!(result, Ok(3));
+    }
+}
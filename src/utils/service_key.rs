@@ -0,0 +1,87 @@
+use std::sync::{Mutex, OnceLock};
+use std::time::Duration;
+use chrono::{DateTime, Utc};
+use keyring::Entry;
+use log::{info, error};
+use serde::Serialize;
+use utoipa::ToSchema;
+
+pub const SERVICE_NAME: &str = "webhook_service";
+pub const USERNAME: &str = "webhook";
+
+/// Health of the keyring holding the service key
+#[derive(Debug, Clone, Default, Serialize, ToSchema)]
+pub struct SecretProviderHealth {
+    pub reachable: bool,
+    pub last_checked: Option<DateTime<Utc>>,
+    /// When the encrypted environment secrets were last decrypted
+    pub last_refreshed: Option<DateTime<Utc>>,
+    pub last_error: Option<String>,
+}
+
+fn health_state() -> &'static Mutex<SecretProviderHealth> {
+    static HEALTH: OnceLock<Mutex<SecretProviderHealth>> = OnceLock::new();
+    HEALTH.get_or_init(|| Mutex::new(SecretProviderHealth::default()))
+}
+
+/// Returns a snapshot of the secret provider health
+pub fn health() -> SecretProviderHealth {
+    health_state().lock().unwrap().clone()
+}
+
+/// Records that secrets were (re)loaded from the provider
+pub fn record_refresh() {
+    health_state().lock().unwrap().last_refreshed = Some(Utc::now());
+}
+
+fn record_check(result: &Result<String, keyring::Error>) {
+    let mut health = health_state().lock().unwrap();
+    let was_reachable = health.reachable || health.last_checked.is_none();
+    health.last_checked = Some(Utc::now());
+    match result {
+        Ok(_) => {
+            if !health.reachable && health.last_error.is_some() {
+                info!("Keyring is reachable again");
+            }
+            health.reachable = true;
+            health.last_error = None;
+        }
+        Err(err) => {
+            if was_reachable {
+                error!("ALERT: keyring became unavailable, the next restart will fail: {}", err);
+            }
+            health.reachable = false;
+            health.last_error = Some(err.to_string());
+        }
+    }
+}
+
+pub fn get_service_key() -> Result<String, keyring::Error> {
+    let result = Entry::new(SERVICE_NAME, USERNAME).and_then(|entry| entry.get_password());
+    record_check(&result);
+    match result {
+        Ok(password) => {
+            info!("Service key retrieved from keyring");
+            Ok(password)
+        }
+        Err(err) => {
+            error!("Failed to retrieve service key from keyring: {}", err);
+            Err(err)
+        }
+    }
+}
+
+/// Periodically re-reads the service key so a vanished keyring is noticed before a restart
+pub fn spawn_health_task(interval: Duration) {
+    tokio::spawn(async move {
+        let mut ticker = tokio::time::interval(interval);
+        // The first tick fires immediately and startup has just read the key
+        ticker.tick().await;
+        loop {
+            ticker.tick().await;
+            if let Err(e) = tokio::task::spawn_blocking(get_service_key).await {
+                error!("Keyring health check task failed: {}", e);
+            }
+        }
+    });
+}
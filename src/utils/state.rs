@@ -0,0 +1,168 @@
+use std::env;
+use std::fs::File;
+use std::io::{self, Read};
+use std::path::{Path, PathBuf};
+use chrono::Utc;
+use flate2::Compression;
+use flate2::read::GzDecoder;
+use flate2::write::GzEncoder;
+use log::info;
+
+use crate::utils::state_store::StateStore;
+
+/// Documents under the state directory that make up the persistent service
+/// state, exported and imported as a whole: one per store opened on
+/// `state_dir()`, plus the schema version used by the startup migrations
+pub const STATE_FILES: [&str; 18] = ["jobs.json", "backports.json", "reopened.json", "canary.json", "mirror_sync.json", "deliveries.json", "dlq.json", "paused.json", "paused_platforms.json", "webhook_secrets.json", "stats.json", "comments.json", "repo_health.json", "payload_schemas.json", "s3_export.json", "api_tokens.json", "audit.json", "schema_version"];
+
+/// Returns the state directory, taken from `STATE_DIR` or defaulting to `state`
+pub fn state_dir() -> PathBuf {
+    env::var("STATE_DIR")
+        .map(PathBuf::from)
+        .unwrap_or_else(|_| PathBuf::from("state"))
+}
+
+/// Bundles the state documents found in the backend `store` into a gzipped
+/// tarball, one `STATE_FILES` entry per document
+///
+/// # Returns
+/// * `io::Result<usize>` - Number of documents written to the archive
+pub fn export_state(store: &dyn StateStore, state_dir: &Path, archive_path: &Path) -> io::Result<usize> {
+    info!("Exporting {} state of {:?} to {:?}", store.name(), state_dir, archive_path);
+    let file = File::create(archive_path)?;
+    let mut builder = tar::Builder::new(GzEncoder::new(file, Compression::default()));
+
+    let mut count = 0;
+    for name in STATE_FILES.iter() {
+        let Some(contents) = store.load(&state_dir.join(name))? else {
+            info!("Skipping missing state file {}", name);
+            continue;
+        };
+        let mut header = tar::Header::new_gnu();
+        header.set_size(contents.len() as u64);
+        // Tokens and webhook secrets are part of the state
+        header.set_mode(0o600);
+        header.set_mtime(Utc::now().timestamp().max(0) as u64);
+        header.set_cksum();
+        builder.append_data(&mut header, name, contents.as_bytes())?;
+        count += 1;
+    }
+
+    builder.into_inner()?.finish()?;
+    info!("Exported {} state files", count);
+    Ok(count)
+}
+
+/// Restores state documents into the backend `store` from a tarball created
+/// by `export_state`
+///
+/// Only known state files are imported. Existing documents are left
+/// untouched unless `overwrite` is set, so an import never silently replaces
+/// the idempotency records of a running instance. Every entry is read before
+/// anything is written, and the documents are saved all at once, so a bad
+/// archive leaves the state as it was.
+pub fn import_state(store: &dyn StateStore, archive_path: &Path, state_dir: &Path, overwrite: bool) -> io::Result<usize> {
+    info!("Importing state from {:?} into the {} state of {:?}", archive_path, store.name(), state_dir);
+    let file = File::open(archive_path)?;
+    let mut archive = tar::Archive::new(GzDecoder::new(file));
+
+    let mut names: Vec<String> = Vec::new();
+    let mut documents: Vec<(PathBuf, String)> = Vec::new();
+    for entry in archive.entries()? {
+        let mut entry = entry?;
+        let name = entry.path()?.to_string_lossy().to_string();
+        if !STATE_FILES.contains(&name.as_str()) || names.contains(&name) {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("Unexpected entry in state archive: {}", name),
+            ));
+        }
+
+        let target = state_dir.join(&name);
+        if !overwrite && store.load(&target)?.is_some() {
+            return Err(io::Error::new(
+                io::ErrorKind::AlreadyExists,
+                format!("State file {} already exists", target.display()),
+            ));
+        }
+        let mut contents = String::new();
+        entry.read_to_string(&mut contents)?;
+        documents.push((target, contents));
+        names.push(name);
+    }
+
+    store.save_all(&documents)?;
+    info!("Imported {} state files", names.len());
+    Ok(names.len())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+    use crate::utils::state_store::FileStore;
+
+    /// Sources of the crate, for the checks that read the code itself
+    fn sources(dir: &Path, files: &mut Vec<PathBuf>) {
+        for entry in fs::read_dir(dir).unwrap() {
+            let path = entry.unwrap().path();
+            if path.is_dir() {
+                sources(&path, files);
+            } else if path.extension().is_some_and(|extension| extension == "rs") {
+                files.push(path);
+            }
+        }
+    }
+
+    #[test]
+    fn test_every_store_is_a_state_file() {
+        let mut files = Vec::new();
+        sources(&Path::new(env!("CARGO_MANIFEST_DIR")).join("src"), &mut files);
+        let needle = ["state_dir", "().join(\""].concat();
+        let mut stores = 0;
+        for file in files {
+            let source = fs::read_to_string(&file).unwrap();
+            for occurrence in source.split(&needle).skip(1) {
+                let name = occurrence.split('"').next().unwrap();
+                // The SQLite backend's database, which holds the documents
+                if name == "state.db" {
+                    continue;
+                }
+                assert!(STATE_FILES.contains(&name), "{} of {:?} missing from STATE_FILES", name, file);
+                stores += 1;
+            }
+        }
+        assert!(stores >= STATE_FILES.len() - 1, "found only {} stores", stores);
+    }
+
+    #[test]
+    fn test_export_import_roundtrip() {
+        let source = tempfile::tempdir().unwrap();
+        let target = tempfile::tempdir().unwrap();
+        let archive = source.path().join("state.tar.gz");
+
+        fs::write(source.path().join("jobs.json"), "[]").unwrap();
+        fs::write(source.path().join("deliveries.json"), "{}").unwrap();
+
+        assert_eq!(export_state(&FileStore, source.path(), &archive).unwrap(), 2);
+        assert_eq!(import_state(&FileStore, &archive, target.path(), false).unwrap(), 2);
+        assert_eq!(fs::read_to_string(target.path().join("jobs.json")).unwrap(), "[]");
+        assert!(!target.path().join("backports.json").exists());
+
+        // A second import must not clobber existing state without overwrite
+        assert!(import_state(&FileStore, &archive, target.path(), false).is_err());
+        assert!(import_state(&FileStore, &archive, target.path(), true).is_ok());
+
+        // An archive with an unexpected entry imports nothing
+        let bad = source.path().join("bad.tar.gz");
+        let mut builder = tar::Builder::new(GzEncoder::new(File::create(&bad).unwrap(), Compression::default()));
+        builder.append_path_with_name(source.path().join("jobs.json"), "backports.json").unwrap();
+        builder.append_path_with_name(source.path().join("jobs.json"), "unknown.json").unwrap();
+        builder.into_inner().unwrap().finish().unwrap();
+        assert!(import_state(&FileStore, &bad, target.path(), true).is_err());
+        assert!(!target.path().join("backports.json").exists());
+        let mut left: Vec<_> = fs::read_dir(target.path()).unwrap().map(|entry| entry.unwrap().file_name()).collect();
+        left.sort();
+        assert_eq!(left, ["deliveries.json", "jobs.json"]);
+    }
+}
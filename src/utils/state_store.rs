@@ -0,0 +1,392 @@
+use std::env;
+use std::fs;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::sync::OnceLock;
+#[cfg(feature = "database")]
+use std::sync::{mpsc, Mutex};
+#[cfg(feature = "database")]
+use std::thread;
+use log::{info, error};
+
+#[cfg(feature = "database")]
+use crate::utils::{logging, state};
+
+/// Where the persistent state documents (`jobs.json`, `dlq.json`, ...) live
+///
+/// Documents are addressed by their path under the state directory; database
+/// backends key them by file name, so the same stores work unchanged on every
+/// backend.
+pub trait StateStore: Send + Sync {
+    /// Contents of the document, `None` when it does not exist
+    fn load(&self, path: &Path) -> io::Result<Option<String>>;
+    fn save(&self, path: &Path, contents: &str) -> io::Result<()>;
+    /// Writes several documents at once: either all of them or none
+    fn save_all(&self, documents: &[(PathBuf, String)]) -> io::Result<()>;
+    /// Backend name for logs and diagnostics
+    fn name(&self) -> &'static str;
+}
+
+#[cfg(feature = "database")]
+fn document_key(path: &Path) -> io::Result<String> {
+    path.file_name()
+        .map(|name| name.to_string_lossy().to_string())
+        .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, format!("No document name in {:?}", path)))
+}
+
+#[cfg(feature = "database")]
+fn db_error<E: std::fmt::Display>(e: E) -> io::Error {
+    io::Error::other(e.to_string())
+}
+
+/// JSON files under the state directory (the default)
+pub struct FileStore;
+
+/// Directory holding the document at `path`
+fn parent_dir(path: &Path) -> &Path {
+    path.parent().filter(|parent| !parent.as_os_str().is_empty()).unwrap_or(Path::new("."))
+}
+
+/// Renames the staged documents (`<staging>/<index>`) into place, keeping the
+/// files they replace under `staging`; if a rename fails, the installed files
+/// are removed and the replaced ones put back
+fn install(staging: &Path, documents: &[(PathBuf, String)]) -> io::Result<()> {
+    let previous = staging.join("previous");
+    fs::create_dir(&previous)?;
+
+    let mut installed = Vec::new();
+    for (index, (path, _)) in documents.iter().enumerate() {
+        let result = (|| {
+            if path.exists() {
+                fs::rename(path, previous.join(index.to_string()))?;
+            }
+            fs::rename(staging.join(index.to_string()), path)
+        })();
+        if let Err(e) = result {
+            for path in &installed {
+                let _ = fs::remove_file(path);
+            }
+            for (index, (path, _)) in documents.iter().enumerate() {
+                if previous.join(index.to_string()).exists() {
+                    let _ = fs::rename(previous.join(index.to_string()), path);
+                }
+            }
+            return Err(e);
+        }
+        installed.push(path);
+    }
+    Ok(())
+}
+
+impl StateStore for FileStore {
+    fn load(&self, path: &Path) -> io::Result<Option<String>> {
+        match fs::read_to_string(path) {
+            Ok(contents) => Ok(Some(contents)),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Writes a temporary file next to `path` and renames it into place, so a
+    /// crash never leaves a truncated document
+    fn save(&self, path: &Path, contents: &str) -> io::Result<()> {
+        let dir = parent_dir(path);
+        fs::create_dir_all(dir)?;
+        let mut file = tempfile::NamedTempFile::new_in(dir)?;
+        file.write_all(contents.as_bytes())?;
+        file.as_file().sync_all()?;
+        file.persist(path).map_err(|e| e.error)?;
+        Ok(())
+    }
+
+    /// Stages every document in a directory next to the first one before
+    /// renaming them into place
+    fn save_all(&self, documents: &[(PathBuf, String)]) -> io::Result<()> {
+        let Some((first, _)) = documents.first() else { return Ok(()) };
+        let dir = parent_dir(first);
+        fs::create_dir_all(dir)?;
+        let staging = tempfile::Builder::new().prefix(".import-").tempdir_in(dir)?;
+        for (index, (_, contents)) in documents.iter().enumerate() {
+            fs::write(staging.path().join(index.to_string()), contents)?;
+        }
+        install(staging.path(), documents)
+    }
+
+    fn name(&self) -> &'static str {
+        "file"
+    }
+}
+
+#[cfg(feature = "database")]
+/// A single SQLite database file, for small single-binary deployments
+pub struct SqliteStore {
+    connection: Mutex<rusqlite::Connection>,
+}
+
+#[cfg(feature = "database")]
+impl SqliteStore {
+    pub fn open(path: &Path) -> io::Result<SqliteStore> {
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        let connection = rusqlite::Connection::open(path).map_err(db_error)?;
+        connection
+            .execute_batch(
+                "PRAGMA journal_mode = WAL;
+                 CREATE TABLE IF NOT EXISTS state_documents (
+                     name TEXT PRIMARY KEY,
+                     contents TEXT NOT NULL,
+                     updated_at TEXT NOT NULL DEFAULT CURRENT_TIMESTAMP
+                 );",
+            )
+            .map_err(db_error)?;
+        Ok(SqliteStore { connection: Mutex::new(connection) })
+    }
+}
+
+#[cfg(feature = "database")]
+const SQLITE_UPSERT: &str = "INSERT INTO state_documents (name, contents, updated_at) VALUES (?1, ?2, CURRENT_TIMESTAMP)
+    ON CONFLICT(name) DO UPDATE SET contents = excluded.contents, updated_at = excluded.updated_at";
+
+#[cfg(feature = "database")]
+impl StateStore for SqliteStore {
+    fn load(&self, path: &Path) -> io::Result<Option<String>> {
+        let key = document_key(path)?;
+        let connection = self.connection.lock().unwrap();
+        let mut statement = connection
+            .prepare_cached("SELECT contents FROM state_documents WHERE name = ?1")
+            .map_err(db_error)?;
+        let mut rows = statement.query([&key]).map_err(db_error)?;
+        match rows.next().map_err(db_error)? {
+            Some(row) => Ok(Some(row.get(0).map_err(db_error)?)),
+            None => Ok(None),
+        }
+    }
+
+    fn save(&self, path: &Path, contents: &str) -> io::Result<()> {
+        let key = document_key(path)?;
+        self.connection
+            .lock()
+            .unwrap()
+            .execute(SQLITE_UPSERT, rusqlite::params![key, contents])
+            .map_err(db_error)?;
+        Ok(())
+    }
+
+    fn save_all(&self, documents: &[(PathBuf, String)]) -> io::Result<()> {
+        let mut connection = self.connection.lock().unwrap();
+        let transaction = connection.transaction().map_err(db_error)?;
+        for (path, contents) in documents {
+            transaction.execute(SQLITE_UPSERT, rusqlite::params![document_key(path)?, contents]).map_err(db_error)?;
+        }
+        transaction.commit().map_err(db_error)
+    }
+
+    fn name(&self) -> &'static str {
+        "sqlite"
+    }
+}
+
+#[cfg(feature = "database")]
+enum PostgresRequest {
+    Load(String, mpsc::Sender<io::Result<Option<String>>>),
+    Save(String, String, mpsc::Sender<io::Result<()>>),
+    SaveAll(Vec<(String, String)>, mpsc::Sender<io::Result<()>>),
+}
+
+#[cfg(feature = "database")]
+/// A Postgres database shared by several instances
+///
+/// The synchronous client runs its own runtime, which must not be entered
+/// from the server's async tasks, so a dedicated thread owns the connection.
+pub struct PostgresStore {
+    requests: Mutex<mpsc::Sender<PostgresRequest>>,
+}
+
+#[cfg(feature = "database")]
+const POSTGRES_SCHEMA: &str = "CREATE TABLE IF NOT EXISTS state_documents (
+    name TEXT PRIMARY KEY,
+    contents TEXT NOT NULL,
+    updated_at TIMESTAMPTZ NOT NULL DEFAULT now()
+)";
+
+#[cfg(feature = "database")]
+const POSTGRES_UPSERT: &str = "INSERT INTO state_documents (name, contents, updated_at) VALUES ($1, $2, now())
+    ON CONFLICT (name) DO UPDATE SET contents = EXCLUDED.contents, updated_at = EXCLUDED.updated_at";
+
+#[cfg(feature = "database")]
+impl PostgresStore {
+    pub fn connect(url: &str) -> io::Result<PostgresStore> {
+        let url = url.to_string();
+        let (requests, receiver) = mpsc::channel::<PostgresRequest>();
+        let (ready, connected) = mpsc::channel::<io::Result<()>>();
+        thread::Builder::new().name("state-postgres".to_string()).spawn(move || {
+            let mut client = match postgres::Client::connect(&url, postgres::NoTls)
+                .and_then(|mut client| client.batch_execute(POSTGRES_SCHEMA).map(|_| client))
+            {
+                Ok(client) => {
+                    let _ = ready.send(Ok(()));
+                    client
+                }
+                Err(e) => {
+                    let _ = ready.send(Err(db_error(e)));
+                    return;
+                }
+            };
+            for request in receiver {
+                match request {
+                    PostgresRequest::Load(key, reply) => {
+                        let result = client
+                            .query_opt("SELECT contents FROM state_documents WHERE name = $1", &[&key])
+                            .map(|row| row.map(|row| row.get::<_, String>(0)))
+                            .map_err(db_error);
+                        let _ = reply.send(result);
+                    }
+                    PostgresRequest::Save(key, contents, reply) => {
+                        let result = client.execute(POSTGRES_UPSERT, &[&key, &contents]).map(|_| ()).map_err(db_error);
+                        let _ = reply.send(result);
+                    }
+                    PostgresRequest::SaveAll(documents, reply) => {
+                        let result = client
+                            .transaction()
+                            .and_then(|mut transaction| {
+                                for (key, contents) in &documents {
+                                    transaction.execute(POSTGRES_UPSERT, &[key, contents])?;
+                                }
+                                transaction.commit()
+                            })
+                            .map_err(db_error);
+                        let _ = reply.send(result);
+                    }
+                }
+            }
+        })?;
+        connected.recv().map_err(db_error)??;
+        Ok(PostgresStore { requests: Mutex::new(requests) })
+    }
+
+    fn send<T>(&self, request: impl FnOnce(mpsc::Sender<io::Result<T>>) -> PostgresRequest) -> io::Result<T> {
+        let (reply, response) = mpsc::channel();
+        self.requests.lock().unwrap().send(request(reply)).map_err(db_error)?;
+        response.recv().map_err(db_error)?
+    }
+}
+
+#[cfg(feature = "database")]
+impl StateStore for PostgresStore {
+    fn load(&self, path: &Path) -> io::Result<Option<String>> {
+        let key = document_key(path)?;
+        self.send(|reply| PostgresRequest::Load(key, reply))
+    }
+
+    fn save(&self, path: &Path, contents: &str) -> io::Result<()> {
+        let key = document_key(path)?;
+        let contents = contents.to_string();
+        self.send(|reply| PostgresRequest::Save(key, contents, reply))
+    }
+
+    fn save_all(&self, documents: &[(PathBuf, String)]) -> io::Result<()> {
+        let documents = documents
+            .iter()
+            .map(|(path, contents)| Ok((document_key(path)?, contents.clone())))
+            .collect::<io::Result<Vec<_>>>()?;
+        self.send(|reply| PostgresRequest::SaveAll(documents, reply))
+    }
+
+    fn name(&self) -> &'static str {
+        "postgres"
+    }
+}
+
+/// Opens the backend selected by `STATE_BACKEND` (`file`, `sqlite` or `postgres`)
+///
+/// SQLite uses `STATE_DATABASE_URL` as the database path (default
+/// `<state dir>/state.db`); Postgres requires it as a connection URL.
+pub fn open_from_env() -> io::Result<Box<dyn StateStore>> {
+    let backend = env::var("STATE_BACKEND").unwrap_or_else(|_| "file".to_string());
+    #[cfg(feature = "database")]
+    let url = env::var("STATE_DATABASE_URL").ok().filter(|url| !url.is_empty());
+    match backend.trim().to_lowercase().as_str() {
+        "file" | "" => Ok(Box::new(FileStore)),
+        #[cfg(feature = "database")]
+        "sqlite" => {
+            let path = url.map(PathBuf::from).unwrap_or_else(|| state::state_dir().join("state.db"));
+            Ok(Box::new(SqliteStore::open(&path)?))
+        }
+        #[cfg(feature = "database")]
+        "postgres" | "postgresql" => {
+            let url = url.ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "STATE_DATABASE_URL is required for the postgres backend"))?;
+            Ok(Box::new(PostgresStore::connect(&url)?))
+        }
+        #[cfg(not(feature = "database"))]
+        "sqlite" | "postgres" | "postgresql" => Err(io::Error::new(io::ErrorKind::InvalidInput, format!("STATE_BACKEND {} requires the database feature", backend))),
+        other => Err(io::Error::new(io::ErrorKind::InvalidInput, format!("Unknown STATE_BACKEND {}", other))),
+    }
+}
+
+/// The process-wide state backend
+///
+/// Exits the process when the configured backend cannot be opened: running
+/// on the wrong storage would silently lose state.
+pub fn backend() -> &'static dyn StateStore {
+    static BACKEND: OnceLock<Box<dyn StateStore>> = OnceLock::new();
+    BACKEND
+        .get_or_init(|| match open_from_env() {
+            Ok(backend) => {
+                info!("Using the {} state backend", backend.name());
+                backend
+            }
+            Err(e) => {
+                error!("Failed to open the state backend: {}", e);
+                logging::exit(1);
+            }
+        })
+        .as_ref()
+}
+
+/// Reads a state document through the configured backend
+pub fn read_document(path: &Path) -> io::Result<Option<String>> {
+    backend().load(path)
+}
+
+/// Writes a state document through the configured backend
+pub fn write_document(path: &Path, contents: &str) -> io::Result<()> {
+    backend().save(path, contents)
+}
+
+#[cfg(all(test, feature = "database"))]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_sqlite_store_keys_documents_by_name() {
+        let dir = tempfile::tempdir().unwrap();
+        let store = SqliteStore::open(&dir.path().join("state.db")).unwrap();
+        let jobs = dir.path().join("jobs.json");
+        assert_eq!(store.load(&jobs).unwrap(), None);
+        store.save(&jobs, "[]").unwrap();
+        store.save(&jobs, "[1]").unwrap();
+        // Any directory: only the document name matters
+        assert_eq!(store.load(Path::new("/elsewhere/jobs.json")).unwrap().as_deref(), Some("[1]"));
+        assert!(!jobs.exists());
+
+        let reopened = SqliteStore::open(&dir.path().join("state.db")).unwrap();
+        assert_eq!(reopened.load(&jobs).unwrap().as_deref(), Some("[1]"));
+    }
+
+    #[test]
+    fn test_state_archive_roundtrip_through_sqlite() {
+        let dir = tempfile::tempdir().unwrap();
+        let source = SqliteStore::open(&dir.path().join("source.db")).unwrap();
+        source.save_all(&[(dir.path().join("jobs.json"), "[]".to_string()), (dir.path().join("schema_version"), "1\n".to_string())]).unwrap();
+
+        let archive = dir.path().join("state.tar.gz");
+        assert_eq!(state::export_state(&source, dir.path(), &archive).unwrap(), 2);
+        let target = SqliteStore::open(&dir.path().join("target.db")).unwrap();
+        assert_eq!(state::import_state(&target, &archive, dir.path(), false).unwrap(), 2);
+        assert_eq!(target.load(&dir.path().join("jobs.json")).unwrap().as_deref(), Some("[]"));
+        assert!(state::import_state(&target, &archive, dir.path(), false).is_err());
+        // Nothing went to the state directory itself
+        assert!(!dir.path().join("jobs.json").exists());
+    }
+}
@@ -0,0 +1,190 @@
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex, OnceLock};
+use chrono::{DateTime, Duration, NaiveDate, Utc};
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+use log::error;
+
+use crate::utils::clock::{self, Clock};
+use crate::utils::metrics::{self, FailureClass};
+use crate::utils::{state, state_store};
+
+/// Number of days of statistics kept per repository
+const RETENTION_DAYS: i64 = 90;
+
+/// Outcome of a finished job, as counted in the statistics
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum JobOutcome {
+    Succeeded,
+    Conflicted,
+    Failed,
+}
+
+impl JobOutcome {
+    /// Classifies a job result; conflicts are told apart from other failures
+    pub fn from_result(result: &Result<(), String>) -> Self {
+        match result {
+            Ok(()) => JobOutcome::Succeeded,
+            Err(e) if metrics::classify(e) == FailureClass::Conflict => JobOutcome::Conflicted,
+            Err(_) => JobOutcome::Failed,
+        }
+    }
+}
+
+/// Counters of one repository for one day (UTC)
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize, ToSchema)]
+pub struct DailyStats {
+    pub succeeded: u64,
+    pub conflicted: u64,
+    pub failed: u64,
+    /// Summed job durations, for the average
+    pub duration_secs: f64,
+    pub last_activity: Option<DateTime<Utc>>,
+}
+
+impl DailyStats {
+    fn jobs(&self) -> u64 {
+        self.succeeded + self.conflicted + self.failed
+    }
+
+    fn add(&mut self, other: &DailyStats) {
+        self.succeeded += other.succeeded;
+        self.conflicted += other.conflicted;
+        self.failed += other.failed;
+        self.duration_secs += other.duration_secs;
+        self.last_activity = self.last_activity.max(other.last_activity);
+    }
+}
+
+/// Totals of one repository over the requested period
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct RepoSummary {
+    pub repo: String,
+    pub succeeded: u64,
+    pub conflicted: u64,
+    pub failed: u64,
+    /// Share of finished jobs that hit a cherry-pick conflict
+    pub conflict_rate: f64,
+    pub average_duration_secs: f64,
+    pub last_activity: Option<DateTime<Utc>>,
+    pub daily: BTreeMap<NaiveDate, DailyStats>,
+}
+
+/// Per-repository daily statistics persisted as JSON in `stats.json`
+pub struct StatsStore {
+    path: PathBuf,
+    repos: BTreeMap<String, BTreeMap<NaiveDate, DailyStats>>,
+    clock: Arc<dyn Clock>,
+}
+
+impl StatsStore {
+    pub fn open(path: &Path) -> StatsStore {
+        let repos = match state_store::read_document(path) {
+            Ok(Some(contents)) => serde_json::from_str(&contents).unwrap_or_else(|e| {
+                error!("Failed to parse statistics {:?}, starting empty: {}", path, e);
+                BTreeMap::new()
+            }),
+            _ => BTreeMap::new(),
+        };
+        StatsStore { path: path.to_path_buf(), repos, clock: clock::system() }
+    }
+
+    /// Uses `clock` to date the statistics (a `MockClock` in tests)
+    pub fn with_clock(mut self, clock: Arc<dyn Clock>) -> Self {
+        self.clock = clock;
+        self
+    }
+
+    fn save(&self) {
+        let result = serde_json::to_string_pretty(&self.repos)
+            .map_err(std::io::Error::from)
+            .and_then(|contents| state_store::write_document(&self.path, &contents));
+        if let Err(e) = result {
+            error!("Failed to persist statistics {:?}: {}", self.path, e);
+        }
+    }
+
+    /// Counts a finished job of `repo` that took `duration`
+    pub fn record(&mut self, repo: &str, outcome: JobOutcome, duration: Duration) {
+        let now = self.clock.now();
+        let days = self.repos.entry(repo.to_string()).or_default();
+        let day = days.entry(now.date_naive()).or_default();
+        match outcome {
+            JobOutcome::Succeeded => day.succeeded += 1,
+            JobOutcome::Conflicted => day.conflicted += 1,
+            JobOutcome::Failed => day.failed += 1,
+        }
+        day.duration_secs += duration.num_milliseconds().max(0) as f64 / 1000.0;
+        day.last_activity = Some(now);
+
+        let oldest = now.date_naive() - Duration::days(RETENTION_DAYS);
+        days.retain(|date, _| *date > oldest);
+        self.save();
+    }
+
+    /// Summaries of every repository over the last `days` days, by repository name
+    pub fn summaries(&self, days: u32) -> Vec<RepoSummary> {
+        let since = self.clock.now().date_naive() - Duration::days(i64::from(days.max(1)) - 1);
+        self.repos
+            .iter()
+            .map(|(repo, history)| {
+                let daily: BTreeMap<NaiveDate, DailyStats> = history
+                    .range(since..)
+                    .map(|(date, stats)| (*date, stats.clone()))
+                    .collect();
+                let mut total = DailyStats::default();
+                daily.values().for_each(|stats| total.add(stats));
+                let jobs = total.jobs();
+                let ratio = |value: f64| if jobs == 0 { 0.0 } else { value / jobs as f64 };
+                RepoSummary {
+                    repo: repo.clone(),
+                    succeeded: total.succeeded,
+                    conflicted: total.conflicted,
+                    failed: total.failed,
+                    conflict_rate: ratio(total.conflicted as f64),
+                    average_duration_secs: ratio(total.duration_secs),
+                    // Activity older than the period still tells whether a repository is idle
+                    last_activity: history.values().filter_map(|stats| stats.last_activity).max(),
+                    daily,
+                }
+            })
+            .collect()
+    }
+}
+
+/// The process-wide statistics store
+pub fn store() -> &'static Mutex<StatsStore> {
+    static STORE: OnceLock<Mutex<StatsStore>> = OnceLock::new();
+    STORE.get_or_init(|| Mutex::new(StatsStore::open(&state::state_dir().join("stats.json"))))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::clock::MockClock;
+
+    #[test]
+    fn test_statistics_are_aggregated_daily() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("stats.json");
+        let clock = MockClock::new("2024-06-01T08:00:00Z".parse().unwrap());
+        let mut store = StatsStore::open(&path).with_clock(Arc::new(clock.clone()));
+
+        store.record("repo", JobOutcome::Succeeded, Duration::seconds(10));
+        store.record("repo", JobOutcome::from_result(&Err("Cherry-pick conflicts in 2 files".to_string())), Duration::seconds(20));
+        clock.advance(std::time::Duration::from_secs(86_400));
+        store.record("repo", JobOutcome::from_result(&Err("timed out".to_string())), Duration::seconds(30));
+
+        let reopened = StatsStore::open(&path).with_clock(Arc::new(clock.clone()));
+        let all = &reopened.summaries(30)[0];
+        assert_eq!((all.succeeded, all.conflicted, all.failed), (1, 1, 1));
+        assert_eq!(all.daily.len(), 2);
+        assert!((all.conflict_rate - 1.0 / 3.0).abs() < 1e-9);
+        assert!((all.average_duration_secs - 20.0).abs() < 1e-9);
+
+        let today = &reopened.summaries(1)[0];
+        assert_eq!((today.succeeded, today.failed), (0, 1));
+        assert_eq!(today.last_activity, Some(clock.now()));
+    }
+}
@@ -0,0 +1,94 @@
+use log::{info, error};
+
+use crate::models::webhook::ParsedWebhookData;
+use crate::utils::{branch, config, gitcode, outbox};
+use crate::utils::git::api_base_url;
+
+/// Prefix of the label marking a branch the PR was backported to
+pub const BACKPORTED_PREFIX: &str = "backported: ";
+/// Prefix of the label marking a branch the backport failed on
+pub const FAILED_PREFIX: &str = "backport-failed: ";
+
+/// Branches requested by the `br:` labels of the PR
+pub fn requested_branches(webhook_data: &ParsedWebhookData) -> Vec<String> {
+    webhook_data
+        .labels
+        .iter()
+        .filter(|label| label.title.starts_with("br:"))
+        .filter_map(|label| branch::parse_branch_label(label.description.as_deref()?).ok())
+        .collect()
+}
+
+/// Labels to add and to remove so that `current` shows the outcome for `branches`
+///
+/// The labels always describe the latest attempt: a successful run replaces
+/// `backport-failed:` with `backported:` and a failed one does the opposite.
+pub fn label_changes(current: &[&str], branches: &[String], succeeded: bool) -> (Vec<String>, Vec<String>) {
+    let (set, clear) = if succeeded { (BACKPORTED_PREFIX, FAILED_PREFIX) } else { (FAILED_PREFIX, BACKPORTED_PREFIX) };
+    let has = |label: &str| current.iter().any(|c| c.eq_ignore_ascii_case(label));
+    let add = branches
+        .iter()
+        .map(|branch| format!("{}{}", set, branch))
+        .filter(|label| !has(label))
+        .collect();
+    let remove = branches
+        .iter()
+        .map(|branch| format!("{}{}", clear, branch))
+        .filter(|label| has(label))
+        .collect();
+    (add, remove)
+}
+
+/// Updates the status labels of the source PR for `branches` when the
+/// repository enables `status_labels`; failures are logged, and during a
+/// job the changes are written when it ends
+pub fn sync_status_labels(webhook_data: &ParsedWebhookData, platform: &str, branches: &[String], succeeded: bool) {
+    let Some(iid) = webhook_data.iid else { return };
+    let repo_config = match config::load_repo_config_in(&webhook_data.namespace, &webhook_data.repo_name) {
+        Ok(Some(repo_config)) if repo_config.status_labels => repo_config,
+        _ => return,
+    };
+    let current: Vec<&str> = webhook_data.labels.iter().map(|label| label.title.as_str()).collect();
+    let (add, remove) = label_changes(&current, branches, succeeded);
+    if let Some(pr) = outbox::PullRequestRef::source(webhook_data, platform) {
+        // Labels set by an earlier step of the job that this outcome replaces
+        let clear = if succeeded { FAILED_PREFIX } else { BACKPORTED_PREFIX };
+        let retract: Vec<String> = branches.iter().map(|branch| format!("{}{}", clear, branch)).collect();
+        if outbox::defer_labels(pr, &add, &remove, &retract, &repo_config.label_colors) {
+            info!("Deferred status labels of PR #{}: add {:?}, remove {:?}", iid, add, remove);
+            return;
+        }
+    }
+    let base_url = api_base_url(platform);
+
+    if !add.is_empty() {
+        if let Err(e) = gitcode::add_labels_to_pr(base_url, &webhook_data.namespace, &webhook_data.repo_name, iid, &add, &repo_config.label_colors, platform) {
+            error!("Failed to add status labels {:?} to PR #{}: {}", add, iid, e);
+        }
+    }
+    for label in &remove {
+        if let Err(e) = gitcode::remove_label_from_pr(base_url, &webhook_data.namespace, &webhook_data.repo_name, iid, label, platform) {
+            error!("Failed to remove status label {} from PR #{}: {}", label, iid, e);
+        }
+    }
+    info!("Status labels of PR #{}: added {:?}, removed {:?}", iid, add, remove);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_label_changes() {
+        let branches = vec!["release-1.2".to_string(), "release-1.0".to_string()];
+        let current = ["br:", "backport-failed: release-1.0"];
+
+        let (add, remove) = label_changes(&current, &branches, true);
+        assert_eq!(add, vec!["backported: release-1.2", "backported: release-1.0"]);
+        assert_eq!(remove, vec!["backport-failed: release-1.0"]);
+
+        let (add, remove) = label_changes(&current, &branches, false);
+        assert_eq!(add, vec!["backport-failed: release-1.2"]);
+        assert!(remove.is_empty());
+    }
+}
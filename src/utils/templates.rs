@@ -0,0 +1,154 @@
+use std::collections::HashMap;
+use serde::{Deserialize, Serialize};
+
+/// Language of the comments posted by the bot
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Locale {
+    #[default]
+    #[serde(rename = "en")]
+    En,
+    #[serde(rename = "zh-CN")]
+    ZhCn,
+}
+
+/// Comments the bot posts on pull requests
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum MessageKind {
+    /// A backport commit referencing the PR was pushed (`user`, `branch`, `commit`, `url`)
+    PushReference,
+    /// A cherry-pick conflicts (`branch`, `commit`, `files`, `snippet`, `patches`)
+    Conflict,
+    /// Secrets were found in cherry-picked changes (`branch`, `findings`)
+    Secrets,
+    /// Commits lack a DCO sign-off (`commits`)
+    MissingSignOff,
+    /// A `br:` label names an invalid branch (`branch`, `reason`)
+    InvalidBranch,
+    /// A commit exceeds the size limits and needs the override label (`branch`, `commit`, `findings`, `label`)
+    OversizedCommit,
+    /// Commits were left out because the branch already has the same changes (`branch`, `commits`)
+    AlreadyPresent,
+    /// A PR that was already backported was reopened (`branches`, `label`)
+    ReopenedAfterBackport,
+    /// A canary job of a changed configuration did not push (`run`, `of`, `target`, `branches`)
+    CanaryReport,
+}
+
+/// Per-repository comment settings: a locale plus optional template overrides
+///
+/// Templates reference variables as `{name}`; unknown names are left as-is.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct CommentTemplates {
+    pub locale: Locale,
+    #[serde(skip_serializing_if = "HashMap::is_empty")]
+    pub templates: HashMap<MessageKind, String>,
+}
+
+fn builtin(kind: MessageKind, locale: Locale) -> &'static str {
+    match (kind, locale) {
+        (MessageKind::PushReference, Locale::En) =>
+            "**{user}** pushed a commit on branch {branch} that referenced this pull request: [{commit}]({url}?ref={branch})",
+        (MessageKind::PushReference, Locale::ZhCn) =>
+            "**{user}** 在分支 {branch} 上推送了引用此合并请求的提交：[{commit}]({url}?ref={branch})",
+        (MessageKind::Conflict, Locale::En) =>
+            "**Backport to `{branch}` failed**: cherry-pick of {commit} conflicts.\n\nConflicting files:\n{files}{snippet}{patches}\nTo resolve manually:\n```\ngit checkout {branch}\ngit cherry-pick -x {commit}\n```\n",
+        (MessageKind::Conflict, Locale::ZhCn) =>
+            "**回合到 `{branch}` 失败**：cherry-pick {commit} 时发生冲突。\n\n冲突文件：\n{files}{snippet}{patches}\n手动解决：\n```\ngit checkout {branch}\ngit cherry-pick -x {commit}\n```\n",
+        (MessageKind::Secrets, Locale::En) =>
+            "**Backport to `{branch}` blocked**: possible secrets detected in cherry-picked changes.\n\n{findings}\nNothing was pushed. Remove the secrets (and rotate them) before re-running the backport.\n",
+        (MessageKind::Secrets, Locale::ZhCn) =>
+            "**回合到 `{branch}` 已阻止**：在 cherry-pick 的改动中检测到疑似密钥。\n\n{findings}\n未推送任何内容。请移除（并轮换）这些密钥后重新执行回合。\n",
+        (MessageKind::MissingSignOff, Locale::En) =>
+            "**Backport blocked**: this repository requires a `Signed-off-by:` trailer (DCO) on every backported commit.\n\nCommits without sign-off:\n{commits}\nAmend the commits with `git commit --amend -s` and re-run the backport.\n",
+        (MessageKind::MissingSignOff, Locale::ZhCn) =>
+            "**回合已阻止**：本仓库要求每个回合提交都带有 `Signed-off-by:` 签署（DCO）。\n\n缺少签署的提交：\n{commits}\n请使用 `git commit --amend -s` 修改提交后重新执行回合。\n",
+        (MessageKind::InvalidBranch, Locale::En) =>
+            "**Backport blocked**: the branch label `{branch}` is not a valid branch name: {reason}.\n\nFix the label description and re-run the backport.\n",
+        (MessageKind::InvalidBranch, Locale::ZhCn) =>
+            "**回合已阻止**：分支标签 `{branch}` 不是合法的分支名：{reason}。\n\n请修正标签描述后重新执行回合。\n",
+        (MessageKind::OversizedCommit, Locale::En) =>
+            "**Backport to `{branch}` blocked**: commit {commit} exceeds the size limits.\n\n{findings}\nNothing was pushed. Add the `{label}` label to backport it anyway.\n",
+        (MessageKind::OversizedCommit, Locale::ZhCn) =>
+            "**回合到 `{branch}` 已阻止**：提交 {commit} 超出大小限制。\n\n{findings}\n未推送任何内容。如仍需回合，请添加 `{label}` 标签。\n",
+        (MessageKind::AlreadyPresent, Locale::En) =>
+            "**Backport to `{branch}`**: these commits were not cherry-picked because `{branch}` already has the same changes:\n{commits}",
+        (MessageKind::AlreadyPresent, Locale::ZhCn) =>
+            "**回合到 `{branch}`**：以下提交未被 cherry-pick，因为 `{branch}` 已包含相同的改动：\n{commits}",
+        (MessageKind::ReopenedAfterBackport, Locale::En) =>
+            "**This PR was reopened after it was backported** onto:\n{branches}\nIf it is merged again, it will only be backported again with the `{label}` label.\n",
+        (MessageKind::ReopenedAfterBackport, Locale::ZhCn) =>
+            "**此 PR 在回合后被重新打开**，已回合到：\n{branches}\n再次合入时，仅在添加 `{label}` 标签后才会重新回合。\n",
+        (MessageKind::CanaryReport, Locale::En) =>
+            "**Canary run {run}/{of}**: the repository configuration changed, so nothing was pushed. With the new configuration this PR would push to {target}:\n{branches}\nReal pushes resume once an operator confirms the configuration.\n",
+        (MessageKind::CanaryReport, Locale::ZhCn) =>
+            "**试运行 {run}/{of}**：仓库配置已变更，因此未推送任何内容。按新配置，此 PR 将推送到 {target}：\n{branches}\n管理员确认配置后将恢复实际推送。\n",
+    }
+}
+
+/// Substitutes `{name}` placeholders in a single pass
+///
+/// Substituted values are not expanded again, so user content containing
+/// braces (code snippets, commit messages) is inserted verbatim.
+pub fn render_template(template: &str, vars: &[(&str, &str)]) -> String {
+    let mut output = String::with_capacity(template.len());
+    let mut rest = template;
+    while let Some(start) = rest.find('{') {
+        output.push_str(&rest[..start]);
+        let after = &rest[start + 1..];
+        let value = after.find('}').and_then(|end| {
+            let name = &after[..end];
+            vars.iter().find(|(key, _)| *key == name).map(|(_, value)| (end, *value))
+        });
+        match value {
+            Some((end, value)) => {
+                output.push_str(value);
+                rest = &after[end + 1..];
+            }
+            None => {
+                output.push('{');
+                rest = after;
+            }
+        }
+    }
+    output.push_str(rest);
+    output
+}
+
+impl CommentTemplates {
+    /// Renders `kind` with the repository's override or the built-in template of its locale
+    pub fn render(&self, kind: MessageKind, vars: &[(&str, &str)]) -> String {
+        let template = self
+            .templates
+            .get(&kind)
+            .map(String::as_str)
+            .unwrap_or_else(|| builtin(kind, self.locale));
+        render_template(template, vars)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_render_template() {
+        assert_eq!(render_template("{a} and {b}", &[("a", "x"), ("b", "{a}")]), "x and {a}");
+        assert_eq!(render_template("fn f() {unknown} {", &[]), "fn f() {unknown} {");
+    }
+
+    #[test]
+    fn test_locale_and_overrides() {
+        let vars = [("commits", "- abc\n")];
+        let english = CommentTemplates::default();
+        assert!(english.render(MessageKind::MissingSignOff, &vars).starts_with("**Backport blocked**"));
+
+        let config: CommentTemplates = serde_yaml::from_str(
+            "locale: zh-CN\ntemplates:\n  conflict: \"冲突: {branch}\"\n",
+        ).unwrap();
+        assert!(config.render(MessageKind::MissingSignOff, &vars).contains("- abc\n"));
+        assert!(config.render(MessageKind::MissingSignOff, &vars).starts_with("**回合已阻止**"));
+        assert_eq!(config.render(MessageKind::Conflict, &[("branch", "release")]), "冲突: release");
+    }
+}
@@ -0,0 +1,227 @@
+use std::sync::{Mutex, OnceLock};
+use serde::Serialize;
+use utoipa::ToSchema;
+use serde_json::Value;
+use log::{info, error};
+
+use crate::utils::config::{self, Config};
+use crate::utils::git::api_base_url;
+use crate::utils::{gitcode, gitlab};
+use crate::utils::remote_url::RemoteUrl;
+
+/// What the bot does with a repository, and so which permission its token needs
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum Access {
+    /// Pushing backport branches to the target
+    ContentsWrite,
+    /// Commenting on and labelling the source PRs
+    PullRequestsWrite,
+}
+
+impl Access {
+    /// Name of the permission as shown on the platform
+    pub fn permission(&self, platform: &str) -> &'static str {
+        match (self, platform) {
+            (Access::ContentsWrite, "github") => "contents:write",
+            (Access::PullRequestsWrite, "github") => "pull-requests:write",
+            (Access::ContentsWrite, _) => "push",
+            (Access::PullRequestsWrite, _) => "pull",
+        }
+    }
+}
+
+/// One token permission the bot relies on
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Requirement {
+    pub platform: &'static str,
+    pub namespace: String,
+    pub repo: String,
+    pub access: Access,
+}
+
+/// Outcome of verifying one requirement
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct ScopeCheck {
+    pub platform: String,
+    pub repo: String,
+    pub access: Access,
+    pub permission: &'static str,
+    pub ok: bool,
+    pub detail: String,
+}
+
+/// Permissions needed for the configured repositories on `platforms`
+///
+/// Targets need push access; comments go to the source repository on
+/// `RepoConfig::source_platform`.
+pub fn requirements(config: &Config, platforms: &[&str]) -> Vec<Requirement> {
+    let mut requirements: Vec<Requirement> = Vec::new();
+    let mut push = |requirement: Requirement| {
+        if platforms.contains(&requirement.platform) && !requirements.contains(&requirement) {
+            requirements.push(requirement);
+        }
+    };
+    let mut repos: Vec<_> = config.repos.values().filter(|repo_config| repo_config.enabled).collect();
+    repos.sort_by(|a, b| a.repo_name.cmp(&b.repo_name));
+    for repo_config in repos {
+        let target = match RemoteUrl::parse(&repo_config.target_repo) {
+            Ok(target) => target,
+            Err(e) => {
+                error!("Skipping token check of {}: {}", repo_config.repo_name, e);
+                continue;
+            }
+        };
+        let Some((namespace, name)) = target.namespace_and_name() else { continue };
+        push(Requirement {
+            platform: target.platform(),
+            namespace: namespace.to_string(),
+            repo: name.to_string(),
+            access: Access::ContentsWrite,
+        });
+        push(Requirement {
+            platform: repo_config.source_platform(),
+            namespace: repo_config.namespace.clone(),
+            repo: repo_config.repo_name.clone(),
+            access: Access::PullRequestsWrite,
+        });
+    }
+    requirements
+}
+
+/// Decides whether a token covers `access` from the repository metadata it
+/// fetched and, for GitHub classic tokens, its `X-OAuth-Scopes` header
+///
+/// Fine-grained GitHub tokens do not expose their permissions; for them the
+/// repository `permissions` of the token's user are the best available check.
+pub fn evaluate(platform: &str, access: Access, oauth_scopes: Option<&str>, repository: &Value) -> Result<String, String> {
+    let permission = access.permission(platform);
+    if let Some(scopes) = oauth_scopes.filter(|_| platform == "github") {
+        let scopes: Vec<&str> = scopes.split(',').map(str::trim).collect();
+        let private = repository["private"].as_bool().unwrap_or(true);
+        let covered = scopes.contains(&"repo") || (!private && scopes.contains(&"public_repo"));
+        if !covered {
+            let needed = if private { "repo" } else { "repo or public_repo" };
+            return Err(format!("classic token scopes [{}] lack {} (needed for {})", scopes.join(", "), needed, permission));
+        }
+    }
+    if platform == "gitlab" {
+        let required = match access {
+            Access::ContentsWrite => gitlab::DEVELOPER_ACCESS,
+            Access::PullRequestsWrite => gitlab::REPORTER_ACCESS,
+        };
+        return match gitlab::access_level(repository) {
+            Some(level) if level >= required => Ok(format!("{} granted", permission)),
+            Some(_) => Err(format!("token lacks {}", permission)),
+            None => Err(format!("project metadata does not report permissions; cannot verify {}", permission)),
+        };
+    }
+    let permissions = match platform {
+        "github" => &repository["permissions"],
+        _ => &repository["permission"],
+    };
+    let granted = match (platform, access) {
+        ("github", Access::ContentsWrite) => permissions["push"].as_bool(),
+        ("github", Access::PullRequestsWrite) => permissions["triage"].as_bool().or(permissions["push"].as_bool()),
+        (_, Access::ContentsWrite) => permissions["push"].as_bool(),
+        (_, Access::PullRequestsWrite) => permissions["pull"].as_bool(),
+    };
+    match granted {
+        Some(true) => Ok(format!("{} granted", permission)),
+        Some(false) => Err(format!("token lacks {}", permission)),
+        None => Err(format!("repository metadata does not report permissions; cannot verify {}", permission)),
+    }
+}
+
+fn check(requirement: &Requirement) -> ScopeCheck {
+    let full_name = format!("{}/{}", requirement.namespace, requirement.repo);
+    let result = gitcode::get_repository_with_scopes(api_base_url(requirement.platform), &requirement.namespace, &requirement.repo, requirement.platform)
+        .map_err(|e| format!("repository not accessible: {}", e))
+        .and_then(|(repository, scopes)| evaluate(requirement.platform, requirement.access, scopes.as_deref(), &repository));
+    let (ok, detail) = match result {
+        Ok(detail) => (true, detail),
+        Err(detail) => (false, detail),
+    };
+    ScopeCheck {
+        platform: requirement.platform.to_string(),
+        repo: full_name,
+        access: requirement.access,
+        permission: requirement.access.permission(requirement.platform),
+        ok,
+        detail,
+    }
+}
+
+fn last_checks() -> &'static Mutex<Option<Vec<ScopeCheck>>> {
+    static CHECKS: OnceLock<Mutex<Option<Vec<ScopeCheck>>>> = OnceLock::new();
+    CHECKS.get_or_init(|| Mutex::new(None))
+}
+
+/// Verifies the tokens of `platforms` against every configured repository,
+/// logging each missing permission, and keeps the results for readiness
+pub fn verify(platforms: &[&str]) -> Vec<ScopeCheck> {
+    let checks: Vec<ScopeCheck> = match config::read_config(config::CONFIG_FILE) {
+        Ok(config) => requirements(&config, platforms).iter().map(check).collect(),
+        Err(e) => {
+            error!("Token check cannot read {}: {}", config::CONFIG_FILE, e);
+            Vec::new()
+        }
+    };
+    for check in &checks {
+        if check.ok {
+            info!("Token check {} {} ({}): {}", check.platform, check.repo, check.permission, check.detail);
+        } else {
+            error!("Token check failed for {} {} ({}): {}", check.platform, check.repo, check.permission, check.detail);
+        }
+    }
+    *last_checks().lock().unwrap() = Some(checks.clone());
+    checks
+}
+
+/// Results of the last `verify`, `None` before it completed
+pub fn last_results() -> Option<Vec<ScopeCheck>> {
+    last_checks().lock().unwrap().clone()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+    use crate::utils::config::RepoConfig;
+    use std::collections::HashMap;
+
+    #[test]
+    fn test_requirements_follow_the_mirror_direction() {
+        let mut repos = HashMap::new();
+        repos.insert("a".to_string(), RepoConfig::new("https://gitcode.com/org/a.git", "org", "a"));
+        repos.insert("b".to_string(), RepoConfig::new("https://gitcode.com/mirror/b.git", "gh-org", "b"));
+        let requirements = requirements(&Config { groups: Default::default(), repos }, &["github", "gitcode"]);
+        let summary: Vec<(&str, String, Access)> = requirements
+            .iter()
+            .map(|r| (r.platform, format!("{}/{}", r.namespace, r.repo), r.access))
+            .collect();
+        assert_eq!(summary, vec![
+            ("gitcode", "org/a".to_string(), Access::ContentsWrite),
+            ("gitcode", "org/a".to_string(), Access::PullRequestsWrite),
+            ("gitcode", "mirror/b".to_string(), Access::ContentsWrite),
+            ("github", "gh-org/b".to_string(), Access::PullRequestsWrite),
+        ]);
+    }
+
+    #[test]
+    fn test_evaluate_reports_the_missing_permission() {
+        let read_only = json!({ "private": true, "permissions": { "pull": true, "triage": false, "push": false } });
+        assert_eq!(
+            evaluate("github", Access::ContentsWrite, None, &read_only),
+            Err("token lacks contents:write".to_string())
+        );
+        let writer = json!({ "private": true, "permissions": { "push": true } });
+        assert!(evaluate("github", Access::PullRequestsWrite, None, &writer).is_ok());
+        assert!(evaluate("github", Access::ContentsWrite, Some("read:org, public_repo"), &writer)
+            .unwrap_err()
+            .contains("lack repo"));
+        let gitcode = json!({ "permission": { "pull": true, "push": false } });
+        assert!(evaluate("gitcode", Access::PullRequestsWrite, None, &gitcode).is_ok());
+        assert_eq!(evaluate("gitcode", Access::ContentsWrite, None, &gitcode), Err("token lacks push".to_string()));
+    }
+}
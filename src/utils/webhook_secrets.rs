@@ -0,0 +1,315 @@
+use std::collections::HashMap;
+use std::env;
+use std::path::{Path, PathBuf};
+use std::sync::{Mutex, OnceLock};
+use chrono::{DateTime, Duration, Utc};
+use rand::RngCore;
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+use log::{info, error};
+
+use crate::error::{Error, Result};
+use crate::utils::{aes_cbc, aes_gcm, git, gitcode, hash, service_key, state, state_store};
+use crate::utils::body::WebhookBody;
+use crate::utils::gitcode::RepoWebhook;
+
+/// Per-repository webhook secret, encrypted with the service key
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct StoredSecret {
+    /// `gcm:` and the hex of the AES-256-GCM nonce, ciphertext and tag; older
+    /// entries hold the hex of a 16-byte IV and an AES-256-CBC ciphertext.
+    /// `None` until the first staged secret is promoted
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub secret: Option<String>,
+    /// Secret replaced by the last rotation, accepted during the grace period
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub previous: Option<String>,
+    /// Secret being set on the forge's webhook, accepted until it is promoted
+    /// or discarded
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub pending: Option<String>,
+    pub rotated_at: DateTime<Utc>,
+}
+
+/// Webhook secrets keyed by `platform/namespace/repo`, persisted in `webhook_secrets.json`
+pub struct WebhookSecrets {
+    path: PathBuf,
+    secrets: HashMap<String, StoredSecret>,
+}
+
+pub fn repo_key(platform: &str, namespace: &str, repo_name: &str) -> String {
+    format!("{}/{}/{}", platform, namespace, repo_name)
+}
+
+/// Generates a random 32-byte secret, hex-encoded
+pub fn generate_secret() -> String {
+    let mut bytes = [0u8; 32];
+    rand::thread_rng().fill_bytes(&mut bytes);
+    hex::encode(bytes)
+}
+
+/// Prefix of secrets encrypted with AES-256-GCM
+const GCM_PREFIX: &str = "gcm:";
+
+pub(crate) fn encrypt(key: &[u8], secret: &str) -> Result<String> {
+    let encrypted = aes_gcm::encrypt(key, secret.as_bytes())?;
+    Ok(format!("{}{}", GCM_PREFIX, hex::encode(encrypted)))
+}
+
+pub(crate) fn decrypt(key: &[u8], stored: &str) -> Result<String> {
+    let plaintext = match stored.strip_prefix(GCM_PREFIX) {
+        Some(encrypted) => aes_gcm::decrypt(key, &hex::decode(encrypted).map_err(|e| Error::crypto(e.to_string()))?)?,
+        // Written before secrets were authenticated
+        None => aes_cbc::decrypt_prefixed(key, &hex::decode(stored).map_err(|e| Error::crypto(e.to_string()))?)?,
+    };
+    String::from_utf8(plaintext).map_err(|e| Error::crypto(e.to_string()))
+}
+
+/// AES key derived from the service key, the same way `main.rs` derives it
+pub fn storage_key() -> Result<&'static [u8]> {
+    static KEY: OnceLock<Vec<u8>> = OnceLock::new();
+    if let Some(key) = KEY.get() {
+        return Ok(key);
+    }
+    let password = service_key::get_service_key().map_err(|e| Error::config(format!("Failed to read the service key: {}", e)))?;
+    let key = hex::decode(hash::sha256_hex(&password)).map_err(|e| Error::crypto(e.to_string()))?;
+    Ok(KEY.get_or_init(|| key))
+}
+
+/// How long the replaced secret keeps verifying deliveries (`WEBHOOK_SECRET_GRACE_SECS`, default 600)
+fn grace_period() -> Duration {
+    let secs = env::var("WEBHOOK_SECRET_GRACE_SECS")
+        .ok()
+        .and_then(|value| value.parse().ok())
+        .unwrap_or(600);
+    Duration::seconds(secs)
+}
+
+impl WebhookSecrets {
+    pub fn open(path: &Path) -> WebhookSecrets {
+        let secrets = match state_store::read_document(path) {
+            Ok(Some(contents)) => serde_json::from_str(&contents).unwrap_or_else(|e| {
+                error!("Failed to parse webhook secrets {:?}, starting empty: {}", path, e);
+                HashMap::new()
+            }),
+            _ => HashMap::new(),
+        };
+        WebhookSecrets { path: path.to_path_buf(), secrets }
+    }
+
+    fn save(&self) -> std::io::Result<()> {
+        let contents = serde_json::to_string_pretty(&self.secrets)?;
+        state_store::write_document(&self.path, &contents)
+    }
+
+    fn persist(&self) -> Result<()> {
+        Ok(self.save()?)
+    }
+
+    /// Stores `secret` as the pending secret of `repo`, accepted alongside the
+    /// current one until `promote` or `discard`
+    pub fn stage(&mut self, repo: &str, secret: &str, key: &[u8]) -> Result<()> {
+        let encrypted = encrypt(key, secret)?;
+        let stored = self.secrets.entry(repo.to_string()).or_insert_with(|| StoredSecret {
+            secret: None,
+            previous: None,
+            pending: None,
+            rotated_at: Utc::now(),
+        });
+        stored.pending = Some(encrypted);
+        self.persist()
+    }
+
+    /// Makes the pending secret of `repo` current, keeping the current one as
+    /// the previous secret
+    pub fn promote(&mut self, repo: &str) -> Result<()> {
+        let stored = self.secrets.get_mut(repo).filter(|stored| stored.pending.is_some())
+            .ok_or_else(|| Error::job(format!("No pending webhook secret for {}", repo)))?;
+        stored.previous = stored.secret.take();
+        stored.secret = stored.pending.take();
+        stored.rotated_at = Utc::now();
+        self.persist()
+    }
+
+    /// Drops the pending secret of `repo` after the forge rejected it
+    pub fn discard(&mut self, repo: &str) -> Result<()> {
+        if let Some(stored) = self.secrets.get_mut(repo) {
+            stored.pending = None;
+            if stored.secret.is_none() {
+                self.secrets.remove(repo);
+            }
+        }
+        self.persist()
+    }
+
+    /// Whether `repo` has a current secret of its own
+    pub fn is_provisioned(&self, repo: &str) -> bool {
+        self.secrets.get(repo).is_some_and(|stored| stored.secret.is_some())
+    }
+
+    /// Secrets currently accepted for `repo`: the current and pending ones,
+    /// plus the previous one while within `grace` of the rotation
+    pub fn accepted(&self, repo: &str, key: &[u8], now: DateTime<Utc>, grace: Duration) -> Vec<String> {
+        let stored = match self.secrets.get(repo) {
+            Some(stored) => stored,
+            None => return Vec::new(),
+        };
+        let mut encrypted: Vec<&String> = stored.secret.iter().chain(stored.pending.iter()).collect();
+        if now - stored.rotated_at < grace {
+            encrypted.extend(stored.previous.as_ref());
+        }
+        encrypted
+            .into_iter()
+            .filter_map(|value| decrypt(key, value).map_err(|e| error!("Failed to decrypt webhook secret of {}: {}", repo, e)).ok())
+            .collect()
+    }
+}
+
+/// The process-wide webhook secret store
+pub fn store() -> &'static Mutex<WebhookSecrets> {
+    static STORE: OnceLock<Mutex<WebhookSecrets>> = OnceLock::new();
+    STORE.get_or_init(|| Mutex::new(WebhookSecrets::open(&state::state_dir().join("webhook_secrets.json"))))
+}
+
+/// Keys a delivery's signature may be verified with: the repository's own
+/// secrets, and the platform key until one of them is current
+pub fn verification_keys(platform: &str, body: &WebhookBody, platform_key: &str) -> Vec<String> {
+    let mut keys = Vec::new();
+    let mut provisioned = false;
+    if let (Some((namespace, repo_name)), Ok(key)) = (body.repo_identity(platform), storage_key()) {
+        let repo = repo_key(platform, &namespace, &repo_name);
+        let secrets = store().lock().unwrap();
+        provisioned = secrets.is_provisioned(&repo);
+        keys = secrets.accepted(&repo, key, Utc::now(), grace_period());
+    }
+    if !provisioned {
+        keys.push(platform_key.to_string());
+    }
+    keys
+}
+
+/// Generates a secret for `repo`, stages it, hands it to `apply` to set on
+/// the forge, then promotes it; a staged secret the forge rejected is discarded
+///
+/// Deliveries signed with either the current or the new secret verify while
+/// the forge is being updated.
+pub fn provision<F>(repo: &str, key: &[u8], apply: F) -> Result<()>
+where
+    F: FnOnce(&str) -> Result<()>,
+{
+    let secret = generate_secret();
+    store().lock().unwrap().stage(repo, &secret, key)?;
+    if let Err(e) = apply(&secret) {
+        if let Err(discard) = store().lock().unwrap().discard(repo) {
+            error!("Failed to discard the pending webhook secret of {}: {}", repo, discard);
+        }
+        return Err(e);
+    }
+    store().lock().unwrap().promote(repo)
+}
+
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct RotateRequest {
+    pub platform: String,
+    pub namespace: String,
+    pub repo_name: String,
+    /// Delivery URL of the hook to update; required when the repository has several hooks
+    #[serde(default)]
+    pub webhook_url: Option<String>,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct RotateReport {
+    pub hook_id: u64,
+    pub rotated_at: DateTime<Utc>,
+}
+
+/// Generates a new secret, sets it on the forge's webhook and stores it encrypted
+///
+/// The secret is stored as pending before the forge is updated, so no
+/// delivery is rejected during the rotation.
+pub fn rotate(request: &RotateRequest) -> Result<RotateReport> {
+    if request.platform != "github" && request.platform != "gitcode" {
+        return Err(Error::config(format!("Unsupported platform {}", request.platform)));
+    }
+    let key = storage_key()?;
+    let base = git::api_base_url(&request.platform);
+    let hooks = gitcode::list_webhooks(base, &request.namespace, &request.repo_name, &request.platform)?;
+    let hook = select_hook(&hooks, request.webhook_url.as_deref())?;
+
+    let repo = repo_key(&request.platform, &request.namespace, &request.repo_name);
+    provision(&repo, key, |secret| {
+        gitcode::update_webhook_secret(base, &request.namespace, &request.repo_name, hook, secret, &request.platform)
+    })?;
+    info!("Rotated webhook secret of {} (hook {})", repo, hook.id);
+    Ok(RotateReport { hook_id: hook.id, rotated_at: Utc::now() })
+}
+
+fn select_hook<'a>(hooks: &'a [RepoWebhook], url: Option<&str>) -> Result<&'a RepoWebhook> {
+    match (url, hooks) {
+        (Some(url), _) => hooks
+            .iter()
+            .find(|hook| hook.url == url)
+            .ok_or_else(|| Error::config(format!("No webhook delivers to {}", url))),
+        (None, [hook]) => Ok(hook),
+        (None, []) => Err(Error::config("The repository has no webhook")),
+        (None, _) => Err(Error::config("The repository has several webhooks; specify webhook_url")),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_rotation_keeps_previous_secret_during_grace() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("webhook_secrets.json");
+        let key = [3u8; 32];
+        let grace = Duration::seconds(600);
+
+        let mut secrets = WebhookSecrets::open(&path);
+        secrets.stage("github/org/repo", "old", &key).unwrap();
+        assert!(!secrets.is_provisioned("github/org/repo"));
+        secrets.promote("github/org/repo").unwrap();
+        secrets.stage("github/org/repo", "new", &key).unwrap();
+        // Both verify while the forge is being updated
+        assert_eq!(secrets.accepted("github/org/repo", &key, Utc::now(), grace), vec!["old", "new"]);
+        secrets.promote("github/org/repo").unwrap();
+
+        let reopened = WebhookSecrets::open(&path);
+        assert!(!std::fs::read_to_string(&path).unwrap().contains("\"new\""));
+        assert_eq!(reopened.accepted("github/org/repo", &key, Utc::now(), grace), vec!["new", "old"]);
+        let later = Utc::now() + Duration::seconds(601);
+        assert_eq!(reopened.accepted("github/org/repo", &key, later, grace), vec!["new"]);
+        assert!(reopened.accepted("github/org/other", &key, Utc::now(), grace).is_empty());
+
+        // A secret the forge rejected is dropped, the current one stays
+        let mut secrets = reopened;
+        secrets.stage("github/org/repo", "rejected", &key).unwrap();
+        secrets.discard("github/org/repo").unwrap();
+        assert_eq!(secrets.accepted("github/org/repo", &key, later, grace), vec!["new"]);
+        secrets.stage("github/org/other", "rejected", &key).unwrap();
+        secrets.discard("github/org/other").unwrap();
+        assert!(!WebhookSecrets::open(&path).secrets.contains_key("github/org/other"));
+
+        // Secrets stored before AES-GCM still decrypt, tampered ones do not
+        let legacy = hex::encode(aes_cbc::encrypt(&key, b"legacy").unwrap());
+        assert_eq!(decrypt(&key, &legacy).unwrap(), "legacy");
+        let mut tampered = encrypt(&key, "new").unwrap();
+        let flipped = if tampered.ends_with('0') { "1" } else { "0" };
+        tampered.replace_range(tampered.len() - 1.., flipped);
+        assert!(decrypt(&key, &tampered).is_err());
+    }
+
+    #[test]
+    fn test_select_hook() {
+        let hooks = vec![
+            RepoWebhook { id: 1, url: "https://a/hooks/github".to_string() },
+            RepoWebhook { id: 2, url: "https://b/hooks/github".to_string() },
+        ];
+        assert_eq!(select_hook(&hooks, Some("https://b/hooks/github")).unwrap().id, 2);
+        assert!(select_hook(&hooks, None).is_err());
+        assert_eq!(select_hook(&hooks[..1], None).unwrap().id, 1);
+    }
+}